@@ -1,23 +1,44 @@
 DROP TABLE IF EXISTS user;
 CREATE TABLE user (
-    id VARCHAR(16) PRIMARY KEY NOT NULL,
+    id VARCHAR(26) PRIMARY KEY NOT NULL,
     username TEXT NOT NULL,
+    username_skeleton TEXT NOT NULL,
     nickname TEXT,
     password TEXT NOT NULL,
     permission TEXT NOT NULL,
-    avatar_id VARCHAR(8),
+    avatar_id VARCHAR(26),
+    quota_max_bytes INTEGER,
+    quota_max_files INTEGER,
+    bio TEXT,
+    signature TEXT,
+    location TEXT,
+    website TEXT,
     date_edited TEXT,
     date_created TEXT NOT NULL,
+    last_active TEXT,
+    last_login TEXT,
     deleted INTEGER not null,
     FOREIGN KEY(avatar_id) REFERENCES attachment(id)
 );
+CREATE UNIQUE INDEX user_username ON user(username COLLATE NOCASE);
+CREATE INDEX user_username_skeleton ON user(username_skeleton);
+
+DROP TABLE IF EXISTS username_history;
+CREATE TABLE username_history (
+    username TEXT NOT NULL,
+    user_id VARCHAR(26) NOT NULL,
+    date_changed TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES user(id)
+);
+CREATE INDEX username_history_username ON username_history(username COLLATE NOCASE);
 
 DROP TABLE IF EXISTS category;
 CREATE TABLE category (
-    id VARCHAR(8) PRIMARY KEY NOT NULL,
+    id VARCHAR(26) PRIMARY KEY NOT NULL,
     title TEXT NOT NULL,
     minimum_write_permission TEXT NOT NULL,
     minimum_read_permission TEXT NOT NULL,
+    qa_mode INTEGER not null,
     deleted INTEGER not null,
     date_edited TEXT,
     date_created TEXT NOT NULL
@@ -25,38 +46,138 @@ CREATE TABLE category (
 
 DROP TABLE IF EXISTS post;
 CREATE TABLE post (
-    id VARCHAR(8) PRIMARY KEY NOT NULL,
+    id VARCHAR(26) PRIMARY KEY NOT NULL,
     title TEXT NOT NULL,
     content TEXT NOT NULL,
-    category_id VARCHAR(8) NOT NULL,
-    creator_id VARCHAR(8) NOT NULL,
+    category_id VARCHAR(26) NOT NULL,
+    creator_id VARCHAR(26) NOT NULL,
     locked INTEGER not null,
+    pinned INTEGER not null,
+    announcement INTEGER not null,
     deleted INTEGER not null,
+    accepted_reply_id VARCHAR(26),
     date_created TEXT NOT NULL,
     date_edited TEXT,
     FOREIGN KEY(creator_id) REFERENCES user(id)
     FOREIGN KEY(category_id) REFERENCES category(id)
+    FOREIGN KEY(accepted_reply_id) REFERENCES reply(id)
 );
 
 DROP TABLE IF EXISTS reply;
 CREATE TABLE reply (
-    id VARCHAR(8) PRIMARY KEY NOT NULL,
-    creator_id VARCHAR(8) NOT NULL,
+    id VARCHAR(26) PRIMARY KEY NOT NULL,
+    creator_id VARCHAR(26) NOT NULL,
     content TEXT NOT NULL,
-    post_id VARCHAR(8) NOT NULL,
+    post_id VARCHAR(26) NOT NULL,
+    parent_reply_id VARCHAR(26),
+    quote_source TEXT,
+    quote_source_id VARCHAR(26),
+    quote_excerpt TEXT,
     date_edited TEXT,
     date_created TEXT NOT NULL,
     deleted INTEGER not null,
     FOREIGN KEY(post_id) REFERENCES post(id),
+    FOREIGN KEY(parent_reply_id) REFERENCES reply(id),
     FOREIGN KEY(creator_id) REFERENCES user(id)
 );
 
 
+DROP TABLE IF EXISTS post_read;
+CREATE TABLE post_read (
+    user_id VARCHAR(26) NOT NULL,
+    post_id VARCHAR(26) NOT NULL,
+    last_read_reply_id VARCHAR(26),
+    date_read TEXT NOT NULL,
+    PRIMARY KEY(user_id, post_id),
+    FOREIGN KEY(user_id) REFERENCES user(id),
+    FOREIGN KEY(post_id) REFERENCES post(id),
+    FOREIGN KEY(last_read_reply_id) REFERENCES reply(id)
+);
+
+DROP TABLE IF EXISTS category_visit;
+CREATE TABLE category_visit (
+    user_id VARCHAR(26) NOT NULL,
+    category_id VARCHAR(26) NOT NULL,
+    date_visited TEXT NOT NULL,
+    PRIMARY KEY(user_id, category_id),
+    FOREIGN KEY(user_id) REFERENCES user(id),
+    FOREIGN KEY(category_id) REFERENCES category(id)
+);
+
+DROP TABLE IF EXISTS subscription;
+CREATE TABLE subscription (
+    user_id VARCHAR(26) NOT NULL,
+    target TEXT NOT NULL,
+    target_id VARCHAR(26) NOT NULL,
+    level TEXT NOT NULL,
+    date_created TEXT NOT NULL,
+    PRIMARY KEY(user_id, target, target_id),
+    FOREIGN KEY(user_id) REFERENCES user(id)
+);
+CREATE INDEX subscription_target ON subscription(target, target_id);
+
+DROP TABLE IF EXISTS notification;
+CREATE TABLE notification (
+    id VARCHAR(26) PRIMARY KEY NOT NULL,
+    user_id VARCHAR(26) NOT NULL,
+    kind TEXT NOT NULL,
+    post_id VARCHAR(26) NOT NULL,
+    reply_id VARCHAR(26),
+    creator_id VARCHAR(26) NOT NULL,
+    date_created TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES user(id),
+    FOREIGN KEY(post_id) REFERENCES post(id),
+    FOREIGN KEY(reply_id) REFERENCES reply(id),
+    FOREIGN KEY(creator_id) REFERENCES user(id)
+);
+CREATE INDEX notification_user ON notification(user_id);
+
+DROP TABLE IF EXISTS email_settings;
+CREATE TABLE email_settings (
+    user_id VARCHAR(26) PRIMARY KEY NOT NULL,
+    email TEXT NOT NULL,
+    frequency TEXT NOT NULL,
+    unsubscribe_token TEXT NOT NULL,
+    last_sent TEXT NOT NULL,
+    verified INTEGER NOT NULL,
+    confirmation_token TEXT,
+    confirmation_sent TEXT,
+    date_edited TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES user(id)
+);
+CREATE UNIQUE INDEX email_settings_unsubscribe_token ON email_settings(unsubscribe_token);
+CREATE UNIQUE INDEX email_settings_confirmation_token ON email_settings(confirmation_token);
+
 DROP TABLE IF EXISTS attachment;
 CREATE TABLE attachment (
-    id VARCHAR(8) PRIMARY KEY NOT NULL,
-    path TEXT NOT NULL,
-    creator_id VARCHAR(8) NOT NULL,
+    id VARCHAR(26) PRIMARY KEY NOT NULL,
+    blob_key TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    creator_id VARCHAR(26) NOT NULL,
+    link_target TEXT,
+    link_target_id VARCHAR(26),
+    date_created TEXT NOT NULL,
+    FOREIGN KEY(creator_id) REFERENCES user(id),
+    FOREIGN KEY(blob_key) REFERENCES blob(key)
+);
+
+DROP TABLE IF EXISTS blob;
+CREATE TABLE blob (
+    key TEXT PRIMARY KEY NOT NULL,
+    size INTEGER NOT NULL,
+    ref_count INTEGER NOT NULL,
+    date_created TEXT NOT NULL
+);
+
+DROP TABLE IF EXISTS reaction;
+CREATE TABLE reaction (
+    creator_id VARCHAR(26) NOT NULL,
+    target TEXT NOT NULL,
+    target_id VARCHAR(26) NOT NULL,
+    kind TEXT NOT NULL,
     date_created TEXT NOT NULL,
+    PRIMARY KEY(creator_id, target, target_id, kind),
     FOREIGN KEY(creator_id) REFERENCES user(id)
 );
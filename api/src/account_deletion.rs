@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use crate::{
+    db::models::{Name, UserId, RELEASED_USERNAME_PREFIX},
+    text_validation::TextError,
+};
+
+/// what happens to the username of a deleted account
+#[derive(Clone, Copy)]
+pub enum UsernamePolicy {
+    /// the username stays taken so nobody can impersonate the former user
+    Reserve,
+    /// the username is renamed away and can be registered again
+    Release,
+}
+
+pub const DEFAULT_USERNAME_POLICY: UsernamePolicy = UsernamePolicy::Reserve;
+
+pub struct AccountDeletionConfig {
+    pub username_policy: UsernamePolicy,
+}
+
+pub type AccountDeletionConfigParam = Arc<AccountDeletionConfig>;
+
+impl TryFrom<&str> for UsernamePolicy {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "reserve" => Ok(UsernamePolicy::Reserve),
+            "release" => Ok(UsernamePolicy::Release),
+            other => Err(format!("expected `reserve` or `release`, got `{other}`")),
+        }
+    }
+}
+
+/// the name a released account is renamed to, unique because the id is.
+/// the prefix is short enough for the whole id to fit in a [`Name`]
+pub fn released_username(id: &UserId) -> Result<Name, TextError> {
+    Name::try_from(format!("{RELEASED_USERNAME_PREFIX}{id}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_unchecked::FromUnchecked, usernames};
+
+    #[test]
+    fn released_usernames_fit_and_stay_reserved() {
+        let id = UserId::new();
+        let username = released_username(&id).expect("released username should be valid");
+
+        assert_eq!(username.to_string(), format!("[del]{id}"));
+        assert!(usernames::is_reserved(&username));
+    }
+
+    #[test]
+    fn released_usernames_of_legacy_ids() {
+        let id = UserId::from_unchecked("abcd1234".to_string());
+        let username = released_username(&id).expect("released username should be valid");
+        assert_eq!(username.to_string(), "[del]abcd1234");
+    }
+
+    #[test]
+    fn parses_username_policy() {
+        assert!(matches!(
+            UsernamePolicy::try_from("reserve"),
+            Ok(UsernamePolicy::Reserve)
+        ));
+        assert!(matches!(
+            UsernamePolicy::try_from("release"),
+            Ok(UsernamePolicy::Release)
+        ));
+        assert!(UsernamePolicy::try_from("Release").is_err());
+    }
+}
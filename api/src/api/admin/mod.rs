@@ -0,0 +1,3 @@
+mod users;
+
+pub use users::route as users_route;
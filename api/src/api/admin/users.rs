@@ -0,0 +1,105 @@
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::Response,
+    db::{
+        database::{DatabaseParam, SearchUsers},
+        models::{Permission, UserSort, UserSummary},
+    },
+    permission_verification,
+};
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::QueryParam, prelude::ToSchema, Depot};
+use serde::Serialize;
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 100;
+
+#[derive(Serialize, ToSchema)]
+struct ResponsePage {
+    users: Vec<UserSummary>,
+    page: i64,
+    per_page: i64,
+    /// the number of users matching the filters over all pages
+    total: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: ResponsePage,
+}
+
+/// pages start at 1, users are sorted newest first unless `sort` says otherwise
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 429, 500), security(("session" = [])))]
+pub async fn route(
+    search: QueryParam<String, false>,
+    permission: QueryParam<Permission, false>,
+    deleted: QueryParam<bool, false>,
+    sort: QueryParam<UserSort, false>,
+    page: QueryParam<i64, false>,
+    per_page: QueryParam<i64, false>,
+    depot: &mut Depot,
+) -> Result<Response<RouteResponse>, ApiError> {
+    let admin = AuthenticatedUser::from_depot(depot)?;
+
+    let list_permission = permission_verification::permission_for_important_actions();
+    if !permission_verification::is_allowed(&admin.permission, &list_permission) {
+        let err = format!(
+            "you must be {} or above to list users, you are {}",
+            list_permission, admin.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    let page = page.into_inner().unwrap_or(1);
+    if page < 1 {
+        return Err(ApiError::validation_failed(
+            "page",
+            "page must be 1 or above",
+        ));
+    }
+
+    let per_page = per_page.into_inner().unwrap_or(DEFAULT_PER_PAGE);
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err(ApiError::validation_failed(
+            "per_page",
+            format!("per_page must be between 1 and {MAX_PER_PAGE}"),
+        ));
+    }
+
+    let search = SearchUsers {
+        search: search
+            .into_inner()
+            .filter(|search| !search.trim().is_empty()),
+        permission: permission.into_inner(),
+        deleted: deleted.into_inner(),
+        sort: sort.into_inner().unwrap_or(UserSort::Newest),
+        limit: per_page,
+        offset: (page - 1).saturating_mul(per_page),
+    };
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let users = db
+        .search_users(&search)
+        .await
+        .with_context(|| "unable to search users")?;
+    let total = db
+        .count_users(&search)
+        .await
+        .with_context(|| "unable to count users")?;
+
+    Ok(Response::with_ok(RouteResponse {
+        data: ResponsePage {
+            users,
+            page,
+            per_page,
+            total,
+        },
+        ok: true,
+    }))
+}
@@ -1,27 +1,732 @@
-use crate::api::response::{message_response, Message, Response};
-use crate::db::{database::DatabaseParam, models::Id};
-use salvo::{oapi::extract::PathParam, Depot};
+use crate::api::auth::OptionalUser;
+use crate::api::error::ApiError;
+use crate::attachment_blobs;
+use crate::attachment_validation::AttachmentConfigParam;
+use crate::db::database::Database;
+use crate::db::models::{AttachmentLink, AttachmentTarget, Permission};
+use crate::db::{
+    database::DatabaseParam,
+    models::{AttachmentId, PostId},
+};
+use crate::image_processing::{self, ImageSize};
+use crate::permission_verification;
+use crate::storage::blob_store::BlobStoreParam;
+use eyre::{eyre, Context};
+use salvo::http::header::{
+    ACCEPT_RANGES, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+    ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE, X_CONTENT_TYPE_OPTIONS,
+};
+use salvo::http::StatusCode;
+use salvo::{
+    oapi::extract::{PathParam, QueryParam},
+    Depot, Request,
+};
+use sqlx::types::chrono::{DateTime, Utc};
+use std::ops::Range;
+use tokio::sync::RwLockReadGuard;
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
+/// attachments follow the read permission of the category they are posted in,
+/// unlinked ones (avatars, uploads not yet posted) are readable by anyone.
+/// attachments of deleted replies, posts or categories are only shown to those who can see
+/// deleted content. returns the permission needed to read the attachment
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    permission: &Permission,
+    link: &AttachmentLink,
+) -> Result<Permission, ApiError> {
+    let deleted_permission = permission_verification::permission_for_important_actions();
+    let include_deleted = permission_verification::is_allowed(permission, &deleted_permission);
+
+    let reply = match link.target {
+        AttachmentTarget::Post => None,
+        AttachmentTarget::Reply => Some(
+            db.reply_from_id(&link.target_id.to_typed())
+                .await
+                .with_context(|| "unable to get reply from id")?
+                .filter(|reply| include_deleted || !reply.deleted)
+                .ok_or_else(|| ApiError::not_found("attachment not found"))?,
+        ),
+    };
+    let post_id: PostId = reply
+        .as_ref()
+        .map_or_else(|| link.target_id.to_typed(), |reply| reply.post_id.clone());
+
+    let post = db
+        .post_from_id(&post_id)
+        .await
+        .with_context(|| "unable to get post from id")?
+        .filter(|post| include_deleted || !post.deleted)
+        .ok_or_else(|| ApiError::not_found("attachment not found"))?;
+
+    let category = db
+        .category_from_id(&post.category_id)
+        .await
+        .with_context(|| "unable to get category from id")?
+        .filter(|category| include_deleted || !category.deleted)
+        .ok_or_else(|| ApiError::not_found("attachment not found"))?;
+
+    if !permission_verification::is_allowed(permission, &category.minimum_read_permission) {
+        let err = format!(
+            "you must be {} or above to read attachments in category {}, you are {}",
+            category.minimum_read_permission, category.title, permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(category.minimum_read_permission)
+}
+
+enum RangeRequest {
+    Full,
+    Partial(Range<u64>),
+    Unsatisfiable,
+}
+
+/// only a single byte range is served, anything else falls back to the full body
+fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let Some((start, end)) = header
+        .strip_prefix("bytes=")
+        .filter(|ranges| !ranges.contains(','))
+        .and_then(|range| range.trim().split_once('-'))
+    else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => start..end.saturating_add(1).min(size),
+        (Ok(start), Err(_)) if end.is_empty() => start..size,
+        (Err(_), Ok(suffix)) if start.is_empty() => size.saturating_sub(suffix)..size,
+        _ => return RangeRequest::Full,
+    };
+
+    if range.start >= size || range.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(range)
+    }
+}
+
+/// weak comparison, as used by If-None-Match
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header.trim() == "*"
+        || header
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == etag)
+}
+
+fn http_date(date: &DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn not_modified_since(header: &str, last_modified: &DateTime<Utc>) -> bool {
+    DateTime::parse_from_rfc2822(header)
+        .is_ok_and(|since| last_modified.timestamp() <= since.timestamp())
+}
+
+#[salvo::endpoint(status_codes(200, 206, 304, 400, 403, 404, 416, 429, 500), security((), ("session" = [])))]
 pub async fn route(
-    attachment_id: PathParam<Id>,
+    attachment_id: PathParam<AttachmentId>,
+    size: QueryParam<ImageSize, false>,
     depot: &mut Depot,
-    request: &mut salvo::Request,
+    request: &mut Request,
     response: &mut salvo::Response,
-) -> Result<(), Response<Message>> {
+) -> Result<(), ApiError> {
+    let OptionalUser(user) = OptionalUser::from_depot(depot)?;
+    let permission = user.map_or(Permission::default(), |user| user.permission);
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
-    let db = db.read().await;
-    let attachment = db
-        .attachment_from_id(&attachment_id)
-        .await
-        .map_err(|err| log::error!("unable to get attachment from id: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid attachment id"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+    let blob_store = depot
+        .obtain::<BlobStoreParam>()
+        .map_err(|err| eyre!("unable to get blob store from depot: {err:?}"))?;
+    let attachment_config = depot
+        .obtain::<AttachmentConfigParam>()
+        .map_err(|err| eyre!("unable to get attachment config from depot: {err:?}"))?;
+
+    let (attachment, is_public) = {
+        let db = db.read().await;
+        let attachment = db
+            .attachment_from_id(&attachment_id)
+            .await
+            .with_context(|| "unable to get attachment from id")?
+            .ok_or_else(|| ApiError::not_found("attachment not found"))?;
+
+        let is_public = match &attachment.link {
+            Some(link) => {
+                let required = verify_valid_user_permission(&db, &permission, link).await?;
+                permission_verification::is_allowed(&Permission::default(), &required)
+            }
+            None => true,
+        };
+
+        (attachment, is_public)
+    };
+
+    let (blob_key, content_type) = match size.into_inner() {
+        Some(size) if image_processing::is_image(&attachment.content_type) => (
+            size.blob_key(&attachment.blob_key),
+            image_processing::variant_content_type(&attachment.content_type).to_string(),
+        ),
+        Some(_) => {
+            return Err(ApiError::validation_failed(
+                "size",
+                "attachment is not an image",
+            ))
+        }
+        None => (attachment.blob_key, attachment.content_type),
+    };
+
+    // blobs are addressed by their hash, which makes the hash a strong validator
+    let etag = format!("\"{}\"", attachment_blobs::hash_from_blob_key(&blob_key));
+    let last_modified = DateTime::parse_from_rfc3339(&attachment.date_created)
+        .with_context(|| "unable to parse attachment creation date")?
+        .with_timezone(&Utc);
+    let cache_control = if is_public {
+        &attachment_config.public_cache_control
+    } else {
+        &attachment_config.restricted_cache_control
+    };
+
+    response
+        .add_header(ETAG, &etag, true)
+        .and_then(|response| response.add_header(LAST_MODIFIED, http_date(&last_modified), true))
+        .and_then(|response| response.add_header(CACHE_CONTROL, cache_control, true))
+        .and_then(|response| response.add_header(ACCEPT_RANGES, "bytes", true))
+        .with_context(|| "unable to set attachment headers")?;
+
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
 
-    response.send_file(attachment.path, request.headers()).await;
+    // If-Modified-Since is only considered when If-None-Match is absent
+    let not_modified = match (header(IF_NONE_MATCH), header(IF_MODIFIED_SINCE)) {
+        (Some(if_none_match), _) => etag_matches(if_none_match, &etag),
+        (None, Some(if_modified_since)) => not_modified_since(if_modified_since, &last_modified),
+        (None, None) => false,
+    };
+    if not_modified {
+        response.status_code(StatusCode::NOT_MODIFIED);
+        return Ok(());
+    }
+
+    // a range is only honoured when If-Range, if sent, still matches the current blob
+    let range = header(RANGE).filter(|_| match header(IF_RANGE) {
+        Some(if_range) if if_range.starts_with('"') => if_range == etag,
+        Some(if_range) => not_modified_since(if_range, &last_modified),
+        None => true,
+    });
+
+    let range = match range {
+        Some(range) => {
+            let total = blob_store
+                .size(&blob_key)
+                .await
+                .with_context(|| "unable to get attachment blob size")?
+                .ok_or_else(|| ApiError::not_found("attachment not found"))?;
+            match parse_range(range, total) {
+                RangeRequest::Full => None,
+                RangeRequest::Partial(range) => Some((range, total)),
+                RangeRequest::Unsatisfiable => {
+                    response
+                        .add_header(CONTENT_RANGE, format!("bytes */{total}"), true)
+                        .with_context(|| "unable to set content range")?;
+                    response.status_code(StatusCode::RANGE_NOT_SATISFIABLE);
+                    return Ok(());
+                }
+            }
+        }
+        None => None,
+    };
+
+    let blob = match &range {
+        Some((range, _)) => blob_store.get_range(&blob_key, range.clone()).await,
+        None => blob_store.get(&blob_key).await,
+    }
+    .with_context(|| "unable to get attachment blob")?
+    .ok_or_else(|| ApiError::not_found("attachment not found"))?;
+
+    if let Some((range, total)) = &range {
+        response
+            .add_header(
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{total}", range.start, range.end - 1),
+                true,
+            )
+            .with_context(|| "unable to set content range")?;
+        response.status_code(StatusCode::PARTIAL_CONTENT);
+    }
+
+    // only images are shown inline, anything else is downloaded so browsers never render it as a page
+    let disposition = if image_processing::is_image(&content_type) {
+        "inline"
+    } else {
+        "attachment"
+    };
+    response
+        .add_header(CONTENT_TYPE, content_type, true)
+        .and_then(|response| {
+            response.add_header(
+                CONTENT_DISPOSITION,
+                format!("{disposition}; filename=\"{}\"", attachment.file_name),
+                true,
+            )
+        })
+        .and_then(|response| response.add_header(X_CONTENT_TYPE_OPTIONS, "nosniff", true))
+        .with_context(|| "unable to set attachment headers")?;
+    if let Some(size) = blob.size {
+        response
+            .add_header(CONTENT_LENGTH, size, true)
+            .with_context(|| "unable to set content length")?;
+    }
+    response
+        .streaming(blob.stream)
+        .with_context(|| "unable to stream attachment")?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use salvo::http::header::HeaderName;
+    use salvo::{
+        affix,
+        test::{ResponseExt, TestClient},
+        Router, Service,
+    };
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::api::auth::LoadTestUser;
+    use crate::attachment_validation::{
+        AttachmentConfig, DEFAULT_MAX_SIZE, DEFAULT_PUBLIC_CACHE_CONTROL,
+        DEFAULT_RESTRICTED_CACHE_CONTROL,
+    };
+    use crate::db::database::{
+        CreateAttachment, CreateCategory, CreatePost, CreateReply, CreateUser, EditAttachment,
+        EditCategory, EditPost, EditReply,
+    };
+    use crate::db::models::{CategoryId, Content, Id, Name, ReplyId, Title, UserId};
+    use crate::db::sqlite::SqliteDb;
+    use crate::from_unchecked::FromUnchecked;
+    use crate::password::HashedPassword;
+    use crate::storage::{blob_store::BlobStore, local::LocalBlobStore};
+
+    const CONTENT: &[u8] = b"0123456789";
+
+    /// a category with one post and one reply, and an attachment linked to the post
+    struct Forum {
+        db: DatabaseParam,
+        blob_store: BlobStoreParam,
+        admin_id: UserId,
+        category_id: CategoryId,
+        post_id: PostId,
+        reply_id: ReplyId,
+        attachment_id: AttachmentId,
+    }
+
+    impl Forum {
+        async fn new() -> Self {
+            let mut db = SqliteDb::in_memory().await;
+            let admin_id = db
+                .create_user(CreateUser {
+                    username: Name::from_unchecked("admin".to_string()),
+                    nickname: None,
+                    password: HashedPassword::from_unchecked(String::new()),
+                    permission: Permission::Admin,
+                    avatar_id: None,
+                })
+                .await
+                .expect("user should be created");
+            let category_id = db
+                .create_category(CreateCategory {
+                    title: Title::from_unchecked("category".to_string()),
+                    minimum_write_permission: Permission::User,
+                    minimum_read_permission: Permission::default(),
+                    qa_mode: false,
+                })
+                .await
+                .expect("category should be created");
+            let post_id = db
+                .create_post(CreatePost {
+                    category_id: category_id.clone(),
+                    title: Title::from_unchecked("post".to_string()),
+                    content: Content::from_unchecked("content".to_string()),
+                    creator_id: admin_id.clone(),
+                })
+                .await
+                .expect("post should be created");
+            let reply_id = db
+                .create_reply(CreateReply {
+                    creator_id: admin_id.clone(),
+                    post_id: post_id.clone(),
+                    parent_reply_id: None,
+                    quote: None,
+                    content: Content::from_unchecked("reply".to_string()),
+                })
+                .await
+                .expect("reply should be created");
+
+            let blob_key =
+                attachment_blobs::blob_key_from_hash(&attachment_blobs::hash_bytes(CONTENT));
+            let root = std::env::temp_dir().join(format!(
+                "decorum-attachment-test-{}",
+                crate::ids::generate_id()
+            ));
+            let blob_store = LocalBlobStore::new(root);
+            blob_store
+                .put_bytes(&blob_key, CONTENT.into())
+                .await
+                .expect("blob should be stored");
+            db.acquire_blob(&blob_key, CONTENT.len() as i64)
+                .await
+                .expect("blob should be acquired");
+
+            let attachment_id = db
+                .create_attachment(CreateAttachment {
+                    creator_id: admin_id.clone(),
+                    blob_key,
+                    file_name: "digits.txt".to_string(),
+                    content_type: "text/plain".to_string(),
+                    size: CONTENT.len() as i64,
+                })
+                .await
+                .expect("attachment should be created");
+
+            let forum = Forum {
+                db: Arc::new(RwLock::new(db)),
+                blob_store: Arc::new(blob_store),
+                admin_id,
+                category_id,
+                post_id,
+                reply_id,
+                attachment_id,
+            };
+            forum
+                .link(AttachmentTarget::Post, Id::from(&forum.post_id))
+                .await;
+            forum
+        }
+
+        async fn link(&self, target: AttachmentTarget, target_id: Id) {
+            self.db
+                .write()
+                .await
+                .edit_attachment(EditAttachment {
+                    id: self.attachment_id.clone(),
+                    link: Some(AttachmentLink { target, target_id }),
+                })
+                .await
+                .expect("attachment should be linked");
+        }
+
+        async fn delete_post(&self) {
+            let mut db = self.db.write().await;
+            let post = db
+                .post_from_id(&self.post_id)
+                .await
+                .expect("post should load")
+                .expect("post should exist");
+            db.edit_post(EditPost {
+                id: post.id,
+                category_id: post.category_id,
+                title: post.title,
+                content: post.content,
+                deleted: true,
+                locked: post.locked,
+                pinned: post.pinned,
+                announcement: post.announcement,
+                accepted_reply_id: post.accepted_reply_id,
+            })
+            .await
+            .expect("post should be deleted");
+        }
+
+        async fn delete_reply(&self) {
+            self.db
+                .write()
+                .await
+                .edit_reply(EditReply {
+                    id: self.reply_id.clone(),
+                    quote: None,
+                    content: Content::from_unchecked("reply".to_string()),
+                    deleted: true,
+                })
+                .await
+                .expect("reply should be deleted");
+        }
+
+        async fn delete_category(&self) {
+            let mut db = self.db.write().await;
+            let category = db
+                .category_from_id(&self.category_id)
+                .await
+                .expect("category should load")
+                .expect("category should exist");
+            db.edit_category(EditCategory {
+                id: category.id,
+                title: category.title,
+                minimum_write_permission: category.minimum_write_permission,
+                minimum_read_permission: category.minimum_read_permission,
+                qa_mode: category.qa_mode,
+                deleted: true,
+            })
+            .await
+            .expect("category should be deleted");
+        }
+
+        fn service(&self, user_id: Option<&UserId>) -> Service {
+            let config = AttachmentConfig {
+                max_size: DEFAULT_MAX_SIZE,
+                allowed_types: vec!["text/plain".to_string()],
+                public_cache_control: DEFAULT_PUBLIC_CACHE_CONTROL.to_string(),
+                restricted_cache_control: DEFAULT_RESTRICTED_CACHE_CONTROL.to_string(),
+            };
+
+            Service::new(
+                Router::new()
+                    .hoop(affix::inject::<DatabaseParam>(self.db.clone()))
+                    .hoop(affix::inject::<BlobStoreParam>(self.blob_store.clone()))
+                    .hoop(affix::inject::<AttachmentConfigParam>(Arc::new(config)))
+                    .hoop(LoadTestUser(user_id.cloned()))
+                    .push(Router::with_path("attachment/<attachment_id>").get(route)),
+            )
+        }
+
+        fn url(&self) -> String {
+            format!("http://127.0.0.1/attachment/{}", self.attachment_id)
+        }
+
+        async fn status(&self, user_id: Option<&UserId>) -> Option<StatusCode> {
+            TestClient::get(self.url())
+                .send(&self.service(user_id))
+                .await
+                .status_code
+        }
+    }
+
+    fn header(response: &salvo::Response, name: HeaderName) -> Option<&str> {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    fn partial(header: &str, size: u64) -> Option<Range<u64>> {
+        match parse_range(header, size) {
+            RangeRequest::Partial(range) => Some(range),
+            _ => None,
+        }
+    }
+
+    fn date(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date)
+            .expect("date should parse")
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(partial("bytes=0-3", 10), Some(0..4));
+        assert_eq!(partial("bytes=2-2", 10), Some(2..3));
+        // ends past the size are cut off
+        assert_eq!(partial("bytes=5-100", 10), Some(5..10));
+        // open ended
+        assert_eq!(partial("bytes=4-", 10), Some(4..10));
+        // suffix
+        assert_eq!(partial("bytes=-3", 10), Some(7..10));
+        assert_eq!(partial("bytes=-20", 10), Some(0..10));
+    }
+
+    #[test]
+    fn rejects_ranges_past_the_end() {
+        assert!(matches!(
+            parse_range("bytes=10-", 10),
+            RangeRequest::Unsatisfiable
+        ));
+        assert!(matches!(
+            parse_range("bytes=12-20", 10),
+            RangeRequest::Unsatisfiable
+        ));
+        assert!(matches!(
+            parse_range("bytes=-0", 10),
+            RangeRequest::Unsatisfiable
+        ));
+    }
+
+    #[test]
+    fn falls_back_to_the_full_body() {
+        // start after end is invalid syntax, which is ignored rather than refused
+        assert!(matches!(parse_range("bytes=5-2", 10), RangeRequest::Full));
+        assert!(matches!(
+            parse_range("bytes=0-1,4-5", 10),
+            RangeRequest::Full
+        ));
+        assert!(matches!(parse_range("items=0-1", 10), RangeRequest::Full));
+        assert!(matches!(parse_range("bytes=a-b", 10), RangeRequest::Full));
+        assert!(matches!(parse_range("bytes=-", 10), RangeRequest::Full));
+    }
+
+    #[test]
+    fn matches_etags() {
+        let etag = "\"abc\"";
+        assert!(etag_matches("\"abc\"", etag));
+        assert!(etag_matches("W/\"abc\"", etag));
+        assert!(etag_matches("\"xyz\", W/\"abc\"", etag));
+        assert!(etag_matches(" * ", etag));
+        assert!(!etag_matches("\"xyz\"", etag));
+        assert!(!etag_matches("abc", etag));
+    }
+
+    #[test]
+    fn compares_modification_dates() {
+        let last_modified = date("2024-03-01T12:00:00.500Z");
+        let header = http_date(&last_modified);
+        assert_eq!(header, "Fri, 01 Mar 2024 12:00:00 GMT");
+
+        // http dates have no fractions of seconds
+        assert!(not_modified_since(&header, &last_modified));
+        assert!(not_modified_since(
+            "Sat, 02 Mar 2024 00:00:00 GMT",
+            &last_modified
+        ));
+        assert!(!not_modified_since(
+            "Fri, 01 Mar 2024 11:59:59 GMT",
+            &last_modified
+        ));
+        assert!(!not_modified_since("yesterday", &last_modified));
+    }
+
+    #[tokio::test]
+    async fn serves_byte_ranges() {
+        let forum = Forum::new().await;
+        let mut response = TestClient::get(forum.url())
+            .add_header(RANGE, "bytes=2-5", true)
+            .send(&forum.service(None))
+            .await;
+
+        assert_eq!(response.status_code, Some(StatusCode::PARTIAL_CONTENT));
+        assert_eq!(header(&response, CONTENT_RANGE), Some("bytes 2-5/10"));
+        assert_eq!(
+            response.take_string().await.expect("body should read"),
+            "2345"
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_ranges_past_the_end() {
+        let forum = Forum::new().await;
+        let response = TestClient::get(forum.url())
+            .add_header(RANGE, "bytes=10-", true)
+            .send(&forum.service(None))
+            .await;
+
+        assert_eq!(
+            response.status_code,
+            Some(StatusCode::RANGE_NOT_SATISFIABLE)
+        );
+        assert_eq!(header(&response, CONTENT_RANGE), Some("bytes */10"));
+    }
+
+    #[tokio::test]
+    async fn ignores_ranges_of_changed_blobs() {
+        let forum = Forum::new().await;
+        let mut response = TestClient::get(forum.url())
+            .add_header(RANGE, "bytes=2-5", true)
+            .add_header(IF_RANGE, "\"outdated\"", true)
+            .send(&forum.service(None))
+            .await;
+
+        assert_eq!(response.status_code, Some(StatusCode::OK));
+        assert_eq!(
+            response.take_bytes(None).await.expect("body should read"),
+            CONTENT
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_conditional_requests() {
+        let forum = Forum::new().await;
+        let response = TestClient::get(forum.url())
+            .send(&forum.service(None))
+            .await;
+        let etag = header(&response, ETAG)
+            .expect("etag should be set")
+            .to_string();
+        let last_modified = header(&response, LAST_MODIFIED)
+            .expect("last modified should be set")
+            .to_string();
+
+        let status = |if_none_match: Option<&str>, if_modified_since: Option<&str>| {
+            let mut client = TestClient::get(forum.url());
+            if let Some(if_none_match) = if_none_match {
+                client = client.add_header(IF_NONE_MATCH, if_none_match, true);
+            }
+            if let Some(if_modified_since) = if_modified_since {
+                client = client.add_header(IF_MODIFIED_SINCE, if_modified_since, true);
+            }
+            let service = forum.service(None);
+            async move { client.send(&service).await.status_code }
+        };
+
+        let not_modified = Some(StatusCode::NOT_MODIFIED);
+        assert_eq!(status(Some(&etag), None).await, not_modified);
+        assert_eq!(status(Some(&format!("W/{etag}")), None).await, not_modified);
+        assert_eq!(status(None, Some(&last_modified)).await, not_modified);
+        // If-None-Match decides on its own when both are sent
+        assert_eq!(
+            status(Some("\"outdated\""), Some(&last_modified)).await,
+            Some(StatusCode::OK)
+        );
+        assert_eq!(
+            status(None, Some("Thu, 01 Jan 1970 00:00:00 GMT")).await,
+            Some(StatusCode::OK)
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_attachments_of_deleted_posts() {
+        let forum = Forum::new().await;
+        assert_eq!(forum.status(None).await, Some(StatusCode::OK));
+
+        forum.delete_post().await;
+        assert_eq!(forum.status(None).await, Some(StatusCode::NOT_FOUND));
+        assert_eq!(
+            forum.status(Some(&forum.admin_id)).await,
+            Some(StatusCode::OK)
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_attachments_of_deleted_replies() {
+        let forum = Forum::new().await;
+        forum
+            .link(AttachmentTarget::Reply, Id::from(&forum.reply_id))
+            .await;
+        assert_eq!(forum.status(None).await, Some(StatusCode::OK));
+
+        forum.delete_reply().await;
+        assert_eq!(forum.status(None).await, Some(StatusCode::NOT_FOUND));
+        assert_eq!(
+            forum.status(Some(&forum.admin_id)).await,
+            Some(StatusCode::OK)
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_attachments_in_deleted_categories() {
+        let forum = Forum::new().await;
+        forum.delete_category().await;
+
+        assert_eq!(forum.status(None).await, Some(StatusCode::NOT_FOUND));
+        assert_eq!(
+            forum.status(Some(&forum.admin_id)).await,
+            Some(StatusCode::OK)
+        );
+    }
+}
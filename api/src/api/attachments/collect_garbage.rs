@@ -0,0 +1,67 @@
+use eyre::{eyre, Context};
+use std::time::Duration;
+
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    attachment_gc,
+    db::database::DatabaseParam,
+    permission_verification,
+    storage::blob_store::BlobStoreParam,
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    grace_period_seconds: Option<u64>,
+}
+
+fn verify_valid_user_permission(user: &User) -> Result<(), ApiError> {
+    let gc_permission = permission_verification::permission_for_important_actions();
+
+    if !permission_verification::is_allowed(&user.permission, &gc_permission) {
+        let err = format!(
+            "you must be {} or above to collect unreferenced attachments, you are {}",
+            gc_permission, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest {
+        grace_period_seconds,
+    }) = request;
+
+    let grace_period =
+        grace_period_seconds.map_or(attachment_gc::DEFAULT_GRACE_PERIOD, Duration::from_secs);
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+    let blob_store = depot
+        .obtain::<BlobStoreParam>()
+        .map_err(|err| eyre!("unable to get blob store from depot: {err:?}"))?;
+
+    verify_valid_user_permission(&user)?;
+
+    let removed = attachment_gc::collect_garbage(db, blob_store, grace_period)
+        .await
+        .with_context(|| "unable to collect unreferenced attachments")?;
+
+    Ok(message_response::ok(format!(
+        "removed {removed} unreferenced attachments"
+    )))
+}
@@ -1,30 +1,27 @@
-use salvo::session::SessionDepotExt;
+use eyre::{eyre, Context};
+
+use salvo::http::header::CONTENT_LENGTH;
 use salvo::{Depot, Request};
-use tokio::sync::RwLockReadGuard;
 
-use crate::permission_verification;
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::database::{Database, DatabaseParam};
+use crate::db::models::{AttachmentQuota, AttachmentUsage, User};
 use crate::{
     api::response::{message_response, CreatedResponseResult},
     db::database::CreateAttachment,
 };
 use crate::{
-    api::response::{Message, Response},
-    db::{
-        database::{Database, DatabaseParam},
-        models::Id,
-    },
+    attachment_blobs::{self, store_blob, BlobSource},
+    attachment_validation::{self, AttachmentConfigParam},
+    image_processing, permission_verification,
+    storage::blob_store::BlobStoreParam,
 };
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
-    db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
-) -> Result<(), Response<Message>> {
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+/// room for the multipart boundaries and part headers around the file itself
+const MULTIPART_OVERHEAD: u64 = 16 * 1024;
 
+fn verify_valid_user_permission(user: &User) -> Result<(), ApiError> {
     let attachment_permission = permission_verification::permission_for_attachment_upload();
 
     if !permission_verification::is_allowed(&user.permission, &attachment_permission) {
@@ -32,43 +29,384 @@ async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
             "you must be {} or above to upload attachments, you are {}",
             attachment_permission, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
+
+fn check_quota(
+    usage: &AttachmentUsage,
+    quota: &AttachmentQuota,
+    size: i64,
+) -> Result<(), ApiError> {
+    if usage.files + 1 > quota.max_files {
+        let err = format!(
+            "attachment quota exceeded: you have uploaded {} of {} allowed files",
+            usage.files, quota.max_files
+        );
+        return Err(ApiError::invalid_request(err));
+    }
+
+    if usage.bytes + size > quota.max_bytes {
+        let err = format!(
+            "attachment quota exceeded: {} of {} bytes used, this upload needs {} more",
+            usage.bytes, quota.max_bytes, size
+        );
+        return Err(ApiError::invalid_request(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(201, 400, 403, 500))]
+async fn verify_within_quota<Db: Database + Sync + Send + ?Sized>(
+    db: &Db,
+    user: &User,
+    size: i64,
+) -> Result<(), ApiError> {
+    let usage = db
+        .attachment_usage(&user.id)
+        .await
+        .with_context(|| "unable to get attachment usage")?;
+
+    let quota = user.attachment_quota.unwrap_or_else(|| {
+        permission_verification::attachment_quota_for_permission(&user.permission)
+    });
+
+    check_quota(&usage, &quota, size)
+}
+
+#[salvo::endpoint(status_codes(201, 400, 401, 403, 429, 500), security(("session" = [])))]
 pub async fn route(depot: &mut Depot, request: &mut Request) -> CreatedResponseResult {
-    let creator_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+    let blob_store = depot
+        .obtain::<BlobStoreParam>()
+        .map_err(|err| eyre!("unable to get blob store from depot: {err:?}"))?;
+
+    let attachment_config = depot
+        .obtain::<AttachmentConfigParam>()
+        .map_err(|err| eyre!("unable to get attachment config from depot: {err:?}"))?;
+
+    verify_valid_user_permission(&user)?;
+
+    let too_large = || {
+        ApiError::validation_failed(
+            "file",
+            format!(
+                "attachment too large, maximum size is {} bytes",
+                attachment_config.max_size
+            ),
+        )
+    };
+
+    let content_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > attachment_config.max_size + MULTIPART_OVERHEAD)
+    {
+        return Err(too_large());
+    }
 
     let file = request
         .first_file()
         .await
-        .ok_or_else(|| message_response::bad_request("missing file"))?;
+        .ok_or_else(|| ApiError::validation_failed("file", "missing file"))?;
 
-    {
-        let db = db.read().await;
-        verify_valid_user_permission(&db, &creator_id).await?;
+    if file.size() == 0 {
+        return Err(ApiError::validation_failed("file", "attachment is empty"));
     }
-    let id = {
-        let mut db = db.write().await;
-        db.create_attachment(CreateAttachment {
-            file_name: file.name().unwrap_or("file"),
-            temp_path: file.path(),
-            creator_id,
+    if file.size() > attachment_config.max_size {
+        return Err(too_large());
+    }
+
+    // rejects users who are already at their quota before any work is done, the bytes are
+    // charged below once the size of what is actually stored is known
+    verify_within_quota(&*db.read().await, &user, 0).await?;
+
+    let content_type = attachment_validation::sniff_content_type(file.path())
+        .await
+        .with_context(|| "unable to read uploaded file")?;
+    if !attachment_config.is_allowed(content_type) {
+        return Err(ApiError::validation_failed(
+            "file",
+            format!(
+                "attachment type '{content_type}' is not allowed, allowed types are {}",
+                attachment_config.allowed_types.join(", ")
+            ),
+        ));
+    }
+
+    let file_name = attachment_validation::sanitize_file_name(file.name().unwrap_or_default());
+    let (source, hash, size) = if image_processing::is_image(content_type) {
+        let data = tokio::fs::read(file.path())
+            .await
+            .with_context(|| "unable to read uploaded file")?;
+        let processed = tokio::task::spawn_blocking(move || {
+            image_processing::process_image(&data, content_type)
         })
         .await
-        .map_err(|err| log::error!("unable to save attachment in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
+        .with_context(|| "unable to join image processing task")?
+        .map_err(|err| log::info!("rejected invalid image: {err:?}"))
+        .map_err(|()| ApiError::validation_failed("file", "attachment is not a valid image"))?;
+
+        let hash = attachment_blobs::hash_bytes(&processed.original);
+        let size = processed.original.len() as u64;
+        (BlobSource::Image(processed), hash, size)
+    } else {
+        let hash = attachment_blobs::hash_file(file.path())
+            .await
+            .with_context(|| "unable to hash uploaded file")?;
+        (BlobSource::File(file.path()), hash, file.size())
+    };
+    let size = i64::try_from(size).unwrap_or(i64::MAX);
+    let blob_key = attachment_blobs::blob_key_from_hash(&hash);
+
+    let already_stored = {
+        let db = db.read().await;
+        db.blob_ref_count(&blob_key)
+            .await
+            .with_context(|| "unable to get blob from database")?
+            .is_some()
+    };
+
+    // storing the same content twice is harmless, so this happens outside the lock
+    if !already_stored {
+        store_blob(blob_store.as_ref(), &blob_key, &source)
+            .await
+            .with_context(|| "unable to store attachment blob")?;
+    }
+
+    let (id, ref_count) = {
+        let mut db = db.write().await;
+        let ref_count = db
+            .acquire_blob(&blob_key, size)
+            .await
+            .with_context(|| "unable to save blob in database")?;
+
+        // checked under the same lock as the insert, so concurrent uploads can't both fit
+        let id = match verify_within_quota(&*db, &user, size).await {
+            Ok(()) => db
+                .create_attachment(CreateAttachment {
+                    blob_key: blob_key.clone(),
+                    file_name,
+                    content_type: content_type.to_string(),
+                    size,
+                    creator_id: user.id.clone(),
+                })
+                .await
+                .map_err(|err| err.wrap_err("unable to save attachment in database").into()),
+            Err(err) => Err(err),
+        };
+        match id {
+            Ok(id) => (id, ref_count),
+            Err(err) => {
+                if let Err(err) =
+                    attachment_blobs::release(&mut *db, blob_store.as_ref(), &blob_key).await
+                {
+                    log::error!("unable to release blob '{blob_key}': {err:?}");
+                }
+                return Err(err);
+            }
+        }
     };
 
+    // whoever held the blob before may have released and removed it since it was checked or
+    // stored above. removing happens under the write lock, so it is done by now, and the
+    // reference held from here on keeps anyone else from removing it while it is put back
+    if ref_count == 1 {
+        let stored = match blob_store.size(&blob_key).await {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => store_blob(blob_store.as_ref(), &blob_key, &source).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = stored {
+            let mut db = db.write().await;
+            let removed = match db.remove_attachment(&id).await {
+                Ok(()) => attachment_blobs::release(&mut *db, blob_store.as_ref(), &blob_key).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = removed {
+                log::error!("unable to remove attachment '{id}' without a blob: {err:?}");
+            }
+            return Err(err.wrap_err("unable to store attachment blob").into());
+        }
+    }
+
     Ok(message_response::created_with_id("created", id))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use salvo::{affix, http::StatusCode, test::TestClient, Router, Service};
+
+    use super::*;
+    use crate::api::test_forum::TestForum;
+    use crate::attachment_validation::{
+        AttachmentConfig, DEFAULT_MAX_SIZE, DEFAULT_PUBLIC_CACHE_CONTROL,
+        DEFAULT_RESTRICTED_CACHE_CONTROL,
+    };
+    use crate::db::database::EditUser;
+    use crate::storage::local::LocalBlobStore;
+
+    const QUOTA: AttachmentQuota = AttachmentQuota {
+        max_bytes: 100,
+        max_files: 2,
+    };
+
+    #[test]
+    fn allows_uploads_that_fit() {
+        let usage = AttachmentUsage {
+            bytes: 40,
+            files: 1,
+        };
+        assert!(check_quota(&usage, &QUOTA, 60).is_ok());
+        assert!(check_quota(&AttachmentUsage { bytes: 0, files: 0 }, &QUOTA, 0).is_ok());
+    }
+
+    #[test]
+    fn rejects_uploads_over_the_byte_quota() {
+        let usage = AttachmentUsage {
+            bytes: 40,
+            files: 1,
+        };
+        let err = check_quota(&usage, &QUOTA, 61).expect_err("upload should not fit");
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn rejects_uploads_over_the_file_quota() {
+        let usage = AttachmentUsage { bytes: 0, files: 2 };
+        assert!(check_quota(&usage, &QUOTA, 0).is_err());
+    }
+
+    const BOUNDARY: &str = "decorum-test-boundary";
+    const CONTENT: &[u8] = b"some notes worth keeping";
+
+    fn service(forum: &TestForum, blob_store: &BlobStoreParam) -> Service {
+        let config = AttachmentConfig {
+            max_size: DEFAULT_MAX_SIZE,
+            allowed_types: vec!["text/plain".to_string()],
+            public_cache_control: DEFAULT_PUBLIC_CACHE_CONTROL.to_string(),
+            restricted_cache_control: DEFAULT_RESTRICTED_CACHE_CONTROL.to_string(),
+        };
+
+        forum.service(
+            Some(&forum.user_id),
+            Router::with_path("create_attachment")
+                .hoop(affix::inject::<BlobStoreParam>(blob_store.clone()))
+                .hoop(affix::inject::<AttachmentConfigParam>(Arc::new(config)))
+                .post(route),
+        )
+    }
+
+    fn blob_store() -> BlobStoreParam {
+        Arc::new(LocalBlobStore::new(std::env::temp_dir().join(format!(
+            "decorum-upload-test-{}",
+            crate::ids::generate_id()
+        ))))
+    }
+
+    async fn upload(service: &Service) -> StatusCode {
+        let mut body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; \
+            filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(CONTENT);
+        body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+        TestClient::post("http://127.0.0.1/create_attachment")
+            .bytes(body)
+            .add_header(
+                "content-type",
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+                true,
+            )
+            .send(service)
+            .await
+            .status_code
+            .expect("response should have a status")
+    }
+
+    fn blob_key() -> String {
+        attachment_blobs::blob_key_from_hash(&attachment_blobs::hash_bytes(CONTENT))
+    }
+
+    async fn ref_count(forum: &TestForum) -> Option<i64> {
+        forum
+            .db
+            .read()
+            .await
+            .blob_ref_count(&blob_key())
+            .await
+            .expect("blob should load")
+    }
+
+    #[tokio::test]
+    async fn stores_the_blob_and_holds_a_reference() {
+        let forum = TestForum::new().await;
+        let blob_store = blob_store();
+        let service = service(&forum, &blob_store);
+
+        assert_eq!(upload(&service).await, StatusCode::CREATED);
+        assert_eq!(ref_count(&forum).await, Some(1));
+        assert_eq!(
+            blob_store
+                .size(&blob_key())
+                .await
+                .expect("blob store should answer"),
+            Some(CONTENT.len() as u64)
+        );
+
+        assert_eq!(upload(&service).await, StatusCode::CREATED);
+        assert_eq!(ref_count(&forum).await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn uploads_over_the_quota_release_their_blob() {
+        let forum = TestForum::new().await;
+        let user = forum
+            .db
+            .read()
+            .await
+            .user_from_id(&forum.user_id)
+            .await
+            .expect("user should load")
+            .expect("user should exist");
+        forum
+            .db
+            .write()
+            .await
+            .edit_user(EditUser {
+                id: user.id,
+                nickname: user.nickname,
+                password: user.password,
+                permission: user.permission,
+                avatar_id: user.avatar_id,
+                attachment_quota: Some(AttachmentQuota {
+                    max_bytes: 1024,
+                    max_files: 1,
+                }),
+                bio: user.bio,
+                signature: user.signature,
+                location: user.location,
+                website: user.website,
+                deleted: user.deleted,
+            })
+            .await
+            .expect("user should be edited");
+        let blob_store = blob_store();
+        let service = service(&forum, &blob_store);
+
+        assert_eq!(upload(&service).await, StatusCode::CREATED);
+        assert_eq!(upload(&service).await, StatusCode::BAD_REQUEST);
+        assert_eq!(ref_count(&forum).await, Some(1));
+    }
+}
@@ -0,0 +1,126 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+use tokio::sync::RwLockReadGuard;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    db::{
+        database::{Database, DatabaseParam, EditAttachment},
+        models::{AttachmentId, AttachmentLink, AttachmentTarget, Id, PostId},
+    },
+    permission_verification,
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    attachment_id: String,
+    target: AttachmentTarget,
+    target_id: String,
+}
+
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    attachment_id: &AttachmentId,
+    target: &AttachmentTarget,
+    target_id: &Id,
+) -> Result<(), ApiError> {
+    let attachment = db
+        .attachment_from_id(attachment_id)
+        .await
+        .with_context(|| "unable to get attachment from id")?
+        .ok_or_else(|| ApiError::not_found("attachment not found"))?;
+
+    if attachment.creator_id != user.id {
+        return Err(ApiError::permission_denied(
+            "you can only link attachments uploaded by you",
+        ));
+    }
+
+    let reply = match target {
+        AttachmentTarget::Post => None,
+        AttachmentTarget::Reply => Some(
+            db.reply_from_id(&target_id.to_typed())
+                .await
+                .with_context(|| format!("unable to get reply with id '{}'", target_id))?
+                .ok_or_else(|| ApiError::not_found("reply not found"))?,
+        ),
+    };
+    let post_id: PostId = reply
+        .as_ref()
+        .map_or_else(|| target_id.to_typed(), |reply| reply.post_id.clone());
+
+    let post = db
+        .post_from_id(&post_id)
+        .await
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .ok_or_else(|| ApiError::not_found("post not found"))?;
+
+    let target_creator_id = reply
+        .as_ref()
+        .map_or(&post.creator_id, |reply| &reply.creator_id);
+    if *target_creator_id != user.id {
+        return Err(ApiError::permission_denied(
+            "you can only link attachments to your own posts and replies",
+        ));
+    }
+
+    let category = db
+        .category_from_id(&post.category_id)
+        .await
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
+        let err = format!(
+            "you must be {} or above to attach files in category {}, you are {}",
+            category.minimum_write_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest {
+        attachment_id,
+        target,
+        target_id,
+    }) = request;
+
+    let attachment_id = AttachmentId::try_from(attachment_id)
+        .map_err(|_| ApiError::validation_failed("attachment_id", "invalid attachment id"))?;
+    let target_id = Id::try_from(target_id)
+        .map_err(|_| ApiError::validation_failed("target_id", "invalid target id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let db = db.read().await;
+        verify_valid_user_permission(&db, &user, &attachment_id, &target, &target_id).await?;
+    }
+    {
+        let mut db = db.write().await;
+        db.edit_attachment(EditAttachment {
+            id: attachment_id,
+            link: Some(AttachmentLink { target, target_id }),
+        })
+        .await
+        .with_context(|| "unable to link attachment")?;
+    }
+
+    Ok(message_response::ok("linked"))
+}
@@ -0,0 +1,50 @@
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::api::response::Response;
+use crate::db::models::AttachmentLink;
+use crate::db::{database::DatabaseParam, models::AttachmentId};
+use eyre::{eyre, Context};
+use salvo::{prelude::ToSchema, Depot};
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema)]
+struct ResponseAttachment {
+    id: AttachmentId,
+    file_name: String,
+    content_type: String,
+    size: i64,
+    link: Option<AttachmentLink>,
+    date_created: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: Vec<ResponseAttachment>,
+}
+
+#[salvo::endpoint(status_codes(200, 401, 403, 429, 500), security(("session" = [])))]
+pub async fn route(depot: &mut Depot) -> Result<Response<RouteResponse>, ApiError> {
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let data = db
+        .attachments_from_creator(&user.id)
+        .await
+        .with_context(|| format!("unable to get attachments from user {}", user.id))?
+        .into_iter()
+        .map(|attachment| ResponseAttachment {
+            id: attachment.id,
+            file_name: attachment.file_name,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            link: attachment.link,
+            date_created: attachment.date_created,
+        })
+        .collect();
+
+    Ok(Response::with_ok(RouteResponse { ok: true, data }))
+}
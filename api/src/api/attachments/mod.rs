@@ -1,5 +1,13 @@
 mod attachment_from_id;
+mod collect_garbage;
 mod create_attachment;
+mod link;
+mod list_mine;
+mod remove;
 
 pub use attachment_from_id::route as attachment_from_id_route;
+pub use collect_garbage::route as collect_garbage_route;
 pub use create_attachment::route as create_attachment_route;
+pub use link::route as link_route;
+pub use list_mine::route as list_mine_route;
+pub use remove::route as remove_route;
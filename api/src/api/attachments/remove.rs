@@ -0,0 +1,91 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    attachment_blobs,
+    db::{
+        database::DatabaseParam,
+        models::{AttachmentId, UserId},
+    },
+    permission_verification,
+    storage::blob_store::BlobStoreParam,
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    id: String,
+}
+
+fn verify_valid_user_permission(
+    user: &User,
+    attachment_creator_id: &UserId,
+) -> Result<(), ApiError> {
+    let remove_permission = permission_verification::permission_for_important_actions();
+
+    if *attachment_creator_id != user.id
+        && !permission_verification::is_allowed(&user.permission, &remove_permission)
+    {
+        let err = format!(
+            "you must be {} or above to remove attachments uploaded by others, you are {}",
+            remove_permission, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { id }) = request;
+
+    let id = AttachmentId::try_from(id)
+        .map_err(|_| ApiError::validation_failed("id", "invalid attachment id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+    let blob_store = depot
+        .obtain::<BlobStoreParam>()
+        .map_err(|err| eyre!("unable to get blob store from depot: {err:?}"))?;
+
+    let attachment = {
+        let db = db.read().await;
+        let attachment = db
+            .attachment_from_id(&id)
+            .await
+            .with_context(|| "unable to get attachment from id")?
+            .ok_or_else(|| ApiError::not_found("attachment not found"))?;
+        verify_valid_user_permission(&user, &attachment.creator_id)?;
+        attachment
+    };
+
+    {
+        let mut db = db.write().await;
+        db.remove_attachment(&attachment.id)
+            .await
+            .with_context(|| "unable to remove attachment")?;
+
+        // the row is gone at this point, a blob left behind is only wasted space
+        if let Err(err) =
+            attachment_blobs::release(&mut *db, blob_store.as_ref(), &attachment.blob_key).await
+        {
+            log::error!(
+                "unable to release blob '{}' of attachment: {err:?}",
+                attachment.blob_key
+            );
+        }
+    }
+
+    Ok(message_response::ok("removed"))
+}
@@ -0,0 +1,291 @@
+use std::{ops::Deref, time::Duration};
+
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+    session::SessionDepotExt,
+    Depot, FlowCtrl, Request, Writer,
+};
+
+use crate::{
+    api::error::ApiError,
+    db::{
+        database::DatabaseParam,
+        models::{Permission, User, UserId},
+    },
+    iso_date_strings::utc_date_iso_string_ago,
+};
+
+/// endpoints that need a logged in user list this scheme under `security`
+pub const SESSION_SECURITY_SCHEME: &str = "session";
+const SESSION_COOKIE: &str = "salvo.session.id";
+/// how stale `last_active` may get before a request updates it,
+/// so not every request needs the write lock
+const LAST_ACTIVE_RESOLUTION: Duration = Duration::from_secs(5 * 60);
+
+pub fn session_security_scheme() -> SecurityScheme {
+    SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE)))
+}
+
+/// the user behind the session, put in the depot by [`load_user`]
+struct CurrentUser(Option<User>);
+
+/// a logged in user that is neither deleted nor banned
+pub struct AuthenticatedUser(pub User);
+
+/// a logged in user that is not deleted but may be banned, for the routes that let users
+/// look after their own data
+pub struct LoggedInUser(pub User);
+
+/// the logged in user if there is one, for routes that anonymous users can use as well
+pub struct OptionalUser(pub Option<User>);
+
+impl Deref for AuthenticatedUser {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AuthenticatedUser {
+    pub fn from_depot(depot: &mut Depot) -> Result<Self, ApiError> {
+        let LoggedInUser(user) = LoggedInUser::from_depot(depot)?;
+
+        if let Permission::Banned = user.permission {
+            return Err(ApiError::permission_denied("you are banned"));
+        }
+
+        Ok(AuthenticatedUser(user))
+    }
+}
+
+impl LoggedInUser {
+    pub fn from_depot(depot: &mut Depot) -> Result<Self, ApiError> {
+        let OptionalUser(user) = OptionalUser::from_depot(depot)?;
+        let user = user.ok_or(ApiError::InvalidSession)?;
+
+        Ok(LoggedInUser(user))
+    }
+}
+
+impl OptionalUser {
+    pub fn from_depot(depot: &mut Depot) -> Result<Self, ApiError> {
+        let CurrentUser(user) = depot
+            .scrape::<CurrentUser>()
+            .map_err(|_| eyre!("current user is missing from depot, is load_user hooped?"))?;
+
+        Ok(OptionalUser(user))
+    }
+}
+
+async fn current_user(depot: &Depot) -> Result<Option<User>, ApiError> {
+    let Some(user_id) = depot
+        .session()
+        .and_then(|session| session.get::<UserId>("user_id"))
+    else {
+        return Ok(None);
+    };
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let user = db
+        .read()
+        .await
+        .user_from_id(&user_id)
+        .await
+        .with_context(|| format!("unable to get user with id '{user_id}'"))?;
+
+    // sessions of deleted users stay around in cookies but are no longer valid
+    let Some(user) = user.filter(|user| !user.deleted) else {
+        return Ok(None);
+    };
+
+    let active_after = utc_date_iso_string_ago(LAST_ACTIVE_RESOLUTION)?;
+    if user
+        .last_active
+        .as_ref()
+        .is_none_or(|last_active| *last_active < active_after)
+    {
+        db.write()
+            .await
+            .edit_last_active(&user.id)
+            .await
+            .with_context(|| format!("unable to mark user with id '{user_id}' as active"))?;
+    }
+
+    Ok(Some(user))
+}
+
+/// stands in for [`load_user`] in route tests, loading the given user without a session
+#[cfg(test)]
+pub struct LoadTestUser(pub Option<UserId>);
+
+#[cfg(test)]
+#[salvo::async_trait]
+impl salvo::Handler for LoadTestUser {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        depot: &mut Depot,
+        _res: &mut salvo::Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let user = match &self.0 {
+            Some(user_id) => depot
+                .obtain::<DatabaseParam>()
+                .expect("database should be injected")
+                .read()
+                .await
+                .user_from_id(user_id)
+                .await
+                .expect("user should load"),
+            None => None,
+        };
+        depot.inject(CurrentUser(user));
+    }
+}
+
+/// loads the user of the session once per request so routes don't have to
+#[salvo::handler]
+pub async fn load_user(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut salvo::Response,
+    ctrl: &mut FlowCtrl,
+) {
+    match current_user(depot).await {
+        Ok(user) => {
+            depot.inject(CurrentUser(user));
+        }
+        Err(err) => {
+            err.write(req, depot, res).await;
+            ctrl.skip_rest();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{affix, http::StatusCode, session::Session, test::TestClient, Router, Service};
+
+    use super::*;
+    use crate::api::test_forum::TestForum;
+
+    /// a session that is logged in as the given user
+    struct TestSession(Option<UserId>);
+
+    #[salvo::async_trait]
+    impl salvo::Handler for TestSession {
+        async fn handle(
+            &self,
+            _req: &mut Request,
+            depot: &mut Depot,
+            _res: &mut salvo::Response,
+            _ctrl: &mut FlowCtrl,
+        ) {
+            let mut session = Session::new();
+            if let Some(user_id) = &self.0 {
+                session
+                    .insert("user_id", user_id.to_string())
+                    .expect("user id should be stored");
+            }
+            depot.set_session(session);
+        }
+    }
+
+    #[salvo::handler]
+    async fn authenticated(depot: &mut Depot) -> Result<String, ApiError> {
+        let AuthenticatedUser(user) = AuthenticatedUser::from_depot(depot)?;
+        Ok(user.id.to_string())
+    }
+
+    #[salvo::handler]
+    async fn logged_in(depot: &mut Depot) -> Result<String, ApiError> {
+        let LoggedInUser(user) = LoggedInUser::from_depot(depot)?;
+        Ok(user.id.to_string())
+    }
+
+    async fn status(forum: &TestForum, user_id: Option<&UserId>, path: &str) -> StatusCode {
+        let service = Service::new(
+            Router::new()
+                .hoop(affix::inject::<DatabaseParam>(forum.db.clone()))
+                .hoop(TestSession(user_id.cloned()))
+                .hoop(load_user)
+                .push(Router::with_path("authenticated").get(authenticated))
+                .push(Router::with_path("logged_in").get(logged_in)),
+        );
+        let response = TestClient::get(format!("http://127.0.0.1/{path}"))
+            .send(&service)
+            .await;
+        response.status_code.expect("response should have a status")
+    }
+
+    #[tokio::test]
+    async fn loads_the_user_of_the_session_and_marks_them_active() {
+        let forum = TestForum::new().await;
+
+        assert_eq!(
+            status(&forum, Some(&forum.user_id), "authenticated").await,
+            StatusCode::OK
+        );
+        let user = forum
+            .db
+            .read()
+            .await
+            .user_from_id(&forum.user_id)
+            .await
+            .expect("user should load")
+            .expect("user should exist");
+        assert!(user.last_active.is_some());
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_sessions() {
+        let forum = TestForum::new().await;
+
+        assert_eq!(
+            status(&forum, None, "authenticated").await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&forum, None, "logged_in").await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_deleted_users() {
+        let forum = TestForum::new().await;
+        forum
+            .edit_user(&forum.user_id, |user| user.deleted = true)
+            .await;
+
+        assert_eq!(
+            status(&forum, Some(&forum.user_id), "authenticated").await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&forum, Some(&forum.user_id), "logged_in").await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn banned_users_only_reach_routes_for_their_own_data() {
+        let forum = TestForum::new().await;
+        forum
+            .edit_user(&forum.user_id, |user| user.permission = Permission::Banned)
+            .await;
+
+        assert_eq!(
+            status(&forum, Some(&forum.user_id), "authenticated").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(&forum, Some(&forum.user_id), "logged_in").await,
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use salvo::{
+    http::ResBody,
+    oapi::{Components, Operation, ToSchema},
+    prelude::{EndpointOutRegister, StatusCode},
+    writing::Json,
+    Depot, FlowCtrl, Request, Writer,
+};
+use serde::Serialize;
+
+use crate::db::database::DatabaseError;
+
+/// every error a route can respond with, the code is stable so clients can match on it
+/// while the message is meant for humans and may change
+#[derive(Debug)]
+pub enum ApiError {
+    /// not logged in, or the session belongs to a user that no longer exists
+    InvalidSession,
+    InvalidCredentials,
+    /// logged in, but not allowed to do this
+    PermissionDenied(String),
+    NotFound(String),
+    Conflict(String),
+    ValidationFailed {
+        field: &'static str,
+        message: String,
+    },
+    InvalidRequest(String),
+    TooManyRequests,
+    /// logged when written, clients only ever see a generic message
+    Internal(eyre::Report),
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidSession,
+    InvalidCredentials,
+    PermissionDenied,
+    NotFound,
+    Conflict,
+    ValidationFailed,
+    InvalidRequest,
+    TooManyRequests,
+    InternalError,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorMessage {
+    ok: bool,
+    code: ErrorCode,
+    data: String,
+    /// the request field that failed validation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+}
+
+impl ApiError {
+    pub fn permission_denied<S: ToString>(message: S) -> Self {
+        ApiError::PermissionDenied(message.to_string())
+    }
+
+    pub fn not_found<S: ToString>(message: S) -> Self {
+        ApiError::NotFound(message.to_string())
+    }
+
+    pub fn conflict<S: ToString>(message: S) -> Self {
+        ApiError::Conflict(message.to_string())
+    }
+
+    pub fn validation_failed<S: ToString>(field: &'static str, message: S) -> Self {
+        ApiError::ValidationFailed {
+            field,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn invalid_request<S: ToString>(message: S) -> Self {
+        ApiError::InvalidRequest(message.to_string())
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::InvalidSession => ErrorCode::InvalidSession,
+            ApiError::InvalidCredentials => ErrorCode::InvalidCredentials,
+            ApiError::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Conflict(_) => ErrorCode::Conflict,
+            ApiError::ValidationFailed { .. } => ErrorCode::ValidationFailed,
+            ApiError::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            ApiError::TooManyRequests => ErrorCode::TooManyRequests,
+            ApiError::Internal(_) => ErrorCode::InternalError,
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidSession | ApiError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            ApiError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::ValidationFailed { .. } | ApiError::InvalidRequest(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            ApiError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn into_message(self) -> ErrorMessage {
+        let code = self.code();
+        let (data, field) = match self {
+            ApiError::InvalidSession => ("invalid session".to_string(), None),
+            ApiError::InvalidCredentials => ("invalid username or password".to_string(), None),
+            ApiError::TooManyRequests => ("too many requests".to_string(), None),
+            ApiError::PermissionDenied(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::InvalidRequest(message) => (message, None),
+            ApiError::ValidationFailed { field, message } => (message, Some(field.to_string())),
+            ApiError::Internal(err) => {
+                log::error!("{err:?}");
+                ("internal server error".to_string(), None)
+            }
+        };
+
+        ErrorMessage {
+            ok: false,
+            code,
+            data,
+            field,
+        }
+    }
+}
+
+impl From<DatabaseError> for ApiError {
+    fn from(err: DatabaseError) -> Self {
+        ApiError::Internal(err)
+    }
+}
+
+impl EndpointOutRegister for ApiError {
+    #[inline]
+    fn register(components: &mut Components, operation: &mut Operation) {
+        let schema = ErrorMessage::to_schema(components);
+        // each endpoint narrows these down to what it can return with `status_codes`
+        for code in [
+            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::FORBIDDEN,
+            StatusCode::NOT_FOUND,
+            StatusCode::CONFLICT,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ] {
+            operation.responses.insert(
+                code.as_str(),
+                salvo::oapi::Response::new(
+                    code.canonical_reason()
+                        .unwrap_or("No further explanation is available."),
+                )
+                .add_content("application/json", schema.clone()),
+            );
+        }
+    }
+}
+
+#[salvo::async_trait]
+impl Writer for ApiError {
+    async fn write(mut self, _req: &mut Request, _depot: &mut Depot, res: &mut salvo::Response) {
+        res.status_code(self.status_code())
+            .render(Json(self.into_message()));
+    }
+}
+
+/// renders errors raised before a route runs, like rate limiting, unknown paths
+/// and malformed request data, in the same shape as [`ApiError`]
+#[salvo::handler]
+pub async fn catcher(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut salvo::Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let err = match res.status_code {
+        Some(StatusCode::BAD_REQUEST) => match &res.body {
+            ResBody::Error(err) => match &err.cause {
+                Some(cause) => ApiError::invalid_request(format!("invalid request: {cause}")),
+                None => ApiError::invalid_request("invalid request"),
+            },
+            _ => ApiError::invalid_request("invalid request"),
+        },
+        Some(StatusCode::NOT_FOUND) => ApiError::not_found("not found"),
+        Some(StatusCode::TOO_MANY_REQUESTS) => ApiError::TooManyRequests,
+        _ => return,
+    };
+
+    err.write(req, depot, res).await;
+    ctrl.skip_rest();
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{
+        catcher::Catcher,
+        test::{ResponseExt, TestClient},
+        Router, Service,
+    };
+
+    use super::*;
+
+    fn message_json(err: ApiError) -> serde_json::Value {
+        serde_json::to_value(err.into_message()).expect("message should serialize")
+    }
+
+    #[test]
+    fn maps_errors_to_status_codes() {
+        let cases = [
+            (ApiError::InvalidSession, StatusCode::UNAUTHORIZED),
+            (ApiError::InvalidCredentials, StatusCode::UNAUTHORIZED),
+            (ApiError::permission_denied("no"), StatusCode::FORBIDDEN),
+            (ApiError::not_found("gone"), StatusCode::NOT_FOUND),
+            (ApiError::conflict("taken"), StatusCode::CONFLICT),
+            (
+                ApiError::validation_failed("title", "too long"),
+                StatusCode::BAD_REQUEST,
+            ),
+            (ApiError::invalid_request("bad"), StatusCode::BAD_REQUEST),
+            (ApiError::TooManyRequests, StatusCode::TOO_MANY_REQUESTS),
+            (
+                ApiError::Internal(eyre::eyre!("broken")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+
+        for (err, status_code) in cases {
+            assert_eq!(err.status_code(), status_code, "{err:?}");
+        }
+    }
+
+    #[test]
+    fn serializes_codes_in_snake_case() {
+        let message = message_json(ApiError::permission_denied("you must be Admin"));
+        assert_eq!(
+            message,
+            serde_json::json!({
+                "ok": false,
+                "code": "permission_denied",
+                "data": "you must be Admin",
+            })
+        );
+    }
+
+    #[test]
+    fn names_the_field_that_failed_validation() {
+        let message = message_json(ApiError::validation_failed("title", "too long"));
+        assert_eq!(message["code"], "validation_failed");
+        assert_eq!(message["field"], "title");
+        assert_eq!(message["data"], "too long");
+    }
+
+    #[test]
+    fn hides_internal_errors() {
+        let message = message_json(ApiError::Internal(eyre::eyre!("password=hunter2")));
+        assert_eq!(message["code"], "internal_error");
+        assert_eq!(message["data"], "internal server error");
+    }
+
+    #[tokio::test]
+    async fn catches_unknown_paths() {
+        let service = Service::new(Router::new()).catcher(Catcher::default().hoop(catcher));
+        let mut response = TestClient::get("http://127.0.0.1/missing")
+            .send(&service)
+            .await;
+
+        assert_eq!(response.status_code, Some(StatusCode::NOT_FOUND));
+        let message: serde_json::Value = response.take_json().await.expect("body should be json");
+        assert_eq!(message["code"], "not_found");
+    }
+}
@@ -1,4 +1,9 @@
+pub mod admin;
 pub mod attachments;
+pub mod auth;
+pub mod error;
 pub mod posts;
 mod response;
+#[cfg(test)]
+pub mod test_forum;
 pub mod users;
@@ -0,0 +1,199 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+use tokio::sync::RwLockReadGuard;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::db::{
+    database::{Database, DatabaseParam},
+    models::{Post, PostId, ReplyId},
+};
+use crate::permission_verification;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    db::database::EditPost,
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    post_id: String,
+    reply_id: Option<String>,
+}
+
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    post: &Post,
+    reply_id: Option<&ReplyId>,
+) -> Result<(), ApiError> {
+    let accept_permission = permission_verification::permission_for_important_actions();
+
+    if post.creator_id != user.id
+        && !permission_verification::is_allowed(&user.permission, &accept_permission)
+    {
+        let err = format!(
+            "you must be {} or above to accept replies on posts of other users, you are {}",
+            accept_permission, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    let category = db
+        .category_from_id(&post.category_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !category.qa_mode {
+        return Err(ApiError::invalid_request(
+            "category does not allow accepted replies",
+        ));
+    }
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
+        let err = format!(
+            "you must be {} or above to edit posts in category {}, you are {}",
+            category.minimum_write_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    if let Some(reply_id) = reply_id {
+        let reply = db
+            .reply_from_id(reply_id)
+            .await
+            .with_context(|| "unable to get reply from database")?
+            .ok_or_else(|| ApiError::not_found("reply not found"))?;
+
+        if reply.post_id != post.id || reply.deleted {
+            return Err(ApiError::validation_failed("reply_id", "invalid reply id"));
+        }
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { post_id, reply_id }) = request;
+
+    let post_id = PostId::try_from(post_id)
+        .map_err(|_| ApiError::validation_failed("post_id", "invalid post id"))?;
+    let reply_id = match reply_id {
+        Some(reply_id) => Some(
+            ReplyId::try_from(reply_id)
+                .map_err(|_| ApiError::validation_failed("reply_id", "invalid reply id"))?,
+        ),
+        None => None,
+    };
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let post = {
+        let db = db.read().await;
+        let post = db
+            .post_from_id(&post_id)
+            .await
+            .with_context(|| "unable to get post from database")?
+            .ok_or_else(|| ApiError::not_found("post not found"))?;
+        verify_valid_user_permission(&db, &user, &post, reply_id.as_ref()).await?;
+
+        post
+    };
+    {
+        let mut db = db.write().await;
+        db.edit_post(EditPost {
+            id: post.id,
+            category_id: post.category_id,
+            title: post.title,
+            content: post.content,
+            deleted: post.deleted,
+            locked: post.locked,
+            pinned: post.pinned,
+            announcement: post.announcement,
+            accepted_reply_id: reply_id,
+        })
+        .await
+        .with_context(|| "unable to save post in database")?;
+    }
+
+    Ok(message_response::ok("edited"))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::models::{Permission, UserId};
+
+    async fn accept(forum: &TestForum, user_id: &UserId, reply_id: Option<&ReplyId>) -> StatusCode {
+        let service = forum.service(Some(user_id), Router::with_path("accept_reply").post(route));
+        let body = json!({
+            "post_id": forum.post_id.to_string(),
+            "reply_id": reply_id.map(ToString::to_string),
+        });
+        post_json(&service, "accept_reply", &body).await.0
+    }
+
+    #[tokio::test]
+    async fn the_creator_accepts_and_clears_a_reply() {
+        let forum = TestForum::with_qa_mode(true).await;
+
+        assert_eq!(
+            accept(&forum, &forum.user_id, Some(&forum.reply_id)).await,
+            StatusCode::OK
+        );
+        assert!(forum.post().await.accepted_reply_id == Some(forum.reply_id.clone()));
+
+        assert_eq!(accept(&forum, &forum.user_id, None).await, StatusCode::OK);
+        assert!(forum.post().await.accepted_reply_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn needs_a_qa_category() {
+        let forum = TestForum::new().await;
+
+        assert_eq!(
+            accept(&forum, &forum.user_id, Some(&forum.reply_id)).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert!(forum.post().await.accepted_reply_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn only_the_creator_or_admins_accept() {
+        let forum = TestForum::with_qa_mode(true).await;
+        let other_id = forum.create_user("other", Permission::User).await;
+
+        assert_eq!(
+            accept(&forum, &other_id, Some(&forum.reply_id)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            accept(&forum, &forum.admin_id, Some(&forum.reply_id)).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_removed_replies() {
+        let forum = TestForum::with_qa_mode(true).await;
+        forum.remove_reply(&forum.reply_id).await;
+
+        assert_eq!(
+            accept(&forum, &forum.user_id, Some(&forum.reply_id)).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
@@ -1,12 +1,12 @@
+use crate::api::auth::OptionalUser;
+use crate::api::error::ApiError;
 use crate::{
-    api::response::{message_response, Message, Response},
+    api::response::Response,
     db::models::{Category, Permission},
 };
-use crate::{
-    db::{database::DatabaseParam, models::Id},
-    permission_verification,
-};
-use salvo::{prelude::ToSchema, session::SessionDepotExt, Depot};
+use crate::{db::database::DatabaseParam, permission_verification};
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::QueryParam, prelude::ToSchema, Depot};
 use serde::Serialize;
 
 #[derive(Serialize, ToSchema)]
@@ -15,32 +15,35 @@ struct RouteResponse {
     data: Vec<Category>,
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
-pub async fn route(depot: &mut Depot) -> Result<Response<RouteResponse>, Response<Message>> {
-    let user_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"));
+#[salvo::endpoint(status_codes(200, 400, 403, 429, 500), security((), ("session" = [])))]
+pub async fn route(
+    include_deleted: QueryParam<bool, false>,
+    depot: &mut Depot,
+) -> Result<Response<RouteResponse>, ApiError> {
+    let OptionalUser(user) = OptionalUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let db = db.read().await;
-    let permission = if let Some(user_id) = user_id {
-        db.user_from_id(&user_id)
-            .await
-            .map_err(|err| log::error!("unable to get user from id: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .map_or(Permission::default(), |user| user.permission)
-    } else {
-        Permission::default()
-    };
+    let permission = user.map_or(Permission::default(), |user| user.permission);
+    let include_deleted = include_deleted.into_inner().unwrap_or(false);
+    let deleted_permission = permission_verification::permission_for_important_actions();
+
+    if include_deleted && !permission_verification::is_allowed(&permission, &deleted_permission) {
+        let err = format!(
+            "you must be {} or above to see deleted categories, you are {}",
+            deleted_permission, permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
     let categories = db
         .all_categories()
         .await
-        .map_err(|err| log::error!("unable to get all categories: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
+        .with_context(|| "unable to get all categories")?
         .into_iter()
+        .filter(|category| include_deleted || !category.deleted)
         .filter(|category| {
             permission_verification::is_allowed(&permission, &category.minimum_read_permission)
         })
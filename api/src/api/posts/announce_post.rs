@@ -0,0 +1,147 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+use tokio::sync::RwLockReadGuard;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::db::{
+    database::{Database, DatabaseParam},
+    models::{CategoryId, PostId},
+};
+use crate::permission_verification;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    db::database::EditPost,
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    id: String,
+    announcement: bool,
+}
+
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    category_id: &CategoryId,
+) -> Result<(), ApiError> {
+    let announcement_permission = permission_verification::permission_for_important_actions();
+
+    if !permission_verification::is_allowed(&user.permission, &announcement_permission) {
+        let err = format!(
+            "you must be {} or above to announce posts, you are {}",
+            announcement_permission, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    let category = db
+        .category_from_id(category_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
+        let err = format!(
+            "you must be {} or above to edit posts in category {}, you are {}",
+            category.minimum_write_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { id, announcement }) = request;
+
+    let id = PostId::try_from(id).map_err(|_| ApiError::validation_failed("id", "invalid id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let post = {
+        let db = db.read().await;
+        let post = db
+            .post_from_id(&id)
+            .await
+            .with_context(|| "unable to get post from database")?
+            .ok_or_else(|| ApiError::not_found("post not found"))?;
+        verify_valid_user_permission(&db, &user, &post.category_id).await?;
+
+        post
+    };
+    {
+        let mut db = db.write().await;
+        db.edit_post(EditPost {
+            id: post.id,
+            category_id: post.category_id,
+            title: post.title,
+            content: post.content,
+            deleted: post.deleted,
+            locked: post.locked,
+            pinned: post.pinned,
+            announcement,
+            accepted_reply_id: post.accepted_reply_id,
+        })
+        .await
+        .with_context(|| "unable to save post in database")?;
+    }
+
+    Ok(message_response::ok("edited"))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::models::UserId;
+
+    async fn announce(forum: &TestForum, user_id: &UserId, announcement: bool) -> StatusCode {
+        let service = forum.service(
+            Some(user_id),
+            Router::with_path("announce_post").post(route),
+        );
+        let body = json!({ "id": forum.post_id.to_string(), "announcement": announcement });
+        post_json(&service, "announce_post", &body).await.0
+    }
+
+    #[tokio::test]
+    async fn admins_announce_and_unannounce_posts() {
+        let forum = TestForum::new().await;
+
+        assert_eq!(
+            announce(&forum, &forum.admin_id, true).await,
+            StatusCode::OK
+        );
+        assert!(forum.post().await.announcement);
+
+        assert_eq!(
+            announce(&forum, &forum.admin_id, false).await,
+            StatusCode::OK
+        );
+        assert!(!forum.post().await.announcement);
+    }
+
+    #[tokio::test]
+    async fn only_admins_announce_posts() {
+        let forum = TestForum::new().await;
+
+        assert_eq!(
+            announce(&forum, &forum.user_id, true).await,
+            StatusCode::FORBIDDEN
+        );
+        assert!(!forum.post().await.announcement);
+    }
+}
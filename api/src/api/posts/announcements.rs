@@ -0,0 +1,121 @@
+use crate::api::auth::OptionalUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::Response,
+    db::models::{Permission, Post},
+};
+use crate::{db::database::DatabaseParam, permission_verification};
+use eyre::{eyre, Context};
+use salvo::{prelude::ToSchema, Depot};
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: Vec<Post>,
+}
+
+#[salvo::endpoint(status_codes(200, 429, 500), security((), ("session" = [])))]
+pub async fn route(depot: &mut Depot) -> Result<Response<RouteResponse>, ApiError> {
+    let OptionalUser(user) = OptionalUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let permission = user.map_or(Permission::default(), |user| user.permission);
+    let categories: Vec<_> = db
+        .all_categories()
+        .await
+        .with_context(|| "unable to get all categories")?
+        .into_iter()
+        .filter(|category| !category.deleted)
+        .filter(|category| {
+            permission_verification::is_allowed(&permission, &category.minimum_read_permission)
+        })
+        .collect();
+    let announcements = db
+        .announcements()
+        .await
+        .with_context(|| "unable to get announcements")?
+        .into_iter()
+        .filter(|post| !post.deleted)
+        .filter(|post| {
+            categories
+                .iter()
+                .any(|category| category.id == post.category_id)
+        })
+        .collect();
+
+    Ok(Response::with_ok(RouteResponse {
+        data: announcements,
+        ok: true,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+
+    use super::*;
+    use crate::api::test_forum::{get_json, TestForum};
+    use crate::db::database::{CreateCategory, CreatePost};
+    use crate::db::models::{Content, Title, UserId};
+    use crate::from_unchecked::FromUnchecked;
+
+    async fn titles(forum: &TestForum, user_id: Option<&UserId>) -> Vec<String> {
+        let service = forum.service(user_id, Router::with_path("announcements").get(route));
+        let (status, body) = get_json(&service, "announcements").await;
+        assert_eq!(status, StatusCode::OK);
+
+        body["data"]
+            .as_array()
+            .expect("data should be a list")
+            .iter()
+            .map(|post| post["title"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lists_the_announcements_the_user_may_read() {
+        let forum = TestForum::new().await;
+        let _ = forum.create_post("not announced").await;
+        forum.edit_post(|post| post.announcement = true).await;
+        let removed_id = forum.create_post("removed").await;
+        forum
+            .edit_post_with_id(&removed_id, |post| {
+                post.announcement = true;
+                post.deleted = true;
+            })
+            .await;
+        let staff_post_id = {
+            let mut db = forum.db.write().await;
+            let category_id = db
+                .create_category(CreateCategory {
+                    title: Title::from_unchecked("staff".to_string()),
+                    minimum_write_permission: Permission::Admin,
+                    minimum_read_permission: Permission::Admin,
+                    qa_mode: false,
+                })
+                .await
+                .expect("category should be created");
+            db.create_post(CreatePost {
+                category_id,
+                title: Title::from_unchecked("staff only".to_string()),
+                content: Content::from_unchecked("content".to_string()),
+                creator_id: forum.admin_id.clone(),
+            })
+            .await
+            .expect("post should be created")
+        };
+        forum
+            .edit_post_with_id(&staff_post_id, |post| post.announcement = true)
+            .await;
+
+        assert_eq!(titles(&forum, None).await, ["post"]);
+        assert_eq!(
+            titles(&forum, Some(&forum.admin_id)).await,
+            ["post", "staff only"]
+        );
+    }
+}
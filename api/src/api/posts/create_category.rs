@@ -1,20 +1,19 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
-use tokio::sync::RwLockReadGuard;
 
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::permission_verification;
 use crate::{api::response::message_response, db::models::Permission};
-use crate::{api::response::Response, permission_verification};
 use crate::{
-    api::response::{CreatedResponseResult, Message},
-    db::{
-        database::{CreateCategory, Database, DatabaseParam},
-        models::Id,
-    },
+    api::response::CreatedResponseResult,
+    db::database::{CreateCategory, DatabaseParam},
 };
 
 #[derive(Deserialize, Extractible, ToSchema)]
@@ -27,20 +26,14 @@ struct MinimumPermissionRequest {
 struct RouteRequest {
     title: String,
     minimum_permissions: MinimumPermissionRequest,
+    qa_mode: Option<bool>,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
-    db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
+fn verify_valid_user_permission(
+    user: &User,
     minimum_read_permission: &Permission,
     minimum_write_permission: &Permission,
-) -> Result<(), Response<Message>> {
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
+) -> Result<(), ApiError> {
     let category_permission = permission_verification::permission_for_important_actions();
 
     if !permission_verification::is_allowed(&user.permission, &category_permission) {
@@ -48,7 +41,7 @@ async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
             "you must be {} or above to create categories, you are {}",
             category_permission, user.permission,
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     if !permission_verification::is_allowed(&user.permission, minimum_write_permission) {
@@ -56,20 +49,20 @@ async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
             "you must be {} or above to create categories with write permission {}, you are {}",
             minimum_write_permission, minimum_write_permission, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
     if !permission_verification::is_allowed(&user.permission, minimum_read_permission) {
         let err = format!(
             "you must be {} or above to create categories with read permission {}, you are {}",
             minimum_read_permission, minimum_read_permission, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(201, 400, 403, 500))]
+#[salvo::endpoint(status_codes(201, 400, 401, 403, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> CreatedResponseResult {
     let JsonBody(RouteRequest {
         title,
@@ -78,34 +71,28 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Create
                 read: read_permission,
                 write: write_permission,
             },
+        qa_mode,
     }) = request;
 
     let title = title
         .try_into()
-        .map_err(|_| message_response::bad_request("invalid title"))?;
-    let creator_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+        .map_err(|err| ApiError::validation_failed("title", format!("invalid title: {err}")))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
-    {
-        let db = db.read().await;
-        verify_valid_user_permission(&db, &creator_id, &read_permission, &write_permission).await?;
-    }
+    verify_valid_user_permission(&user, &read_permission, &write_permission)?;
     let id = {
         let mut db = db.write().await;
         db.create_category(CreateCategory {
             title,
             minimum_read_permission: read_permission,
             minimum_write_permission: write_permission,
+            qa_mode: qa_mode.unwrap_or(false),
         })
         .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
+        .with_context(|| "unable to save post in database")?
     };
 
     Ok(message_response::created_with_id("created", id))
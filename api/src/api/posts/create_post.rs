@@ -1,21 +1,21 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
 use tokio::sync::RwLockReadGuard;
 
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
 use crate::api::response::{message_response, CreatedResponseResult};
-use crate::{
-    api::response::{Message, Response},
-    db::{
-        database::{CreatePost, Database, DatabaseParam},
-        models::{Content, Id},
-    },
+use crate::db::models::User;
+use crate::db::{
+    database::{CreatePost, CreateSubscription, Database, DatabaseParam},
+    models::{CategoryId, Content, Id, SubscriptionLevel, SubscriptionTarget},
 };
-use crate::{db::models::Title, permission_verification};
+use crate::{db::models::Title, permission_verification, subscriptions};
 
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
@@ -24,35 +24,28 @@ struct RouteRequest {
     content: String,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
     db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
-    category_id: &Id,
-) -> Result<(), Response<Message>> {
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
+    user: &User,
+    category_id: &CategoryId,
+) -> Result<(), ApiError> {
     let category = db
         .category_from_id(category_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+        .await?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
 
     if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
         let err = format!(
             "you must be {} or above to create posts in category {}, you are {}",
             category.minimum_write_permission, category.title, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(201, 400, 403, 500))]
+#[salvo::endpoint(status_codes(201, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> CreatedResponseResult {
     let JsonBody(RouteRequest {
         category_id,
@@ -60,38 +53,48 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Create
         content,
     }) = request;
 
-    let category_id = Id::try_from(category_id)
-        .map_err(|_| message_response::bad_request("invalid category id"))?;
-    let title =
-        Title::try_from(title).map_err(|_| message_response::bad_request("invalid title"))?;
-    let content =
-        Content::try_from(content).map_err(|_| message_response::bad_request("invalid content"))?;
+    let category_id = CategoryId::try_from(category_id)
+        .map_err(|_| ApiError::validation_failed("category_id", "invalid category id"))?;
+    let title = Title::try_from(title)
+        .map_err(|err| ApiError::validation_failed("title", format!("invalid title: {err}")))?;
+    let content = Content::try_from(content)
+        .map_err(|err| ApiError::validation_failed("content", format!("invalid content: {err}")))?;
 
-    let creator_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     {
         let db = db.read().await;
-        verify_valid_user_permission(&db, &creator_id, &category_id).await?;
+        verify_valid_user_permission(&db, &user, &category_id).await?;
     }
     let id = {
         let mut db = db.write().await;
-        db.create_post(CreatePost {
-            category_id,
-            title,
-            content,
-            creator_id,
+        let id = db
+            .create_post(CreatePost {
+                category_id,
+                title,
+                content,
+                creator_id: user.id.clone(),
+            })
+            .await
+            .with_context(|| "unable to save post in database")?;
+        db.create_subscription(CreateSubscription {
+            user_id: user.id.clone(),
+            target: SubscriptionTarget::Post,
+            target_id: Id::from(&id),
+            level: SubscriptionLevel::Watching,
         })
         .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
+        .with_context(|| "unable to subscribe to post")?;
+        id
     };
 
+    // the post is saved either way, so failing to notify is only logged
+    if let Err(err) = subscriptions::notify_new_post(db, &id).await {
+        log::error!("unable to notify subscribers of post '{id}': {err:?}");
+    }
+
     Ok(message_response::created_with_id("created", id))
 }
@@ -1,46 +1,57 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
 use tokio::sync::RwLockReadGuard;
 
-use crate::{api::response::CreatedResponseResult, db::database::CreateReply};
-use crate::{api::response::Response, permission_verification};
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::permission_verification;
 use crate::{
-    api::response::{message_response, Message},
+    api::response::message_response,
     db::{
         database::{Database, DatabaseParam},
-        models::{Content, Id},
+        models::{
+            Content, Id, PostId, Quote, QuoteSource, ReplyId, SubscriptionLevel, SubscriptionTarget,
+        },
     },
+    subscriptions,
+};
+use crate::{
+    api::response::CreatedResponseResult,
+    db::database::{CreateReply, CreateSubscription},
 };
 
+#[derive(Deserialize, Extractible, ToSchema)]
+struct QuoteRequest {
+    source: QuoteSource,
+    source_id: String,
+    excerpt: String,
+}
+
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
     post_id: String,
+    parent_reply_id: Option<String>,
+    quote: Option<QuoteRequest>,
     content: String,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
     db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
-    post_id: &Id,
-) -> Result<(), Response<Message>> {
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|err| log::error!("unable to get user with id '{}': {err:?}", user_id))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
+    user: &User,
+    post_id: &PostId,
+    parent_reply_id: Option<&ReplyId>,
+) -> Result<(), ApiError> {
     let post = db
         .post_from_id(post_id)
         .await
-        .map_err(|err| log::error!("unable to get post with id '{}': {err:?}", post_id))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid post id"))?;
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .ok_or_else(|| ApiError::not_found("post not found"))?;
 
     let post_on_locked_posts_permission =
         permission_verification::permission_for_important_actions();
@@ -48,7 +59,7 @@ async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
     if post.locked
         && !permission_verification::is_allowed(&user.permission, &post_on_locked_posts_permission)
     {
-        return Err(message_response::unauthorized(
+        return Err(ApiError::permission_denied(
             "unable to reply to locked posts",
         ));
     }
@@ -56,59 +67,265 @@ async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
     let category = db
         .category_from_id(&post.category_id)
         .await
-        .map_err(|err| {
-            log::error!(
-                "unable to get category with id '{}': {err:?}",
-                post.category_id,
-            );
-        })
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
 
     if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
         let err = format!(
             "you must be {} or above to create replies in category {}, you are {}",
             category.minimum_write_permission, category.title, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
+    }
+
+    if let Some(parent_reply_id) = parent_reply_id {
+        let parent_reply = db
+            .reply_from_id(parent_reply_id)
+            .await
+            .with_context(|| format!("unable to get reply with id '{}'", parent_reply_id))?
+            .ok_or_else(|| ApiError::not_found("parent reply not found"))?;
+
+        if &parent_reply.post_id != post_id || parent_reply.deleted {
+            return Err(ApiError::validation_failed(
+                "parent_reply_id",
+                "invalid parent reply id",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+async fn verify_valid_quote<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    quote: &Quote,
+) -> Result<(), ApiError> {
+    let reply = match quote.source {
+        QuoteSource::Post => None,
+        QuoteSource::Reply => Some(
+            db.reply_from_id(&quote.source_id.to_typed())
+                .await
+                .with_context(|| format!("unable to get reply with id '{}'", quote.source_id))?
+                .filter(|reply| !reply.deleted)
+                .ok_or_else(|| ApiError::not_found("quote source not found"))?,
+        ),
+    };
+    let post_id: PostId = reply
+        .as_ref()
+        .map_or_else(|| quote.source_id.to_typed(), |reply| reply.post_id.clone());
+
+    let post = db
+        .post_from_id(&post_id)
+        .await
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .filter(|post| !post.deleted)
+        .ok_or_else(|| ApiError::not_found("quote source not found"))?;
+
+    let category = db
+        .category_from_id(&post.category_id)
+        .await
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_read_permission) {
+        let err = format!(
+            "you must be {} or above to quote posts in category {}, you are {}",
+            category.minimum_read_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    let source_content = reply
+        .map_or(post.content, |reply| reply.content)
+        .to_string();
+    if !source_content.contains(&quote.excerpt.to_string()) {
+        return Err(ApiError::validation_failed(
+            "quote.excerpt",
+            "quote excerpt does not match source",
+        ));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(201, 400, 403, 500))]
+#[salvo::endpoint(status_codes(201, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> CreatedResponseResult {
-    let JsonBody(RouteRequest { post_id, content }) = request;
+    let JsonBody(RouteRequest {
+        post_id,
+        parent_reply_id,
+        quote,
+        content,
+    }) = request;
 
-    let post_id =
-        Id::try_from(post_id).map_err(|_| message_response::bad_request("invalid post id"))?;
-    let content =
-        Content::try_from(content).map_err(|_| message_response::bad_request("invalid content"))?;
+    let post_id = PostId::try_from(post_id)
+        .map_err(|_| ApiError::validation_failed("post_id", "invalid post id"))?;
+    let parent_reply_id = match parent_reply_id {
+        Some(parent_reply_id) => Some(ReplyId::try_from(parent_reply_id).map_err(|_| {
+            ApiError::validation_failed("parent_reply_id", "invalid parent reply id")
+        })?),
+        None => None,
+    };
+    let quote = match quote {
+        Some(QuoteRequest {
+            source,
+            source_id,
+            excerpt,
+        }) => Some(Quote {
+            source,
+            source_id: Id::try_from(source_id).map_err(|_| {
+                ApiError::validation_failed("quote.source_id", "invalid quote source id")
+            })?,
+            excerpt: Content::try_from(excerpt).map_err(|err| {
+                ApiError::validation_failed(
+                    "quote.excerpt",
+                    format!("invalid quote excerpt: {err}"),
+                )
+            })?,
+        }),
+        None => None,
+    };
+    let content = Content::try_from(content)
+        .map_err(|err| ApiError::validation_failed("content", format!("invalid content: {err}")))?;
 
-    let creator_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     {
         let db = db.read().await;
-        verify_valid_user_permission(&db, &creator_id, &post_id).await?;
+        verify_valid_user_permission(&db, &user, &post_id, parent_reply_id.as_ref()).await?;
+        if let Some(quote) = &quote {
+            verify_valid_quote(&db, &user, quote).await?;
+        }
     }
     let id = {
         let mut db = db.write().await;
-        db.create_reply(CreateReply {
-            creator_id,
-            post_id,
-            content,
-        })
-        .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
+        let subscription = CreateSubscription {
+            user_id: user.id.clone(),
+            target: SubscriptionTarget::Post,
+            target_id: Id::from(&post_id),
+            level: SubscriptionLevel::Normal,
+        };
+        let id = db
+            .create_reply(CreateReply {
+                creator_id: user.id.clone(),
+                post_id,
+                parent_reply_id,
+                quote,
+                content,
+            })
+            .await
+            .with_context(|| "unable to save post in database")?;
+        db.create_subscription(subscription)
+            .await
+            .with_context(|| "unable to subscribe to post")?;
+        id
     };
 
+    // the reply is saved either way, so failing to notify is only logged
+    if let Err(err) = subscriptions::notify_new_reply(db, &id).await {
+        log::error!("unable to notify subscribers of reply '{id}': {err:?}");
+    }
+
     Ok(message_response::created_with_id("created", id))
 }
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+
+    async fn reply_quoting(
+        forum: &TestForum,
+        source: &str,
+        source_id: &str,
+        excerpt: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let service = forum.service(
+            Some(&forum.admin_id),
+            Router::with_path("create_reply").post(route),
+        );
+        let body = json!({
+            "post_id": forum.post_id.to_string(),
+            "quote": { "source": source, "source_id": source_id, "excerpt": excerpt },
+            "content": "quoting",
+        });
+        post_json(&service, "create_reply", &body).await
+    }
+
+    #[tokio::test]
+    async fn quotes_excerpts_of_posts_and_replies() {
+        let forum = TestForum::new().await;
+
+        let (status, body) = reply_quoting(
+            &forum,
+            "Post",
+            &forum.post_id.to_string(),
+            "content of the post",
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let reply_id = ReplyId::try_from(
+            body["data"]
+                .as_str()
+                .expect("id should be returned")
+                .to_string(),
+        )
+        .expect("id should be valid");
+        let quote = forum
+            .reply(&reply_id)
+            .await
+            .quote
+            .expect("quote should be saved");
+        assert!(matches!(quote.source, QuoteSource::Post));
+        assert_eq!(quote.excerpt.to_string(), "content of the post");
+
+        let (status, _) =
+            reply_quoting(&forum, "Reply", &forum.reply_id.to_string(), "of the reply").await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn rejects_excerpts_missing_from_the_source() {
+        let forum = TestForum::new().await;
+
+        // the excerpt is in the reply, not in the quoted post
+        let (status, body) = reply_quoting(
+            &forum,
+            "Post",
+            &forum.post_id.to_string(),
+            "content of the reply",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["field"], "quote.excerpt");
+        assert_eq!(
+            forum
+                .db
+                .read()
+                .await
+                .replies_from_post(&forum.post_id)
+                .await
+                .expect("replies should load")
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_quotes_of_removed_replies() {
+        let forum = TestForum::new().await;
+        forum.remove_reply(&forum.reply_id).await;
+
+        let (status, _) =
+            reply_quoting(&forum, "Reply", &forum.reply_id.to_string(), "of the reply").await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
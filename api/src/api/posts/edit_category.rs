@@ -1,17 +1,16 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
-use tokio::sync::RwLockReadGuard;
 
-use crate::{
-    api::response::Message,
-    db::{database::Database, models::Id},
-};
-use crate::{api::response::Response, permission_verification};
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::CategoryId;
+use crate::db::models::User;
+use crate::permission_verification;
 use crate::{
     api::response::{message_response, MessageResponseResult},
     db::{
@@ -28,31 +27,25 @@ struct MinimumPermissionRequest {
 
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
-    id: Id,
+    id: CategoryId,
     title: String,
     minimum_permissions: MinimumPermissionRequest,
+    qa_mode: Option<bool>,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
-    db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
+fn verify_valid_user_permission(
+    user: &User,
     minimum_read_permission: &Permission,
     minimum_write_permission: &Permission,
-) -> Result<(), Response<Message>> {
+) -> Result<(), ApiError> {
     let category_permission = permission_verification::permission_for_important_actions();
 
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
     if !permission_verification::is_allowed(&user.permission, &category_permission) {
         let err = format!(
             "you must be {} or above to edit categories, you are {}",
             category_permission, user.permission,
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     if !permission_verification::is_allowed(&user.permission, minimum_write_permission) {
@@ -60,20 +53,20 @@ async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
             "you must be {} or above to edit categories with write permission {}, you are {}",
             minimum_write_permission, minimum_write_permission, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
     if !permission_verification::is_allowed(&user.permission, minimum_read_permission) {
         let err = format!(
             "you must be {} or above to edit categories with read permission {}, you are {}",
             minimum_read_permission, minimum_read_permission, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(201, 400, 403, 500))]
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
     let JsonBody(RouteRequest {
         id,
@@ -83,29 +76,25 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
                 read: read_permission,
                 write: write_permission,
             },
+        qa_mode,
     }) = request;
 
     let title = title
         .try_into()
-        .map_err(|_| message_response::bad_request("invalid title"))?;
-    let creator_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+        .map_err(|err| ApiError::validation_failed("title", format!("invalid title: {err}")))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let category = {
         let db = db.read().await;
-        verify_valid_user_permission(&db, &creator_id, &read_permission, &write_permission).await?;
+        verify_valid_user_permission(&user, &read_permission, &write_permission)?;
         let category = db
             .category_from_id(&id)
             .await
-            .map_err(|err| log::error!("unable to get category from database: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+            .with_context(|| "unable to get category from database")?
+            .ok_or_else(|| ApiError::not_found("category not found"))?;
         category
     };
     {
@@ -115,12 +104,94 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
             title,
             minimum_read_permission: read_permission,
             minimum_write_permission: write_permission,
+            qa_mode: qa_mode.unwrap_or(category.qa_mode),
             deleted: category.deleted,
         })
         .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to save post in database")?;
     }
 
     Ok(message_response::ok("edited"))
 }
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::models::UserId;
+
+    fn request(id: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "title": "edited",
+            "minimum_permissions": { "read": "Unverified", "write": "User" },
+        })
+    }
+
+    async fn edit(forum: &TestForum, user_id: &UserId, body: &serde_json::Value) -> StatusCode {
+        let service = forum.service(
+            Some(user_id),
+            Router::with_path("edit_category").post(route),
+        );
+        post_json(&service, "edit_category", body).await.0
+    }
+
+    #[tokio::test]
+    async fn editing_returns_ok() {
+        let forum = TestForum::new().await;
+
+        let status = edit(
+            &forum,
+            &forum.admin_id,
+            &request(&forum.category_id.to_string()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(forum.category().await.title.to_string(), "edited");
+    }
+
+    #[tokio::test]
+    async fn only_admins_can_edit() {
+        let forum = TestForum::new().await;
+
+        let status = edit(
+            &forum,
+            &forum.user_id,
+            &request(&forum.category_id.to_string()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(forum.category().await.title.to_string(), "category");
+    }
+
+    #[tokio::test]
+    async fn missing_categories_are_not_found() {
+        let forum = TestForum::new().await;
+
+        let status = edit(
+            &forum,
+            &forum.admin_id,
+            &request(&forum.post_id.to_string()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn needs_a_login() {
+        let forum = TestForum::new().await;
+        let service = forum.service(None, Router::with_path("edit_category").post(route));
+
+        let (status, _) = post_json(
+            &service,
+            "edit_category",
+            &request(&forum.category_id.to_string()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(forum.category().await.title.to_string(), "category");
+    }
+}
@@ -1,23 +1,23 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
 use tokio::sync::RwLockReadGuard;
 
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::db::{
+    database::{Database, DatabaseParam},
+    models::{CategoryId, Content, PostId},
+};
 use crate::{
     api::response::{message_response, MessageResponseResult},
     db::database::EditPost,
 };
-use crate::{
-    api::response::{Message, Response},
-    db::{
-        database::{Database, DatabaseParam},
-        models::{Content, Id},
-    },
-};
 use crate::{db::models::Title, permission_verification};
 
 #[derive(Deserialize, Extractible, ToSchema)]
@@ -28,35 +28,28 @@ struct RouteRequest {
     content: String,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
     db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
-    category_id: &Id,
-) -> Result<(), Response<Message>> {
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
+    user: &User,
+    category_id: &CategoryId,
+) -> Result<(), ApiError> {
     let category = db
         .category_from_id(category_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+        .await?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
 
     if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
         let err = format!(
             "you must be {} or above to edit posts in category {}, you are {}",
             category.minimum_write_permission, category.title, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(201, 400, 403, 500))]
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
     let JsonBody(RouteRequest {
         id,
@@ -65,35 +58,32 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
         content,
     }) = request;
 
-    let id = Id::try_from(id).map_err(|_| message_response::bad_request("invalid id"))?;
-    let category_id = Id::try_from(category_id)
-        .map_err(|_| message_response::bad_request("invalid category id"))?;
-    let title =
-        Title::try_from(title).map_err(|_| message_response::bad_request("invalid title"))?;
-    let content =
-        Content::try_from(content).map_err(|_| message_response::bad_request("invalid content"))?;
-
-    let creator_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let id = PostId::try_from(id).map_err(|_| ApiError::validation_failed("id", "invalid id"))?;
+    let category_id = CategoryId::try_from(category_id)
+        .map_err(|_| ApiError::validation_failed("category_id", "invalid category id"))?;
+    let title = Title::try_from(title)
+        .map_err(|err| ApiError::validation_failed("title", format!("invalid title: {err}")))?;
+    let content = Content::try_from(content)
+        .map_err(|err| ApiError::validation_failed("content", format!("invalid content: {err}")))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let post = {
         let db = db.read().await;
-        verify_valid_user_permission(&db, &creator_id, &category_id).await?;
+        verify_valid_user_permission(&db, &user, &category_id).await?;
         let post = db
             .post_from_id(&id)
             .await
-            .map_err(|err| log::error!("unable to get post from database: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .ok_or_else(|| message_response::bad_request("invalid id"))?;
+            .with_context(|| "unable to get post from database")?
+            .ok_or_else(|| ApiError::not_found("post not found"))?;
 
-        if post.creator_id != creator_id {
-            return Err(message_response::unauthorized("invalid session"));
+        if post.creator_id != user.id {
+            return Err(ApiError::permission_denied(
+                "you can only edit your own posts",
+            ));
         }
 
         post
@@ -107,11 +97,77 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
             content,
             deleted: post.deleted,
             locked: post.locked,
+            pinned: post.pinned,
+            announcement: post.announcement,
+            accepted_reply_id: post.accepted_reply_id,
         })
         .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to save post in database")?;
     }
 
     Ok(message_response::ok("edited"))
 }
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::models::UserId;
+
+    fn request(forum: &TestForum, title: &str) -> serde_json::Value {
+        json!({
+            "id": forum.post_id.to_string(),
+            "category_id": forum.category_id.to_string(),
+            "title": title,
+            "content": "edited content",
+        })
+    }
+
+    async fn edit(forum: &TestForum, user_id: &UserId, body: &serde_json::Value) -> StatusCode {
+        let service = forum.service(Some(user_id), Router::with_path("edit_post").post(route));
+        post_json(&service, "edit_post", body).await.0
+    }
+
+    #[tokio::test]
+    async fn editing_returns_ok() {
+        let forum = TestForum::new().await;
+
+        let status = edit(&forum, &forum.user_id, &request(&forum, "edited")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(forum.post().await.title.to_string(), "edited");
+    }
+
+    #[tokio::test]
+    async fn only_the_creator_can_edit() {
+        let forum = TestForum::new().await;
+
+        let status = edit(&forum, &forum.admin_id, &request(&forum, "edited")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(forum.post().await.title.to_string(), "post");
+    }
+
+    #[tokio::test]
+    async fn missing_posts_are_not_found() {
+        let forum = TestForum::new().await;
+        let mut body = request(&forum, "edited");
+        body["id"] = json!(forum.reply_id.to_string());
+
+        assert_eq!(
+            edit(&forum, &forum.user_id, &body).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn needs_a_login() {
+        let forum = TestForum::new().await;
+        let service = forum.service(None, Router::with_path("edit_post").post(route));
+
+        let (status, _) = post_json(&service, "edit_post", &request(&forum, "edited")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(forum.post().await.title.to_string(), "post");
+    }
+}
@@ -1,112 +1,279 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
 use tokio::sync::RwLockReadGuard;
 
-use crate::{api::response::MessageResponseResult, db::database::EditReply};
-use crate::{api::response::Response, permission_verification};
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::permission_verification;
 use crate::{
-    api::response::{message_response, Message},
+    api::response::message_response,
     db::{
         database::{Database, DatabaseParam},
-        models::{Content, Id},
+        models::{Content, Id, PostId, Quote, QuoteSource, ReplyId},
     },
 };
+use crate::{api::response::MessageResponseResult, db::database::EditReply};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct QuoteRequest {
+    source: QuoteSource,
+    source_id: String,
+    excerpt: String,
+}
 
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
     id: String,
+    quote: Option<QuoteRequest>,
     content: String,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
     db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
-    post_id: &Id,
-) -> Result<(), Response<Message>> {
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|err| log::error!("unable to get user with id '{}': {err:?}", user_id))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
+    user: &User,
+    post_id: &PostId,
+) -> Result<(), ApiError> {
     let post = db
         .post_from_id(post_id)
         .await
-        .map_err(|err| log::error!("unable to get post with id '{}': {err:?}", post_id))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid post id"))?;
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .ok_or_else(|| ApiError::not_found("post not found"))?;
 
     let category = db
         .category_from_id(&post.category_id)
         .await
-        .map_err(|err| {
-            log::error!(
-                "unable to get category with id '{}': {err:?}",
-                post.category_id,
-            );
-        })
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
 
     if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
         let err = format!(
             "you must be {} or above to create replies in category {}, you are {}",
             category.minimum_write_permission, category.title, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(201, 400, 403, 500))]
+async fn verify_valid_quote<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    quote: &Quote,
+) -> Result<(), ApiError> {
+    let reply = match quote.source {
+        QuoteSource::Post => None,
+        QuoteSource::Reply => Some(
+            db.reply_from_id(&quote.source_id.to_typed())
+                .await
+                .with_context(|| format!("unable to get reply with id '{}'", quote.source_id))?
+                .filter(|reply| !reply.deleted)
+                .ok_or_else(|| ApiError::not_found("quote source not found"))?,
+        ),
+    };
+    let post_id: PostId = reply
+        .as_ref()
+        .map_or_else(|| quote.source_id.to_typed(), |reply| reply.post_id.clone());
+
+    let post = db
+        .post_from_id(&post_id)
+        .await
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .filter(|post| !post.deleted)
+        .ok_or_else(|| ApiError::not_found("quote source not found"))?;
+
+    let category = db
+        .category_from_id(&post.category_id)
+        .await
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_read_permission) {
+        let err = format!(
+            "you must be {} or above to quote posts in category {}, you are {}",
+            category.minimum_read_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    let source_content = reply
+        .map_or(post.content, |reply| reply.content)
+        .to_string();
+    if !source_content.contains(&quote.excerpt.to_string()) {
+        return Err(ApiError::validation_failed(
+            "quote.excerpt",
+            "quote excerpt does not match source",
+        ));
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
-    let JsonBody(RouteRequest { id, content }) = request;
+    let JsonBody(RouteRequest { id, quote, content }) = request;
 
-    let id = Id::try_from(id).map_err(|_| message_response::bad_request("invalid reply id"))?;
-    let content =
-        Content::try_from(content).map_err(|_| message_response::bad_request("invalid content"))?;
+    let id =
+        ReplyId::try_from(id).map_err(|_| ApiError::validation_failed("id", "invalid reply id"))?;
+    let quote = match quote {
+        Some(QuoteRequest {
+            source,
+            source_id,
+            excerpt,
+        }) => Some(Quote {
+            source,
+            source_id: Id::try_from(source_id).map_err(|_| {
+                ApiError::validation_failed("quote.source_id", "invalid quote source id")
+            })?,
+            excerpt: Content::try_from(excerpt).map_err(|err| {
+                ApiError::validation_failed(
+                    "quote.excerpt",
+                    format!("invalid quote excerpt: {err}"),
+                )
+            })?,
+        }),
+        None => None,
+    };
+    let content = Content::try_from(content)
+        .map_err(|err| ApiError::validation_failed("content", format!("invalid content: {err}")))?;
 
-    let creator_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let reply = {
         let db = db.read().await;
         let reply = db
             .reply_from_id(&id)
             .await
-            .map_err(|err| log::error!("unable to get reply from database: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .ok_or_else(|| message_response::bad_request("invalid reply id"))?;
-        if reply.creator_id != creator_id {
-            return Err(message_response::unauthorized("invalid reply id"));
+            .with_context(|| "unable to get reply from database")?
+            .ok_or_else(|| ApiError::not_found("reply not found"))?;
+        if reply.creator_id != user.id {
+            return Err(ApiError::permission_denied(
+                "you can only edit your own replies",
+            ));
+        }
+        verify_valid_user_permission(&db, &user, &reply.post_id).await?;
+        if let Some(quote) = &quote {
+            verify_valid_quote(&db, &user, quote).await?;
         }
-        verify_valid_user_permission(&db, &creator_id, &reply.post_id).await?;
         reply
     };
     {
         let mut db = db.write().await;
         db.edit_reply(EditReply {
             id: reply.id,
+            quote,
             content,
             deleted: reply.deleted,
         })
         .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to save post in database")?;
     }
 
-    Ok(message_response::created("created"))
+    Ok(message_response::ok("edited"))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::models::UserId;
+
+    async fn edit(forum: &TestForum, user_id: &UserId, body: &serde_json::Value) -> StatusCode {
+        let service = forum.service(Some(user_id), Router::with_path("edit_reply").post(route));
+        post_json(&service, "edit_reply", body).await.0
+    }
+
+    #[tokio::test]
+    async fn editing_returns_ok() {
+        let forum = TestForum::new().await;
+        let body = json!({ "id": forum.reply_id.to_string(), "content": "edited" });
+
+        assert_eq!(edit(&forum, &forum.user_id, &body).await, StatusCode::OK);
+        assert_eq!(
+            forum.reply(&forum.reply_id).await.content.to_string(),
+            "edited"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_creator_can_edit() {
+        let forum = TestForum::new().await;
+        let body = json!({ "id": forum.reply_id.to_string(), "content": "edited" });
+
+        assert_eq!(
+            edit(&forum, &forum.admin_id, &body).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn quotes_must_match_their_source() {
+        let forum = TestForum::new().await;
+        let post_id = forum.post_id.to_string();
+        let quoting = |excerpt: &str| {
+            json!({
+                "id": forum.reply_id.to_string(),
+                "quote": { "source": "Post", "source_id": post_id, "excerpt": excerpt },
+                "content": "edited",
+            })
+        };
+
+        assert_eq!(
+            edit(&forum, &forum.user_id, &quoting("content of the reply")).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert!(forum.reply(&forum.reply_id).await.quote.is_none());
+
+        assert_eq!(
+            edit(&forum, &forum.user_id, &quoting("the content")).await,
+            StatusCode::OK
+        );
+        let quote = forum
+            .reply(&forum.reply_id)
+            .await
+            .quote
+            .expect("quote should be saved");
+        assert_eq!(quote.excerpt.to_string(), "the content");
+    }
+
+    #[tokio::test]
+    async fn missing_replies_are_not_found() {
+        let forum = TestForum::new().await;
+        let body = json!({ "id": forum.post_id.to_string(), "content": "edited" });
+
+        assert_eq!(
+            edit(&forum, &forum.user_id, &body).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn needs_a_login() {
+        let forum = TestForum::new().await;
+        let service = forum.service(None, Router::with_path("edit_reply").post(route));
+
+        let (status, _) = post_json(
+            &service,
+            "edit_reply",
+            &json!({ "id": forum.reply_id.to_string(), "content": "edited" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            forum.reply(&forum.reply_id).await.content.to_string(),
+            "the content of the reply"
+        );
+    }
 }
@@ -1,24 +1,24 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
 use tokio::sync::RwLockReadGuard;
 
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::db::{
+    database::{Database, DatabaseParam},
+    models::{CategoryId, PostId},
+};
 use crate::permission_verification;
 use crate::{
     api::response::{message_response, MessageResponseResult},
     db::database::EditPost,
 };
-use crate::{
-    api::response::{Message, Response},
-    db::{
-        database::{Database, DatabaseParam},
-        models::Id,
-    },
-};
 
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
@@ -26,64 +26,56 @@ struct RouteRequest {
     locked: bool,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
     db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
-    category_id: &Id,
-) -> Result<(), Response<Message>> {
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
+    user: &User,
+    category_id: &CategoryId,
+) -> Result<(), ApiError> {
     let lock_permission = permission_verification::permission_for_important_actions();
 
     if !permission_verification::is_allowed(&user.permission, &lock_permission) {
-        return Err(message_response::unauthorized("invalid session"));
+        let err = format!(
+            "you must be {} or above to lock posts, you are {}",
+            lock_permission, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
     }
 
     let category = db
         .category_from_id(category_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+        .await?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
 
     if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
         let err = format!(
             "you must be {} or above to edit posts in category {}, you are {}",
             category.minimum_write_permission, category.title, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(200, 400, 403, 500))]
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
     let JsonBody(RouteRequest { id, locked }) = request;
 
-    let id = Id::try_from(id).map_err(|_| message_response::bad_request("invalid id"))?;
+    let id = PostId::try_from(id).map_err(|_| ApiError::validation_failed("id", "invalid id"))?;
 
-    let user_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let post = {
         let db = db.read().await;
         let post = db
             .post_from_id(&id)
             .await
-            .map_err(|err| log::error!("unable to get post from database: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .ok_or_else(|| message_response::bad_request("invalid id"))?;
-        verify_valid_user_permission(&db, &user_id, &post.category_id).await?;
+            .with_context(|| "unable to get post from database")?
+            .ok_or_else(|| ApiError::not_found("post not found"))?;
+        verify_valid_user_permission(&db, &user, &post.category_id).await?;
 
         post
     };
@@ -96,10 +88,12 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
             content: post.content,
             deleted: post.deleted,
             locked,
+            pinned: post.pinned,
+            announcement: post.announcement,
+            accepted_reply_id: post.accepted_reply_id,
         })
         .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to save post in database")?;
     }
 
     Ok(message_response::ok("edited"))
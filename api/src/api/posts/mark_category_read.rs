@@ -0,0 +1,59 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::api::response::{message_response, MessageResponseResult};
+use crate::db::{database::DatabaseParam, models::CategoryId};
+use crate::permission_verification;
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    category_id: String,
+}
+
+/// marks every post of the category as read up to its latest reply
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { category_id }) = request;
+
+    let category_id = CategoryId::try_from(category_id)
+        .map_err(|_| ApiError::validation_failed("category_id", "invalid category id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let db = db.read().await;
+        let category = db
+            .category_from_id(&category_id)
+            .await
+            .with_context(|| format!("unable to get category with id '{}'", category_id))?
+            .filter(|category| !category.deleted)
+            .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+        if !permission_verification::is_allowed(&user.permission, &category.minimum_read_permission)
+        {
+            let err = format!(
+                "you must be {} or above to read posts in category {}, you are {}",
+                category.minimum_read_permission, category.title, user.permission
+            );
+            return Err(ApiError::permission_denied(err));
+        }
+    }
+    {
+        let mut db = db.write().await;
+        db.mark_category_read(&user.id, &category_id)
+            .await
+            .with_context(|| "unable to save read statuses in database")?;
+    }
+
+    Ok(message_response::ok("marked as read"))
+}
@@ -0,0 +1,111 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+use tokio::sync::RwLockReadGuard;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::permission_verification;
+use crate::{
+    api::response::message_response,
+    db::{
+        database::{Database, DatabaseParam},
+        models::{PostId, ReplyId},
+    },
+};
+use crate::{api::response::MessageResponseResult, db::database::MarkPostRead};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    post_id: String,
+    /// the last reply that was read, the whole post is marked as read when missing
+    reply_id: Option<String>,
+}
+
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    post_id: &PostId,
+    reply_id: Option<&ReplyId>,
+) -> Result<(), ApiError> {
+    let post = db
+        .post_from_id(post_id)
+        .await
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .filter(|post| !post.deleted)
+        .ok_or_else(|| ApiError::not_found("post not found"))?;
+
+    let category = db
+        .category_from_id(&post.category_id)
+        .await
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .filter(|category| !category.deleted)
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_read_permission) {
+        let err = format!(
+            "you must be {} or above to read posts in category {}, you are {}",
+            category.minimum_read_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    if let Some(reply_id) = reply_id {
+        let reply = db
+            .reply_from_id(reply_id)
+            .await
+            .with_context(|| format!("unable to get reply with id '{}'", reply_id))?
+            .ok_or_else(|| ApiError::not_found("reply not found"))?;
+
+        if &reply.post_id != post_id {
+            return Err(ApiError::validation_failed(
+                "reply_id",
+                "reply does not belong to post",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { post_id, reply_id }) = request;
+
+    let post_id = PostId::try_from(post_id)
+        .map_err(|_| ApiError::validation_failed("post_id", "invalid post id"))?;
+    let reply_id = match reply_id {
+        Some(reply_id) => Some(
+            ReplyId::try_from(reply_id)
+                .map_err(|_| ApiError::validation_failed("reply_id", "invalid reply id"))?,
+        ),
+        None => None,
+    };
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let db = db.read().await;
+        verify_valid_user_permission(&db, &user, &post_id, reply_id.as_ref()).await?;
+    }
+    {
+        let mut db = db.write().await;
+        db.mark_post_read(MarkPostRead {
+            user_id: user.id.clone(),
+            post_id,
+            last_read_reply_id: reply_id,
+        })
+        .await
+        .with_context(|| "unable to save read status in database")?;
+    }
+
+    Ok(message_response::ok("marked as read"))
+}
@@ -1,4 +1,7 @@
+mod accept_reply;
 mod all_categories;
+mod announce_post;
+mod announcements;
 mod create_category;
 mod create_post;
 mod create_reply;
@@ -6,14 +9,29 @@ mod edit_category;
 mod edit_post;
 mod edit_reply;
 mod lock_post;
+mod mark_category_read;
+mod mark_read;
+mod pin_post;
 mod post_from_id;
 mod posts_from_category;
+mod react;
+mod reactions;
 mod remove_category;
 mod remove_post;
 mod remove_reply;
 mod replies_from_post;
+mod restore_category;
+mod restore_post;
+mod restore_reply;
+mod subscribe;
+mod unreact;
+mod unread_posts;
+mod unsubscribe;
 
+pub use accept_reply::route as accept_reply_route;
 pub use all_categories::route as all_categories_route;
+pub use announce_post::route as announce_post_route;
+pub use announcements::route as announcements_route;
 pub use create_category::route as create_category_route;
 pub use create_post::route as create_post_route;
 pub use create_reply::route as create_reply_route;
@@ -21,9 +39,20 @@ pub use edit_category::route as edit_category_route;
 pub use edit_post::route as edit_post_route;
 pub use edit_reply::route as edit_reply_route;
 pub use lock_post::route as lock_post_route;
+pub use mark_category_read::route as mark_category_read_route;
+pub use mark_read::route as mark_read_route;
+pub use pin_post::route as pin_post_route;
 pub use post_from_id::route as post_from_id_route;
 pub use posts_from_category::route as posts_from_category_route;
+pub use react::route as react_route;
 pub use remove_category::route as remove_category_route;
 pub use remove_post::route as remove_post_route;
 pub use remove_reply::route as remove_reply_route;
 pub use replies_from_post::route as replies_from_post_route;
+pub use restore_category::route as restore_category_route;
+pub use restore_post::route as restore_post_route;
+pub use restore_reply::route as restore_reply_route;
+pub use subscribe::route as subscribe_route;
+pub use unreact::route as unreact_route;
+pub use unread_posts::route as unread_posts_route;
+pub use unsubscribe::route as unsubscribe_route;
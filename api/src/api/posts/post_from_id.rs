@@ -1,71 +1,165 @@
-use crate::api::response::{message_response, Message, Response};
-use crate::db::models::{Permission, Post};
-use crate::db::{database::DatabaseParam, models::Id};
+use crate::api::auth::OptionalUser;
+use crate::api::error::ApiError;
+use crate::api::response::Response;
+use crate::db::models::{
+    AttachmentTarget, Content, Permission, ReactionCount, ReactionTarget, SubscriptionLevel,
+    SubscriptionTarget, Title,
+};
+use crate::db::{
+    database::DatabaseParam,
+    models::{AttachmentId, CategoryId, Id, PostId, ReplyId, UserId},
+};
 use crate::permission_verification;
-use salvo::session::SessionDepotExt;
-use salvo::{oapi::extract::PathParam, prelude::ToSchema, Depot};
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::{PathParam, QueryParam},
+    prelude::ToSchema,
+    Depot,
+};
 use serde::Serialize;
 
+#[derive(Serialize, ToSchema)]
+struct ResponsePost {
+    id: PostId,
+    category_id: CategoryId,
+    title: Title,
+    content: Content,
+    creator_id: UserId,
+    deleted: bool,
+    locked: bool,
+    pinned: bool,
+    announcement: bool,
+    accepted_reply_id: Option<ReplyId>,
+    date_created: String,
+    date_edited: Option<String>,
+    reactions: Vec<ReactionCount>,
+    attachments: Vec<ResponseAttachment>,
+    /// the oldest reply the reader has not read yet, missing when not logged in or all are read
+    first_unread_reply_id: Option<ReplyId>,
+    /// how the reader follows this post, missing when not logged in or not subscribed
+    subscription_level: Option<SubscriptionLevel>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ResponseAttachment {
+    id: AttachmentId,
+    file_name: String,
+    content_type: String,
+}
+
 #[derive(Serialize, ToSchema)]
 struct RouteResponse {
     ok: bool,
-    data: Post,
+    data: ResponsePost,
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
+#[salvo::endpoint(status_codes(200, 400, 403, 404, 429, 500), security((), ("session" = [])))]
 pub async fn route(
-    category_id: PathParam<Id>,
-    post_id: PathParam<Id>,
+    category_id: PathParam<CategoryId>,
+    post_id: PathParam<PostId>,
+    include_deleted: QueryParam<bool, false>,
     depot: &mut Depot,
-) -> Result<Response<RouteResponse>, Response<Message>> {
-    let user_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"));
+) -> Result<Response<RouteResponse>, ApiError> {
+    let OptionalUser(user) = OptionalUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let db = db.read().await;
-    let permission = if let Some(user_id) = user_id {
-        db.user_from_id(&user_id)
-            .await
-            .map_err(|err| log::error!("unable to get user from id: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .map_or(Permission::default(), |user| user.permission)
-    } else {
-        Permission::default()
+    let (user_id, permission) = match user {
+        Some(user) => (Some(user.id), user.permission),
+        None => (None, Permission::default()),
     };
+    let include_deleted = include_deleted.into_inner().unwrap_or(false);
+    let deleted_permission = permission_verification::permission_for_important_actions();
+
+    if include_deleted && !permission_verification::is_allowed(&permission, &deleted_permission) {
+        let err = format!(
+            "you must be {} or above to see deleted posts, you are {}",
+            deleted_permission, permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
 
     let category = db
         .category_from_id(&category_id)
         .await
-        .map_err(|err| log::error!("unable to get all categories: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+        .with_context(|| "unable to get all categories")?
+        .filter(|category| include_deleted || !category.deleted)
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
 
     if !permission_verification::is_allowed(&permission, &category.minimum_read_permission) {
         let err = format!(
             "you must be {} or above to read posts in category {}, you are {}",
             category.minimum_read_permission, category.title, permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     };
 
     let data = db
         .post_from_id(&post_id)
         .await
-        .map_err(|err| {
-            log::error!("unable to get post from category with id {category_id}: {err:?}");
-        })
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| format!("unable to get post from category with id {category_id}"))?
+        .filter(|post| include_deleted || !post.deleted);
 
     if data.as_ref().is_some_and(|v| v.category_id != category.id) {
-        return Err(message_response::bad_request("invalid category or post id"));
+        return Err(ApiError::not_found("post not found"));
     }
 
-    match data {
-        Some(data) => Ok(Response::with_ok(RouteResponse { data, ok: true })),
-        None => Err(message_response::bad_request("invalid post id")),
-    }
+    let post = data.ok_or_else(|| ApiError::not_found("post not found"))?;
+
+    let reactions = db
+        .reactions_from_target(&ReactionTarget::Post, &Id::from(&post.id), user_id.as_ref())
+        .await
+        .with_context(|| format!("unable to get reactions from post with id {post_id}"))?;
+
+    let attachments = db
+        .attachments_from_target(&AttachmentTarget::Post, &Id::from(&post.id))
+        .await
+        .with_context(|| format!("unable to get attachments from post with id {post_id}"))?
+        .into_iter()
+        .map(|attachment| ResponseAttachment {
+            id: attachment.id,
+            file_name: attachment.file_name,
+            content_type: attachment.content_type,
+        })
+        .collect();
+
+    let (first_unread_reply_id, subscription_level) = match &user_id {
+        Some(user_id) => {
+            let first_unread_reply_id = db
+                .read_status_from_post(user_id, &post.id)
+                .await
+                .with_context(|| format!("unable to get read status of post with id {post_id}"))?
+                .and_then(|status| status.first_unread_reply_id);
+            let subscription_level = db
+                .subscription(user_id, &SubscriptionTarget::Post, &Id::from(&post.id))
+                .await
+                .with_context(|| format!("unable to get subscription to post with id {post_id}"))?
+                .map(|subscription| subscription.level);
+            (first_unread_reply_id, subscription_level)
+        }
+        None => (None, None),
+    };
+
+    let data = ResponsePost {
+        id: post.id,
+        category_id: post.category_id,
+        title: post.title,
+        content: post.content,
+        creator_id: post.creator_id,
+        deleted: post.deleted,
+        locked: post.locked,
+        pinned: post.pinned,
+        announcement: post.announcement,
+        accepted_reply_id: post.accepted_reply_id,
+        date_created: post.date_created,
+        date_edited: post.date_edited,
+        reactions,
+        attachments,
+        first_unread_reply_id,
+        subscription_level,
+    };
+
+    Ok(Response::with_ok(RouteResponse { data, ok: true }))
 }
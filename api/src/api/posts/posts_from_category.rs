@@ -1,66 +1,240 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::api::auth::OptionalUser;
+use crate::api::error::ApiError;
 use crate::{
-    api::response::{message_response, Message, Response},
-    db::models::{Permission, Post},
+    api::response::Response,
+    db::models::{Content, Permission, PostReadStatus, ReplyId, Title},
 };
 use crate::{
-    db::{database::DatabaseParam, models::Id},
+    db::{
+        database::DatabaseParam,
+        models::{CategoryId, PostId, UserId},
+    },
+    iso_date_strings::utc_date_iso_string_ago,
     permission_verification,
 };
-use salvo::{oapi::extract::PathParam, prelude::ToSchema, session::SessionDepotExt, Depot};
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::{PathParam, QueryParam},
+    prelude::ToSchema,
+    Depot,
+};
 use serde::Serialize;
 
+/// how stale a category visit may get before listing the category records a new one,
+/// so browsing back and forth doesn't need the write lock on every request
+const VISIT_RESOLUTION: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Serialize, ToSchema)]
+struct ResponsePost {
+    id: PostId,
+    category_id: CategoryId,
+    title: Title,
+    content: Content,
+    creator_id: UserId,
+    deleted: bool,
+    locked: bool,
+    pinned: bool,
+    announcement: bool,
+    accepted_reply_id: Option<ReplyId>,
+    date_created: String,
+    date_edited: Option<String>,
+    /// replies the reader has not read yet, missing when not logged in
+    unread_count: Option<i64>,
+    /// whether the post was created after the reader last listed this category,
+    /// missing when not logged in
+    new_since_last_visit: Option<bool>,
+}
+
 #[derive(Serialize, ToSchema)]
 struct RouteResponse {
     ok: bool,
-    data: Vec<Post>,
+    data: Vec<ResponsePost>,
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
+/// listing the posts counts as a visit of the category for logged in users,
+/// so posts are only new since the previous listing, visits within
+/// [`VISIT_RESOLUTION`] of the previous one are not recorded
+#[salvo::endpoint(status_codes(200, 400, 403, 404, 429, 500), security((), ("session" = [])))]
 pub async fn route(
-    category_id: PathParam<Id>,
+    category_id: PathParam<CategoryId>,
+    solved: QueryParam<bool, false>,
+    include_deleted: QueryParam<bool, false>,
     depot: &mut Depot,
-) -> Result<Response<RouteResponse>, Response<Message>> {
-    let user_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"));
+) -> Result<Response<RouteResponse>, ApiError> {
+    let OptionalUser(user) = OptionalUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
-    let db = db.read().await;
-    let permission = if let Some(user_id) = user_id {
-        db.user_from_id(&user_id)
-            .await
-            .map_err(|err| log::error!("unable to get user from id: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .map_or(Permission::default(), |user| user.permission)
-    } else {
-        Permission::default()
+    let (user_id, permission) = match user {
+        Some(user) => (Some(user.id), user.permission),
+        None => (None, Permission::default()),
     };
+    let include_deleted = include_deleted.into_inner().unwrap_or(false);
+    let deleted_permission = permission_verification::permission_for_important_actions();
 
-    let category = db
-        .category_from_id(&category_id)
-        .await
-        .map_err(|err| log::error!("unable to get all categories: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
-
-    if !permission_verification::is_allowed(&permission, &category.minimum_read_permission) {
+    if include_deleted && !permission_verification::is_allowed(&permission, &deleted_permission) {
         let err = format!(
-            "you must be {} or above to read posts in category {}, you are {}",
-            category.minimum_read_permission, category.title, permission
+            "you must be {} or above to see deleted posts, you are {}",
+            deleted_permission, permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
+    }
+
+    let (data, previous_visit) = {
+        let db = db.read().await;
+        let category = db
+            .category_from_id(&category_id)
+            .await
+            .with_context(|| "unable to get all categories")?
+            .filter(|category| include_deleted || !category.deleted)
+            .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+        if !permission_verification::is_allowed(&permission, &category.minimum_read_permission) {
+            let err = format!(
+                "you must be {} or above to read posts in category {}, you are {}",
+                category.minimum_read_permission, category.title, permission
+            );
+            return Err(ApiError::permission_denied(err));
+        };
+
+        let (read_statuses, previous_visit) = match &user_id {
+            Some(user_id) => {
+                let read_statuses: HashMap<String, PostReadStatus> = db
+                    .read_statuses(user_id, Some(&category_id))
+                    .await
+                    .with_context(|| "unable to get read statuses")?
+                    .into_iter()
+                    .map(|status| (status.post_id.to_string(), status))
+                    .collect();
+                let previous_visit = db
+                    .category_visit(user_id, &category_id)
+                    .await
+                    .with_context(|| "unable to get category visit")?;
+                (Some(read_statuses), previous_visit)
+            }
+            None => (None, None),
+        };
+
+        let posts = db
+            .posts_from_category(&category_id)
+            .await
+            .with_context(|| format!("unable to get posts from category with id {category_id}"))?
+            .into_iter()
+            .filter(|post| include_deleted || !post.deleted);
+
+        let posts: Vec<_> = match solved.into_inner() {
+            Some(solved) => posts
+                .filter(|post| post.accepted_reply_id.is_some() == solved)
+                .collect(),
+            None => posts.collect(),
+        };
+
+        let mut data: Vec<ResponsePost> = posts
+            .into_iter()
+            .map(|post| {
+                let (unread_count, new_since_last_visit) = match &read_statuses {
+                    Some(read_statuses) => (
+                        Some(
+                            read_statuses
+                                .get(&post.id.to_string())
+                                .map_or(0, |status| status.unread_count),
+                        ),
+                        Some(
+                            previous_visit
+                                .as_ref()
+                                .is_none_or(|visit| post.date_created > *visit),
+                        ),
+                    ),
+                    None => (None, None),
+                };
+
+                ResponsePost {
+                    id: post.id,
+                    category_id: post.category_id,
+                    title: post.title,
+                    content: post.content,
+                    creator_id: post.creator_id,
+                    deleted: post.deleted,
+                    locked: post.locked,
+                    pinned: post.pinned,
+                    announcement: post.announcement,
+                    accepted_reply_id: post.accepted_reply_id,
+                    date_created: post.date_created,
+                    date_edited: post.date_edited,
+                    unread_count,
+                    new_since_last_visit,
+                }
+            })
+            .collect();
+        data.sort_by_key(|post| !post.pinned);
+        (data, previous_visit)
     };
 
-    let data = db
-        .posts_from_category(&category_id)
-        .await
-        .map_err(|err| {
-            log::error!("unable to get posts from category with id {category_id}: {err:?}");
-        })
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+    if let Some(user_id) = &user_id {
+        let visited_after = utc_date_iso_string_ago(VISIT_RESOLUTION)?;
+        if previous_visit.is_none_or(|visit| visit < visited_after) {
+            let mut db = db.write().await;
+            db.edit_category_visit(user_id, &category_id)
+                .await
+                .with_context(|| "unable to record category visit")?;
+        }
+    }
 
     Ok(Response::with_ok(RouteResponse { data, ok: true }))
 }
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+
+    use super::*;
+    use crate::api::test_forum::{get_json, TestForum};
+
+    async fn titles(forum: &TestForum, query: &str) -> Vec<String> {
+        let service = forum.service(
+            None,
+            Router::with_path("posts_from_category/<category_id>").get(route),
+        );
+        let (status, body) = get_json(
+            &service,
+            &format!("posts_from_category/{}{query}", forum.category_id),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        body["data"]
+            .as_array()
+            .expect("data should be a list")
+            .iter()
+            .map(|post| post["title"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn filters_posts_by_whether_they_are_solved() {
+        let forum = TestForum::with_qa_mode(true).await;
+        let _ = forum.create_post("open").await;
+        let reply_id = forum.reply_id.clone();
+        forum
+            .edit_post(|post| post.accepted_reply_id = Some(reply_id))
+            .await;
+
+        assert_eq!(titles(&forum, "?solved=true").await, ["post"]);
+        assert_eq!(titles(&forum, "?solved=false").await, ["open"]);
+        assert_eq!(titles(&forum, "").await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn lists_pinned_posts_first() {
+        let forum = TestForum::new().await;
+        let other_id = forum.create_post("other").await;
+        forum
+            .edit_post_with_id(&other_id, |post| post.pinned = true)
+            .await;
+
+        assert_eq!(titles(&forum, "").await, ["other", "post"]);
+    }
+}
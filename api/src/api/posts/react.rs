@@ -0,0 +1,199 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::api::posts::reactions::verify_valid_user_permission;
+use crate::{
+    api::response::message_response,
+    db::{
+        database::DatabaseParam,
+        models::{Id, ReactionKind, ReactionTarget},
+    },
+};
+use crate::{api::response::MessageResponseResult, db::database::CreateReaction};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    target: ReactionTarget,
+    target_id: String,
+    kind: ReactionKind,
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest {
+        target,
+        target_id,
+        kind,
+    }) = request;
+
+    let target_id = Id::try_from(target_id)
+        .map_err(|_| ApiError::validation_failed("target_id", "invalid target id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let db = db.read().await;
+        verify_valid_user_permission(&db, &user, &target, &target_id).await?;
+    }
+    {
+        let mut db = db.write().await;
+        db.create_reaction(CreateReaction {
+            creator_id: user.id.clone(),
+            target,
+            target_id,
+            kind,
+        })
+        .await
+        .with_context(|| "unable to save reaction in database")?;
+    }
+
+    Ok(message_response::ok("reacted"))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::models::{Permission, ReactionCount, UserId};
+
+    async fn react(
+        forum: &TestForum,
+        user_id: &UserId,
+        target: &str,
+        target_id: &str,
+    ) -> StatusCode {
+        let service = forum.service(Some(user_id), Router::with_path("react").post(route));
+        let body = json!({ "target": target, "target_id": target_id, "kind": "Heart" });
+        post_json(&service, "react", &body).await.0
+    }
+
+    async fn reactions(
+        forum: &TestForum,
+        target: ReactionTarget,
+        target_id: &str,
+    ) -> Vec<ReactionCount> {
+        forum
+            .db
+            .read()
+            .await
+            .reactions_from_target(
+                &target,
+                &Id::try_from(target_id.to_string()).expect("id should be valid"),
+                None,
+            )
+            .await
+            .expect("reactions should load")
+    }
+
+    #[tokio::test]
+    async fn reacts_to_posts_and_replies() {
+        let forum = TestForum::new().await;
+        let post_id = forum.post_id.to_string();
+        let reply_id = forum.reply_id.to_string();
+
+        assert_eq!(
+            react(&forum, &forum.user_id, "Post", &post_id).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            react(&forum, &forum.admin_id, "Reply", &reply_id).await,
+            StatusCode::OK
+        );
+
+        let post_reactions = reactions(&forum, ReactionTarget::Post, &post_id).await;
+        assert_eq!(post_reactions.len(), 1);
+        assert!(matches!(post_reactions[0].kind, ReactionKind::Heart));
+        assert_eq!(post_reactions[0].count, 1);
+        assert_eq!(
+            reactions(&forum, ReactionTarget::Reply, &reply_id)
+                .await
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn reacting_twice_counts_once() {
+        let forum = TestForum::new().await;
+        let post_id = forum.post_id.to_string();
+
+        assert_eq!(
+            react(&forum, &forum.user_id, "Post", &post_id).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            react(&forum, &forum.user_id, "Post", &post_id).await,
+            StatusCode::OK
+        );
+
+        let post_reactions = reactions(&forum, ReactionTarget::Post, &post_id).await;
+        assert_eq!(post_reactions[0].count, 1);
+    }
+
+    #[tokio::test]
+    async fn needs_write_permission_in_the_category() {
+        let forum = TestForum::new().await;
+        let unverified_id = forum
+            .create_user("unverified", Permission::Unverified)
+            .await;
+        let post_id = forum.post_id.to_string();
+
+        assert_eq!(
+            react(&forum, &unverified_id, "Post", &post_id).await,
+            StatusCode::FORBIDDEN
+        );
+        assert!(reactions(&forum, ReactionTarget::Post, &post_id)
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_removed_targets() {
+        let forum = TestForum::new().await;
+        forum.remove_reply(&forum.reply_id).await;
+
+        assert_eq!(
+            react(&forum, &forum.user_id, "Reply", &forum.reply_id.to_string()).await,
+            StatusCode::NOT_FOUND
+        );
+
+        forum.edit_post(|post| post.deleted = true).await;
+        assert_eq!(
+            react(&forum, &forum.user_id, "Post", &forum.post_id.to_string()).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_locked_posts_and_their_replies() {
+        let forum = TestForum::new().await;
+        forum.edit_post(|post| post.locked = true).await;
+
+        assert_eq!(
+            react(&forum, &forum.user_id, "Post", &forum.post_id.to_string()).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            react(&forum, &forum.user_id, "Reply", &forum.reply_id.to_string()).await,
+            StatusCode::FORBIDDEN
+        );
+        // admins moderate locked posts
+        assert_eq!(
+            react(&forum, &forum.admin_id, "Post", &forum.post_id.to_string()).await,
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use eyre::Context;
+use tokio::sync::RwLockReadGuard;
+
+use crate::api::error::ApiError;
+use crate::db::{
+    database::Database,
+    models::{Id, PostId, ReactionTarget, User},
+};
+use crate::permission_verification;
+
+/// reactions are added and removed under the same rules, the user has to be able to write
+/// in the category and the target has to be neither removed nor locked
+pub async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    target: &ReactionTarget,
+    target_id: &Id,
+) -> Result<(), ApiError> {
+    let post_id: PostId = match target {
+        ReactionTarget::Post => target_id.to_typed(),
+        ReactionTarget::Reply => {
+            let reply = db
+                .reply_from_id(&target_id.to_typed())
+                .await
+                .with_context(|| format!("unable to get reply with id '{}'", target_id))?
+                .filter(|reply| !reply.deleted)
+                .ok_or_else(|| ApiError::not_found("reply not found"))?;
+            reply.post_id
+        }
+    };
+
+    let post = db
+        .post_from_id(&post_id)
+        .await
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .filter(|post| !post.deleted)
+        .ok_or_else(|| ApiError::not_found("post not found"))?;
+
+    if post.locked
+        && !permission_verification::is_allowed(
+            &user.permission,
+            &permission_verification::permission_for_important_actions(),
+        )
+    {
+        return Err(ApiError::permission_denied(
+            "unable to react in locked posts",
+        ));
+    }
+
+    let category = db
+        .category_from_id(&post.category_id)
+        .await
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .filter(|category| !category.deleted)
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
+        let err = format!(
+            "you must be {} or above to react in category {}, you are {}",
+            category.minimum_write_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
@@ -1,17 +1,16 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
-use tokio::sync::RwLockReadGuard;
 
-use crate::{
-    api::response::Message,
-    db::{database::Database, models::Id},
-};
-use crate::{api::response::Response, permission_verification};
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::CategoryId;
+use crate::db::models::User;
+use crate::permission_verification;
 use crate::{
     api::response::{message_response, MessageResponseResult},
     db::{
@@ -22,29 +21,22 @@ use crate::{
 
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
-    id: Id,
+    id: CategoryId,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
-    db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
+fn verify_valid_user_permission(
+    user: &User,
     minimum_read_permission: &Permission,
     minimum_write_permission: &Permission,
-) -> Result<(), Response<Message>> {
+) -> Result<(), ApiError> {
     let category_permission = permission_verification::permission_for_important_actions();
 
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|_| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
     if !permission_verification::is_allowed(&user.permission, &category_permission) {
         let err = format!(
             "you must be {} or above to edit categories, you are {}",
             category_permission, user.permission,
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     if !permission_verification::is_allowed(&user.permission, minimum_write_permission) {
@@ -52,47 +44,40 @@ async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
             "you must be {} or above to edit categories with write permission {}, you are {}",
             minimum_write_permission, minimum_write_permission, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
     if !permission_verification::is_allowed(&user.permission, minimum_read_permission) {
         let err = format!(
             "you must be {} or above to edit categories with read permission {}, you are {}",
             minimum_read_permission, minimum_read_permission, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(200, 400, 403, 500))]
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
     let JsonBody(RouteRequest { id }) = request;
 
-    let creator_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let category = {
         let db = db.read().await;
         let category = db
             .category_from_id(&id)
             .await
-            .map_err(|err| log::error!("unable to get category from database: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+            .with_context(|| "unable to get category from database")?
+            .ok_or_else(|| ApiError::not_found("category not found"))?;
         verify_valid_user_permission(
-            &db,
-            &creator_id,
+            &user,
             &category.minimum_read_permission,
             &category.minimum_write_permission,
-        )
-        .await?;
+        )?;
         category
     };
     {
@@ -102,11 +87,11 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
             title: category.title,
             minimum_write_permission: category.minimum_write_permission,
             minimum_read_permission: category.minimum_read_permission,
+            qa_mode: category.qa_mode,
             deleted: true,
         })
         .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to save post in database")?;
     }
 
     Ok(message_response::ok("deleted"))
@@ -1,65 +1,59 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
 use tokio::sync::RwLockReadGuard;
 
-use crate::{api::response::MessageResponseResult, db::database::EditReply};
-use crate::{api::response::Response, permission_verification};
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::permission_verification;
 use crate::{
-    api::response::{message_response, Message},
+    api::response::message_response,
     db::{
         database::{Database, DatabaseParam},
-        models::Id,
+        models::{PostId, ReplyId, UserId},
     },
 };
+use crate::{
+    api::response::MessageResponseResult,
+    db::database::{EditPost, EditReply},
+};
 
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
     id: String,
 }
 
-async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
     db: &RwLockReadGuard<'_, Db>,
-    user_id: &Id,
-    reply_creator_id: &Id,
-    post_id: &Id,
-) -> Result<(), Response<Message>> {
-    let user = db
-        .user_from_id(user_id)
-        .await
-        .map_err(|err| log::error!("unable to get user with id '{}': {err:?}", user_id))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
-
+    user: &User,
+    reply_creator_id: &UserId,
+    post_id: &PostId,
+) -> Result<(), ApiError> {
     let post = db
         .post_from_id(post_id)
         .await
-        .map_err(|err| log::error!("unable to get post with id '{}': {err:?}", post_id))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid post id"))?;
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .ok_or_else(|| ApiError::not_found("post not found"))?;
 
     let category = db
         .category_from_id(&post.category_id)
         .await
-        .map_err(|err| {
-            log::error!(
-                "unable to get category with id '{}': {err:?}",
-                post.category_id,
-            );
-        })
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
 
     let remove_permission = permission_verification::permission_for_important_actions();
 
-    if reply_creator_id != user_id
+    if *reply_creator_id != user.id
         && !permission_verification::is_allowed(&user.permission, &remove_permission)
     {
-        return Err(message_response::unauthorized("invalid reply id"));
+        return Err(ApiError::permission_denied(
+            "you can only remove your own replies",
+        ));
     }
 
     if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
@@ -67,49 +61,122 @@ async fn verify_valid_user_permission<'a, Db: Database + Sync + Send + ?Sized>(
             "you must be {} or above to create replies in category {}, you are {}",
             category.minimum_write_permission, category.title, user.permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     }
 
     Ok(())
 }
 
-#[salvo::endpoint(status_codes(200, 400, 403, 500))]
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
     let JsonBody(RouteRequest { id }) = request;
 
-    let id = Id::try_from(id).map_err(|_| message_response::bad_request("invalid reply id"))?;
+    let id =
+        ReplyId::try_from(id).map_err(|_| ApiError::validation_failed("id", "invalid reply id"))?;
 
-    let user_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let user = AuthenticatedUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let reply = {
         let db = db.read().await;
         let reply = db
             .reply_from_id(&id)
             .await
-            .map_err(|err| log::error!("unable to get reply from database: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .ok_or_else(|| message_response::bad_request("invalid reply id"))?;
-        verify_valid_user_permission(&db, &user_id, &reply.creator_id, &reply.post_id).await?;
+            .with_context(|| "unable to get reply from database")?
+            .ok_or_else(|| ApiError::not_found("reply not found"))?;
+        verify_valid_user_permission(&db, &user, &reply.creator_id, &reply.post_id).await?;
         reply
     };
     {
         let mut db = db.write().await;
         db.edit_reply(EditReply {
-            id: reply.id,
+            id: reply.id.clone(),
+            quote: reply.quote,
             content: reply.content,
             deleted: true,
         })
         .await
-        .map_err(|err| log::error!("unable to save post in database: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to save post in database")?;
+
+        // a removed reply no longer answers the post, so the post counts as unsolved again
+        let post = db
+            .post_from_id(&reply.post_id)
+            .await
+            .with_context(|| "unable to get post from database")?
+            .ok_or_else(|| ApiError::not_found("post not found"))?;
+        if post.accepted_reply_id.as_ref() == Some(&reply.id) {
+            db.edit_post(EditPost {
+                id: post.id,
+                category_id: post.category_id,
+                title: post.title,
+                content: post.content,
+                deleted: post.deleted,
+                locked: post.locked,
+                pinned: post.pinned,
+                announcement: post.announcement,
+                accepted_reply_id: None,
+            })
+            .await
+            .with_context(|| "unable to save post in database")?;
+        }
+    }
+
+    Ok(message_response::ok("deleted"))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+
+    #[tokio::test]
+    async fn removing_returns_ok() {
+        let forum = TestForum::new().await;
+        let service = forum.service(
+            Some(&forum.user_id),
+            Router::with_path("remove_reply").post(route),
+        );
+
+        let (status, _) = post_json(
+            &service,
+            "remove_reply",
+            &json!({ "id": forum.reply_id.to_string() }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(forum.reply(&forum.reply_id).await.deleted);
     }
 
-    Ok(message_response::created("created"))
+    #[tokio::test]
+    async fn removing_the_accepted_reply_unsolves_the_post() {
+        let forum = TestForum::with_qa_mode(true).await;
+        let other_reply_id = forum.create_reply(&forum.admin_id, "another answer").await;
+        let reply_id = forum.reply_id.clone();
+        forum
+            .edit_post(|post| post.accepted_reply_id = Some(reply_id))
+            .await;
+        let service = forum.service(
+            Some(&forum.admin_id),
+            Router::with_path("remove_reply").post(route),
+        );
+
+        let body = json!({ "id": other_reply_id.to_string() });
+        assert_eq!(
+            post_json(&service, "remove_reply", &body).await.0,
+            StatusCode::OK
+        );
+        assert!(forum.post().await.accepted_reply_id == Some(forum.reply_id.clone()));
+
+        let body = json!({ "id": forum.reply_id.to_string() });
+        assert_eq!(
+            post_json(&service, "remove_reply", &body).await.0,
+            StatusCode::OK
+        );
+        assert!(forum.post().await.accepted_reply_id.is_none());
+    }
 }
@@ -1,69 +1,542 @@
+use std::collections::HashMap;
+
+use crate::api::auth::OptionalUser;
+use crate::api::error::ApiError;
+use crate::from_unchecked::FromUnchecked;
 use crate::{
-    api::response::{message_response, Message, Response},
-    db::models::{Permission, Reply},
+    api::response::Response,
+    db::models::{
+        AttachmentTarget, Content, Name, Permission, Quote, QuoteSource, ReactionCount,
+        ReactionTarget,
+    },
 };
 use crate::{
-    db::{database::DatabaseParam, models::Id},
+    db::{
+        database::{Database, DatabaseParam},
+        models::{AttachmentId, Id, PostId, ReplyId, UserId},
+    },
     permission_verification,
 };
-use salvo::{oapi::extract::PathParam, prelude::ToSchema, session::SessionDepotExt, Depot};
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::{PathParam, QueryParam},
+    prelude::ToSchema,
+    Depot,
+};
 use serde::Serialize;
+use tokio::sync::RwLockReadGuard;
+
+const DEFAULT_MAX_DEPTH: usize = 8;
+/// nesting is built and serialized recursively, so how deep it goes has to stay bounded
+const MAX_DEPTH_LIMIT: usize = 32;
+
+#[derive(Serialize, ToSchema)]
+struct ResponseReply {
+    id: ReplyId,
+    creator_id: UserId,
+    post_id: PostId,
+    parent_reply_id: Option<ReplyId>,
+    quote: Option<ResponseQuote>,
+    content: Content,
+    deleted: bool,
+    date_created: String,
+    date_edited: Option<String>,
+    reactions: Vec<ReactionCount>,
+    attachments: Vec<ResponseAttachment>,
+    children: Vec<ResponseReply>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ResponseAttachment {
+    id: AttachmentId,
+    file_name: String,
+    content_type: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ResponseQuote {
+    source: QuoteSource,
+    source_id: Id,
+    excerpt: Content,
+    author_id: UserId,
+    author_username: Name,
+    date_created: String,
+    /// path of the quoted post or reply in the api, not a web link
+    link: String,
+}
 
 #[derive(Serialize, ToSchema)]
 struct RouteResponse {
     ok: bool,
-    data: Vec<Reply>,
+    data: Vec<ResponseReply>,
+}
+
+/// quotes whose source no longer exists, was deleted, or lives in a category the reader
+/// cannot read, are left out of the response
+async fn resolve_quote<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    permission: &Permission,
+    include_deleted: bool,
+    quote: Quote,
+) -> Result<Option<ResponseQuote>, ApiError> {
+    let reply = match quote.source {
+        QuoteSource::Post => None,
+        QuoteSource::Reply => {
+            let reply = db
+                .reply_from_id(&quote.source_id.to_typed())
+                .await
+                .with_context(|| "unable to get quoted reply")?;
+            match reply.filter(|reply| include_deleted || !reply.deleted) {
+                Some(reply) => Some(reply),
+                None => return Ok(None),
+            }
+        }
+    };
+    let post_id: PostId = reply
+        .as_ref()
+        .map_or_else(|| quote.source_id.to_typed(), |reply| reply.post_id.clone());
+
+    let Some(post) = db
+        .post_from_id(&post_id)
+        .await
+        .with_context(|| "unable to get quoted post")?
+        .filter(|post| include_deleted || !post.deleted)
+    else {
+        return Ok(None);
+    };
+    let Some(category) = db
+        .category_from_id(&post.category_id)
+        .await
+        .with_context(|| "unable to get category of quoted post")?
+        .filter(|category| include_deleted || !category.deleted)
+    else {
+        return Ok(None);
+    };
+
+    if !permission_verification::is_allowed(permission, &category.minimum_read_permission) {
+        return Ok(None);
+    }
+
+    let (creator_id, date_created, link) = match reply {
+        Some(reply) => (
+            reply.creator_id,
+            reply.date_created,
+            format!(
+                "/posts/post_from_id/{}/{}#{}",
+                category.id, post.id, reply.id
+            ),
+        ),
+        None => (
+            post.creator_id,
+            post.date_created,
+            format!("/posts/post_from_id/{}/{}", category.id, post.id),
+        ),
+    };
+
+    let Some(author) = db
+        .user_from_id(&creator_id)
+        .await
+        .with_context(|| "unable to get author of quote")?
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(ResponseQuote {
+        source: quote.source,
+        source_id: quote.source_id,
+        excerpt: quote.excerpt,
+        author_id: author.id,
+        author_username: author.username,
+        date_created,
+        link,
+    }))
+}
+
+fn take_subtree(
+    index: usize,
+    slots: &mut [Option<ResponseReply>],
+    children: &[Vec<usize>],
+) -> Option<ResponseReply> {
+    let mut reply = slots[index].take()?;
+    reply.children = children[index]
+        .iter()
+        .filter_map(|child| take_subtree(*child, slots, children))
+        .collect();
+    Some(reply)
+}
+
+/// replies nested deeper than `max_depth` are attached to their ancestor at `max_depth - 1`,
+/// so they show up as siblings at the deepest allowed level
+fn nest_replies(replies: Vec<ResponseReply>, max_depth: usize) -> Vec<ResponseReply> {
+    let positions: HashMap<String, usize> = replies
+        .iter()
+        .enumerate()
+        .map(|(index, reply)| (reply.id.to_string(), index))
+        .collect();
+
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); replies.len()];
+    let mut roots = Vec::new();
+    for (index, reply) in replies.iter().enumerate() {
+        let parent = reply
+            .parent_reply_id
+            .as_ref()
+            .and_then(|parent_id| positions.get(&parent_id.to_string()));
+        match parent {
+            Some(parent) => children[*parent].push(index),
+            None => roots.push(index),
+        }
+    }
+
+    // walks down from the top level carrying the ancestor at `max_depth - 1`, replies that
+    // are never reached because their parents form a loop stay at the top level
+    let mut effective_parents: Vec<Option<usize>> = vec![None; replies.len()];
+    let mut stack: Vec<(usize, usize, Option<usize>)> =
+        roots.into_iter().map(|root| (root, 0, None)).collect();
+    while let Some((index, depth, anchor)) = stack.pop() {
+        let anchor = if Some(depth) == max_depth.checked_sub(1) {
+            Some(index)
+        } else {
+            anchor
+        };
+        for child in &children[index] {
+            effective_parents[*child] = if depth < max_depth {
+                Some(index)
+            } else {
+                anchor
+            };
+            stack.push((*child, depth + 1, anchor));
+        }
+    }
+
+    let mut nested_children: Vec<Vec<usize>> = vec![Vec::new(); replies.len()];
+    let mut top_level = Vec::new();
+    for (index, parent) in effective_parents.iter().enumerate() {
+        match parent {
+            Some(parent) => nested_children[*parent].push(index),
+            None => top_level.push(index),
+        }
+    }
+
+    let mut slots: Vec<Option<ResponseReply>> = replies.into_iter().map(Some).collect();
+    top_level
+        .into_iter()
+        .filter_map(|index| take_subtree(index, &mut slots, &nested_children))
+        .collect()
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
+#[salvo::endpoint(status_codes(200, 400, 403, 404, 429, 500), security((), ("session" = [])))]
 pub async fn route(
-    post_id: PathParam<Id>,
+    post_id: PathParam<PostId>,
+    nested: QueryParam<bool, false>,
+    max_depth: QueryParam<usize, false>,
+    include_deleted: QueryParam<bool, false>,
     depot: &mut Depot,
-) -> Result<Response<RouteResponse>, Response<Message>> {
-    let user_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"));
+) -> Result<Response<RouteResponse>, ApiError> {
+    let OptionalUser(user) = OptionalUser::from_depot(depot)?;
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let db = db.read().await;
-    let permission = if let Some(user_id) = user_id {
-        db.user_from_id(&user_id)
-            .await
-            .map_err(|err| log::error!("unable to get user from id: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?
-            .map_or(Permission::default(), |user| user.permission)
-    } else {
-        Permission::default()
+    let (user_id, permission) = match user {
+        Some(user) => (Some(user.id), user.permission),
+        None => (None, Permission::default()),
     };
+    let include_deleted = include_deleted.into_inner().unwrap_or(false);
+    let max_depth = max_depth.into_inner().unwrap_or(DEFAULT_MAX_DEPTH);
+    if max_depth > MAX_DEPTH_LIMIT {
+        return Err(ApiError::validation_failed(
+            "max_depth",
+            format!("max_depth must be {MAX_DEPTH_LIMIT} or below"),
+        ));
+    }
+    let deleted_permission = permission_verification::permission_for_important_actions();
+
+    if include_deleted && !permission_verification::is_allowed(&permission, &deleted_permission) {
+        let err = format!(
+            "you must be {} or above to see deleted replies, you are {}",
+            deleted_permission, permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
     let post = db
         .post_from_id(&post_id)
         .await
-        .map_err(|err| log::error!("unable to get post from id: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid post id"))?;
+        .with_context(|| "unable to get post from id")?
+        .filter(|post| include_deleted || !post.deleted)
+        .ok_or_else(|| ApiError::not_found("post not found"))?;
     let category = db
         .category_from_id(&post.category_id)
         .await
-        .map_err(|err| log::error!("unable to get all categories: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid category id"))?;
+        .with_context(|| "unable to get all categories")?
+        .filter(|category| include_deleted || !category.deleted)
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
 
     if !permission_verification::is_allowed(&permission, &category.minimum_read_permission) {
         let err = format!(
             "you must be {} or above to read posts in category {}, you are {}",
             category.minimum_read_permission, category.title, permission
         );
-        return Err(message_response::unauthorized(err));
+        return Err(ApiError::permission_denied(err));
     };
 
-    let data = db
+    let mut replies = db
         .replies_from_post(&post_id)
         .await
-        .map_err(|err| log::error!("unable to get replies from post with id {post_id}: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| format!("unable to get replies from post with id {post_id}"))?;
+
+    if let Some(accepted_reply_id) = &post.accepted_reply_id {
+        replies.sort_by_key(|reply| reply.deleted || &reply.id != accepted_reply_id);
+    }
+
+    let mut data = Vec::with_capacity(replies.len());
+    for reply in replies {
+        // deleted replies stay in the thread so their children keep a parent,
+        // but only admins asking for deleted content see what they said
+        let tombstone = reply.deleted && !include_deleted;
+        let reactions = db
+            .reactions_from_target(
+                &ReactionTarget::Reply,
+                &Id::from(&reply.id),
+                user_id.as_ref(),
+            )
+            .await
+            .with_context(|| format!("unable to get reactions from reply with id {}", reply.id))?;
+
+        let attachments = if tombstone {
+            Vec::new()
+        } else {
+            db.attachments_from_target(&AttachmentTarget::Reply, &Id::from(&reply.id))
+                .await
+                .with_context(|| {
+                    format!("unable to get attachments from reply with id {}", reply.id)
+                })?
+                .into_iter()
+                .map(|attachment| ResponseAttachment {
+                    id: attachment.id,
+                    file_name: attachment.file_name,
+                    content_type: attachment.content_type,
+                })
+                .collect()
+        };
+
+        let quote = match reply.quote {
+            Some(quote) if !tombstone => {
+                resolve_quote(&db, &permission, include_deleted, quote).await?
+            }
+            _ => None,
+        };
+
+        let content = if tombstone {
+            Content::from_unchecked("[deleted]".to_string())
+        } else {
+            reply.content
+        };
+
+        data.push(ResponseReply {
+            id: reply.id,
+            creator_id: reply.creator_id,
+            post_id: reply.post_id,
+            parent_reply_id: reply.parent_reply_id,
+            quote,
+            content,
+            deleted: reply.deleted,
+            date_created: reply.date_created,
+            date_edited: reply.date_edited,
+            reactions,
+            attachments,
+            children: Vec::new(),
+        });
+    }
+
+    let data = if nested.into_inner().unwrap_or(false) {
+        nest_replies(data, max_depth)
+    } else {
+        data
+    };
 
     Ok(Response::with_ok(RouteResponse { data, ok: true }))
 }
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+
+    use super::*;
+    use crate::api::test_forum::{get_json, TestForum};
+    use crate::db::database::CreateReply;
+
+    fn reply(id: &str, parent_reply_id: Option<&str>) -> ResponseReply {
+        ResponseReply {
+            id: ReplyId::from_unchecked(id.to_string()),
+            creator_id: UserId::from_unchecked("creator0".to_string()),
+            post_id: PostId::from_unchecked("post0000".to_string()),
+            parent_reply_id: parent_reply_id.map(|id| ReplyId::from_unchecked(id.to_string())),
+            quote: None,
+            content: Content::from_unchecked("content".to_string()),
+            deleted: false,
+            date_created: String::new(),
+            date_edited: None,
+            reactions: Vec::new(),
+            attachments: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn ids(replies: &[ResponseReply]) -> Vec<String> {
+        replies.iter().map(|reply| reply.id.to_string()).collect()
+    }
+
+    fn depth(replies: &[ResponseReply]) -> usize {
+        replies
+            .iter()
+            .map(|reply| 1 + depth(&reply.children))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn nests_children_under_their_parent_in_order() {
+        let replies = vec![
+            reply("a", None),
+            reply("b", Some("a")),
+            reply("c", None),
+            reply("d", Some("a")),
+            reply("e", Some("b")),
+        ];
+
+        let nested = nest_replies(replies, DEFAULT_MAX_DEPTH);
+
+        assert_eq!(ids(&nested), ["a", "c"]);
+        assert_eq!(ids(&nested[0].children), ["b", "d"]);
+        assert_eq!(ids(&nested[0].children[0].children), ["e"]);
+    }
+
+    #[test]
+    fn replies_with_unknown_parents_are_top_level() {
+        let nested = nest_replies(vec![reply("a", Some("gone")), reply("b", None)], 8);
+
+        assert_eq!(ids(&nested), ["a", "b"]);
+    }
+
+    #[test]
+    fn too_deep_replies_become_siblings_at_the_deepest_level() {
+        let replies = vec![
+            reply("a", None),
+            reply("b", Some("a")),
+            reply("c", Some("b")),
+            reply("d", Some("c")),
+        ];
+
+        let nested = nest_replies(replies, 1);
+
+        assert_eq!(ids(&nested), ["a"]);
+        assert_eq!(ids(&nested[0].children), ["b", "c", "d"]);
+        assert!(nested[0]
+            .children
+            .iter()
+            .all(|child| child.children.is_empty()));
+    }
+
+    #[test]
+    fn max_depth_zero_keeps_every_reply_top_level() {
+        let replies = vec![
+            reply("a", None),
+            reply("b", Some("a")),
+            reply("c", Some("b")),
+        ];
+
+        let nested = nest_replies(replies, 0);
+
+        assert_eq!(ids(&nested), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn replies_in_a_loop_are_kept_at_the_top_level() {
+        let replies = vec![reply("a", Some("b")), reply("b", Some("a"))];
+
+        let nested = nest_replies(replies, 8);
+
+        assert_eq!(ids(&nested), ["a", "b"]);
+    }
+
+    #[test]
+    fn long_chains_are_flattened_at_the_depth_limit() {
+        let replies: Vec<_> = (0..10_000_usize)
+            .map(|index| {
+                let parent = index.checked_sub(1).map(|parent| parent.to_string());
+                reply(&index.to_string(), parent.as_deref())
+            })
+            .collect();
+
+        let nested = nest_replies(replies, MAX_DEPTH_LIMIT);
+
+        assert_eq!(depth(&nested), MAX_DEPTH_LIMIT + 1);
+    }
+
+    /// the forum with its reply removed and a reply to the removed one
+    async fn forum_with_removed_parent() -> (TestForum, ReplyId) {
+        let forum = TestForum::new().await;
+        let child_id = forum
+            .db
+            .write()
+            .await
+            .create_reply(CreateReply {
+                creator_id: forum.admin_id.clone(),
+                post_id: forum.post_id.clone(),
+                parent_reply_id: Some(forum.reply_id.clone()),
+                quote: None,
+                content: Content::from_unchecked("the child".to_string()),
+            })
+            .await
+            .expect("reply should be created");
+        forum.remove_reply(&forum.reply_id).await;
+
+        (forum, child_id)
+    }
+
+    async fn replies(
+        forum: &TestForum,
+        user_id: Option<&UserId>,
+        query: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let service = forum.service(
+            user_id,
+            Router::with_path("replies_from_post/<post_id>").get(route),
+        );
+        get_json(
+            &service,
+            &format!("replies_from_post/{}{query}", forum.post_id),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn removed_replies_leave_a_tombstone_for_their_children() {
+        let (forum, child_id) = forum_with_removed_parent().await;
+
+        let (status, body) = replies(&forum, Some(&forum.user_id), "?nested=true").await;
+        assert_eq!(status, StatusCode::OK);
+        let tombstone = &body["data"][0];
+        assert_eq!(tombstone["id"], forum.reply_id.to_string());
+        assert_eq!(tombstone["deleted"], true);
+        assert_eq!(tombstone["content"], "[deleted]");
+        assert_eq!(tombstone["children"][0]["id"], child_id.to_string());
+        assert_eq!(tombstone["children"][0]["content"], "the child");
+    }
+
+    #[tokio::test]
+    async fn only_admins_see_what_removed_replies_said() {
+        let (forum, _) = forum_with_removed_parent().await;
+
+        let (status, body) = replies(&forum, Some(&forum.admin_id), "?include_deleted=true").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"][0]["content"], "the content of the reply");
+
+        let (status, _) = replies(&forum, Some(&forum.user_id), "?include_deleted=true").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = replies(&forum, None, "?include_deleted=true").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
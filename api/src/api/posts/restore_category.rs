@@ -0,0 +1,153 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::CategoryId;
+use crate::db::models::User;
+use crate::permission_verification;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    db::{
+        database::{DatabaseParam, EditCategory},
+        models::Permission,
+    },
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    id: CategoryId,
+}
+
+fn verify_valid_user_permission(
+    user: &User,
+    minimum_read_permission: &Permission,
+    minimum_write_permission: &Permission,
+) -> Result<(), ApiError> {
+    let category_permission = permission_verification::permission_for_important_actions();
+
+    if !permission_verification::is_allowed(&user.permission, &category_permission) {
+        let err = format!(
+            "you must be {} or above to restore categories, you are {}",
+            category_permission, user.permission,
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    if !permission_verification::is_allowed(&user.permission, minimum_write_permission) {
+        let err = format!(
+            "you must be {} or above to restore categories with write permission {}, you are {}",
+            minimum_write_permission, minimum_write_permission, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+    if !permission_verification::is_allowed(&user.permission, minimum_read_permission) {
+        let err = format!(
+            "you must be {} or above to restore categories with read permission {}, you are {}",
+            minimum_read_permission, minimum_read_permission, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { id }) = request;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let category = {
+        let db = db.read().await;
+        let category = db
+            .category_from_id(&id)
+            .await
+            .with_context(|| "unable to get category from database")?
+            .ok_or_else(|| ApiError::not_found("category not found"))?;
+        verify_valid_user_permission(
+            &user,
+            &category.minimum_read_permission,
+            &category.minimum_write_permission,
+        )?;
+        category
+    };
+    {
+        let mut db = db.write().await;
+        db.edit_category(EditCategory {
+            id: category.id,
+            title: category.title,
+            minimum_write_permission: category.minimum_write_permission,
+            minimum_read_permission: category.minimum_read_permission,
+            qa_mode: category.qa_mode,
+            deleted: false,
+        })
+        .await
+        .with_context(|| "unable to save category in database")?;
+    }
+
+    Ok(message_response::ok("restored"))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::models::UserId;
+
+    async fn restore(forum: &TestForum, user_id: &UserId) -> StatusCode {
+        let service = forum.service(
+            Some(user_id),
+            Router::with_path("restore_category").post(route),
+        );
+        let body = json!({ "id": forum.category_id.to_string() });
+        post_json(&service, "restore_category", &body).await.0
+    }
+
+    async fn remove(forum: &TestForum) {
+        let category = forum.category().await;
+        forum
+            .db
+            .write()
+            .await
+            .edit_category(EditCategory {
+                id: category.id,
+                title: category.title,
+                minimum_write_permission: category.minimum_write_permission,
+                minimum_read_permission: category.minimum_read_permission,
+                qa_mode: category.qa_mode,
+                deleted: true,
+            })
+            .await
+            .expect("category should be saved");
+    }
+
+    #[tokio::test]
+    async fn admins_restore_categories() {
+        let forum = TestForum::new().await;
+        remove(&forum).await;
+
+        assert_eq!(restore(&forum, &forum.admin_id).await, StatusCode::OK);
+        assert!(!forum.category().await.deleted);
+    }
+
+    #[tokio::test]
+    async fn only_admins_restore_categories() {
+        let forum = TestForum::new().await;
+        remove(&forum).await;
+
+        assert_eq!(restore(&forum, &forum.user_id).await, StatusCode::FORBIDDEN);
+        assert!(forum.category().await.deleted);
+    }
+}
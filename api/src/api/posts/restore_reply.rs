@@ -0,0 +1,140 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+use tokio::sync::RwLockReadGuard;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::permission_verification;
+use crate::{
+    api::response::message_response,
+    db::{
+        database::{Database, DatabaseParam},
+        models::{PostId, ReplyId},
+    },
+};
+use crate::{api::response::MessageResponseResult, db::database::EditReply};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    id: String,
+}
+
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    post_id: &PostId,
+) -> Result<(), ApiError> {
+    let restore_permission = permission_verification::permission_for_important_actions();
+
+    if !permission_verification::is_allowed(&user.permission, &restore_permission) {
+        let err = format!(
+            "you must be {} or above to restore replies, you are {}",
+            restore_permission, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    let post = db
+        .post_from_id(post_id)
+        .await
+        .with_context(|| format!("unable to get post with id '{}'", post_id))?
+        .ok_or_else(|| ApiError::not_found("post not found"))?;
+
+    let category = db
+        .category_from_id(&post.category_id)
+        .await
+        .with_context(|| format!("unable to get category with id '{}'", post.category_id))?
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_write_permission) {
+        let err = format!(
+            "you must be {} or above to edit replies in category {}, you are {}",
+            category.minimum_write_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { id }) = request;
+
+    let id =
+        ReplyId::try_from(id).map_err(|_| ApiError::validation_failed("id", "invalid reply id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let reply = {
+        let db = db.read().await;
+        let reply = db
+            .reply_from_id(&id)
+            .await
+            .with_context(|| "unable to get reply from database")?
+            .ok_or_else(|| ApiError::not_found("reply not found"))?;
+        verify_valid_user_permission(&db, &user, &reply.post_id).await?;
+        reply
+    };
+    {
+        let mut db = db.write().await;
+        db.edit_reply(EditReply {
+            id: reply.id,
+            quote: reply.quote,
+            content: reply.content,
+            deleted: false,
+        })
+        .await
+        .with_context(|| "unable to save reply in database")?;
+    }
+
+    Ok(message_response::ok("restored"))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::models::UserId;
+
+    async fn restore(forum: &TestForum, user_id: &UserId) -> StatusCode {
+        let service = forum.service(
+            Some(user_id),
+            Router::with_path("restore_reply").post(route),
+        );
+        let body = json!({ "id": forum.reply_id.to_string() });
+        post_json(&service, "restore_reply", &body).await.0
+    }
+
+    #[tokio::test]
+    async fn admins_restore_replies() {
+        let forum = TestForum::new().await;
+        forum.remove_reply(&forum.reply_id).await;
+
+        assert_eq!(restore(&forum, &forum.admin_id).await, StatusCode::OK);
+        let reply = forum.reply(&forum.reply_id).await;
+        assert!(!reply.deleted);
+        assert_eq!(reply.content.to_string(), "the content of the reply");
+    }
+
+    #[tokio::test]
+    async fn only_admins_restore_replies() {
+        let forum = TestForum::new().await;
+        forum.remove_reply(&forum.reply_id).await;
+
+        assert_eq!(restore(&forum, &forum.user_id).await, StatusCode::FORBIDDEN);
+        assert!(forum.reply(&forum.reply_id).await.deleted);
+    }
+}
@@ -0,0 +1,101 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+use tokio::sync::RwLockReadGuard;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::models::User;
+use crate::permission_verification;
+use crate::{
+    api::response::message_response,
+    db::{
+        database::{Database, DatabaseParam},
+        models::{CategoryId, Id, PostId, SubscriptionLevel, SubscriptionTarget},
+    },
+};
+use crate::{api::response::MessageResponseResult, db::database::CreateSubscription};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    target: SubscriptionTarget,
+    target_id: String,
+    level: SubscriptionLevel,
+}
+
+async fn verify_valid_user_permission<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user: &User,
+    target: &SubscriptionTarget,
+    target_id: &Id,
+) -> Result<(), ApiError> {
+    let category_id: CategoryId = match target {
+        SubscriptionTarget::Category => target_id.to_typed(),
+        SubscriptionTarget::Post => {
+            let post_id: PostId = target_id.to_typed();
+            db.post_from_id(&post_id)
+                .await
+                .with_context(|| format!("unable to get post with id '{}'", post_id))?
+                .filter(|post| !post.deleted)
+                .ok_or_else(|| ApiError::not_found("post not found"))?
+                .category_id
+        }
+    };
+
+    let category = db
+        .category_from_id(&category_id)
+        .await
+        .with_context(|| format!("unable to get category with id '{}'", category_id))?
+        .filter(|category| !category.deleted)
+        .ok_or_else(|| ApiError::not_found("category not found"))?;
+
+    if !permission_verification::is_allowed(&user.permission, &category.minimum_read_permission) {
+        let err = format!(
+            "you must be {} or above to subscribe in category {}, you are {}",
+            category.minimum_read_permission, category.title, user.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    Ok(())
+}
+
+/// changes the level when already subscribed
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest {
+        target,
+        target_id,
+        level,
+    }) = request;
+
+    let target_id = Id::try_from(target_id)
+        .map_err(|_| ApiError::validation_failed("target_id", "invalid target id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let db = db.read().await;
+        verify_valid_user_permission(&db, &user, &target, &target_id).await?;
+    }
+    {
+        let mut db = db.write().await;
+        db.edit_subscription(CreateSubscription {
+            user_id: user.id.clone(),
+            target,
+            target_id,
+            level,
+        })
+        .await
+        .with_context(|| "unable to save subscription in database")?;
+    }
+
+    Ok(message_response::ok("subscribed"))
+}
@@ -0,0 +1,133 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::api::posts::reactions::verify_valid_user_permission;
+use crate::{
+    api::response::message_response,
+    db::{
+        database::DatabaseParam,
+        models::{Id, ReactionKind, ReactionTarget},
+    },
+};
+use crate::{api::response::MessageResponseResult, db::database::RemoveReaction};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    target: ReactionTarget,
+    target_id: String,
+    kind: ReactionKind,
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest {
+        target,
+        target_id,
+        kind,
+    }) = request;
+
+    let target_id = Id::try_from(target_id)
+        .map_err(|_| ApiError::validation_failed("target_id", "invalid target id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let db = db.read().await;
+        verify_valid_user_permission(&db, &user, &target, &target_id).await?;
+    }
+    {
+        let mut db = db.write().await;
+        db.remove_reaction(RemoveReaction {
+            creator_id: user.id.clone(),
+            target,
+            target_id,
+            kind,
+        })
+        .await
+        .with_context(|| "unable to remove reaction from database")?;
+    }
+
+    Ok(message_response::ok("unreacted"))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::{database::CreateReaction, models::UserId};
+
+    async fn unreact(forum: &TestForum, user_id: &UserId) -> StatusCode {
+        let service = forum.service(Some(user_id), Router::with_path("unreact").post(route));
+        let body = json!({
+            "target": "Post",
+            "target_id": forum.post_id.to_string(),
+            "kind": "Heart",
+        });
+        post_json(&service, "unreact", &body).await.0
+    }
+
+    async fn reacted(forum: &TestForum) -> bool {
+        forum
+            .db
+            .read()
+            .await
+            .reactions_from_target(&ReactionTarget::Post, &Id::from(&forum.post_id), None)
+            .await
+            .expect("reactions should load")
+            .iter()
+            .any(|reaction| reaction.count > 0)
+    }
+
+    async fn forum_with_reaction() -> TestForum {
+        let forum = TestForum::new().await;
+        forum
+            .db
+            .write()
+            .await
+            .create_reaction(CreateReaction {
+                creator_id: forum.user_id.clone(),
+                target: ReactionTarget::Post,
+                target_id: Id::from(&forum.post_id),
+                kind: ReactionKind::Heart,
+            })
+            .await
+            .expect("reaction should be saved");
+        forum
+    }
+
+    #[tokio::test]
+    async fn removes_only_the_users_reaction() {
+        let forum = forum_with_reaction().await;
+
+        assert_eq!(unreact(&forum, &forum.admin_id).await, StatusCode::OK);
+        assert!(reacted(&forum).await);
+        assert_eq!(unreact(&forum, &forum.user_id).await, StatusCode::OK);
+        assert!(!reacted(&forum).await);
+        // unreacting again changes nothing
+        assert_eq!(unreact(&forum, &forum.user_id).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_removed_and_locked_posts() {
+        let forum = forum_with_reaction().await;
+
+        forum.edit_post(|post| post.locked = true).await;
+        assert_eq!(unreact(&forum, &forum.user_id).await, StatusCode::FORBIDDEN);
+        forum.edit_post(|post| post.deleted = true).await;
+        assert_eq!(unreact(&forum, &forum.user_id).await, StatusCode::NOT_FOUND);
+        assert!(reacted(&forum).await);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::Response,
+    db::{
+        database::DatabaseParam,
+        models::{Post, PostReadStatus, ReplyId},
+    },
+    permission_verification,
+};
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::QueryParam, prelude::ToSchema, Depot};
+use serde::Serialize;
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 100;
+
+#[derive(Serialize, ToSchema)]
+struct ResponseUnreadPost {
+    post: Post,
+    /// zero for posts that were never opened and have no replies
+    unread_count: i64,
+    opened: bool,
+    first_unread_reply_id: Option<ReplyId>,
+    last_activity: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ResponsePage {
+    posts: Vec<ResponseUnreadPost>,
+    page: i64,
+    per_page: i64,
+    /// the number of unread posts over all pages
+    total: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: ResponsePage,
+}
+
+/// posts with unread replies and posts that were never opened, from every category the user
+/// can read, with the latest activity first, pages start at 1
+#[salvo::endpoint(status_codes(200, 400, 401, 429, 500), security(("session" = [])))]
+pub async fn route(
+    page: QueryParam<i64, false>,
+    per_page: QueryParam<i64, false>,
+    depot: &mut Depot,
+) -> Result<Response<RouteResponse>, ApiError> {
+    let user = AuthenticatedUser::from_depot(depot)?;
+
+    let page = page.into_inner().unwrap_or(1);
+    if page < 1 {
+        return Err(ApiError::validation_failed(
+            "page",
+            "page must be 1 or above",
+        ));
+    }
+
+    let per_page = per_page.into_inner().unwrap_or(DEFAULT_PER_PAGE);
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err(ApiError::validation_failed(
+            "per_page",
+            format!("per_page must be between 1 and {MAX_PER_PAGE}"),
+        ));
+    }
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let categories = db
+        .all_categories()
+        .await
+        .with_context(|| "unable to get all categories")?
+        .into_iter()
+        .filter(|category| !category.deleted)
+        .filter(|category| {
+            permission_verification::is_allowed(&user.permission, &category.minimum_read_permission)
+        });
+
+    let mut read_statuses: HashMap<String, PostReadStatus> = db
+        .read_statuses(&user.id, None)
+        .await
+        .with_context(|| "unable to get read statuses")?
+        .into_iter()
+        .filter(|status| !status.opened || status.unread_count > 0)
+        .map(|status| (status.post_id.to_string(), status))
+        .collect();
+
+    let mut posts = Vec::new();
+    for category in categories {
+        let category_posts = db
+            .posts_from_category(&category.id)
+            .await
+            .with_context(|| {
+                format!("unable to get posts from category with id {}", category.id)
+            })?;
+
+        for post in category_posts.into_iter().filter(|post| !post.deleted) {
+            if let Some(status) = read_statuses.remove(&post.id.to_string()) {
+                posts.push(ResponseUnreadPost {
+                    post,
+                    unread_count: status.unread_count,
+                    opened: status.opened,
+                    first_unread_reply_id: status.first_unread_reply_id,
+                    last_activity: status.last_activity,
+                });
+            }
+        }
+    }
+    posts.sort_by(|a, b| b.last_activity.cmp(&a.last_activity));
+
+    let total = posts.len() as i64;
+    let posts = posts
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page) as usize)
+        .take(per_page as usize)
+        .collect();
+
+    Ok(Response::with_ok(RouteResponse {
+        data: ResponsePage {
+            posts,
+            page,
+            per_page,
+            total,
+        },
+        ok: true,
+    }))
+}
@@ -0,0 +1,51 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::api::response::{message_response, MessageResponseResult};
+use crate::db::{
+    database::DatabaseParam,
+    models::{Id, SubscriptionTarget},
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    target: SubscriptionTarget,
+    target_id: String,
+}
+
+/// works on targets the user can no longer read as well, so they can be cleaned up
+#[salvo::endpoint(status_codes(200, 400, 401, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { target, target_id }) = request;
+
+    let target_id = Id::try_from(target_id)
+        .map_err(|_| ApiError::validation_failed("target_id", "invalid target id"))?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let db = db.read().await;
+        db.subscription(&user.id, &target, &target_id)
+            .await
+            .with_context(|| "unable to get subscription from database")?
+            .ok_or_else(|| ApiError::not_found("subscription not found"))?;
+    }
+    {
+        let mut db = db.write().await;
+        db.remove_subscription(&user.id, &target, &target_id)
+            .await
+            .with_context(|| "unable to remove subscription from database")?;
+    }
+
+    Ok(message_response::ok("unsubscribed"))
+}
@@ -1,4 +1,4 @@
-use crate::db::models::Id;
+use crate::{api::error::ApiError, db::models::Id};
 use salvo::{
     oapi::{self, Components, Operation},
     prelude::{EndpointOutRegister, StatusCode, ToSchema},
@@ -6,8 +6,8 @@ use salvo::{
 };
 use serde::Serialize;
 
-pub type CreatedResponseResult = Result<Response<CreatedWithIdMessage>, Response<Message>>;
-pub type MessageResponseResult = Result<Response<Message>, Response<Message>>;
+pub type CreatedResponseResult = Result<Response<CreatedWithIdMessage>, ApiError>;
+pub type MessageResponseResult = Result<Response<Message>, ApiError>;
 
 pub mod message_response {
     use crate::db::models::Id;
@@ -29,18 +29,17 @@ pub mod message_response {
     }
 
     impl_message_response!(ok, 200, true);
-    impl_message_response!(created, 201, true);
-    impl_message_response!(bad_request, 400, false);
-    impl_message_response!(unauthorized, 403, false);
-    impl_message_response!(internal_server_error, 500, false);
 
-    pub fn created_with_id<S: ToString>(message: S, id: Id) -> Response<CreatedWithIdMessage> {
+    pub fn created_with_id<S: ToString, I: Into<Id>>(
+        message: S,
+        id: I,
+    ) -> Response<CreatedWithIdMessage> {
         Response {
             code: 201,
             data: CreatedWithIdMessage {
                 ok: true,
                 message: message.to_string(),
-                data: id,
+                data: id.into(),
             },
         }
     }
@@ -58,13 +57,8 @@ impl<T: ToSchema> EndpointOutRegister for Response<T> {
     #[inline]
     fn register(components: &mut Components, operation: &mut Operation) {
         let schema = T::to_schema(components);
-        for code in [
-            StatusCode::OK,
-            StatusCode::CREATED,
-            StatusCode::BAD_REQUEST,
-            StatusCode::UNAUTHORIZED,
-            StatusCode::INTERNAL_SERVER_ERROR,
-        ] {
+        // errors are registered by ApiError
+        for code in [StatusCode::OK, StatusCode::CREATED] {
             operation.responses.insert(
                 code.as_str(),
                 salvo::oapi::Response::new(
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use salvo::{
+    affix,
+    http::StatusCode,
+    test::{ResponseExt, TestClient},
+    Router, Service,
+};
+use tokio::sync::RwLock;
+
+use crate::api::auth::LoadTestUser;
+use crate::db::{
+    database::{
+        CreateCategory, CreatePost, CreateReply, CreateUser, Database, DatabaseParam, EditPost,
+        EditReply, EditUser,
+    },
+    models::{
+        Category, CategoryId, Content, Name, Permission, Post, PostId, Reply, ReplyId, Title,
+        UserId,
+    },
+    sqlite::SqliteDb,
+};
+use crate::from_unchecked::FromUnchecked;
+use crate::password::HashedPassword;
+
+/// a category with one post and one reply written by a regular user, and an admin, for
+/// route tests
+pub struct TestForum {
+    pub db: DatabaseParam,
+    pub admin_id: UserId,
+    pub user_id: UserId,
+    pub category_id: CategoryId,
+    pub post_id: PostId,
+    pub reply_id: ReplyId,
+}
+
+impl TestForum {
+    pub async fn new() -> Self {
+        Self::with_qa_mode(false).await
+    }
+
+    pub async fn with_qa_mode(qa_mode: bool) -> Self {
+        let mut db = SqliteDb::in_memory().await;
+        let admin_id = create_user(&mut db, "admin", Permission::Admin).await;
+        let user_id = create_user(&mut db, "user", Permission::User).await;
+        let category_id = db
+            .create_category(CreateCategory {
+                title: Title::from_unchecked("category".to_string()),
+                minimum_write_permission: Permission::User,
+                minimum_read_permission: Permission::default(),
+                qa_mode,
+            })
+            .await
+            .expect("category should be created");
+        let post_id = db
+            .create_post(CreatePost {
+                category_id: category_id.clone(),
+                title: Title::from_unchecked("post".to_string()),
+                content: Content::from_unchecked("the content of the post".to_string()),
+                creator_id: user_id.clone(),
+            })
+            .await
+            .expect("post should be created");
+        let reply_id = db
+            .create_reply(CreateReply {
+                creator_id: user_id.clone(),
+                post_id: post_id.clone(),
+                parent_reply_id: None,
+                quote: None,
+                content: Content::from_unchecked("the content of the reply".to_string()),
+            })
+            .await
+            .expect("reply should be created");
+
+        TestForum {
+            db: Arc::new(RwLock::new(db)),
+            admin_id,
+            user_id,
+            category_id,
+            post_id,
+            reply_id,
+        }
+    }
+
+    pub async fn create_user(&self, username: &str, permission: Permission) -> UserId {
+        create_user(&mut *self.db.write().await, username, permission).await
+    }
+
+    /// another post in the category by the regular user
+    pub async fn create_post(&self, title: &str) -> PostId {
+        self.db
+            .write()
+            .await
+            .create_post(CreatePost {
+                category_id: self.category_id.clone(),
+                title: Title::from_unchecked(title.to_string()),
+                content: Content::from_unchecked("more content".to_string()),
+                creator_id: self.user_id.clone(),
+            })
+            .await
+            .expect("post should be created")
+    }
+
+    /// a reply to the post by the given user
+    pub async fn create_reply(&self, creator_id: &UserId, content: &str) -> ReplyId {
+        self.db
+            .write()
+            .await
+            .create_reply(CreateReply {
+                creator_id: creator_id.clone(),
+                post_id: self.post_id.clone(),
+                parent_reply_id: None,
+                quote: None,
+                content: Content::from_unchecked(content.to_string()),
+            })
+            .await
+            .expect("reply should be created")
+    }
+
+    /// saves the user with the change applied
+    pub async fn edit_user(&self, user_id: &UserId, change: impl FnOnce(&mut EditUser)) {
+        let mut db = self.db.write().await;
+        let user = db
+            .user_from_id(user_id)
+            .await
+            .expect("user should load")
+            .expect("user should exist");
+        let mut edit = EditUser {
+            id: user.id,
+            nickname: user.nickname,
+            password: user.password,
+            permission: user.permission,
+            avatar_id: user.avatar_id,
+            attachment_quota: user.attachment_quota,
+            bio: user.bio,
+            signature: user.signature,
+            location: user.location,
+            website: user.website,
+            deleted: user.deleted,
+        };
+        change(&mut edit);
+        db.edit_user(edit).await.expect("user should be saved");
+    }
+
+    /// saves the post with the change applied
+    pub async fn edit_post(&self, change: impl FnOnce(&mut EditPost)) {
+        self.edit_post_with_id(&self.post_id, change).await;
+    }
+
+    pub async fn edit_post_with_id(&self, post_id: &PostId, change: impl FnOnce(&mut EditPost)) {
+        let post = self.post_with_id(post_id).await;
+        let mut edit = EditPost {
+            id: post.id,
+            category_id: post.category_id,
+            title: post.title,
+            content: post.content,
+            deleted: post.deleted,
+            locked: post.locked,
+            pinned: post.pinned,
+            announcement: post.announcement,
+            accepted_reply_id: post.accepted_reply_id,
+        };
+        change(&mut edit);
+        self.db
+            .write()
+            .await
+            .edit_post(edit)
+            .await
+            .expect("post should be saved");
+    }
+
+    pub async fn remove_reply(&self, reply_id: &ReplyId) {
+        let reply = self.reply(reply_id).await;
+        self.db
+            .write()
+            .await
+            .edit_reply(EditReply {
+                id: reply.id,
+                quote: reply.quote,
+                content: reply.content,
+                deleted: true,
+            })
+            .await
+            .expect("reply should be saved");
+    }
+
+    /// the routes with the database injected and the given user logged in
+    pub fn service(&self, user_id: Option<&UserId>, router: Router) -> Service {
+        Service::new(
+            Router::new()
+                .hoop(affix::inject::<DatabaseParam>(self.db.clone()))
+                .hoop(LoadTestUser(user_id.cloned()))
+                .push(router),
+        )
+    }
+
+    pub async fn category(&self) -> Category {
+        self.db
+            .read()
+            .await
+            .category_from_id(&self.category_id)
+            .await
+            .expect("category should load")
+            .expect("category should exist")
+    }
+
+    pub async fn post(&self) -> Post {
+        self.post_with_id(&self.post_id).await
+    }
+
+    pub async fn post_with_id(&self, post_id: &PostId) -> Post {
+        self.db
+            .read()
+            .await
+            .post_from_id(post_id)
+            .await
+            .expect("post should load")
+            .expect("post should exist")
+    }
+
+    pub async fn reply(&self, reply_id: &ReplyId) -> Reply {
+        self.db
+            .read()
+            .await
+            .reply_from_id(reply_id)
+            .await
+            .expect("reply should load")
+            .expect("reply should exist")
+    }
+}
+
+async fn create_user<Db: Database + ?Sized>(
+    db: &mut Db,
+    username: &str,
+    permission: Permission,
+) -> UserId {
+    db.create_user(CreateUser {
+        username: Name::from_unchecked(username.to_string()),
+        nickname: None,
+        password: HashedPassword::from_unchecked(String::new()),
+        permission,
+        avatar_id: None,
+    })
+    .await
+    .expect("user should be created")
+}
+
+/// posts the json body to the path, returning the status and the json response
+pub async fn post_json(
+    service: &Service,
+    path: &str,
+    body: &serde_json::Value,
+) -> (StatusCode, serde_json::Value) {
+    let mut response = TestClient::post(format!("http://127.0.0.1/{path}"))
+        .json(body)
+        .send(service)
+        .await;
+    let status = response.status_code.expect("response should have a status");
+    let body = response.take_json().await.expect("response should be json");
+
+    (status, body)
+}
+
+/// gets the path, returning the status and the json response
+pub async fn get_json(service: &Service, path: &str) -> (StatusCode, serde_json::Value) {
+    let mut response = TestClient::get(format!("http://127.0.0.1/{path}"))
+        .send(service)
+        .await;
+    let status = response.status_code.expect("response should have a status");
+    let body = response.take_json().await.expect("response should be json");
+
+    (status, body)
+}
@@ -0,0 +1,102 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    db::{
+        database::{DatabaseParam, UsernameTaken},
+        models::Name,
+    },
+    usernames,
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    username: String,
+}
+
+/// the old username keeps pointing at the user, a user can change their username
+/// once per [`usernames::CHANGE_COOLDOWN`]
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 409, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { username }) = request;
+
+    let username: Name = username.try_into().map_err(|err| {
+        ApiError::validation_failed("username", format!("invalid username: {err}"))
+    })?;
+
+    if usernames::is_reserved(&username) {
+        return Err(ApiError::validation_failed(
+            "username",
+            "username is reserved",
+        ));
+    }
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+
+    if user.username == username {
+        return Ok(message_response::ok("success"));
+    }
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let db = db.read().await;
+
+        let last_change = db
+            .last_username_change(&user.id)
+            .await
+            .with_context(|| "unable to read username history from db")?;
+
+        if usernames::is_cooling_down(last_change.as_deref())? {
+            return Err(ApiError::TooManyRequests);
+        }
+
+        // lookups ignore case, so changing only the case of a username finds the user themselves
+        let owner = db
+            .user_from_username(&username)
+            .await
+            .with_context(|| "unable to read username from db")?;
+        let previous_owner = db
+            .user_from_previous_username(&username)
+            .await
+            .with_context(|| "unable to read username history from db")?;
+
+        if owner.is_some_and(|owner| owner.id != user.id)
+            || previous_owner.is_some_and(|previous_owner| previous_owner.id != user.id)
+        {
+            return Err(ApiError::conflict("username is already taken"));
+        }
+
+        let lookalike = db
+            .user_from_confusable_username(&username, Some(&user.id))
+            .await
+            .with_context(|| "unable to read similar usernames from db")?;
+
+        if lookalike.is_some() {
+            return Err(ApiError::conflict(
+                "username is too similar to an existing one",
+            ));
+        }
+    }
+
+    db.write()
+        .await
+        .change_username(&user.id, &username)
+        .await
+        .map_err(|err| match err.downcast_ref::<UsernameTaken>() {
+            Some(_) => ApiError::conflict("username is already taken"),
+            None => err.wrap_err("unable to change username").into(),
+        })?;
+
+    Ok(message_response::ok("success"))
+}
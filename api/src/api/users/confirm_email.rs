@@ -0,0 +1,43 @@
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::QueryParam, Depot};
+
+use crate::api::error::ApiError;
+use crate::api::response::{message_response, MessageResponseResult};
+use crate::db::database::DatabaseParam;
+use crate::email_confirmation::CONFIRMATION_VALIDITY;
+use crate::iso_date_strings::{utc_date_iso_string, utc_date_iso_string_ago};
+
+/// posted from the confirmation link of an email, emails are only sent to the address after
+/// this. it works without logging in so the token is the only proof the address is theirs
+#[salvo::endpoint(status_codes(200, 400, 404, 429, 500))]
+pub async fn route(token: QueryParam<String, true>, depot: &mut Depot) -> MessageResponseResult {
+    let token = token.into_inner();
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let mut db = db.write().await;
+    let settings = db
+        .email_settings_from_confirmation_token(&token)
+        .await
+        .with_context(|| "unable to get email settings from database")?
+        .ok_or_else(|| ApiError::not_found("confirmation link is invalid"))?;
+
+    let expired_before = utc_date_iso_string_ago(CONFIRMATION_VALIDITY)?;
+    if settings
+        .confirmation_sent
+        .is_none_or(|sent| sent < expired_before)
+    {
+        return Err(ApiError::invalid_request(
+            "confirmation link has expired, save the email settings again for a new one",
+        ));
+    }
+
+    // replies from before the address was confirmed are never sent
+    db.confirm_email(&settings.user_id, &utc_date_iso_string())
+        .await
+        .with_context(|| "unable to confirm email in database")?;
+
+    Ok(message_response::ok("email address confirmed"))
+}
@@ -0,0 +1,282 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    session::SessionDepotExt,
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::account_deletion::{self, AccountDeletionConfigParam, UsernamePolicy};
+use crate::api::auth::LoggedInUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    db::database::{DatabaseParam, EditUser},
+    password::Password,
+};
+
+/// what happens to the posts and replies of the deleted account
+#[derive(Deserialize, ToSchema)]
+enum AuthoredContent {
+    /// kept, but credited to a placeholder user
+    Anonymize,
+    /// marked as deleted, only admins can still see it
+    Remove,
+}
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    password: String,
+    authored_content: AuthoredContent,
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest {
+        password,
+        authored_content,
+    }) = request;
+
+    let password = Password::try_from(password)
+        .map_err(|_| ApiError::validation_failed("password", "invalid password"))?;
+
+    let LoggedInUser(user) = LoggedInUser::from_depot(depot)?;
+
+    let is_valid = bcrypt::verify::<String>(password.into(), (&user.password).into())
+        .with_context(|| "unable to verify with bcrypt")?;
+
+    if !is_valid {
+        return Err(ApiError::InvalidCredentials);
+    }
+
+    let username_policy = depot
+        .obtain::<AccountDeletionConfigParam>()
+        .map_err(|err| eyre!("unable to get account deletion config from depot: {err:?}"))?
+        .username_policy;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    {
+        let mut db = db.write().await;
+
+        match authored_content {
+            AuthoredContent::Anonymize => db
+                .anonymize_content_from_creator(&user.id)
+                .await
+                .with_context(|| format!("unable to anonymize content of user {}", user.id))?,
+            AuthoredContent::Remove => db
+                .remove_content_from_creator(&user.id)
+                .await
+                .with_context(|| format!("unable to remove content of user {}", user.id))?,
+        }
+
+        if let UsernamePolicy::Release = username_policy {
+            let released_username = account_deletion::released_username(&user.id)
+                .with_context(|| format!("invalid released username for user {}", user.id))?;
+            db.edit_username(&user.id, &released_username)
+                .await
+                .with_context(|| format!("unable to release username of user {}", user.id))?;
+            db.remove_username_history(&user.id)
+                .await
+                .with_context(|| format!("unable to release old usernames of user {}", user.id))?;
+        }
+
+        db.remove_private_data_from_user(&user.id)
+            .await
+            .with_context(|| format!("unable to remove private data of user {}", user.id))?;
+
+        db.edit_user(EditUser {
+            id: user.id.clone(),
+            nickname: None,
+            password: user.password,
+            permission: user.permission,
+            avatar_id: None,
+            attachment_quota: user.attachment_quota,
+            bio: None,
+            signature: None,
+            location: None,
+            website: None,
+            deleted: true,
+        })
+        .await
+        .with_context(|| format!("unable to delete user {}", user.id))?;
+    }
+
+    if let Some(session) = depot.session_mut() {
+        session.remove("user_id");
+    }
+
+    Ok(message_response::ok("account deleted"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use salvo::{affix, http::StatusCode, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_forum::{post_json, TestForum};
+    use crate::db::database::{
+        CreateNotification, CreateReaction, CreateSubscription, EditEmailSettings, MarkPostRead,
+    };
+    use crate::db::models::{
+        Email, EmailFrequency, NotificationKind, Permission, ReactionKind, ReactionTarget,
+        SubscriptionLevel, SubscriptionTarget, UserId,
+    };
+    use crate::from_unchecked::FromUnchecked;
+    use crate::password::HashedPassword;
+
+    const PASSWORD: &str = "correct horse battery";
+
+    /// gives the user a password that can be checked and the given permission
+    async fn prepare_user(forum: &TestForum, user_id: &UserId, permission: Permission) {
+        let password = bcrypt::hash(PASSWORD, 4).expect("password should hash");
+        forum
+            .edit_user(user_id, |user| {
+                user.password = HashedPassword::from_unchecked(password);
+                user.permission = permission;
+            })
+            .await;
+    }
+
+    /// subscriptions, notifications, a reaction, read state and email settings of the user
+    async fn add_private_data(forum: &TestForum, user_id: &UserId) {
+        let mut db = forum.db.write().await;
+        db.create_subscription(CreateSubscription {
+            user_id: user_id.clone(),
+            target: SubscriptionTarget::Post,
+            target_id: (&forum.post_id).into(),
+            level: SubscriptionLevel::Watching,
+        })
+        .await
+        .expect("subscription should be created");
+        db.create_notification(CreateNotification {
+            user_id: user_id.clone(),
+            kind: NotificationKind::NewReply,
+            post_id: forum.post_id.clone(),
+            reply_id: Some(forum.reply_id.clone()),
+            creator_id: forum.user_id.clone(),
+        })
+        .await
+        .expect("notification should be created");
+        db.create_reaction(CreateReaction {
+            creator_id: user_id.clone(),
+            target: ReactionTarget::Post,
+            target_id: (&forum.post_id).into(),
+            kind: ReactionKind::Heart,
+        })
+        .await
+        .expect("reaction should be created");
+        db.mark_post_read(MarkPostRead {
+            user_id: user_id.clone(),
+            post_id: forum.post_id.clone(),
+            last_read_reply_id: None,
+        })
+        .await
+        .expect("post should be marked read");
+        db.edit_category_visit(user_id, &forum.category_id)
+            .await
+            .expect("visit should be recorded");
+        db.edit_email_settings(EditEmailSettings {
+            user_id: user_id.clone(),
+            email: Email::from_unchecked(format!("{user_id}@example.com")),
+            frequency: EmailFrequency::Daily,
+            unsubscribe_token: format!("unsubscribe {user_id}"),
+            last_sent: String::new(),
+            verified: true,
+            confirmation_token: None,
+            confirmation_sent: None,
+        })
+        .await
+        .expect("email settings should be saved");
+    }
+
+    /// whether any of the data added by [`add_private_data`] is still there
+    async fn private_data_left(forum: &TestForum, user_id: &UserId) -> [bool; 6] {
+        let db = forum.db.read().await;
+        let reactions = db
+            .reactions_from_target(
+                &ReactionTarget::Post,
+                &(&forum.post_id).into(),
+                Some(user_id),
+            )
+            .await
+            .expect("reactions should load");
+
+        [
+            !db.subscriptions_from_user(user_id)
+                .await
+                .expect("subscriptions should load")
+                .is_empty(),
+            !db.notifications_from_user(user_id)
+                .await
+                .expect("notifications should load")
+                .is_empty(),
+            reactions.iter().any(|reaction| reaction.reacted),
+            db.read_status_from_post(user_id, &forum.post_id)
+                .await
+                .expect("read status should load")
+                .is_some_and(|status| status.opened),
+            db.category_visit(user_id, &forum.category_id)
+                .await
+                .expect("visit should load")
+                .is_some(),
+            db.email_settings(user_id)
+                .await
+                .expect("email settings should load")
+                .is_some(),
+        ]
+    }
+
+    async fn delete_account(forum: &TestForum, user_id: &UserId) -> StatusCode {
+        let service = forum.service(
+            Some(user_id),
+            Router::new()
+                .hoop(affix::inject::<AccountDeletionConfigParam>(Arc::new(
+                    account_deletion::AccountDeletionConfig {
+                        username_policy: UsernamePolicy::Reserve,
+                    },
+                )))
+                .push(Router::with_path("delete_account").post(route)),
+        );
+        let body = json!({ "password": PASSWORD, "authored_content": "Anonymize" });
+        post_json(&service, "delete_account", &body).await.0
+    }
+
+    #[tokio::test]
+    async fn removes_the_private_data_of_the_user_only() {
+        let forum = TestForum::new().await;
+        prepare_user(&forum, &forum.user_id, Permission::User).await;
+        add_private_data(&forum, &forum.user_id).await;
+        add_private_data(&forum, &forum.admin_id).await;
+
+        assert_eq!(delete_account(&forum, &forum.user_id).await, StatusCode::OK);
+
+        assert_eq!(private_data_left(&forum, &forum.user_id).await, [false; 6]);
+        assert_eq!(private_data_left(&forum, &forum.admin_id).await, [true; 6]);
+    }
+
+    #[tokio::test]
+    async fn banned_users_delete_their_account() {
+        let forum = TestForum::new().await;
+        prepare_user(&forum, &forum.user_id, Permission::Banned).await;
+        add_private_data(&forum, &forum.user_id).await;
+
+        assert_eq!(delete_account(&forum, &forum.user_id).await, StatusCode::OK);
+
+        assert_eq!(private_data_left(&forum, &forum.user_id).await, [false; 6]);
+        let user = forum
+            .db
+            .read()
+            .await
+            .user_from_id(&forum.user_id)
+            .await
+            .expect("user should load")
+            .expect("user should exist");
+        assert!(user.deleted);
+    }
+}
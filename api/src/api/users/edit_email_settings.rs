@@ -0,0 +1,422 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::email_confirmation;
+use crate::email_digest::EmailDigestConfigParam;
+use crate::iso_date_strings::{utc_date_iso_string, utc_date_iso_string_ago};
+use crate::mail::transport::MailTransportParam;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    db::{
+        database::{DatabaseParam, EditEmailSettings},
+        models::{Email, EmailFrequency},
+    },
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    /// null forgets the email address
+    email: Option<String>,
+    frequency: EmailFrequency,
+}
+
+/// replies from before emails were turned on are never sent, and a new address gets a new
+/// unsubscribe link so links sent to the old one stop working. an address has to be
+/// confirmed from the link mailed to it before anything else is sent there
+#[salvo::endpoint(status_codes(200, 400, 401, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest { email, frequency }) = request;
+
+    let email = email
+        .map(|email| {
+            Email::try_from(email).map_err(|err| {
+                ApiError::validation_failed("email", format!("invalid email: {err}"))
+            })
+        })
+        .transpose()?;
+
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?
+        .clone();
+
+    let Some(email) = email else {
+        let mut db = db.write().await;
+        db.remove_email_settings(&user.id)
+            .await
+            .with_context(|| "unable to remove email settings from database")?;
+
+        return Ok(message_response::ok("email settings removed"));
+    };
+
+    let stored = db
+        .read()
+        .await
+        .email_settings(&user.id)
+        .await
+        .with_context(|| "unable to get email settings from database")?;
+    // confirmations are throttled per user rather than per address, so switching between
+    // addresses or turning emails off and on again sends no more mail than waiting
+    let resent_after = utc_date_iso_string_ago(email_confirmation::RESEND_INTERVAL)?;
+    let last_confirmation_sent = stored
+        .as_ref()
+        .and_then(|stored| stored.confirmation_sent.clone());
+    let sent_recently = last_confirmation_sent
+        .as_ref()
+        .is_some_and(|sent| *sent > resent_after);
+    let current = stored.filter(|current| current.email == email);
+
+    let verified = current.as_ref().is_some_and(|current| current.verified);
+    let unsubscribe_token = match &current {
+        Some(current) => current.unsubscribe_token.clone(),
+        None => Uuid::new_v4().simple().to_string(),
+    };
+    let last_sent = match &current {
+        Some(current) if current.frequency != EmailFrequency::Off => current.last_sent.clone(),
+        _ => utc_date_iso_string(),
+    };
+
+    let pending = current
+        .and_then(|current| current.confirmation_token.zip(current.confirmation_sent))
+        .filter(|(_, sent)| *sent > resent_after);
+    let (confirmation_token, confirmation_sent) = if verified || frequency == EmailFrequency::Off {
+        (None, last_confirmation_sent)
+    } else if let Some((token, sent)) = pending {
+        (Some(token), Some(sent))
+    } else if sent_recently {
+        return Err(ApiError::TooManyRequests);
+    } else {
+        let token = Uuid::new_v4().simple().to_string();
+        send_confirmation(depot, &email, &token).await?;
+        (Some(token), Some(utc_date_iso_string()))
+    };
+    let message = if confirmation_token.is_some() {
+        "email settings edited, confirm the address from the email sent to it"
+    } else {
+        "email settings edited"
+    };
+
+    db.write()
+        .await
+        .edit_email_settings(EditEmailSettings {
+            user_id: user.id.clone(),
+            email,
+            frequency,
+            unsubscribe_token,
+            last_sent,
+            verified,
+            confirmation_token,
+            confirmation_sent,
+        })
+        .await
+        .with_context(|| "unable to save email settings in database")?;
+
+    Ok(message_response::ok(message))
+}
+
+/// sent before the settings are saved, so a failed attempt can be retried right away
+async fn send_confirmation(depot: &Depot, email: &Email, token: &str) -> Result<(), ApiError> {
+    let (Ok(transport), Ok(config)) = (
+        depot.obtain::<MailTransportParam>(),
+        depot.obtain::<EmailDigestConfigParam>(),
+    ) else {
+        return Err(ApiError::invalid_request("this forum does not send emails"));
+    };
+
+    transport
+        .send(email_confirmation::compose(config, email, token)?)
+        .await
+        .with_context(|| "unable to send confirmation email")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use lettre::Message;
+    use salvo::{affix, http::StatusCode, test::TestClient, Router, Service};
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::api::auth::LoadTestUser;
+    use crate::api::users::confirm_email_route;
+    use crate::db::{
+        database::{CreateUser, Database},
+        models::{EmailSettings, Name, Permission, UserId},
+        sqlite::SqliteDb,
+    };
+    use crate::email_digest::EmailDigestConfig;
+    use crate::from_unchecked::FromUnchecked;
+    use crate::mail::transport::{MailTransport, MailTransportError};
+    use crate::password::HashedPassword;
+
+    /// keeps every mail instead of sending it
+    #[derive(Default)]
+    struct RecordingTransport(Mutex<Vec<Message>>);
+
+    #[salvo::async_trait]
+    impl MailTransport for RecordingTransport {
+        async fn send(&self, message: Message) -> Result<(), MailTransportError> {
+            self.0.lock().expect("mails should lock").push(message);
+            Ok(())
+        }
+    }
+
+    struct Forum {
+        db: DatabaseParam,
+        user_id: UserId,
+        mails: Arc<RecordingTransport>,
+    }
+
+    impl Forum {
+        async fn new() -> Self {
+            let mut db = SqliteDb::in_memory().await;
+            let user_id = db
+                .create_user(CreateUser {
+                    username: Name::from_unchecked("reader".to_string()),
+                    nickname: None,
+                    password: HashedPassword::from_unchecked(String::new()),
+                    permission: Permission::User,
+                    avatar_id: None,
+                })
+                .await
+                .expect("user should be created");
+
+            Forum {
+                db: Arc::new(RwLock::new(db)),
+                user_id,
+                mails: Arc::default(),
+            }
+        }
+
+        fn service(&self, sends_mail: bool) -> Service {
+            let mut router = Router::new().hoop(affix::inject::<DatabaseParam>(self.db.clone()));
+            if sends_mail {
+                let config = EmailDigestConfig {
+                    from: "forum@example.com"
+                        .parse()
+                        .expect("address should be valid"),
+                    public_url: "https://api.example.com".to_string(),
+                    frontend_url: "https://forum.example.com".to_string(),
+                };
+                router = router
+                    .hoop(affix::inject::<MailTransportParam>(self.mails.clone()))
+                    .hoop(affix::inject::<EmailDigestConfigParam>(Arc::new(config)));
+            }
+
+            Service::new(
+                router
+                    .hoop(LoadTestUser(Some(self.user_id.clone())))
+                    .push(Router::with_path("edit_email_settings").post(route))
+                    .push(Router::with_path("confirm_email").post(confirm_email_route)),
+            )
+        }
+
+        async fn edit(&self, frequency: &str, sends_mail: bool) -> StatusCode {
+            self.edit_address("reader@example.com", frequency, sends_mail)
+                .await
+        }
+
+        async fn edit_address(&self, email: &str, frequency: &str, sends_mail: bool) -> StatusCode {
+            TestClient::post("http://127.0.0.1/edit_email_settings")
+                .json(&serde_json::json!({
+                    "email": email,
+                    "frequency": frequency,
+                }))
+                .send(&self.service(sends_mail))
+                .await
+                .status_code
+                .expect("response should have a status")
+        }
+
+        async fn confirm(&self, token: &str) -> StatusCode {
+            TestClient::post(format!("http://127.0.0.1/confirm_email?token={token}"))
+                .send(&self.service(true))
+                .await
+                .status_code
+                .expect("response should have a status")
+        }
+
+        async fn settings(&self) -> Option<EmailSettings> {
+            self.db
+                .read()
+                .await
+                .email_settings(&self.user_id)
+                .await
+                .expect("email settings should load")
+        }
+
+        fn mail_count(&self) -> usize {
+            self.mails.0.lock().expect("mails should lock").len()
+        }
+    }
+
+    #[tokio::test]
+    async fn a_new_address_is_sent_a_confirmation_first() {
+        let forum = Forum::new().await;
+
+        assert_eq!(forum.edit("Daily", true).await, StatusCode::OK);
+        assert_eq!(forum.mail_count(), 1);
+        let settings = forum.settings().await.expect("settings should be saved");
+        assert!(!settings.verified);
+        assert!(settings.confirmation_token.is_some());
+        assert!(forum
+            .db
+            .read()
+            .await
+            .email_settings_to_send()
+            .await
+            .expect("email settings should load")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn confirming_turns_emails_on() {
+        let forum = Forum::new().await;
+        assert_eq!(forum.edit("Daily", true).await, StatusCode::OK);
+        let token = forum
+            .settings()
+            .await
+            .and_then(|settings| settings.confirmation_token)
+            .expect("a confirmation should be pending");
+
+        assert_eq!(forum.confirm("unknown").await, StatusCode::NOT_FOUND);
+        assert_eq!(forum.confirm(&token).await, StatusCode::OK);
+        let settings = forum.settings().await.expect("settings should be saved");
+        assert!(settings.verified);
+        assert!(settings.confirmation_token.is_none());
+        assert_eq!(
+            forum
+                .db
+                .read()
+                .await
+                .email_settings_to_send()
+                .await
+                .expect("email settings should load")
+                .len(),
+            1
+        );
+
+        // a confirmed address is not asked again
+        assert_eq!(forum.edit("Weekly", true).await, StatusCode::OK);
+        assert_eq!(forum.mail_count(), 1);
+        assert!(forum
+            .settings()
+            .await
+            .is_some_and(|settings| settings.verified));
+    }
+
+    #[tokio::test]
+    async fn saving_again_keeps_the_pending_confirmation() {
+        let forum = Forum::new().await;
+        assert_eq!(forum.edit("Daily", true).await, StatusCode::OK);
+        let token = forum
+            .settings()
+            .await
+            .and_then(|settings| settings.confirmation_token);
+
+        assert_eq!(forum.edit("Weekly", true).await, StatusCode::OK);
+        assert_eq!(forum.mail_count(), 1);
+        assert_eq!(
+            forum
+                .settings()
+                .await
+                .and_then(|settings| settings.confirmation_token),
+            token
+        );
+    }
+
+    #[tokio::test]
+    async fn confirmations_are_throttled_whatever_the_address() {
+        let forum = Forum::new().await;
+        assert_eq!(forum.edit("Daily", true).await, StatusCode::OK);
+
+        assert_eq!(
+            forum.edit_address("other@example.com", "Daily", true).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(forum.mail_count(), 1);
+        assert!(forum
+            .settings()
+            .await
+            .is_some_and(|settings| settings.email.to_string() == "reader@example.com"));
+
+        // turning emails off and on again does not start over either
+        assert_eq!(forum.edit("Off", true).await, StatusCode::OK);
+        assert_eq!(
+            forum.edit("Daily", true).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(forum.mail_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_confirmations_are_rejected() {
+        let forum = Forum::new().await;
+        assert_eq!(forum.edit("Daily", true).await, StatusCode::OK);
+        let settings = forum.settings().await.expect("settings should be saved");
+        let token = settings
+            .confirmation_token
+            .clone()
+            .expect("a confirmation should be pending");
+        forum
+            .db
+            .write()
+            .await
+            .edit_email_settings(EditEmailSettings {
+                user_id: settings.user_id,
+                email: settings.email,
+                frequency: settings.frequency,
+                unsubscribe_token: settings.unsubscribe_token,
+                last_sent: settings.last_sent,
+                verified: false,
+                confirmation_token: Some(token.clone()),
+                confirmation_sent: Some(
+                    utc_date_iso_string_ago(
+                        email_confirmation::CONFIRMATION_VALIDITY
+                            + email_confirmation::RESEND_INTERVAL,
+                    )
+                    .expect("date should be valid"),
+                ),
+            })
+            .await
+            .expect("email settings should be saved");
+
+        assert_eq!(forum.confirm(&token).await, StatusCode::BAD_REQUEST);
+        assert!(forum
+            .settings()
+            .await
+            .is_some_and(|settings| !settings.verified));
+    }
+
+    #[tokio::test]
+    async fn nothing_is_sent_while_emails_are_off() {
+        let forum = Forum::new().await;
+
+        assert_eq!(forum.edit("Off", false).await, StatusCode::OK);
+        assert_eq!(forum.mail_count(), 0);
+        assert!(forum
+            .settings()
+            .await
+            .is_some_and(|settings| settings.confirmation_token.is_none()));
+    }
+
+    #[tokio::test]
+    async fn needs_mail_to_turn_emails_on() {
+        let forum = Forum::new().await;
+
+        assert_eq!(forum.edit("Daily", false).await, StatusCode::BAD_REQUEST);
+        assert!(forum.settings().await.is_none());
+    }
+}
@@ -1,42 +1,114 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use tokio::sync::RwLockReadGuard;
 
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
 use crate::{
     api::response::{message_response, MessageResponseResult},
     db::{
-        database::{DatabaseParam, EditUser},
-        models::{Id, Name},
+        database::{Database, DatabaseParam, EditUser},
+        models::{AttachmentId, Bio, Link, Location, Name, Signature, UserId},
     },
+    image_processing,
     password::{HashedPassword, Password, PasswordError},
+    text_validation::TextError,
 };
 
 type RequestNickname = Option<String>;
 type RequestAvatarId = Option<String>;
 type RequestPassword = String;
+type RequestProfileField = Option<String>;
 
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
+    #[serde(default, deserialize_with = "deserialize_clearable")]
     nickname: Option<RequestNickname>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
     avatar_id: Option<RequestAvatarId>,
     password: Option<RequestPassword>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    bio: Option<RequestProfileField>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    signature: Option<RequestProfileField>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    location: Option<RequestProfileField>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    website: Option<RequestProfileField>,
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
+/// serde reads an explicit `null` as a missing field, this keeps it apart
+/// so `null` clears the field while leaving it out keeps the current value
+fn deserialize_clearable<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<String>>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+/// `None` leaves the field alone, `Some(None)` clears it
+fn parse_profile_field<T: TryFrom<String, Error = TextError>>(
+    value: Option<RequestProfileField>,
+    field: &'static str,
+) -> Result<Option<Option<T>>, ApiError> {
+    match value {
+        Some(Some(value)) => T::try_from(value)
+            .map(|value| Some(Some(value)))
+            .map_err(|err| ApiError::validation_failed(field, format!("invalid {field}: {err}"))),
+        Some(None) => Ok(Some(None)),
+        None => Ok(None),
+    }
+}
+
+async fn verify_valid_avatar<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    avatar_id: &AttachmentId,
+    user_id: &UserId,
+) -> Result<(), ApiError> {
+    let attachment = db
+        .attachment_from_id(avatar_id)
+        .await
+        .with_context(|| "unable to get attachment from id")?
+        .ok_or_else(|| ApiError::not_found("avatar not found"))?;
+
+    if attachment.creator_id != *user_id {
+        return Err(ApiError::validation_failed(
+            "avatar_id",
+            "avatar must be an attachment uploaded by you",
+        ));
+    }
+
+    if !image_processing::is_image(&attachment.content_type) {
+        return Err(ApiError::validation_failed(
+            "avatar_id",
+            "avatar must be an image",
+        ));
+    }
+
+    Ok(())
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
     let JsonBody(RouteRequest {
         nickname,
         avatar_id,
         password,
+        bio,
+        signature,
+        location,
+        website,
     }) = request;
 
     let nickname = nickname.map(|value| {
         value.map(|value| {
-            Name::try_from(value).map_err(|_| message_response::bad_request("invalid nickname"))
+            Name::try_from(value).map_err(|err| {
+                ApiError::validation_failed("nickname", format!("invalid nickname: {err}"))
+            })
         })
     });
 
@@ -48,7 +120,8 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
 
     let avatar_id = avatar_id.map(|value| {
         value.map(|value| {
-            Id::try_from(value).map_err(|_| message_response::bad_request("invalid avatar id"))
+            AttachmentId::try_from(value)
+                .map_err(|_| ApiError::validation_failed("avatar_id", "invalid avatar id"))
         })
     });
 
@@ -71,32 +144,28 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
                 HashedPassword::try_from(password).map_err(|_| "invalid password")
             })
         })
-        .map(|password| password.map_err(message_response::bad_request));
+        .map(|password| password.map_err(|err| ApiError::validation_failed("password", err)));
 
     let password = match password {
         Some(result) => Some(result?),
         None => None,
     };
 
-    let user_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let bio: Option<Option<Bio>> = parse_profile_field(bio, "bio")?;
+    let signature: Option<Option<Signature>> = parse_profile_field(signature, "signature")?;
+    let location: Option<Option<Location>> = parse_profile_field(location, "location")?;
+    let website: Option<Option<Link>> = parse_profile_field(website, "website")?;
+
+    let AuthenticatedUser(user) = AuthenticatedUser::from_depot(depot)?;
 
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
-    let user = {
+    if let Some(Some(avatar_id)) = &avatar_id {
         let db = db.read().await;
-        let user = db
-            .user_from_id(&user_id)
-            .await
-            .map_err(|err| log::error!("unable to read id from db: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?;
-        user.ok_or_else(|| message_response::bad_request("invalid session"))?
-    };
+        verify_valid_avatar(&db, avatar_id, &user.id).await?;
+    }
 
     {
         let mut db = db.write().await;
@@ -105,13 +174,71 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
             avatar_id: avatar_id.unwrap_or(user.avatar_id),
             nickname: nickname.unwrap_or(user.nickname),
             password: password.unwrap_or(user.password),
+            attachment_quota: user.attachment_quota,
+            bio: bio.unwrap_or(user.bio),
+            signature: signature.unwrap_or(user.signature),
+            location: location.unwrap_or(user.location),
+            website: website.unwrap_or(user.website),
             permission: user.permission,
             deleted: user.deleted,
         })
         .await
-        .map_err(|err| log::error!("unable to edit user: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to edit user")?;
     }
 
     Ok(message_response::ok("success"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> RouteRequest {
+        serde_json::from_str(json).expect("request should deserialize")
+    }
+
+    #[test]
+    fn tells_null_apart_from_missing_fields() {
+        let request = request(r#"{"bio": null, "location": "Utrecht"}"#);
+
+        assert_eq!(request.bio, Some(None));
+        assert_eq!(request.location, Some(Some("Utrecht".to_string())));
+        assert_eq!(request.signature, None);
+        assert_eq!(request.nickname, None);
+    }
+
+    #[test]
+    fn parses_profile_fields() {
+        let website =
+            parse_profile_field::<Link>(Some(Some("https://example.com".to_string())), "website")
+                .expect("website should be valid");
+        assert_eq!(
+            website.map(|website| website.map(|website| website.to_string())),
+            Some(Some("https://example.com".to_string()))
+        );
+
+        assert!(matches!(
+            parse_profile_field::<Location>(Some(None), "location"),
+            Ok(Some(None))
+        ));
+        assert!(matches!(
+            parse_profile_field::<Location>(None, "location"),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_profile_fields() {
+        let result =
+            parse_profile_field::<Link>(Some(Some("javascript:alert(1)".to_string())), "website");
+        assert!(matches!(
+            result,
+            Err(ApiError::ValidationFailed {
+                field: "website",
+                ..
+            })
+        ));
+
+        assert!(parse_profile_field::<Location>(Some(Some(String::new())), "location").is_err());
+    }
+}
@@ -1,68 +1,56 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
-    session::SessionDepotExt,
     Depot,
 };
 use serde::Deserialize;
 
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
 use crate::{
     api::response::{message_response, MessageResponseResult},
     db::{
         database::{DatabaseParam, EditUser},
-        models::{Id, Permission},
+        models::{Permission, UserId},
     },
     permission_verification::{self, permission_for_important_actions},
 };
 
 #[derive(Deserialize, Extractible, ToSchema)]
 struct RouteRequest {
-    id: Id,
+    id: UserId,
     permission: Permission,
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
     let JsonBody(RouteRequest { id, permission }) = request;
 
-    let admin_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+    let admin = AuthenticatedUser::from_depot(depot)?;
 
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
+    if !permission_verification::is_allowed(&admin.permission, &permission_for_important_actions())
+        || !permission_verification::is_allowed(&admin.permission, &permission)
     {
-        let db = db.read().await;
-        let user = db
-            .user_from_id(&admin_id)
-            .await
-            .map_err(|err| log::error!("unable to read id from db: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?;
-        let user = user.ok_or_else(|| message_response::bad_request("invalid session"))?;
-        if !permission_verification::is_allowed(
-            &user.permission,
-            &permission_for_important_actions(),
-        ) || !permission_verification::is_allowed(&user.permission, &permission)
-        {
-            return Err(message_response::unauthorized("invalid session"));
-        }
-    };
-
-    let id = Id::try_from(id).map_err(|_| message_response::bad_request("invalid user id"))?;
+        let err = format!(
+            "you must be {} or above and at least {permission} to change permissions, you are {}",
+            permission_for_important_actions(),
+            admin.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
 
     let user = {
         let db = db.read().await;
         let user = db
             .user_from_id(&id)
             .await
-            .map_err(|err| log::error!("unable to read id from db: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?;
-        let user = user.ok_or_else(|| message_response::bad_request("invalid session"))?;
-        user
+            .with_context(|| "unable to read id from db")?;
+        user.ok_or_else(|| ApiError::not_found("user not found"))?
     };
 
     {
@@ -71,13 +59,17 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Messag
             id,
             avatar_id: user.avatar_id,
             nickname: user.nickname,
+            attachment_quota: user.attachment_quota,
             password: user.password,
             permission,
+            bio: user.bio,
+            signature: user.signature,
+            location: user.location,
+            website: user.website,
             deleted: user.deleted,
         })
         .await
-        .map_err(|err| log::error!("unable to edit user: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to edit user")?;
     }
 
     Ok(message_response::ok("success"))
@@ -0,0 +1,91 @@
+use eyre::{eyre, Context};
+use salvo::{
+    oapi::extract::JsonBody,
+    prelude::{Extractible, ToSchema},
+    Depot,
+};
+use serde::Deserialize;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::{message_response, MessageResponseResult},
+    db::{
+        database::{DatabaseParam, EditUser},
+        models::{AttachmentQuota, UserId},
+    },
+    permission_verification::{self, permission_for_important_actions},
+};
+
+#[derive(Deserialize, Extractible, ToSchema)]
+struct RouteRequest {
+    id: String,
+    /// leaving this out resets the user to the quota of their permission level
+    attachment_quota: Option<AttachmentQuota>,
+}
+
+#[salvo::endpoint(status_codes(200, 400, 401, 403, 404, 429, 500), security(("session" = [])))]
+pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
+    let JsonBody(RouteRequest {
+        id,
+        attachment_quota,
+    }) = request;
+
+    let id =
+        UserId::try_from(id).map_err(|_| ApiError::validation_failed("id", "invalid user id"))?;
+
+    if attachment_quota
+        .as_ref()
+        .is_some_and(|quota| quota.max_bytes < 0 || quota.max_files < 0)
+    {
+        return Err(ApiError::validation_failed(
+            "attachment_quota",
+            "invalid attachment quota: limits cannot be negative",
+        ));
+    }
+
+    let admin = AuthenticatedUser::from_depot(depot)?;
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    if !permission_verification::is_allowed(&admin.permission, &permission_for_important_actions())
+    {
+        let err = format!(
+            "you must be {} or above to change attachment quotas, you are {}",
+            permission_for_important_actions(),
+            admin.permission
+        );
+        return Err(ApiError::permission_denied(err));
+    }
+
+    let user = {
+        let db = db.read().await;
+        db.user_from_id(&id)
+            .await
+            .with_context(|| "unable to read id from db")?
+            .ok_or_else(|| ApiError::not_found("user not found"))?
+    };
+
+    {
+        let mut db = db.write().await;
+        db.edit_user(EditUser {
+            id,
+            avatar_id: user.avatar_id,
+            nickname: user.nickname,
+            attachment_quota,
+            password: user.password,
+            permission: user.permission,
+            bio: user.bio,
+            signature: user.signature,
+            location: user.location,
+            website: user.website,
+            deleted: user.deleted,
+        })
+        .await
+        .with_context(|| "unable to edit user")?;
+    }
+
+    Ok(message_response::ok("success"))
+}
@@ -0,0 +1,50 @@
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::database::DatabaseParam;
+use crate::{
+    api::response::Response,
+    db::models::{Email, EmailFrequency},
+};
+use eyre::{eyre, Context};
+use salvo::{prelude::ToSchema, Depot};
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema)]
+struct ResponseEmailSettings {
+    email: Email,
+    frequency: EmailFrequency,
+    /// nothing is emailed before the address is confirmed
+    verified: bool,
+    date_edited: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    /// null when the user has not given an email address
+    data: Option<ResponseEmailSettings>,
+}
+
+#[salvo::endpoint(status_codes(200, 401, 429, 500), security(("session" = [])))]
+pub async fn route(depot: &mut Depot) -> Result<Response<RouteResponse>, ApiError> {
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let settings = db
+        .read()
+        .await
+        .email_settings(&user.id)
+        .await
+        .with_context(|| format!("unable to get email settings of user with id {}", user.id))?;
+
+    let data = settings.map(|settings| ResponseEmailSettings {
+        email: settings.email,
+        frequency: settings.frequency,
+        verified: settings.verified,
+        date_edited: settings.date_edited,
+    });
+
+    Ok(Response::with_ok(RouteResponse { data, ok: true }))
+}
@@ -0,0 +1,51 @@
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::QueryParam, prelude::ToSchema, Depot};
+use serde::Serialize;
+
+use crate::api::error::ApiError;
+use crate::api::response::Response;
+use crate::db::{
+    database::DatabaseParam,
+    models::{Email, EmailFrequency},
+};
+
+#[derive(Serialize, ToSchema)]
+struct ResponseEmailSettings {
+    email: Email,
+    frequency: EmailFrequency,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: ResponseEmailSettings,
+}
+
+/// what the unsubscribe link of an email would turn off, opening the link changes nothing
+/// since mail scanners and link previews fetch it too, posting to it unsubscribes
+#[salvo::endpoint(status_codes(200, 400, 404, 429, 500))]
+pub async fn route(
+    token: QueryParam<String, true>,
+    depot: &mut Depot,
+) -> Result<Response<RouteResponse>, ApiError> {
+    let token = token.into_inner();
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let settings = db
+        .read()
+        .await
+        .email_settings_from_unsubscribe_token(&token)
+        .await
+        .with_context(|| "unable to get email settings from database")?
+        .ok_or_else(|| ApiError::not_found("unsubscribe link is invalid"))?;
+
+    let data = ResponseEmailSettings {
+        email: settings.email,
+        frequency: settings.frequency,
+    };
+
+    Ok(Response::with_ok(RouteResponse { data, ok: true }))
+}
@@ -0,0 +1,175 @@
+use crate::api::auth::LoggedInUser;
+use crate::api::error::ApiError;
+use crate::db::{
+    database::DatabaseParam,
+    models::{AttachmentId, UserId},
+};
+use crate::{
+    api::response::Response,
+    db::models::{
+        AttachmentLink, AttachmentQuota, Bio, Email, EmailFrequency, Link, Location, Name,
+        Permission, Post, Reply, Signature,
+    },
+};
+use eyre::{eyre, Context};
+use salvo::{prelude::ToSchema, Depot};
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema)]
+struct ResponseProfile {
+    id: UserId,
+    username: Name,
+    nickname: Option<Name>,
+    permission: Permission,
+    avatar_id: Option<AttachmentId>,
+    attachment_quota: Option<AttachmentQuota>,
+    bio: Option<Bio>,
+    signature: Option<Signature>,
+    location: Option<Location>,
+    website: Option<Link>,
+    date_created: String,
+    date_edited: Option<String>,
+    last_active: Option<String>,
+    last_login: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ResponseAttachment {
+    id: AttachmentId,
+    file_name: String,
+    content_type: String,
+    size: i64,
+    link: Option<AttachmentLink>,
+    date_created: String,
+    /// path of the download route in the api, not a web link
+    download_link: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ResponseEmailSettings {
+    email: Email,
+    frequency: EmailFrequency,
+    /// nothing is emailed before the address is confirmed
+    verified: bool,
+    date_edited: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ResponseExport {
+    profile: ResponseProfile,
+    email_settings: Option<ResponseEmailSettings>,
+    posts: Vec<Post>,
+    replies: Vec<Reply>,
+    attachments: Vec<ResponseAttachment>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: ResponseExport,
+}
+
+/// everything stored about the logged in user, deleted posts and replies included
+#[salvo::endpoint(status_codes(200, 401, 429, 500), security(("session" = [])))]
+pub async fn route(depot: &mut Depot) -> Result<Response<RouteResponse>, ApiError> {
+    let LoggedInUser(user) = LoggedInUser::from_depot(depot)?;
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let posts = db
+        .posts_from_creator(&user.id)
+        .await
+        .with_context(|| format!("unable to get posts from user with id {}", user.id))?;
+    let replies = db
+        .replies_from_creator(&user.id)
+        .await
+        .with_context(|| format!("unable to get replies from user with id {}", user.id))?;
+    let attachments = db
+        .attachments_from_creator(&user.id)
+        .await
+        .with_context(|| format!("unable to get attachments from user with id {}", user.id))?
+        .into_iter()
+        .map(|attachment| ResponseAttachment {
+            download_link: format!("/attachments/attachment_from_id/{}", attachment.id),
+            id: attachment.id,
+            file_name: attachment.file_name,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            link: attachment.link,
+            date_created: attachment.date_created,
+        })
+        .collect();
+    let email_settings = db
+        .email_settings(&user.id)
+        .await
+        .with_context(|| format!("unable to get email settings of user with id {}", user.id))?
+        .map(|settings| ResponseEmailSettings {
+            email: settings.email,
+            frequency: settings.frequency,
+            verified: settings.verified,
+            date_edited: settings.date_edited,
+        });
+
+    let data = ResponseExport {
+        profile: ResponseProfile {
+            id: user.id,
+            username: user.username,
+            nickname: user.nickname,
+            permission: user.permission,
+            avatar_id: user.avatar_id,
+            attachment_quota: user.attachment_quota,
+            bio: user.bio,
+            signature: user.signature,
+            location: user.location,
+            website: user.website,
+            date_created: user.date_created,
+            date_edited: user.date_edited,
+            last_active: user.last_active,
+            last_login: user.last_login,
+        },
+        email_settings,
+        posts,
+        replies,
+        attachments,
+    };
+
+    Ok(Response::with_ok(RouteResponse { data, ok: true }))
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{http::StatusCode, Router};
+
+    use super::*;
+    use crate::api::test_forum::{get_json, TestForum};
+
+    #[tokio::test]
+    async fn banned_users_export_their_data() {
+        let forum = TestForum::new().await;
+        forum
+            .edit_user(&forum.user_id, |user| user.permission = Permission::Banned)
+            .await;
+        let service = forum.service(Some(&forum.user_id), Router::with_path("export").get(route));
+
+        let (status, body) = get_json(&service, "export").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["profile"]["id"], forum.user_id.to_string());
+        assert_eq!(body["data"]["posts"][0]["id"], forum.post_id.to_string());
+        assert_eq!(body["data"]["replies"][0]["id"], forum.reply_id.to_string());
+    }
+
+    #[tokio::test]
+    async fn needs_a_login() {
+        let forum = TestForum::new().await;
+        let service = forum.service(None, Router::with_path("export").get(route));
+
+        assert_eq!(
+            get_json(&service, "export").await.0,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
@@ -6,6 +7,7 @@ use salvo::{
 };
 use serde::Deserialize;
 
+use crate::api::error::ApiError;
 use crate::{
     api::response::{message_response, MessageResponseResult},
     db::{database::DatabaseParam, models::Name},
@@ -18,50 +20,47 @@ struct RouteRequest {
     password: String,
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
+#[salvo::endpoint(status_codes(200, 400, 401, 429, 500))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> MessageResponseResult {
     let JsonBody(RouteRequest { username, password }) = request;
 
-    let username =
-        Name::try_from(username).map_err(|_| message_response::bad_request("invalid username"))?;
+    let username = Name::try_from(username)
+        .map_err(|_| ApiError::validation_failed("username", "invalid username"))?;
 
     let password = Password::try_from(password)
-        .map_err(|_| message_response::bad_request("invalid password"))?;
+        .map_err(|_| ApiError::validation_failed("password", "invalid password"))?;
 
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let user = {
         let db = db.read().await;
         let user = db
             .user_from_username(&username)
             .await
-            .map_err(|err| log::error!("unable to read username from db: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?;
-        user.ok_or_else(|| message_response::bad_request("invalid username or password"))?
+            .with_context(|| "unable to read username from db")?;
+        // deleted accounts, including the placeholder for anonymized content, can't log in
+        user.filter(|user| !user.deleted)
+            .ok_or(ApiError::InvalidCredentials)?
     };
     let is_valid = bcrypt::verify::<String>(password.into(), (&user.password).into())
-        .map_err(|err| log::error!("unable to verify with bcrypt: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to verify with bcrypt")?;
 
     if !is_valid {
-        return Err(message_response::bad_request(
-            "invalid username or password",
-        ));
+        return Err(ApiError::InvalidCredentials);
     }
 
+    db.write()
+        .await
+        .edit_last_login(&user.id)
+        .await
+        .with_context(|| format!("unable to edit last login of user {}", user.id))?;
+
     let mut session = Session::new();
     session
-        .insert("user_id", &user.id.to_string())
-        .map_err(|err| {
-            log::error!(
-                "unable to insert user session for user {}: {err:?}",
-                user.id
-            );
-        })
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .insert("user_id", user.id.to_string())
+        .with_context(|| format!("unable to insert user session for user {}", user.id))?;
     depot.set_session(session);
 
     Ok(message_response::ok("success"))
@@ -1,15 +1,43 @@
+mod change_username;
+mod confirm_email;
+mod delete_account;
+mod edit_email_settings;
 mod edit_user;
 mod edit_user_permission;
+mod edit_user_quota;
+mod email_settings;
+mod email_settings_from_unsubscribe_token;
+mod export;
 mod login;
 mod logout;
+mod notifications;
+mod posts_by_user;
 mod register;
+mod replies_by_user;
+mod subscriptions;
+mod unsubscribe_email;
 mod user_from_id;
 mod user_from_session;
+mod user_from_username;
 
+pub use change_username::route as change_username_route;
+pub use confirm_email::route as confirm_email_route;
+pub use delete_account::route as delete_account_route;
+pub use edit_email_settings::route as edit_email_settings_route;
 pub use edit_user::route as edit_user_route;
 pub use edit_user_permission::route as edit_user_permission_route;
+pub use edit_user_quota::route as edit_user_quota_route;
+pub use email_settings::route as email_settings_route;
+pub use email_settings_from_unsubscribe_token::route as email_settings_from_unsubscribe_token_route;
+pub use export::route as export_route;
 pub use login::route as login_route;
 pub use logout::route as logout_route;
+pub use notifications::route as notifications_route;
+pub use posts_by_user::route as posts_by_user_route;
 pub use register::route as register_route;
+pub use replies_by_user::route as replies_by_user_route;
+pub use subscriptions::route as subscriptions_route;
+pub use unsubscribe_email::route as unsubscribe_email_route;
 pub use user_from_id::route as user_from_id_route;
 pub use user_from_session::route as user_from_session_route;
+pub use user_from_username::route as user_from_username_route;
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::Response,
+    db::models::{
+        CategoryId, NotificationId, NotificationKind, Post, PostId, ReplyId, Title, UserId,
+    },
+};
+use crate::{db::database::DatabaseParam, permission_verification};
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::QueryParam, prelude::ToSchema, Depot};
+use serde::Serialize;
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 100;
+
+#[derive(Serialize, ToSchema)]
+struct ResponseNotification {
+    id: NotificationId,
+    kind: NotificationKind,
+    category_id: CategoryId,
+    post_id: PostId,
+    post_title: Title,
+    reply_id: Option<ReplyId>,
+    creator_id: UserId,
+    date_created: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ResponsePage {
+    notifications: Vec<ResponseNotification>,
+    page: i64,
+    per_page: i64,
+    /// the number of notifications over all pages
+    total: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: ResponsePage,
+}
+
+/// newest first, leaving out notifications about deleted posts and categories the user can no
+/// longer read, pages start at 1
+#[salvo::endpoint(status_codes(200, 400, 401, 429, 500), security(("session" = [])))]
+pub async fn route(
+    page: QueryParam<i64, false>,
+    per_page: QueryParam<i64, false>,
+    depot: &mut Depot,
+) -> Result<Response<RouteResponse>, ApiError> {
+    let user = AuthenticatedUser::from_depot(depot)?;
+
+    let page = page.into_inner().unwrap_or(1);
+    if page < 1 {
+        return Err(ApiError::validation_failed(
+            "page",
+            "page must be 1 or above",
+        ));
+    }
+
+    let per_page = per_page.into_inner().unwrap_or(DEFAULT_PER_PAGE);
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err(ApiError::validation_failed(
+            "per_page",
+            format!("per_page must be between 1 and {MAX_PER_PAGE}"),
+        ));
+    }
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let categories: Vec<_> = db
+        .all_categories()
+        .await
+        .with_context(|| "unable to get all categories")?
+        .into_iter()
+        .filter(|category| !category.deleted)
+        .filter(|category| {
+            permission_verification::is_allowed(&user.permission, &category.minimum_read_permission)
+        })
+        .collect();
+
+    let notifications = db
+        .notifications_from_user(&user.id)
+        .await
+        .with_context(|| format!("unable to get notifications of user with id {}", user.id))?;
+
+    let mut visible_posts: HashMap<String, Option<Post>> = HashMap::new();
+    let mut data = Vec::new();
+    for notification in notifications {
+        let key = notification.post_id.to_string();
+        if !visible_posts.contains_key(&key) {
+            let post = db
+                .post_from_id(&notification.post_id)
+                .await
+                .with_context(|| format!("unable to get post with id {}", notification.post_id))?
+                .filter(|post| !post.deleted)
+                .filter(|post| {
+                    categories
+                        .iter()
+                        .any(|category| category.id == post.category_id)
+                });
+            visible_posts.insert(key.clone(), post);
+        }
+        let Some(Some(post)) = visible_posts.get(&key) else {
+            continue;
+        };
+
+        data.push(ResponseNotification {
+            id: notification.id,
+            kind: notification.kind,
+            category_id: post.category_id.clone(),
+            post_id: notification.post_id,
+            post_title: post.title.clone(),
+            reply_id: notification.reply_id,
+            creator_id: notification.creator_id,
+            date_created: notification.date_created,
+        });
+    }
+
+    let total = data.len() as i64;
+    let notifications = data
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page) as usize)
+        .take(per_page as usize)
+        .collect();
+
+    Ok(Response::with_ok(RouteResponse {
+        data: ResponsePage {
+            notifications,
+            page,
+            per_page,
+            total,
+        },
+        ok: true,
+    }))
+}
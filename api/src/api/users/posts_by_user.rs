@@ -0,0 +1,67 @@
+use crate::api::auth::OptionalUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::Response,
+    db::models::{Permission, Post},
+};
+use crate::{
+    db::{database::DatabaseParam, models::UserId},
+    permission_verification,
+};
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::PathParam, prelude::ToSchema, Depot};
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: Vec<Post>,
+}
+
+/// newest first, leaving out deleted posts and posts in categories the reader cannot read
+#[salvo::endpoint(status_codes(200, 400, 404, 429, 500), security((), ("session" = [])))]
+pub async fn route(
+    user_id: PathParam<UserId>,
+    depot: &mut Depot,
+) -> Result<Response<RouteResponse>, ApiError> {
+    let OptionalUser(user) = OptionalUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let permission = user.map_or(Permission::default(), |user| user.permission);
+
+    let creator = db
+        .user_from_id(&user_id)
+        .await
+        .with_context(|| "unable to get user from id")?
+        .ok_or_else(|| ApiError::not_found("user not found"))?;
+
+    let categories: Vec<_> = db
+        .all_categories()
+        .await
+        .with_context(|| "unable to get all categories")?
+        .into_iter()
+        .filter(|category| !category.deleted)
+        .filter(|category| {
+            permission_verification::is_allowed(&permission, &category.minimum_read_permission)
+        })
+        .collect();
+
+    let data = db
+        .posts_from_creator(&creator.id)
+        .await
+        .with_context(|| format!("unable to get posts from user with id {}", creator.id))?
+        .into_iter()
+        .rev()
+        .filter(|post| !post.deleted)
+        .filter(|post| {
+            categories
+                .iter()
+                .any(|category| category.id == post.category_id)
+        })
+        .collect();
+
+    Ok(Response::with_ok(RouteResponse { data, ok: true }))
+}
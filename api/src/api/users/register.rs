@@ -1,3 +1,4 @@
+use eyre::{eyre, Context};
 use salvo::{
     oapi::extract::JsonBody,
     prelude::{Extractible, ToSchema},
@@ -5,13 +6,15 @@ use salvo::{
 };
 use serde::Deserialize;
 
+use crate::api::error::ApiError;
 use crate::{
     api::response::{message_response, CreatedResponseResult},
     db::{
-        database::{CreateUser, DatabaseParam},
+        database::{CreateUser, DatabaseParam, UsernameTaken},
         models::{Name, Permission},
     },
     password::{Password, PasswordError},
+    usernames,
 };
 
 #[derive(Deserialize, Extractible, ToSchema)]
@@ -20,45 +23,74 @@ struct RouteRequest {
     password: String,
 }
 
-#[salvo::endpoint(status_codes(201, 400, 500))]
+#[salvo::endpoint(status_codes(201, 400, 409, 429, 500))]
 pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> CreatedResponseResult {
     let JsonBody(RouteRequest { username, password }) = request;
 
-    let username: Name = username.try_into().map_err(message_response::bad_request)?;
+    let username: Name = username.try_into().map_err(|err| {
+        ApiError::validation_failed("username", format!("invalid username: {err}"))
+    })?;
+
+    if usernames::is_reserved(&username) {
+        return Err(ApiError::validation_failed(
+            "username",
+            "username is reserved",
+        ));
+    }
 
     let password: Password = password.try_into().map_err(|err| {
-        message_response::bad_request(match err {
-            PasswordError::TooShort(_) => "invalid password: too short",
-            PasswordError::TooLong(_) => "invalid password: too long",
-            PasswordError::InvalidCharacters => "invalid password: invalid characters",
-        })
+        ApiError::validation_failed(
+            "password",
+            match err {
+                PasswordError::TooShort(_) => "invalid password: too short",
+                PasswordError::TooLong(_) => "invalid password: too long",
+                PasswordError::InvalidCharacters => "invalid password: invalid characters",
+            },
+        )
     })?;
 
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to obtain database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to obtain database from depot: {err:?}"))?;
 
     {
         let db = db.read().await;
-        let user = db.user_from_username(&username).await.map_err(|err| {
-            log::error!("unable to read username from db: {err:?}");
-            message_response::internal_server_error("internal server error")
-        })?;
+        let user = db
+            .user_from_username(&username)
+            .await
+            .with_context(|| "unable to read username from db")?;
 
         if user.is_some() {
-            return Err(message_response::bad_request("user already exists"));
+            return Err(ApiError::conflict("user already exists"));
+        }
+
+        // old usernames keep pointing at their former owner
+        let previous_owner = db
+            .user_from_previous_username(&username)
+            .await
+            .with_context(|| "unable to read username history from db")?;
+
+        if previous_owner.is_some() {
+            return Err(ApiError::conflict("user already exists"));
+        }
+
+        let lookalike = db
+            .user_from_confusable_username(&username, None)
+            .await
+            .with_context(|| "unable to read similar usernames from db")?;
+
+        if lookalike.is_some() {
+            return Err(ApiError::conflict(
+                "username is too similar to an existing one",
+            ));
         }
     }
     let password = bcrypt::hash::<String>(password.into(), bcrypt::DEFAULT_COST)
-        .map_err(|err| log::error!("unable to hash pw: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .with_context(|| "unable to hash pw")?;
     let id = {
         let mut db = db.write().await;
 
-        let password = password
-            .try_into()
-            .map_err(|_| message_response::bad_request("invalid password length"))?;
+        let password = password.into();
 
         db.create_user(CreateUser {
             username,
@@ -68,8 +100,10 @@ pub async fn route(request: JsonBody<RouteRequest>, depot: &mut Depot) -> Create
             avatar_id: None,
         })
         .await
-        .map_err(|err| log::error!("unable to save user in db: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("error creating post"))?
+        .map_err(|err| match err.downcast_ref::<UsernameTaken>() {
+            Some(_) => ApiError::conflict("user already exists"),
+            None => err.wrap_err("unable to save user in db").into(),
+        })?
     };
 
     Ok(message_response::created_with_id("user created", id))
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use crate::api::auth::OptionalUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::Response,
+    db::models::{Permission, Reply},
+};
+use crate::{
+    db::{database::DatabaseParam, models::UserId},
+    permission_verification,
+};
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::PathParam, prelude::ToSchema, Depot};
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: Vec<Reply>,
+}
+
+/// newest first, leaving out deleted replies and replies to posts the reader cannot see
+#[salvo::endpoint(status_codes(200, 400, 404, 429, 500), security((), ("session" = [])))]
+pub async fn route(
+    user_id: PathParam<UserId>,
+    depot: &mut Depot,
+) -> Result<Response<RouteResponse>, ApiError> {
+    let OptionalUser(user) = OptionalUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let permission = user.map_or(Permission::default(), |user| user.permission);
+
+    let creator = db
+        .user_from_id(&user_id)
+        .await
+        .with_context(|| "unable to get user from id")?
+        .ok_or_else(|| ApiError::not_found("user not found"))?;
+
+    let categories: Vec<_> = db
+        .all_categories()
+        .await
+        .with_context(|| "unable to get all categories")?
+        .into_iter()
+        .filter(|category| !category.deleted)
+        .filter(|category| {
+            permission_verification::is_allowed(&permission, &category.minimum_read_permission)
+        })
+        .collect();
+
+    let replies = db
+        .replies_from_creator(&creator.id)
+        .await
+        .with_context(|| format!("unable to get replies from user with id {}", creator.id))?;
+
+    // most replies of a user are in a handful of posts, so each post is only looked up once
+    let mut visible_posts: HashMap<String, bool> = HashMap::new();
+    let mut data = Vec::new();
+    for reply in replies.into_iter().rev().filter(|reply| !reply.deleted) {
+        let post_id = reply.post_id.to_string();
+        let is_visible = match visible_posts.get(&post_id) {
+            Some(is_visible) => *is_visible,
+            None => {
+                let post = db
+                    .post_from_id(&reply.post_id)
+                    .await
+                    .with_context(|| format!("unable to get post with id {post_id}"))?;
+                let is_visible = post.is_some_and(|post| {
+                    !post.deleted
+                        && categories
+                            .iter()
+                            .any(|category| category.id == post.category_id)
+                });
+                visible_posts.insert(post_id, is_visible);
+                is_visible
+            }
+        };
+
+        if is_visible {
+            data.push(reply);
+        }
+    }
+
+    Ok(Response::with_ok(RouteResponse { data, ok: true }))
+}
@@ -0,0 +1,97 @@
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::{
+    api::response::Response,
+    db::models::{CategoryId, Id, PostId, SubscriptionLevel, SubscriptionTarget, Title},
+};
+use crate::{db::database::DatabaseParam, permission_verification};
+use eyre::{eyre, Context};
+use salvo::{prelude::ToSchema, Depot};
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema)]
+struct ResponseSubscription {
+    target: SubscriptionTarget,
+    target_id: Id,
+    level: SubscriptionLevel,
+    /// the title of the post or category
+    title: Title,
+    category_id: CategoryId,
+    date_created: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: Vec<ResponseSubscription>,
+}
+
+/// newest first, leaving out deleted targets and categories the user can no longer read
+#[salvo::endpoint(status_codes(200, 401, 429, 500), security(("session" = [])))]
+pub async fn route(depot: &mut Depot) -> Result<Response<RouteResponse>, ApiError> {
+    let user = AuthenticatedUser::from_depot(depot)?;
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let categories: Vec<_> = db
+        .all_categories()
+        .await
+        .with_context(|| "unable to get all categories")?
+        .into_iter()
+        .filter(|category| !category.deleted)
+        .filter(|category| {
+            permission_verification::is_allowed(&user.permission, &category.minimum_read_permission)
+        })
+        .collect();
+
+    let subscriptions = db
+        .subscriptions_from_user(&user.id)
+        .await
+        .with_context(|| format!("unable to get subscriptions of user with id {}", user.id))?;
+
+    let mut data = Vec::new();
+    for subscription in subscriptions {
+        let (title, category_id) = match subscription.target {
+            SubscriptionTarget::Category => {
+                let category_id: CategoryId = subscription.target_id.to_typed();
+                let Some(category) = categories
+                    .iter()
+                    .find(|category| category.id == category_id)
+                else {
+                    continue;
+                };
+                (category.title.clone(), category_id)
+            }
+            SubscriptionTarget::Post => {
+                let post_id: PostId = subscription.target_id.to_typed();
+                let post = db
+                    .post_from_id(&post_id)
+                    .await
+                    .with_context(|| format!("unable to get post with id {post_id}"))?;
+                let Some(post) = post.filter(|post| !post.deleted) else {
+                    continue;
+                };
+                if !categories
+                    .iter()
+                    .any(|category| category.id == post.category_id)
+                {
+                    continue;
+                }
+                (post.title, post.category_id)
+            }
+        };
+
+        data.push(ResponseSubscription {
+            target: subscription.target,
+            target_id: subscription.target_id,
+            level: subscription.level,
+            title,
+            category_id,
+            date_created: subscription.date_created,
+        });
+    }
+
+    Ok(Response::with_ok(RouteResponse { data, ok: true }))
+}
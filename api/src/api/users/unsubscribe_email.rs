@@ -0,0 +1,144 @@
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::QueryParam, Depot};
+
+use crate::api::error::ApiError;
+use crate::api::response::{message_response, MessageResponseResult};
+use crate::db::{database::DatabaseParam, models::EmailFrequency};
+
+/// posted to the unsubscribe link of an email, by mail clients that support one click
+/// unsubscribing or after confirming it. it works without logging in so the token is
+/// the only proof of who is unsubscribing
+#[salvo::endpoint(status_codes(200, 400, 404, 429, 500))]
+pub async fn route(token: QueryParam<String, true>, depot: &mut Depot) -> MessageResponseResult {
+    let token = token.into_inner();
+
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let mut db = db.write().await;
+    let settings = db
+        .email_settings_from_unsubscribe_token(&token)
+        .await
+        .with_context(|| "unable to get email settings from database")?
+        .ok_or_else(|| ApiError::not_found("unsubscribe link is invalid"))?;
+
+    db.edit_email_frequency(&settings.user_id, &EmailFrequency::Off)
+        .await
+        .with_context(|| "unable to turn emails off in database")?;
+
+    Ok(message_response::ok("you will no longer get emails"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use salvo::{
+        affix,
+        http::StatusCode,
+        test::{ResponseExt, TestClient},
+        Router, Service,
+    };
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::api::users::email_settings_from_unsubscribe_token_route;
+    use crate::db::{
+        database::{CreateUser, Database, EditEmailSettings},
+        models::{Email, Name, Permission, UserId},
+        sqlite::SqliteDb,
+    };
+    use crate::from_unchecked::FromUnchecked;
+    use crate::password::HashedPassword;
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+    async fn subscribed_user() -> (DatabaseParam, UserId) {
+        let mut db = SqliteDb::in_memory().await;
+        let user_id = db
+            .create_user(CreateUser {
+                username: Name::from_unchecked("reader".to_string()),
+                nickname: None,
+                password: HashedPassword::from_unchecked(String::new()),
+                permission: Permission::User,
+                avatar_id: None,
+            })
+            .await
+            .expect("user should be created");
+        db.edit_email_settings(EditEmailSettings {
+            user_id: user_id.clone(),
+            email: Email::from_unchecked("reader@example.com".to_string()),
+            frequency: EmailFrequency::Daily,
+            unsubscribe_token: TOKEN.to_string(),
+            last_sent: String::new(),
+            verified: true,
+            confirmation_token: None,
+            confirmation_sent: None,
+        })
+        .await
+        .expect("email settings should be saved");
+
+        (Arc::new(RwLock::new(db)), user_id)
+    }
+
+    fn service(db: &DatabaseParam) -> Service {
+        Service::new(
+            Router::new()
+                .hoop(affix::inject::<DatabaseParam>(db.clone()))
+                .push(
+                    Router::with_path("unsubscribe_email")
+                        .get(email_settings_from_unsubscribe_token_route)
+                        .post(route),
+                ),
+        )
+    }
+
+    async fn frequency(db: &DatabaseParam, user_id: &UserId) -> EmailFrequency {
+        db.read()
+            .await
+            .email_settings(user_id)
+            .await
+            .expect("email settings should load")
+            .expect("email settings should exist")
+            .frequency
+    }
+
+    #[tokio::test]
+    async fn opening_the_link_changes_nothing() {
+        let (db, user_id) = subscribed_user().await;
+        let mut response =
+            TestClient::get(format!("http://127.0.0.1/unsubscribe_email?token={TOKEN}"))
+                .send(&service(&db))
+                .await;
+
+        assert_eq!(response.status_code, Some(StatusCode::OK));
+        let body: serde_json::Value = response.take_json().await.expect("body should be json");
+        assert_eq!(body["data"]["email"], "reader@example.com");
+        assert_eq!(frequency(&db, &user_id).await, EmailFrequency::Daily);
+    }
+
+    #[tokio::test]
+    async fn posting_to_the_link_unsubscribes() {
+        let (db, user_id) = subscribed_user().await;
+        let response =
+            TestClient::post(format!("http://127.0.0.1/unsubscribe_email?token={TOKEN}"))
+                .raw_form("List-Unsubscribe=One-Click")
+                .send(&service(&db))
+                .await;
+
+        assert_eq!(response.status_code, Some(StatusCode::OK));
+        assert_eq!(frequency(&db, &user_id).await, EmailFrequency::Off);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_tokens() {
+        let (db, user_id) = subscribed_user().await;
+        let response = TestClient::post("http://127.0.0.1/unsubscribe_email?token=unknown")
+            .send(&service(&db))
+            .await;
+
+        assert_eq!(response.status_code, Some(StatusCode::NOT_FOUND));
+        assert_eq!(frequency(&db, &user_id).await, EmailFrequency::Daily);
+    }
+}
@@ -1,19 +1,31 @@
-use crate::db::{database::DatabaseParam, models::Id};
+use crate::api::error::ApiError;
+use crate::db::{
+    database::DatabaseParam,
+    models::{AttachmentId, UserId},
+};
 use crate::{
-    api::response::{message_response, Message, Response},
-    db::models::{Name, Permission},
+    api::response::Response,
+    db::models::{Bio, Link, Location, Name, Permission, Signature},
 };
+use eyre::{eyre, Context};
 use salvo::{oapi::extract::PathParam, prelude::ToSchema, Depot};
 use serde::Serialize;
 
 #[derive(Serialize, ToSchema)]
 struct ResponseUser {
-    id: Id,
+    id: UserId,
     username: Name,
     nickname: Option<Name>,
     permission: Permission,
-    avatar_id: Option<Id>,
+    avatar_id: Option<AttachmentId>,
+    bio: Option<Bio>,
+    signature: Option<Signature>,
+    location: Option<Location>,
+    website: Option<Link>,
+    post_count: i64,
+    reply_count: i64,
     date_created: String,
+    last_active: Option<String>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -22,23 +34,25 @@ struct RouteResponse {
     data: ResponseUser,
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
+#[salvo::endpoint(status_codes(200, 400, 404, 429, 500))]
 pub async fn route(
-    user_id: PathParam<Id>,
+    user_id: PathParam<UserId>,
     depot: &mut Depot,
-) -> Result<Response<RouteResponse>, Response<Message>> {
+) -> Result<Response<RouteResponse>, ApiError> {
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
 
     let db = db.read().await;
     let user = db
         .user_from_id(&user_id)
         .await
-        .map_err(|err| log::error!("unable to get user from id: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?
-        .ok_or_else(|| message_response::bad_request("invalid user id"))?;
+        .with_context(|| "unable to get user from id")?
+        .ok_or_else(|| ApiError::not_found("user not found"))?;
+    let activity = db
+        .user_activity(&user.id)
+        .await
+        .with_context(|| format!("unable to get activity of user with id {}", user.id))?;
 
     let data = ResponseUser {
         id: user.id,
@@ -46,7 +60,14 @@ pub async fn route(
         nickname: user.nickname,
         permission: user.permission,
         avatar_id: user.avatar_id,
+        bio: user.bio,
+        signature: user.signature,
+        location: user.location,
+        website: user.website,
+        post_count: activity.post_count,
+        reply_count: activity.reply_count,
         date_created: user.date_created,
+        last_active: user.last_active,
     };
 
     Ok(Response::with_ok(RouteResponse { data, ok: true }))
@@ -1,19 +1,33 @@
-use crate::db::{database::DatabaseParam, models::Id};
+use crate::api::auth::AuthenticatedUser;
+use crate::api::error::ApiError;
+use crate::db::{
+    database::DatabaseParam,
+    models::{AttachmentId, UserId},
+};
 use crate::{
-    api::response::{message_response, Message, Response},
-    db::models::{Name, Permission},
+    api::response::Response,
+    db::models::{
+        AttachmentQuota, AttachmentUsage, Bio, Link, Location, Name, Permission, Signature,
+    },
+    permission_verification,
 };
-use salvo::session::SessionDepotExt;
+use eyre::{eyre, Context};
 use salvo::{prelude::ToSchema, Depot};
 use serde::Serialize;
 
 #[derive(Serialize, ToSchema)]
 struct ResponseUser {
-    id: Id,
+    id: UserId,
     username: Name,
     nickname: Option<Name>,
     permission: Permission,
-    avatar_id: Option<Id>,
+    avatar_id: Option<AttachmentId>,
+    attachment_usage: AttachmentUsage,
+    attachment_quota: AttachmentQuota,
+    bio: Option<Bio>,
+    signature: Option<Signature>,
+    location: Option<Location>,
+    website: Option<Link>,
     date_created: String,
 }
 
@@ -23,29 +37,24 @@ struct RouteResponse {
     data: ResponseUser,
 }
 
-#[salvo::endpoint(status_codes(200, 400, 500))]
-pub async fn route(
-    depot: &mut Depot,
-) -> Result<Response<RouteResponse>, Response<Message>> {
-    let user_id = depot
-        .session()
-        .and_then(|session| session.get::<Id>("user_id"))
-        .ok_or_else(|| message_response::unauthorized("invalid session"))?;
+#[salvo::endpoint(status_codes(200, 401, 403, 429, 500), security(("session" = [])))]
+pub async fn route(depot: &mut Depot) -> Result<Response<RouteResponse>, ApiError> {
+    let AuthenticatedUser(user) = AuthenticatedUser::from_depot(depot)?;
 
     let db = depot
         .obtain::<DatabaseParam>()
-        .map_err(|err| log::error!("unable to get database from depot: {err:?}"))
-        .map_err(|()| message_response::internal_server_error("internal server error"))?;
-
-    let user = {
-        let db = db.read().await;
-        let user = db
-            .user_from_id(&user_id)
-            .await
-            .map_err(|err| log::error!("unable to read id from db: {err:?}"))
-            .map_err(|()| message_response::internal_server_error("internal server error"))?;
-        user.ok_or_else(|| message_response::bad_request("invalid session"))?
-    };
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let attachment_usage = db
+        .read()
+        .await
+        .attachment_usage(&user.id)
+        .await
+        .with_context(|| "unable to get attachment usage")?;
+
+    let attachment_quota = user.attachment_quota.unwrap_or_else(|| {
+        permission_verification::attachment_quota_for_permission(&user.permission)
+    });
 
     let data = ResponseUser {
         id: user.id,
@@ -53,6 +62,12 @@ pub async fn route(
         nickname: user.nickname,
         permission: user.permission,
         avatar_id: user.avatar_id,
+        attachment_usage,
+        attachment_quota,
+        bio: user.bio,
+        signature: user.signature,
+        location: user.location,
+        website: user.website,
         date_created: user.date_created,
     };
 
@@ -0,0 +1,61 @@
+use crate::api::error::ApiError;
+use crate::db::{database::DatabaseParam, models::UserId};
+use crate::{api::response::Response, db::models::Name};
+use eyre::{eyre, Context};
+use salvo::{oapi::extract::PathParam, prelude::ToSchema, Depot};
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema)]
+struct ResponseUser {
+    id: UserId,
+    username: Name,
+    /// set when the user was found by a username they used before
+    previous_username: Option<Name>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    ok: bool,
+    data: ResponseUser,
+}
+
+/// resolves a username, current or previous, to a user id
+#[salvo::endpoint(status_codes(200, 400, 404, 429, 500))]
+pub async fn route(
+    username: PathParam<Name>,
+    depot: &mut Depot,
+) -> Result<Response<RouteResponse>, ApiError> {
+    let username = username.into_inner();
+    let db = depot
+        .obtain::<DatabaseParam>()
+        .map_err(|err| eyre!("unable to get database from depot: {err:?}"))?;
+
+    let db = db.read().await;
+    let user = db
+        .user_from_username(&username)
+        .await
+        .with_context(|| "unable to get user from username")?;
+
+    let data = match user {
+        Some(user) => ResponseUser {
+            id: user.id,
+            username: user.username,
+            previous_username: None,
+        },
+        None => {
+            let user = db
+                .user_from_previous_username(&username)
+                .await
+                .with_context(|| "unable to get user from previous username")?
+                .ok_or_else(|| ApiError::not_found("user not found"))?;
+
+            ResponseUser {
+                id: user.id,
+                username: user.username,
+                previous_username: Some(username),
+            }
+        }
+    };
+
+    Ok(Response::with_ok(RouteResponse { data, ok: true }))
+}
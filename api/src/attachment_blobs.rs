@@ -0,0 +1,172 @@
+use std::path::Path;
+
+use eyre::Context;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    db::database::Database,
+    image_processing::{self, ProcessedImage},
+    storage::blob_store::{BlobStore, BlobStoreError},
+};
+
+pub fn hash_bytes(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+pub async fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// blobs are stored under their content hash, so identical uploads share one copy
+pub fn blob_key_from_hash(hash: &str) -> String {
+    format!("sha256/{}/{hash}", &hash[..2])
+}
+
+pub fn hash_from_blob_key(blob_key: &str) -> &str {
+    blob_key.rsplit('/').next().unwrap_or(blob_key)
+}
+
+/// what gets stored for an attachment, images with their resized variants
+pub enum BlobSource<'a> {
+    Image(ProcessedImage),
+    File(&'a Path),
+}
+
+pub async fn store_blob(
+    blob_store: &(dyn BlobStore + Send + Sync),
+    blob_key: &str,
+    source: &BlobSource<'_>,
+) -> Result<(), BlobStoreError> {
+    match source {
+        BlobSource::Image(processed) => {
+            blob_store
+                .put_bytes(blob_key, processed.original.clone())
+                .await?;
+            for (size, data) in &processed.variants {
+                blob_store
+                    .put_bytes(&size.blob_key(blob_key), data.clone())
+                    .await?;
+            }
+            Ok(())
+        }
+        BlobSource::File(path) => blob_store.put(blob_key, path).await,
+    }
+}
+
+/// drops one reference to a blob and deletes the stored data once nothing uses it,
+/// callers hold the database write lock so no upload can pick the blob up meanwhile
+pub async fn release(
+    db: &mut (dyn Database + Send + Sync),
+    blob_store: &(dyn BlobStore + Send + Sync),
+    blob_key: &str,
+) -> Result<(), BlobStoreError> {
+    let ref_count = db.release_blob(blob_key).await?;
+
+    if ref_count <= 0 {
+        image_processing::remove_with_variants(blob_store, blob_key)
+            .await
+            .with_context(|| format!("unable to remove blob '{blob_key}'"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::sqlite::SqliteDb, storage::local::LocalBlobStore};
+
+    #[test]
+    fn keys_blobs_by_hash() {
+        let hash = hash_bytes(b"hello");
+        assert_eq!(
+            hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+
+        let blob_key = blob_key_from_hash(&hash);
+        assert_eq!(blob_key, format!("sha256/2c/{hash}"));
+        assert_eq!(hash_from_blob_key(&blob_key), hash);
+        assert_eq!(
+            hash_from_blob_key(&format!("{blob_key}.thumbnail")),
+            format!("{hash}.thumbnail")
+        );
+    }
+
+    #[tokio::test]
+    async fn hashes_files_like_bytes() {
+        let path =
+            std::env::temp_dir().join(format!("decorum-blob-test-{}", crate::ids::generate_id()));
+        let data = vec![7; 200 * 1024];
+        tokio::fs::write(&path, &data)
+            .await
+            .expect("file should be written");
+
+        let hash = hash_file(&path).await.expect("file should hash");
+        tokio::fs::remove_file(&path)
+            .await
+            .expect("file should be removed");
+        assert_eq!(hash, hash_bytes(&data));
+    }
+
+    #[tokio::test]
+    async fn removes_blobs_once_unreferenced() {
+        let mut db = SqliteDb::in_memory().await;
+        let blob_store = LocalBlobStore::new(
+            std::env::temp_dir().join(format!("decorum-blob-test-{}", crate::ids::generate_id())),
+        );
+        let blob_key = blob_key_from_hash(&hash_bytes(b"shared"));
+        blob_store
+            .put_bytes(&blob_key, "shared".into())
+            .await
+            .expect("blob should be stored");
+
+        assert_eq!(
+            db.acquire_blob(&blob_key, 6)
+                .await
+                .expect("blob should be acquired"),
+            1
+        );
+        assert_eq!(
+            db.acquire_blob(&blob_key, 6)
+                .await
+                .expect("blob should be acquired"),
+            2
+        );
+
+        release(&mut db, &blob_store, &blob_key)
+            .await
+            .expect("blob should be released");
+        assert_eq!(
+            blob_store.size(&blob_key).await.expect("size should load"),
+            Some(6)
+        );
+
+        release(&mut db, &blob_store, &blob_key)
+            .await
+            .expect("blob should be released");
+        assert_eq!(
+            blob_store.size(&blob_key).await.expect("size should load"),
+            None
+        );
+        assert_eq!(
+            db.blob_ref_count(&blob_key)
+                .await
+                .expect("blob should load"),
+            None
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use crate::{
+    attachment_blobs, db::database::DatabaseParam, iso_date_strings::utc_date_iso_string_ago,
+    storage::blob_store::BlobStoreParam,
+};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// removes attachments that are linked to nothing and used as nobody's avatar,
+/// the grace period leaves time to link fresh uploads to the post being written
+pub async fn collect_garbage(
+    db: &DatabaseParam,
+    blob_store: &BlobStoreParam,
+    grace_period: Duration,
+) -> eyre::Result<usize> {
+    let created_before = utc_date_iso_string_ago(grace_period)?;
+
+    // everything happens under a single write lock, so nothing can be linked or
+    // start sharing a blob in between
+    let mut db = db.write().await;
+    let attachments = db.unreferenced_attachments(&created_before).await?;
+    for attachment in &attachments {
+        db.remove_attachment(&attachment.id).await?;
+        attachment_blobs::release(&mut *db, blob_store.as_ref(), &attachment.blob_key).await?;
+    }
+
+    Ok(attachments.len())
+}
+
+pub fn spawn(
+    db: DatabaseParam,
+    blob_store: BlobStoreParam,
+    interval: Duration,
+    grace_period: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match collect_garbage(&db, &blob_store, grace_period).await {
+                Ok(0) => {}
+                Ok(removed) => log::info!("removed {removed} unreferenced attachments"),
+                Err(err) => log::error!("unable to collect unreferenced attachments: {err:?}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,183 @@
+use std::{io, path::Path, sync::Arc};
+
+use tokio::io::AsyncReadExt;
+
+pub const DEFAULT_MAX_SIZE: u64 = 8 * 1024 * 1024;
+pub const DEFAULT_ALLOWED_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+    "text/plain",
+];
+
+pub const DEFAULT_PUBLIC_CACHE_CONTROL: &str = "public, max-age=86400";
+pub const DEFAULT_RESTRICTED_CACHE_CONTROL: &str = "private, no-cache";
+
+const MAX_FILE_NAME_LENGTH: usize = 128;
+const SNIFF_LENGTH: u64 = 8192;
+
+pub struct AttachmentConfig {
+    pub max_size: u64,
+    pub allowed_types: Vec<String>,
+    /// for attachments anyone may read
+    pub public_cache_control: String,
+    /// for attachments in categories that require a permission to read,
+    /// these should not end up in shared caches
+    pub restricted_cache_control: String,
+}
+
+pub type AttachmentConfigParam = Arc<AttachmentConfig>;
+
+impl AttachmentConfig {
+    pub fn is_allowed(&self, content_type: &str) -> bool {
+        self.allowed_types
+            .iter()
+            .any(|allowed| allowed == content_type)
+    }
+}
+
+/// determines the content type from the first bytes of the file,
+/// the type claimed by the client is never trusted
+pub async fn sniff_content_type(path: &Path) -> io::Result<&'static str> {
+    let mut head = Vec::new();
+    tokio::fs::File::open(path)
+        .await?
+        .take(SNIFF_LENGTH)
+        .read_to_end(&mut head)
+        .await?;
+
+    if let Some(kind) = infer::get(&head) {
+        return Ok(kind.mime_type());
+    }
+
+    // a multi-byte character cut off at the end of the sniffed bytes is still text
+    let is_text = !head.contains(&0)
+        && match std::str::from_utf8(&head) {
+            Ok(_) => true,
+            Err(err) => err.error_len().is_none(),
+        };
+
+    if is_text {
+        Ok("text/plain")
+    } else {
+        Ok("application/octet-stream")
+    }
+}
+
+/// strips any directory components and replaces everything but ascii letters,
+/// digits, '.', '-' and '_', so the name is safe in storage keys and headers
+pub fn sanitize_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+
+    let name = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    let name = name
+        .trim_start_matches('.')
+        .chars()
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect::<String>();
+
+    if name.chars().all(|c| c == '_' || c == '.') {
+        "file".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::generate_id;
+
+    async fn sniff(data: &[u8]) -> &'static str {
+        let path = std::env::temp_dir().join(format!("decorum-sniff-test-{}", generate_id()));
+        tokio::fs::write(&path, data)
+            .await
+            .expect("write temp file");
+        let content_type = sniff_content_type(&path).await.expect("sniff temp file");
+        tokio::fs::remove_file(&path)
+            .await
+            .expect("remove temp file");
+        content_type
+    }
+
+    #[tokio::test]
+    async fn sniffs_known_signatures_over_the_file_name() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(sniff(png).await, "image/png");
+        assert_eq!(sniff(b"%PDF-1.7\n").await, "application/pdf");
+    }
+
+    #[tokio::test]
+    async fn sniffs_utf8_without_nul_bytes_as_text() {
+        assert_eq!(sniff("hello, wörld".as_bytes()).await, "text/plain");
+        assert_eq!(sniff(b"text\0with a nul").await, "application/octet-stream");
+        assert_eq!(
+            sniff(b"\xff\xfe\xfd invalid").await,
+            "application/octet-stream"
+        );
+    }
+
+    #[tokio::test]
+    async fn text_cut_inside_a_character_at_the_sniff_length_is_still_text() {
+        let mut data = vec![b'a'; SNIFF_LENGTH as usize - 1];
+        data.extend("é".as_bytes());
+
+        assert_eq!(sniff(&data).await, "text/plain");
+    }
+
+    #[test]
+    fn only_listed_types_are_allowed() {
+        let config = AttachmentConfig {
+            max_size: DEFAULT_MAX_SIZE,
+            allowed_types: vec!["image/png".to_string()],
+            public_cache_control: DEFAULT_PUBLIC_CACHE_CONTROL.to_string(),
+            restricted_cache_control: DEFAULT_RESTRICTED_CACHE_CONTROL.to_string(),
+        };
+
+        assert!(config.is_allowed("image/png"));
+        assert!(!config.is_allowed("image/jpeg"));
+        assert!(!config.is_allowed("image/png; charset=utf-8"));
+    }
+
+    #[test]
+    fn sanitize_file_name_drops_directories() {
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_file_name("C:\\Users\\me\\photo.jpg"), "photo.jpg");
+    }
+
+    #[test]
+    fn sanitize_file_name_replaces_unsafe_characters() {
+        assert_eq!(sanitize_file_name("my photo (1).png"), "my_photo__1_.png");
+        assert_eq!(sanitize_file_name("naïve\r\n.txt"), "na_ve__.txt");
+    }
+
+    #[test]
+    fn sanitize_file_name_strips_leading_dots() {
+        assert_eq!(sanitize_file_name(".htaccess"), "htaccess");
+        assert_eq!(sanitize_file_name("..hidden.txt"), "hidden.txt");
+    }
+
+    #[test]
+    fn sanitize_file_name_falls_back_when_nothing_is_left() {
+        assert_eq!(sanitize_file_name(""), "file");
+        assert_eq!(sanitize_file_name("..."), "file");
+        assert_eq!(sanitize_file_name("???"), "file");
+        assert_eq!(sanitize_file_name("dir/"), "file");
+    }
+
+    #[test]
+    fn sanitize_file_name_limits_the_length() {
+        let name = sanitize_file_name(&"a".repeat(500));
+
+        assert_eq!(name.len(), MAX_FILE_NAME_LENGTH);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{path::PathBuf, sync::Arc};
+use std::sync::Arc;
 
 use salvo::async_trait;
 use tokio::sync::RwLock;
@@ -6,99 +6,376 @@ use tokio::sync::RwLock;
 use crate::password::HashedPassword;
 
 use super::models::{
-    Attachment, Category, Content, Id, Name, Permission, Post, Reply, Title, User,
+    Attachment, AttachmentId, AttachmentLink, AttachmentQuota, AttachmentTarget, AttachmentUsage,
+    Bio, Category, CategoryId, Content, Email, EmailFrequency, EmailSettings, Id, Link, Location,
+    Name, Notification, NotificationKind, Permission, Post, PostId, PostReadStatus, Quote,
+    ReactionCount, ReactionKind, ReactionTarget, Reply, ReplyId, Signature, Subscription,
+    SubscriptionLevel, SubscriptionTarget, Title, User, UserActivity, UserId, UserSort,
+    UserSummary,
 };
 
 pub type DatabaseError = eyre::Report;
 
 pub type DatabaseParam = Arc<RwLock<dyn Database + Send + Sync>>;
 
+/// returned instead of a generic error when a username is already in use,
+/// which is checked case insensitively by the database itself
+#[derive(Debug)]
+pub struct UsernameTaken;
+
+impl std::fmt::Display for UsernameTaken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "username is already taken")
+    }
+}
+
+impl std::error::Error for UsernameTaken {}
+
 pub struct CreateUser {
     pub username: Name,
     pub nickname: Option<Name>,
     pub password: HashedPassword,
     pub permission: Permission,
-    pub avatar_id: Option<Id>,
+    pub avatar_id: Option<AttachmentId>,
 }
 
-pub struct CreateAttachment<'a> {
-    pub creator_id: Id,
-    pub file_name: &'a str,
-    pub temp_path: &'a PathBuf,
+pub struct CreateAttachment {
+    pub creator_id: UserId,
+    pub blob_key: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
 }
 
 pub struct CreatePost {
-    pub category_id: Id,
+    pub category_id: CategoryId,
     pub title: Title,
     pub content: Content,
-    pub creator_id: Id,
+    pub creator_id: UserId,
 }
 
 pub struct EditPost {
-    pub id: Id,
-    pub category_id: Id,
+    pub id: PostId,
+    pub category_id: CategoryId,
     pub title: Title,
     pub content: Content,
     pub deleted: bool,
     pub locked: bool,
+    pub pinned: bool,
+    pub announcement: bool,
+    pub accepted_reply_id: Option<ReplyId>,
 }
 
 pub struct CreateCategory {
     pub title: Title,
     pub minimum_write_permission: Permission,
     pub minimum_read_permission: Permission,
+    pub qa_mode: bool,
 }
 
 pub struct EditCategory {
-    pub id: Id,
+    pub id: CategoryId,
     pub title: Title,
     pub minimum_write_permission: Permission,
     pub minimum_read_permission: Permission,
+    pub qa_mode: bool,
     pub deleted: bool,
 }
 
 pub struct CreateReply {
-    pub creator_id: Id,
-    pub post_id: Id,
+    pub creator_id: UserId,
+    pub post_id: PostId,
+    pub parent_reply_id: Option<ReplyId>,
+    pub quote: Option<Quote>,
     pub content: Content,
 }
 
 pub struct EditReply {
-    pub id: Id,
+    pub id: ReplyId,
+    pub quote: Option<Quote>,
     pub content: Content,
     pub deleted: bool,
 }
 
+pub struct CreateReaction {
+    pub creator_id: UserId,
+    pub target: ReactionTarget,
+    pub target_id: Id,
+    pub kind: ReactionKind,
+}
+
+pub struct RemoveReaction {
+    pub creator_id: UserId,
+    pub target: ReactionTarget,
+    pub target_id: Id,
+    pub kind: ReactionKind,
+}
+
 pub struct EditUser {
-    pub id: Id,
+    pub id: UserId,
     pub nickname: Option<Name>,
     pub password: HashedPassword,
     pub permission: Permission,
-    pub avatar_id: Option<Id>,
+    pub avatar_id: Option<AttachmentId>,
+    pub attachment_quota: Option<AttachmentQuota>,
+    pub bio: Option<Bio>,
+    pub signature: Option<Signature>,
+    pub location: Option<Location>,
+    pub website: Option<Link>,
     pub deleted: bool,
 }
 
+pub struct SearchUsers {
+    /// matched against any part of usernames and nicknames, case insensitively
+    pub search: Option<String>,
+    pub permission: Option<Permission>,
+    pub deleted: Option<bool>,
+    pub sort: UserSort,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+pub struct MarkPostRead {
+    pub user_id: UserId,
+    pub post_id: PostId,
+    /// the latest reply of the post when missing
+    pub last_read_reply_id: Option<ReplyId>,
+}
+
+pub struct CreateSubscription {
+    pub user_id: UserId,
+    pub target: SubscriptionTarget,
+    pub target_id: Id,
+    pub level: SubscriptionLevel,
+}
+
+pub struct CreateNotification {
+    pub user_id: UserId,
+    pub kind: NotificationKind,
+    pub post_id: PostId,
+    pub reply_id: Option<ReplyId>,
+    pub creator_id: UserId,
+}
+
+pub struct EditEmailSettings {
+    pub user_id: UserId,
+    pub email: Email,
+    pub frequency: EmailFrequency,
+    pub unsubscribe_token: String,
+    pub last_sent: String,
+    pub verified: bool,
+    pub confirmation_token: Option<String>,
+    pub confirmation_sent: Option<String>,
+}
+
+pub struct EditAttachment {
+    pub id: AttachmentId,
+    pub link: Option<AttachmentLink>,
+}
+
 #[async_trait]
 pub trait Database {
-    async fn create_user(&mut self, data: CreateUser) -> Result<Id, DatabaseError>;
-    async fn create_category(&mut self, data: CreateCategory) -> Result<Id, DatabaseError>;
-    async fn create_post(&mut self, data: CreatePost) -> Result<Id, DatabaseError>;
-    async fn create_reply(&mut self, data: CreateReply) -> Result<Id, DatabaseError>;
-    async fn create_attachment<'a>(
+    /// fails with [`UsernameTaken`] if the username is in use
+    async fn create_user(&mut self, data: CreateUser) -> Result<UserId, DatabaseError>;
+    async fn create_category(&mut self, data: CreateCategory) -> Result<CategoryId, DatabaseError>;
+    async fn create_post(&mut self, data: CreatePost) -> Result<PostId, DatabaseError>;
+    async fn create_reply(&mut self, data: CreateReply) -> Result<ReplyId, DatabaseError>;
+    async fn create_attachment(
         &mut self,
-        data: CreateAttachment<'a>,
-    ) -> Result<Id, DatabaseError>;
-    async fn user_from_id(&self, id: &Id) -> Result<Option<User>, DatabaseError>;
+        data: CreateAttachment,
+    ) -> Result<AttachmentId, DatabaseError>;
+    async fn create_reaction(&mut self, data: CreateReaction) -> Result<(), DatabaseError>;
+    async fn remove_reaction(&mut self, data: RemoveReaction) -> Result<(), DatabaseError>;
+    async fn remove_attachment(&mut self, id: &AttachmentId) -> Result<(), DatabaseError>;
+    /// adds a reference to a stored blob, creating its row if needed, and returns the new count
+    async fn acquire_blob(&mut self, key: &str, size: i64) -> Result<i64, DatabaseError>;
+    /// drops a reference to a stored blob, removing its row at zero, and returns the new count
+    async fn release_blob(&mut self, key: &str) -> Result<i64, DatabaseError>;
+    async fn user_from_id(&self, id: &UserId) -> Result<Option<User>, DatabaseError>;
+    /// usernames are compared case insensitively
     async fn user_from_username(&self, username: &Name) -> Result<Option<User>, DatabaseError>;
-    async fn category_from_id(&self, id: &Id) -> Result<Option<Category>, DatabaseError>;
+    /// the user that most recently gave up this username
+    async fn user_from_previous_username(
+        &self,
+        username: &Name,
+    ) -> Result<Option<User>, DatabaseError>;
+    /// a user whose username looks like `username`, compared by their unicode skeletons
+    async fn user_from_confusable_username(
+        &self,
+        username: &Name,
+        except_id: Option<&UserId>,
+    ) -> Result<Option<User>, DatabaseError>;
+    async fn last_username_change(&self, user_id: &UserId)
+        -> Result<Option<String>, DatabaseError>;
+    async fn category_from_id(&self, id: &CategoryId) -> Result<Option<Category>, DatabaseError>;
     async fn all_categories(&self) -> Result<Vec<Category>, DatabaseError>;
-    async fn post_from_id(&self, id: &Id) -> Result<Option<Post>, DatabaseError>;
-    async fn posts_from_category(&self, id: &Id) -> Result<Vec<Post>, DatabaseError>;
-    async fn replies_from_post(&self, id: &Id) -> Result<Vec<Reply>, DatabaseError>;
-    async fn reply_from_id(&self, id: &Id) -> Result<Option<Reply>, DatabaseError>;
-    async fn attachment_from_id(&self, id: &Id) -> Result<Option<Attachment>, DatabaseError>;
+    async fn post_from_id(&self, id: &PostId) -> Result<Option<Post>, DatabaseError>;
+    async fn posts_from_category(&self, id: &CategoryId) -> Result<Vec<Post>, DatabaseError>;
+    async fn announcements(&self) -> Result<Vec<Post>, DatabaseError>;
+    /// oldest first
+    async fn replies_from_post(&self, id: &PostId) -> Result<Vec<Reply>, DatabaseError>;
+    async fn posts_from_creator(&self, creator_id: &UserId) -> Result<Vec<Post>, DatabaseError>;
+    async fn replies_from_creator(&self, creator_id: &UserId) -> Result<Vec<Reply>, DatabaseError>;
+    async fn reply_from_id(&self, id: &ReplyId) -> Result<Option<Reply>, DatabaseError>;
+    async fn attachment_from_id(
+        &self,
+        id: &AttachmentId,
+    ) -> Result<Option<Attachment>, DatabaseError>;
+    async fn attachments_from_creator(
+        &self,
+        creator_id: &UserId,
+    ) -> Result<Vec<Attachment>, DatabaseError>;
+    async fn attachments_from_target(
+        &self,
+        target: &AttachmentTarget,
+        target_id: &Id,
+    ) -> Result<Vec<Attachment>, DatabaseError>;
+    async fn blob_ref_count(&self, key: &str) -> Result<Option<i64>, DatabaseError>;
+    async fn attachment_usage(&self, creator_id: &UserId)
+        -> Result<AttachmentUsage, DatabaseError>;
+    async fn user_activity(&self, user_id: &UserId) -> Result<UserActivity, DatabaseError>;
+    async fn search_users(&self, data: &SearchUsers) -> Result<Vec<UserSummary>, DatabaseError>;
+    /// the number of users matching the filters of `data`, ignoring sort and pagination
+    async fn count_users(&self, data: &SearchUsers) -> Result<i64, DatabaseError>;
+    async fn unreferenced_attachments(
+        &self,
+        created_before: &str,
+    ) -> Result<Vec<Attachment>, DatabaseError>;
+    async fn reactions_from_target(
+        &self,
+        target: &ReactionTarget,
+        target_id: &Id,
+        user_id: Option<&UserId>,
+    ) -> Result<Vec<ReactionCount>, DatabaseError>;
+    async fn read_status_from_post(
+        &self,
+        user_id: &UserId,
+        post_id: &PostId,
+    ) -> Result<Option<PostReadStatus>, DatabaseError>;
+    /// read statuses of all posts in a category, or in every category when it is missing
+    async fn read_statuses(
+        &self,
+        user_id: &UserId,
+        category_id: Option<&CategoryId>,
+    ) -> Result<Vec<PostReadStatus>, DatabaseError>;
+    /// when the user last listed the posts of a category
+    async fn category_visit(
+        &self,
+        user_id: &UserId,
+        category_id: &CategoryId,
+    ) -> Result<Option<String>, DatabaseError>;
+    async fn mark_post_read(&mut self, data: MarkPostRead) -> Result<(), DatabaseError>;
+    /// marks every post in a category as read up to its latest reply and records a visit
+    async fn mark_category_read(
+        &mut self,
+        user_id: &UserId,
+        category_id: &CategoryId,
+    ) -> Result<(), DatabaseError>;
+    async fn edit_category_visit(
+        &mut self,
+        user_id: &UserId,
+        category_id: &CategoryId,
+    ) -> Result<(), DatabaseError>;
+    /// keeps the level of an existing subscription
+    async fn create_subscription(&mut self, data: CreateSubscription) -> Result<(), DatabaseError>;
+    /// creates the subscription or changes the level of an existing one
+    async fn edit_subscription(&mut self, data: CreateSubscription) -> Result<(), DatabaseError>;
+    async fn remove_subscription(
+        &mut self,
+        user_id: &UserId,
+        target: &SubscriptionTarget,
+        target_id: &Id,
+    ) -> Result<(), DatabaseError>;
+    async fn subscription(
+        &self,
+        user_id: &UserId,
+        target: &SubscriptionTarget,
+        target_id: &Id,
+    ) -> Result<Option<Subscription>, DatabaseError>;
+    async fn subscriptions_from_user(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<Subscription>, DatabaseError>;
+    /// subscriptions of all users to a category and, when given, one of its posts
+    async fn subscriptions_from_targets(
+        &self,
+        category_id: &CategoryId,
+        post_id: Option<&PostId>,
+    ) -> Result<Vec<Subscription>, DatabaseError>;
+    async fn create_notification(&mut self, data: CreateNotification) -> Result<(), DatabaseError>;
+    /// newest first
+    async fn notifications_from_user(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<Notification>, DatabaseError>;
+    async fn email_settings(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<EmailSettings>, DatabaseError>;
+    async fn email_settings_from_unsubscribe_token(
+        &self,
+        unsubscribe_token: &str,
+    ) -> Result<Option<EmailSettings>, DatabaseError>;
+    async fn email_settings_from_confirmation_token(
+        &self,
+        confirmation_token: &str,
+    ) -> Result<Option<EmailSettings>, DatabaseError>;
+    /// settings of every user with a confirmed address whose email frequency is not off
+    async fn email_settings_to_send(&self) -> Result<Vec<EmailSettings>, DatabaseError>;
+    /// creates the settings when the user has none yet
+    async fn edit_email_settings(&mut self, data: EditEmailSettings) -> Result<(), DatabaseError>;
+    async fn edit_email_frequency(
+        &mut self,
+        user_id: &UserId,
+        frequency: &EmailFrequency,
+    ) -> Result<(), DatabaseError>;
+    async fn edit_email_last_sent(
+        &mut self,
+        user_id: &UserId,
+        last_sent: &str,
+    ) -> Result<(), DatabaseError>;
+    /// marks the address as confirmed and forgets the confirmation token
+    async fn confirm_email(
+        &mut self,
+        user_id: &UserId,
+        last_sent: &str,
+    ) -> Result<(), DatabaseError>;
+    async fn remove_email_settings(&mut self, user_id: &UserId) -> Result<(), DatabaseError>;
+    /// replies of other users created after `after` and up to `until`, in posts the user
+    /// created or replied to, leaving out deleted replies and posts, oldest first
+    async fn replies_for_email_digest(
+        &self,
+        user_id: &UserId,
+        after: &str,
+        until: &str,
+    ) -> Result<Vec<Reply>, DatabaseError>;
     async fn edit_user(&mut self, data: EditUser) -> Result<(), DatabaseError>;
     async fn edit_category(&mut self, data: EditCategory) -> Result<(), DatabaseError>;
     async fn edit_post(&mut self, data: EditPost) -> Result<(), DatabaseError>;
     async fn edit_reply(&mut self, data: EditReply) -> Result<(), DatabaseError>;
+    async fn edit_attachment(&mut self, data: EditAttachment) -> Result<(), DatabaseError>;
+    /// renames without keeping the old username in the history
+    async fn edit_username(&mut self, id: &UserId, username: &Name) -> Result<(), DatabaseError>;
+    /// renames and keeps the old username in the history so lookups still find the user
+    async fn change_username(&mut self, id: &UserId, username: &Name) -> Result<(), DatabaseError>;
+    async fn remove_username_history(&mut self, user_id: &UserId) -> Result<(), DatabaseError>;
+    async fn edit_last_active(&mut self, id: &UserId) -> Result<(), DatabaseError>;
+    async fn edit_last_login(&mut self, id: &UserId) -> Result<(), DatabaseError>;
+    /// reassigns posts, replies and attachments to the deleted user placeholder,
+    /// creating the placeholder if needed
+    async fn anonymize_content_from_creator(
+        &mut self,
+        creator_id: &UserId,
+    ) -> Result<(), DatabaseError>;
+    /// marks all posts and replies of a user as deleted, posts are locked as well
+    async fn remove_content_from_creator(
+        &mut self,
+        creator_id: &UserId,
+    ) -> Result<(), DatabaseError>;
+    /// removes the subscriptions, notifications, reactions, read state and email settings
+    /// of a user
+    async fn remove_private_data_from_user(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), DatabaseError>;
 }
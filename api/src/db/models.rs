@@ -2,26 +2,32 @@ use crate::from_unchecked::FromUnchecked;
 use derive_more::Display;
 use salvo::{oapi, prelude::ToSchema, writing::Json, Scribe};
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
 
+use crate::ids::{check_id, generate_id, ID_RULES};
 use crate::password::HashedPassword;
+use crate::text_validation::{TextError, TextRules};
 
 macro_rules! define_newtype {
-    ($name: tt, $length_range: expr) => {
+    ($name: tt, $rules: expr) => {
+        define_newtype!($name, $rules, |_: &str| Ok(()));
+    };
+    ($name: tt, $rules: expr, $check: expr) => {
         #[must_use]
-        #[derive(Serialize, Deserialize, sqlx::Type, Display, oapi::ToSchema, PartialEq)]
+        #[derive(Serialize, Deserialize, sqlx::Type, Display, oapi::ToSchema, PartialEq, Clone)]
         #[sqlx(transparent)]
         pub struct $name(String);
 
+        impl $name {
+            pub const RULES: TextRules = $rules;
+        }
+
         impl TryFrom<String> for $name {
-            type Error = String;
+            type Error = TextError;
 
             fn try_from(value: String) -> Result<Self, Self::Error> {
-                if $length_range.contains(&value.len()) {
-                    Ok(Self(value))
-                } else {
-                    Err("length invalid".to_string())
-                }
+                let value = Self::RULES.validate(value)?;
+                $check(&value)?;
+                Ok(Self(value))
             }
         }
 
@@ -33,11 +39,79 @@ macro_rules! define_newtype {
     };
 }
 
-define_newtype!(Id, 8..=8);
-define_newtype!(Content, 1..=1024);
-define_newtype!(Name, 1..=32);
-define_newtype!(Title, 1..=128);
-define_newtype!(Link, 1..);
+// an id that can belong to more than one kind of row, like the target of a reaction,
+// the kind is stored next to it and decides which typed id it is turned into
+define_newtype!(Id, ID_RULES, check_id);
+define_newtype!(Content, TextRules::multiline(1, 1024));
+define_newtype!(Name, TextRules::single_line(1, 32));
+define_newtype!(Title, TextRules::single_line(1, 128));
+define_newtype!(Link, TextRules::single_line(1, 2048), check_web_link);
+define_newtype!(Bio, TextRules::multiline(1, 1024));
+define_newtype!(Signature, TextRules::multiline(1, 256));
+define_newtype!(Location, TextRules::single_line(1, 64));
+define_newtype!(Email, TextRules::single_line(3, 254), check_email);
+
+/// ids of different kinds of rows are separate types so one can't be passed for another
+macro_rules! define_id {
+    ($name: tt) => {
+        define_newtype!($name, ID_RULES, check_id);
+
+        impl $name {
+            pub fn new() -> Self {
+                Self(generate_id())
+            }
+        }
+
+        impl From<$name> for Id {
+            fn from(value: $name) -> Self {
+                Id(value.0)
+            }
+        }
+
+        impl From<&$name> for Id {
+            fn from(value: &$name) -> Self {
+                Id(value.0.clone())
+            }
+        }
+    };
+}
+
+define_id!(UserId);
+define_id!(CategoryId);
+define_id!(PostId);
+define_id!(ReplyId);
+define_id!(AttachmentId);
+define_id!(NotificationId);
+
+impl Id {
+    /// only valid once the kind stored next to the id has been checked
+    pub fn to_typed<T: FromUnchecked<String>>(&self) -> T {
+        T::from_unchecked(self.0.clone())
+    }
+}
+
+/// links given by users must be absolute http(s) urls,
+/// links built by the api itself are created unchecked and may be relative
+fn check_web_link(value: &str) -> Result<(), TextError> {
+    let is_web_link = url::Url::parse(value)
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some());
+
+    if is_web_link {
+        Ok(())
+    } else {
+        Err(TextError::InvalidFormat(
+            "must be an absolute http or https url",
+        ))
+    }
+}
+
+fn check_email(value: &str) -> Result<(), TextError> {
+    if value.parse::<lettre::Address>().is_ok() {
+        Ok(())
+    } else {
+        Err(TextError::InvalidFormat("must be an email address"))
+    }
+}
 
 macro_rules! impl_json_writer {
     ($name: ident) => {
@@ -49,24 +123,168 @@ macro_rules! impl_json_writer {
     };
 }
 
-#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema)]
+#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema, Default)]
 pub enum Permission {
     Banned,
+    #[default]
     Unverified,
     User,
     Admin,
     Root,
 }
 
-impl Default for Permission {
-    fn default() -> Self {
-        Permission::Unverified
+/// content of users who deleted their account can be reassigned to this user,
+/// generated ids are either hex or 26 characters long so they never collide with it
+pub const DELETED_USER_ID: &str = "deleted0";
+pub const DELETED_USER_USERNAME: &str = "[deleted]";
+/// released usernames are this followed by the id, a longer prefix would not leave room for it
+pub const RELEASED_USERNAME_PREFIX: &str = "[del]";
+
+#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema)]
+pub enum ReactionTarget {
+    Post,
+    Reply,
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema)]
+pub enum AttachmentTarget {
+    Post,
+    Reply,
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema, Clone, Copy, PartialEq)]
+pub enum SubscriptionTarget {
+    Post,
+    Category,
+}
+
+/// a muted post stays quiet in a watched category, otherwise the more attentive level applies
+#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema, Clone, Copy, PartialEq, Debug)]
+pub enum SubscriptionLevel {
+    /// every new reply of a post, or every new post and reply of a category
+    Watching,
+    /// replies to the user, and new posts when subscribed to a category
+    Normal,
+    /// nothing, even when the category of a post is watched
+    Muted,
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema, Clone, Copy)]
+pub enum NotificationKind {
+    NewPost,
+    NewReply,
+    ReplyToYou,
+}
+
+/// how often a user gets emailed about new replies in posts they created or replied to
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Display, ToSchema, Clone, Copy, PartialEq)]
+pub enum EmailFrequency {
+    /// as soon as the digest worker finds new replies
+    Immediate,
+    Daily,
+    Weekly,
+    Off,
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema)]
+pub enum ReactionKind {
+    ThumbsUp,
+    ThumbsDown,
+    Heart,
+    Laugh,
+    Confused,
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Display, ToSchema)]
+pub enum QuoteSource {
+    Post,
+    Reply,
+}
+
+impl From<String> for QuoteSource {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "Post" => QuoteSource::Post,
+            "Reply" => QuoteSource::Reply,
+            _ => unreachable!("should be saved as above"),
+        }
     }
 }
 
-impl Id {
-    pub fn new() -> Self {
-        Self(Uuid::new_v4().to_string()[0..8].to_string())
+impl From<String> for ReactionTarget {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "Post" => ReactionTarget::Post,
+            "Reply" => ReactionTarget::Reply,
+            _ => unreachable!("should be saved as above"),
+        }
+    }
+}
+
+impl From<String> for AttachmentTarget {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "Post" => AttachmentTarget::Post,
+            "Reply" => AttachmentTarget::Reply,
+            _ => unreachable!("should be saved as above"),
+        }
+    }
+}
+
+impl From<String> for SubscriptionTarget {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "Post" => SubscriptionTarget::Post,
+            "Category" => SubscriptionTarget::Category,
+            _ => unreachable!("should be saved as above"),
+        }
+    }
+}
+
+impl From<String> for SubscriptionLevel {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "Watching" => SubscriptionLevel::Watching,
+            "Normal" => SubscriptionLevel::Normal,
+            "Muted" => SubscriptionLevel::Muted,
+            _ => unreachable!("should be saved as above"),
+        }
+    }
+}
+
+impl From<String> for NotificationKind {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "NewPost" => NotificationKind::NewPost,
+            "NewReply" => NotificationKind::NewReply,
+            "ReplyToYou" => NotificationKind::ReplyToYou,
+            _ => unreachable!("should be saved as above"),
+        }
+    }
+}
+
+impl From<String> for EmailFrequency {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "Immediate" => EmailFrequency::Immediate,
+            "Daily" => EmailFrequency::Daily,
+            "Weekly" => EmailFrequency::Weekly,
+            "Off" => EmailFrequency::Off,
+            _ => unreachable!("should be saved as above"),
+        }
+    }
+}
+
+impl From<String> for ReactionKind {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "ThumbsUp" => ReactionKind::ThumbsUp,
+            "ThumbsDown" => ReactionKind::ThumbsDown,
+            "Heart" => ReactionKind::Heart,
+            "Laugh" => ReactionKind::Laugh,
+            "Confused" => ReactionKind::Confused,
+            _ => unreachable!("should be saved as above"),
+        }
     }
 }
 
@@ -85,23 +303,71 @@ impl From<String> for Permission {
 
 #[derive(Deserialize)]
 pub struct User {
-    pub id: Id,
+    pub id: UserId,
     pub username: Name,
     pub nickname: Option<Name>,
     pub password: HashedPassword,
     pub permission: Permission,
-    pub avatar_id: Option<Id>,
+    pub avatar_id: Option<AttachmentId>,
+    pub attachment_quota: Option<AttachmentQuota>,
+    pub bio: Option<Bio>,
+    pub signature: Option<Signature>,
+    pub location: Option<Location>,
+    pub website: Option<Link>,
     pub deleted: bool,
     pub date_created: String,
     pub date_edited: Option<String>,
+    pub last_active: Option<String>,
+    pub last_login: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, oapi::ToSchema, Clone, Copy)]
+pub struct AttachmentQuota {
+    pub max_bytes: i64,
+    pub max_files: i64,
+}
+
+#[derive(Serialize, Deserialize, oapi::ToSchema)]
+pub struct AttachmentUsage {
+    pub bytes: i64,
+    pub files: i64,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+pub enum UserSort {
+    Newest,
+    Oldest,
+}
+
+/// a row of the admin user listing
+#[derive(Serialize, oapi::ToSchema)]
+pub struct UserSummary {
+    pub id: UserId,
+    pub username: Name,
+    pub nickname: Option<Name>,
+    pub permission: Permission,
+    pub deleted: bool,
+    pub date_created: String,
+    pub last_login: Option<String>,
+    pub last_active: Option<String>,
+    pub post_count: i64,
+    pub reply_count: i64,
+}
+
+/// posts and replies that have not been deleted
+#[derive(Serialize, Deserialize, oapi::ToSchema)]
+pub struct UserActivity {
+    pub post_count: i64,
+    pub reply_count: i64,
 }
 
 #[derive(Serialize, Deserialize, oapi::ToSchema)]
 pub struct Category {
-    pub id: Id,
+    pub id: CategoryId,
     pub title: Title,
     pub minimum_write_permission: Permission,
     pub minimum_read_permission: Permission,
+    pub qa_mode: bool,
     pub deleted: bool,
     pub date_created: String,
     pub date_edited: Option<String>,
@@ -110,13 +376,16 @@ impl_json_writer!(Category);
 
 #[derive(Serialize, Deserialize, oapi::ToSchema)]
 pub struct Post {
-    pub id: Id,
-    pub category_id: Id,
+    pub id: PostId,
+    pub category_id: CategoryId,
     pub title: Title,
     pub content: Content,
-    pub creator_id: Id,
+    pub creator_id: UserId,
     pub deleted: bool,
     pub locked: bool,
+    pub pinned: bool,
+    pub announcement: bool,
+    pub accepted_reply_id: Option<ReplyId>,
     pub date_created: String,
     pub date_edited: Option<String>,
 }
@@ -124,9 +393,11 @@ impl_json_writer!(Post);
 
 #[derive(Deserialize, Serialize, oapi::ToSchema)]
 pub struct Reply {
-    pub id: Id,
-    pub creator_id: Id,
-    pub post_id: Id,
+    pub id: ReplyId,
+    pub creator_id: UserId,
+    pub post_id: PostId,
+    pub parent_reply_id: Option<ReplyId>,
+    pub quote: Option<Quote>,
     pub content: Content,
     pub deleted: bool,
     pub date_created: String,
@@ -134,10 +405,108 @@ pub struct Reply {
 }
 impl_json_writer!(Reply);
 
+#[derive(Deserialize, Serialize, oapi::ToSchema)]
+pub struct Quote {
+    pub source: QuoteSource,
+    pub source_id: Id,
+    pub excerpt: Content,
+}
+
 #[derive(Deserialize)]
 pub struct Attachment {
-    pub id: Id,
-    pub path: String,
-    pub creator_id: Id,
+    pub id: AttachmentId,
+    pub blob_key: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub creator_id: UserId,
+    pub link: Option<AttachmentLink>,
     pub date_created: String,
 }
+
+#[derive(Deserialize, Serialize, oapi::ToSchema)]
+pub struct AttachmentLink {
+    pub target: AttachmentTarget,
+    pub target_id: Id,
+}
+
+/// how far a user has read a post, replies count as unread when they were created after
+/// the last reply the user read, so every reply is unread in posts they never opened
+#[derive(Serialize, oapi::ToSchema)]
+pub struct PostReadStatus {
+    pub post_id: PostId,
+    pub opened: bool,
+    /// replies that have not been deleted
+    pub unread_count: i64,
+    pub first_unread_reply_id: Option<ReplyId>,
+    /// the date of the latest reply, or of the post itself when it has none
+    pub last_activity: String,
+}
+
+pub struct Subscription {
+    pub user_id: UserId,
+    pub target: SubscriptionTarget,
+    pub target_id: Id,
+    pub level: SubscriptionLevel,
+    pub date_created: String,
+}
+
+#[derive(Serialize, oapi::ToSchema)]
+pub struct Notification {
+    pub id: NotificationId,
+    pub user_id: UserId,
+    pub kind: NotificationKind,
+    pub post_id: PostId,
+    pub reply_id: Option<ReplyId>,
+    /// the author of the new post or reply
+    pub creator_id: UserId,
+    pub date_created: String,
+}
+
+pub struct EmailSettings {
+    pub user_id: UserId,
+    pub email: Email,
+    pub frequency: EmailFrequency,
+    /// lets the user turn emails off from a link without logging in
+    pub unsubscribe_token: String,
+    /// replies created after this date have not been emailed yet
+    pub last_sent: String,
+    /// whether the user confirmed the address is theirs, nothing else is sent to it before
+    pub verified: bool,
+    /// sent to the address to confirm it, none once confirmed
+    pub confirmation_token: Option<String>,
+    /// when the confirmation token was sent
+    pub confirmation_sent: Option<String>,
+    pub date_edited: String,
+}
+
+#[derive(Serialize, oapi::ToSchema)]
+pub struct ReactionCount {
+    pub kind: ReactionKind,
+    pub count: i64,
+    pub reacted: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_web_links() {
+        assert!(Link::try_from("https://example.com/profile".to_string()).is_ok());
+        assert!(Link::try_from("http://localhost:8080".to_string()).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_links() {
+        for link in [
+            "javascript:alert(1)",
+            "ftp://example.com",
+            "mailto:someone@example.com",
+            "example.com",
+            "https://",
+        ] {
+            assert!(Link::try_from(link.to_string()).is_err(), "{link}");
+        }
+    }
+}
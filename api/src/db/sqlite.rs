@@ -5,16 +5,78 @@ use sqlx::SqlitePool;
 
 use crate::{
     from_unchecked::FromUnchecked, iso_date_strings::utc_date_iso_string, password::HashedPassword,
+    text_validation::skeleton,
 };
 
 use super::{
     database::{
-        CreateAttachment, CreateCategory, CreatePost, CreateReply, CreateUser, Database,
-        DatabaseError, EditCategory, EditPost, EditReply, EditUser,
+        CreateAttachment, CreateCategory, CreateNotification, CreatePost, CreateReaction,
+        CreateReply, CreateSubscription, CreateUser, Database, DatabaseError, EditAttachment,
+        EditCategory, EditEmailSettings, EditPost, EditReply, EditUser, MarkPostRead,
+        RemoveReaction, SearchUsers, UsernameTaken,
+    },
+    models::{
+        Attachment, AttachmentId, AttachmentLink, AttachmentQuota, AttachmentTarget,
+        AttachmentUsage, Bio, Category, CategoryId, Content, Email, EmailFrequency, EmailSettings,
+        Id, Link, Location, Name, Notification, NotificationId, Permission, Post, PostId,
+        PostReadStatus, Quote, ReactionCount, ReactionTarget, Reply, ReplyId, Signature,
+        Subscription, SubscriptionTarget, Title, User, UserActivity, UserId, UserSort, UserSummary,
+        DELETED_USER_ID, DELETED_USER_USERNAME,
     },
-    models::{Attachment, Category, Content, Id, Name, Post, Reply, Title, User},
 };
 
+/// `LIKE` treats `%` and `_` as wildcards, so they are escaped to match literally
+fn like_contains_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('!', "!!")
+        .replace('%', "!%")
+        .replace('_', "!_");
+    format!("%{escaped}%")
+}
+
+/// violations of the username index become [`UsernameTaken`] so routes can tell them apart
+fn username_error<C>(err: sqlx::Error, context: C) -> DatabaseError
+where
+    C: std::fmt::Display + Send + Sync + 'static,
+{
+    match err.as_database_error() {
+        Some(db_err)
+            if db_err.is_unique_violation() && db_err.message().contains("user.username") =>
+        {
+            UsernameTaken.into()
+        }
+        _ => eyre::Report::new(err).wrap_err(context),
+    }
+}
+
+/// the tables of databases from before sortable ids and blob storage, in the order the
+/// upgrade renames them
+const LEGACY_TABLES: [&str; 5] = ["user", "category", "post", "reply", "attachment"];
+
+/// an attachment of a legacy database, its file still lies at `path`
+pub struct LegacyAttachment {
+    pub id: AttachmentId,
+    pub path: String,
+}
+
+/// a legacy attachment whose file was moved to the blob store
+pub struct UpgradedAttachment {
+    pub id: AttachmentId,
+    pub blob_key: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+}
+
+/// a new id is generated this many times at most when it is already taken
+const MAX_ID_ATTEMPTS: u32 = 3;
+
+/// a primary key violation on `column`, like `post.id`, which is retried with a new id
+fn is_id_collision(err: &sqlx::Error, column: &str) -> bool {
+    err.as_database_error()
+        .is_some_and(|db_err| db_err.is_unique_violation() && db_err.message().contains(column))
+}
+
 pub struct SqliteDb {
     pool: SqlitePool,
 }
@@ -27,167 +89,605 @@ impl SqliteDb {
 
         Ok(Self { pool })
     }
+
+    /// an empty database with the schema from prepare.sql, kept in memory on a single
+    /// connection so every query sees the same data
+    #[cfg(test)]
+    pub async fn in_memory() -> Self {
+        Self::in_memory_from(include_str!("../../prepare.sql")).await
+    }
+
+    /// a database kept in memory that starts out with what the sql creates
+    #[cfg(test)]
+    pub async fn in_memory_from(sql: &str) -> Self {
+        use sqlx::{sqlite::SqlitePoolOptions, Executor};
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .expect("in memory database should open");
+        pool.execute(sql).await.expect("schema should apply");
+
+        Self { pool }
+    }
+
+    /// whether the database still has the schema from before sortable ids and blob storage,
+    /// where attachments were files at a path instead of blobs
+    pub async fn is_legacy(&self) -> Result<bool, DatabaseError> {
+        let (legacy,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('attachment') WHERE name='path');",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .with_context(|| "unable to check for a legacy schema")?;
+
+        Ok(legacy)
+    }
+
+    pub async fn legacy_attachments(&self) -> Result<Vec<LegacyAttachment>, DatabaseError> {
+        let attachments: Vec<(String, String)> = sqlx::query_as("SELECT id, path FROM attachment;")
+            .fetch_all(&self.pool)
+            .await
+            .with_context(|| "unable to get legacy attachments")?;
+
+        Ok(attachments
+            .into_iter()
+            .map(|(id, path)| LegacyAttachment {
+                id: AttachmentId::from_unchecked(id),
+                path,
+            })
+            .collect())
+    }
+
+    /// moves a legacy database to the schema of prepare.sql in one transaction, keeping
+    /// its ids, the files of its attachments have to be in the blob store already
+    pub async fn upgrade_legacy(
+        &self,
+        attachments: &[UpgradedAttachment],
+    ) -> Result<(), DatabaseError> {
+        use sqlx::Executor;
+
+        let mut connection = self
+            .pool
+            .acquire()
+            .await
+            .with_context(|| "unable to get a connection")?;
+
+        // the tables are swapped out from under their foreign keys, which are checked once
+        // everything is copied, and the pragma has no effect inside a transaction
+        connection
+            .execute("PRAGMA foreign_keys=OFF;")
+            .await
+            .with_context(|| "unable to turn off foreign keys")?;
+        let upgraded = upgrade_legacy_tables(&mut connection, attachments).await;
+        connection
+            .execute("PRAGMA foreign_keys=ON;")
+            .await
+            .with_context(|| "unable to turn on foreign keys")?;
+
+        upgraded
+    }
+
+    /// replies are ordered by their dates since legacy ids don't sort by creation,
+    /// the id only breaks ties between replies created at the same time
+    async fn read_statuses_where(
+        &self,
+        user_id: &UserId,
+        category_id: Option<&CategoryId>,
+        post_id: Option<&PostId>,
+    ) -> Result<Vec<PostReadStatus>, DatabaseError> {
+        let statuses = sqlx::query!(
+            r#"SELECT post.id AS post_id, post_read.user_id IS NOT NULL AS "opened!: bool", (SELECT COUNT(*) FROM reply WHERE reply.post_id=post.id AND reply.deleted=0 AND (last_read.id IS NULL OR (reply.date_created, reply.id)>(last_read.date_created, last_read.id))) AS "unread_count!: i64", (SELECT reply.id FROM reply WHERE reply.post_id=post.id AND reply.deleted=0 AND (last_read.id IS NULL OR (reply.date_created, reply.id)>(last_read.date_created, last_read.id)) ORDER BY reply.date_created, reply.id LIMIT 1) AS "first_unread_reply_id?: String", COALESCE((SELECT MAX(reply.date_created) FROM reply WHERE reply.post_id=post.id AND reply.deleted=0), post.date_created) AS "last_activity!: String" FROM post LEFT JOIN post_read ON post_read.post_id=post.id AND post_read.user_id=?1 LEFT JOIN reply AS last_read ON last_read.id=post_read.last_read_reply_id WHERE (?2 IS NULL OR post.category_id=?2) AND (?3 IS NULL OR post.id=?3);"#,
+            user_id,
+            category_id,
+            post_id,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("unable to get read statuses of user '{user_id}'"))?;
+
+        Ok(statuses
+            .into_iter()
+            .map(|status| PostReadStatus {
+                post_id: PostId::from_unchecked(status.post_id),
+                opened: status.opened,
+                unread_count: status.unread_count,
+                first_unread_reply_id: status.first_unread_reply_id.map(ReplyId::from_unchecked),
+                last_activity: status.last_activity,
+            })
+            .collect())
+    }
 }
 
-#[salvo::async_trait]
-impl Database for SqliteDb {
-    async fn create_user(&mut self, data: CreateUser) -> Result<Id, DatabaseError> {
-        let id = Id::new();
-        let date_created = utc_date_iso_string();
+async fn upgrade_legacy_tables(
+    connection: &mut sqlx::SqliteConnection,
+    attachments: &[UpgradedAttachment],
+) -> Result<(), DatabaseError> {
+    use sqlx::{Connection, Executor};
+
+    let mut transaction = connection
+        .begin()
+        .await
+        .with_context(|| "unable to begin transaction")?;
+
+    for table in LEGACY_TABLES {
+        transaction
+            .execute(format!("ALTER TABLE {table} RENAME TO legacy_{table};").as_str())
+            .await
+            .with_context(|| format!("unable to rename legacy table {table}"))?;
+    }
+    transaction
+        .execute(include_str!("../../prepare.sql"))
+        .await
+        .with_context(|| "unable to create the current tables")?;
+
+    let date_created = utc_date_iso_string();
+    for attachment in attachments {
+        sqlx::query(
+            "INSERT INTO attachment (id, blob_key, file_name, content_type, size, creator_id, date_created) SELECT id, ?, ?, ?, ?, creator_id, date_created FROM legacy_attachment WHERE id=?;",
+        )
+        .bind(&attachment.blob_key)
+        .bind(&attachment.file_name)
+        .bind(&attachment.content_type)
+        .bind(attachment.size)
+        .bind(&attachment.id)
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| format!("unable to copy legacy attachment {}", attachment.id))?;
 
         sqlx::query!(
-            "INSERT INTO user (id, username, nickname, password, permission, avatar_id, deleted, date_created) VALUES (?, ?, ?, ?, ?, ?, ?, ?);",
-            id,
-            data.username,
-            data.nickname,
-            data.password,
-            data.permission,
-            data.avatar_id,
-            false,
+            "INSERT INTO blob (key, size, ref_count, date_created) VALUES (?, ?, 1, ?) ON CONFLICT(key) DO UPDATE SET ref_count=ref_count+1;",
+            attachment.blob_key,
+            attachment.size,
             date_created,
         )
-        .execute(&self.pool)
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| format!("unable to acquire blob {}", attachment.blob_key))?;
+    }
+
+    transaction
+        .execute(include_str!("../../upgrade_legacy.sql"))
+        .await
+        .with_context(|| "unable to copy the legacy tables")?;
+
+    let users = sqlx::query!("SELECT id, username FROM user;")
+        .fetch_all(&mut *transaction)
+        .await
+        .with_context(|| "unable to get upgraded users")?;
+    for user in users {
+        let username_skeleton = skeleton(&user.username);
+        sqlx::query!(
+            "UPDATE user SET username_skeleton=? WHERE id=?;",
+            username_skeleton,
+            user.id
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| format!("unable to set the username skeleton of user {}", user.id))?;
+    }
+
+    let violations = sqlx::query("PRAGMA foreign_key_check;")
+        .fetch_all(&mut *transaction)
+        .await
+        .with_context(|| "unable to check foreign keys")?;
+    if !violations.is_empty() {
+        return Err(eyre::eyre!(
+            "the upgraded database has {} rows with missing references",
+            violations.len()
+        ));
+    }
+
+    transaction
+        .commit()
         .await
-        .with_context(|| "unable to insert user")?;
+        .with_context(|| "unable to commit the upgrade")?;
+
+    Ok(())
+}
+
+#[salvo::async_trait]
+impl Database for SqliteDb {
+    async fn create_user(&mut self, data: CreateUser) -> Result<UserId, DatabaseError> {
+        let mut attempt = 1;
+        let date_created = utc_date_iso_string();
+        let username_skeleton = skeleton(&data.username.to_string());
 
-        Ok(id)
+        loop {
+            let id = UserId::new();
+            let result = sqlx::query!(
+                "INSERT INTO user (id, username, username_skeleton, nickname, password, permission, avatar_id, deleted, date_created) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+                id,
+                data.username,
+                username_skeleton,
+                data.nickname,
+                data.password,
+                data.permission,
+                data.avatar_id,
+                false,
+                date_created,
+            )
+            .execute(&self.pool)
+            .await;
+
+            match result {
+                Err(err) if attempt < MAX_ID_ATTEMPTS && is_id_collision(&err, "user.id") => {
+                    attempt += 1
+                }
+                result => {
+                    result.map_err(|err| username_error(err, "unable to insert user"))?;
+                    return Ok(id);
+                }
+            }
+        }
     }
-    async fn user_from_id(&self, id: &Id) -> Result<Option<User>, DatabaseError> {
+    async fn user_from_id(&self, id: &UserId) -> Result<Option<User>, DatabaseError> {
         let user = sqlx::query!("SELECT * FROM user WHERE id=?;", id)
             .fetch_optional(&self.pool)
             .await
             .with_context(|| format!("unable to get user with id='{id}'"))?;
 
         Ok(user.map(|user| User {
-            id: Id::from_unchecked(user.id),
+            id: UserId::from_unchecked(user.id),
             username: Name::from_unchecked(user.username),
             nickname: user.nickname.map(Name::from_unchecked),
             password: HashedPassword::from_unchecked(user.password),
             permission: user.permission.into(),
-            avatar_id: user.avatar_id.map(Id::from_unchecked),
+            avatar_id: user.avatar_id.map(AttachmentId::from_unchecked),
+            attachment_quota: match (user.quota_max_bytes, user.quota_max_files) {
+                (Some(max_bytes), Some(max_files)) => Some(AttachmentQuota {
+                    max_bytes,
+                    max_files,
+                }),
+                _ => None,
+            },
+            bio: user.bio.map(Bio::from_unchecked),
+            signature: user.signature.map(Signature::from_unchecked),
+            location: user.location.map(Location::from_unchecked),
+            website: user.website.map(Link::from_unchecked),
             deleted: user.deleted != 0,
             date_created: user.date_created,
             date_edited: user.date_edited,
+            last_active: user.last_active,
+            last_login: user.last_login,
         }))
     }
     async fn user_from_username(&self, username: &Name) -> Result<Option<User>, DatabaseError> {
-        let user = sqlx::query!("SELECT * FROM user WHERE username=?;", username)
-            .fetch_optional(&self.pool)
-            .await
-            .with_context(|| format!("unable to get user with username='{username}'"))?;
+        let user = sqlx::query!(
+            "SELECT * FROM user WHERE username=? COLLATE NOCASE;",
+            username
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| format!("unable to get user with username='{username}'"))?;
 
         Ok(user.map(|user| User {
-            id: Id::from_unchecked(user.id),
+            id: UserId::from_unchecked(user.id),
             username: Name::from_unchecked(user.username),
             nickname: user.nickname.map(Name::from_unchecked),
             password: HashedPassword::from_unchecked(user.password),
-            avatar_id: user.avatar_id.map(Id::from_unchecked),
+            avatar_id: user.avatar_id.map(AttachmentId::from_unchecked),
             permission: user.permission.into(),
+            attachment_quota: match (user.quota_max_bytes, user.quota_max_files) {
+                (Some(max_bytes), Some(max_files)) => Some(AttachmentQuota {
+                    max_bytes,
+                    max_files,
+                }),
+                _ => None,
+            },
+            bio: user.bio.map(Bio::from_unchecked),
+            signature: user.signature.map(Signature::from_unchecked),
+            location: user.location.map(Location::from_unchecked),
+            website: user.website.map(Link::from_unchecked),
             deleted: user.deleted != 0,
             date_edited: user.date_edited,
             date_created: user.date_created,
+            last_active: user.last_active,
+            last_login: user.last_login,
         }))
     }
-    async fn create_post(&mut self, data: CreatePost) -> Result<Id, DatabaseError> {
-        let id = Id::new();
-        let date_created = utc_date_iso_string();
+    async fn user_from_previous_username(
+        &self,
+        username: &Name,
+    ) -> Result<Option<User>, DatabaseError> {
+        let history = sqlx::query!(
+            "SELECT user_id FROM username_history WHERE username=? COLLATE NOCASE ORDER BY date_changed DESC LIMIT 1;",
+            username
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| format!("unable to get username history of '{username}'"))?;
 
-        sqlx::query!(
-            "INSERT INTO post (id, title, content, category_id, creator_id, locked, deleted, date_created) VALUES (?, ?, ?, ?, ?, ?, ?, ?);",
-            id,
-            data.title,
-            data.content,
-            data.category_id,
-            data.creator_id,
-            false,
-            false,
-            date_created,
+        match history {
+            Some(history) => {
+                self.user_from_id(&UserId::from_unchecked(history.user_id))
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn user_from_confusable_username(
+        &self,
+        username: &Name,
+        except_id: Option<&UserId>,
+    ) -> Result<Option<User>, DatabaseError> {
+        let username_skeleton = skeleton(&username.to_string());
+
+        let user = sqlx::query!(
+            "SELECT id FROM user WHERE username_skeleton=? AND (?2 IS NULL OR id!=?2) LIMIT 1;",
+            username_skeleton,
+            except_id,
         )
-        .execute(&self.pool)
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| format!("unable to get users with usernames like '{username}'"))?;
+
+        match user {
+            Some(user) => self.user_from_id(&UserId::from_unchecked(user.id)).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn last_username_change(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<String>, DatabaseError> {
+        let history = sqlx::query!(
+            "SELECT date_changed FROM username_history WHERE user_id=? ORDER BY date_changed DESC LIMIT 1;",
+            user_id
+        )
+        .fetch_optional(&self.pool)
         .await
-        .with_context(|| "unable to insert post")?;
+        .with_context(|| format!("unable to get username history of user '{user_id}'"))?;
+
+        Ok(history.map(|history| history.date_changed))
+    }
+
+    async fn create_post(&mut self, data: CreatePost) -> Result<PostId, DatabaseError> {
+        let mut attempt = 1;
+        let date_created = utc_date_iso_string();
+
+        loop {
+            let id = PostId::new();
+            let result = sqlx::query!(
+                "INSERT INTO post (id, title, content, category_id, creator_id, locked, pinned, announcement, deleted, date_created) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+                id,
+                data.title,
+                data.content,
+                data.category_id,
+                data.creator_id,
+                false,
+                false,
+                false,
+                false,
+                date_created,
+            )
+            .execute(&self.pool)
+            .await;
+
+            match result {
+                Err(err) if attempt < MAX_ID_ATTEMPTS && is_id_collision(&err, "post.id") => {
+                    attempt += 1
+                }
+                result => {
+                    result.with_context(|| "unable to insert post")?;
+                    return Ok(id);
+                }
+            }
+        }
+    }
+
+    async fn create_reply(&mut self, data: CreateReply) -> Result<ReplyId, DatabaseError> {
+        let mut attempt = 1;
+        let date_created = utc_date_iso_string();
+        let quote_source = data.quote.as_ref().map(|quote| &quote.source);
+        let quote_source_id = data.quote.as_ref().map(|quote| &quote.source_id);
+        let quote_excerpt = data.quote.as_ref().map(|quote| &quote.excerpt);
+
+        loop {
+            let id = ReplyId::new();
+            let result = sqlx::query!(
+                "INSERT INTO reply (id, content, creator_id, post_id, parent_reply_id, quote_source, quote_source_id, quote_excerpt, deleted, date_created) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+                id,
+                data.content,
+                data.creator_id,
+                data.post_id,
+                data.parent_reply_id,
+                quote_source,
+                quote_source_id,
+                quote_excerpt,
+                false,
+                date_created,
+            )
+            .execute(&self.pool)
+            .await;
+
+            match result {
+                Err(err) if attempt < MAX_ID_ATTEMPTS && is_id_collision(&err, "reply.id") => {
+                    attempt += 1
+                }
+                result => {
+                    result.with_context(|| "unable to insert reply")?;
+                    return Ok(id);
+                }
+            }
+        }
+    }
+
+    async fn create_category(&mut self, data: CreateCategory) -> Result<CategoryId, DatabaseError> {
+        let mut attempt = 1;
+        let date_created = utc_date_iso_string();
+
+        loop {
+            let id = CategoryId::new();
+            let result = sqlx::query!(
+                "INSERT INTO category (id, title, minimum_write_permission, minimum_read_permission, qa_mode, deleted, date_created) VALUES (?, ?, ?, ?, ?, ?, ?);",
+                id,
+                data.title,
+                data.minimum_write_permission,
+                data.minimum_read_permission,
+                data.qa_mode,
+                false,
+                date_created,
+            )
+            .execute(&self.pool)
+            .await;
+
+            match result {
+                Err(err) if attempt < MAX_ID_ATTEMPTS && is_id_collision(&err, "category.id") => {
+                    attempt += 1
+                }
+                result => {
+                    result.with_context(|| "unable to insert category")?;
+                    return Ok(id);
+                }
+            }
+        }
+    }
+
+    async fn create_attachment(
+        &mut self,
+        data: CreateAttachment,
+    ) -> Result<AttachmentId, DatabaseError> {
+        let mut attempt = 1;
+        let date_created = utc_date_iso_string();
 
-        Ok(id)
+        loop {
+            let id = AttachmentId::new();
+            let result = sqlx::query!(
+                "INSERT INTO attachment (id, blob_key, file_name, content_type, size, creator_id, date_created) VALUES (?, ?, ?, ?, ?, ?, ?);",
+                id,
+                data.blob_key,
+                data.file_name,
+                data.content_type,
+                data.size,
+                data.creator_id,
+                date_created,
+            )
+            .execute(&self.pool)
+            .await;
+
+            match result {
+                Err(err) if attempt < MAX_ID_ATTEMPTS && is_id_collision(&err, "attachment.id") => {
+                    attempt += 1
+                }
+                result => {
+                    result.with_context(|| {
+                        format!(
+                            "unable to insert attachment with blob key {}",
+                            data.blob_key
+                        )
+                    })?;
+                    return Ok(id);
+                }
+            }
+        }
     }
 
-    async fn create_reply(&mut self, data: CreateReply) -> Result<Id, DatabaseError> {
-        let id = Id::new();
+    async fn create_reaction(&mut self, data: CreateReaction) -> Result<(), DatabaseError> {
         let date_created = utc_date_iso_string();
 
         sqlx::query!(
-            "INSERT INTO reply (id, content, creator_id, post_id, deleted, date_created) VALUES (?, ?, ?, ?, ?, ?);",
-            id,
-            data.content,
+            "INSERT OR IGNORE INTO reaction (creator_id, target, target_id, kind, date_created) VALUES (?, ?, ?, ?, ?);",
             data.creator_id,
-            data.post_id,
-            false,
+            data.target,
+            data.target_id,
+            data.kind,
             date_created,
         )
         .execute(&self.pool)
         .await
-        .with_context(|| "unable to insert reply")?;
+        .with_context(|| "unable to insert reaction")?;
 
-        Ok(id)
+        Ok(())
     }
 
-    async fn create_category(&mut self, data: CreateCategory) -> Result<Id, DatabaseError> {
-        let id = Id::new();
-        let date_created = utc_date_iso_string();
-
+    async fn remove_reaction(&mut self, data: RemoveReaction) -> Result<(), DatabaseError> {
         sqlx::query!(
-            "INSERT INTO category (id, title, minimum_write_permission, minimum_read_permission, deleted, date_created) VALUES (?, ?, ?, ?, ?, ?);",
-            id,
-            data.title,
-            data.minimum_write_permission,
-            data.minimum_read_permission,
-            false,
-            date_created,
+            "DELETE FROM reaction WHERE creator_id=? AND target=? AND target_id=? AND kind=?;",
+            data.creator_id,
+            data.target,
+            data.target_id,
+            data.kind,
         )
         .execute(&self.pool)
         .await
-        .with_context(|| "unable to insert category")?;
+        .with_context(|| "unable to remove reaction")?;
 
-        Ok(id)
+        Ok(())
     }
 
-    async fn create_attachment<'a>(
-        &mut self,
-        data: CreateAttachment<'a>,
-    ) -> Result<Id, DatabaseError> {
-        let id = Id::new();
-        let date_created = utc_date_iso_string();
+    async fn remove_attachment(&mut self, id: &AttachmentId) -> Result<(), DatabaseError> {
+        sqlx::query!("UPDATE user SET avatar_id=NULL WHERE avatar_id=?;", id)
+            .execute(&self.pool)
+            .await
+            .with_context(|| "unable to clear avatars using attachment")?;
 
-        let path = format!(
-            "files_uploaded/{}/{}/{}",
-            data.creator_id, id, data.file_name
-        );
+        sqlx::query!("DELETE FROM attachment WHERE id=?;", id)
+            .execute(&self.pool)
+            .await
+            .with_context(|| "unable to remove attachment")?;
+
+        Ok(())
+    }
 
-        std::fs::copy(&data.temp_path, &path)
-            .with_context(|| format!("unable to write files to {path}"))?;
+    async fn acquire_blob(&mut self, key: &str, size: i64) -> Result<i64, DatabaseError> {
+        let date_created = utc_date_iso_string();
 
-        sqlx::query!(
-            "INSERT INTO attachment (id, path, creator_id, date_created) VALUES (?, ?, ?, ?);",
-            id,
-            path,
-            data.creator_id,
+        let blob = sqlx::query!(
+            "INSERT INTO blob (key, size, ref_count, date_created) VALUES (?, ?, 1, ?) ON CONFLICT(key) DO UPDATE SET ref_count=ref_count+1 RETURNING ref_count;",
+            key,
+            size,
             date_created,
         )
-        .execute(&self.pool)
+        .fetch_one(&self.pool)
+        .await
+        .with_context(|| format!("unable to acquire blob with key {key}"))?;
+
+        Ok(blob.ref_count)
+    }
+
+    async fn release_blob(&mut self, key: &str) -> Result<i64, DatabaseError> {
+        let blob = sqlx::query!(
+            "UPDATE blob SET ref_count=ref_count-1 WHERE key=? RETURNING ref_count;",
+            key
+        )
+        .fetch_optional(&self.pool)
         .await
-        .with_context(|| "unable to insert attachment with path {path}")?;
+        .with_context(|| format!("unable to release blob with key {key}"))?;
 
-        Ok(id)
+        let ref_count = blob.map_or(0, |blob| blob.ref_count);
+        if ref_count <= 0 {
+            sqlx::query!("DELETE FROM blob WHERE key=?;", key)
+                .execute(&self.pool)
+                .await
+                .with_context(|| format!("unable to remove blob with key {key}"))?;
+        }
+
+        Ok(ref_count)
     }
 
     async fn edit_user(&mut self, data: EditUser) -> Result<(), DatabaseError> {
         let date_edited = utc_date_iso_string();
+        let quota_max_bytes = data.attachment_quota.as_ref().map(|quota| quota.max_bytes);
+        let quota_max_files = data.attachment_quota.as_ref().map(|quota| quota.max_files);
 
         sqlx::query!(
-            "UPDATE user SET nickname=?, password=?, permission=?, avatar_id=?, deleted=? WHERE id=?;",
+            "UPDATE user SET nickname=?, password=?, permission=?, avatar_id=?, quota_max_bytes=?, quota_max_files=?, bio=?, signature=?, location=?, website=?, deleted=? WHERE id=?;",
             data.nickname,
             data.password,
             data.permission,
             data.avatar_id,
+            quota_max_bytes,
+            quota_max_files,
+            data.bio,
+            data.signature,
+            data.location,
+            data.website,
             data.deleted,
             data.id,
         )
@@ -202,10 +702,11 @@ impl Database for SqliteDb {
         let date_edited = utc_date_iso_string();
 
         sqlx::query!(
-            "UPDATE category SET title=?, minimum_read_permission=?, minimum_write_permission=?, deleted=?, date_edited=? WHERE id=?;",
+            "UPDATE category SET title=?, minimum_read_permission=?, minimum_write_permission=?, qa_mode=?, deleted=?, date_edited=? WHERE id=?;",
             data.title,
             data.minimum_read_permission,
             data.minimum_write_permission,
+            data.qa_mode,
             data.deleted,
             date_edited,
             data.id,
@@ -221,13 +722,16 @@ impl Database for SqliteDb {
         let date_edited = utc_date_iso_string();
 
         sqlx::query!(
-            "UPDATE post SET title=?, content=?, category_id=?, date_edited=?, deleted=?, locked=? WHERE id=?;",
+            "UPDATE post SET title=?, content=?, category_id=?, date_edited=?, deleted=?, locked=?, pinned=?, announcement=?, accepted_reply_id=? WHERE id=?;",
             data.title,
             data.content,
             data.category_id,
             date_edited,
             data.deleted,
             data.locked,
+            data.pinned,
+            data.announcement,
+            data.accepted_reply_id,
             data.id,
         )
         .execute(&self.pool)
@@ -239,10 +743,16 @@ impl Database for SqliteDb {
 
     async fn edit_reply(&mut self, data: EditReply) -> Result<(), DatabaseError> {
         let date_edited = utc_date_iso_string();
+        let quote_source = data.quote.as_ref().map(|quote| &quote.source);
+        let quote_source_id = data.quote.as_ref().map(|quote| &quote.source_id);
+        let quote_excerpt = data.quote.as_ref().map(|quote| &quote.excerpt);
 
         sqlx::query!(
-            "UPDATE reply SET content=?, deleted=?, date_edited=? WHERE id=?;",
+            "UPDATE reply SET content=?, quote_source=?, quote_source_id=?, quote_excerpt=?, deleted=?, date_edited=? WHERE id=?;",
             data.content,
+            quote_source,
+            quote_source_id,
+            quote_excerpt,
             data.deleted,
             date_edited,
             data.id,
@@ -254,131 +764,1508 @@ impl Database for SqliteDb {
         Ok(())
     }
 
-    async fn category_from_id(&self, id: &Id) -> Result<Option<Category>, DatabaseError> {
-        let category = sqlx::query!("SELECT * FROM category WHERE id=?;", id)
-            .fetch_optional(&self.pool)
-            .await
-            .with_context(|| format!("unable to get category with id='{id}'"))?;
+    async fn edit_attachment(&mut self, data: EditAttachment) -> Result<(), DatabaseError> {
+        let link_target = data.link.as_ref().map(|link| &link.target);
+        let link_target_id = data.link.as_ref().map(|link| &link.target_id);
 
-        Ok(category.map(|category| Category {
-            id: Id::from_unchecked(category.id),
-            title: Title::from_unchecked(category.title),
-            minimum_read_permission: category.minimum_read_permission.into(),
-            minimum_write_permission: category.minimum_write_permission.into(),
-            date_created: category.date_created,
-            date_edited: category.date_edited,
-            deleted: category.deleted != 0,
-        }))
+        sqlx::query!(
+            "UPDATE attachment SET link_target=?, link_target_id=? WHERE id=?;",
+            link_target,
+            link_target_id,
+            data.id,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "unable to edit attachment")?;
+
+        Ok(())
     }
 
-    async fn posts_from_category(&self, id: &Id) -> Result<Vec<Post>, DatabaseError> {
-        let posts = sqlx::query!("SELECT * FROM post WHERE category_id=?;", id)
-            .fetch_all(&self.pool)
-            .await
-            .with_context(|| "unable to get posts")?;
+    async fn edit_username(&mut self, id: &UserId, username: &Name) -> Result<(), DatabaseError> {
+        let date_edited = utc_date_iso_string();
+        let username_skeleton = skeleton(&username.to_string());
 
-        Ok(posts
-            .into_iter()
-            .map(|post| Post {
-                id: Id::from_unchecked(post.id),
-                category_id: Id::from_unchecked(post.category_id),
-                title: Title::from_unchecked(post.title),
-                content: Content::from_unchecked(post.content),
-                creator_id: Id::from_unchecked(post.creator_id),
-                date_created: post.date_created,
-                date_edited: post.date_edited,
-                deleted: post.deleted != 0,
-                locked: post.locked != 0,
-            })
-            .collect())
+        sqlx::query!(
+            "UPDATE user SET username=?, username_skeleton=?, date_edited=? WHERE id=?;",
+            username,
+            username_skeleton,
+            date_edited,
+            id,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            username_error(
+                err,
+                format!("unable to edit username of user with id '{id}'"),
+            )
+        })?;
+
+        Ok(())
     }
 
-    async fn all_categories(&self) -> Result<Vec<Category>, DatabaseError> {
-        let categories = sqlx::query!("SELECT * FROM category;")
-            .fetch_all(&self.pool)
+    async fn change_username(&mut self, id: &UserId, username: &Name) -> Result<(), DatabaseError> {
+        let date_changed = utc_date_iso_string();
+        let username_skeleton = skeleton(&username.to_string());
+        let mut transaction = self
+            .pool
+            .begin()
             .await
-            .with_context(|| "unable to get categories")?;
+            .with_context(|| "unable to begin transaction")?;
 
-        Ok(categories
-            .into_iter()
-            .map(|category| Category {
-                id: Id::from_unchecked(category.id),
-                title: Title::from_unchecked(category.title),
-                minimum_read_permission: category.minimum_read_permission.into(),
-                minimum_write_permission: category.minimum_write_permission.into(),
-                date_created: category.date_created,
-                date_edited: category.date_edited,
-                deleted: category.deleted != 0,
-            })
-            .collect())
-    }
+        sqlx::query!(
+            "INSERT INTO username_history (username, user_id, date_changed) SELECT username, id, ? FROM user WHERE id=?;",
+            date_changed,
+            id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| format!("unable to keep username history of user with id '{id}'"))?;
 
-    async fn post_from_id(&self, id: &Id) -> Result<Option<Post>, DatabaseError> {
-        let post = sqlx::query!("SELECT * FROM post WHERE id=?;", id)
-            .fetch_optional(&self.pool)
+        sqlx::query!(
+            "UPDATE user SET username=?, username_skeleton=?, date_edited=? WHERE id=?;",
+            username,
+            username_skeleton,
+            date_changed,
+            id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|err| {
+            username_error(
+                err,
+                format!("unable to change username of user with id '{id}'"),
+            )
+        })?;
+
+        transaction
+            .commit()
             .await
-            .with_context(|| format!("unable to get post with id='{id}'"))?;
+            .with_context(|| "unable to commit username change")?;
 
-        Ok(post.map(|post| Post {
-            id: Id::from_unchecked(post.id),
-            category_id: Id::from_unchecked(post.category_id),
-            title: Title::from_unchecked(post.title),
-            content: Content::from_unchecked(post.content),
-            creator_id: Id::from_unchecked(post.creator_id),
-            date_created: post.date_created,
-            date_edited: post.date_edited,
-            deleted: post.deleted != 0,
-            locked: post.locked != 0,
-        }))
+        Ok(())
     }
 
-    async fn replies_from_post(&self, id: &Id) -> Result<Vec<Reply>, DatabaseError> {
-        let posts = sqlx::query!("SELECT * FROM reply WHERE post_id=?;", id)
-            .fetch_all(&self.pool)
+    async fn remove_username_history(&mut self, user_id: &UserId) -> Result<(), DatabaseError> {
+        sqlx::query!("DELETE FROM username_history WHERE user_id=?;", user_id)
+            .execute(&self.pool)
             .await
-            .with_context(|| "unable to get replies")?;
+            .with_context(|| format!("unable to remove username history of user '{user_id}'"))?;
 
-        Ok(posts
-            .into_iter()
-            .map(|reply| Reply {
-                id: Id::from_unchecked(reply.id),
-                content: Content::from_unchecked(reply.content),
-                creator_id: Id::from_unchecked(reply.creator_id),
-                post_id: Id::from_unchecked(reply.post_id),
-                date_created: reply.date_created,
-                date_edited: reply.date_edited,
-                deleted: reply.deleted != 0,
-            })
-            .collect())
+        Ok(())
     }
 
-    async fn reply_from_id(&self, id: &Id) -> Result<Option<Reply>, DatabaseError> {
+    async fn edit_last_active(&mut self, id: &UserId) -> Result<(), DatabaseError> {
+        let last_active = utc_date_iso_string();
+
+        sqlx::query!("UPDATE user SET last_active=? WHERE id=?;", last_active, id)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("unable to edit last active of user with id '{id}'"))?;
+
+        Ok(())
+    }
+
+    async fn edit_last_login(&mut self, id: &UserId) -> Result<(), DatabaseError> {
+        let last_login = utc_date_iso_string();
+
+        sqlx::query!("UPDATE user SET last_login=? WHERE id=?;", last_login, id)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("unable to edit last login of user with id '{id}'"))?;
+
+        Ok(())
+    }
+
+    async fn anonymize_content_from_creator(
+        &mut self,
+        creator_id: &UserId,
+    ) -> Result<(), DatabaseError> {
+        let date_created = utc_date_iso_string();
+        let permission = Permission::Banned;
+        let username_skeleton = skeleton(DELETED_USER_USERNAME);
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .with_context(|| "unable to begin transaction")?;
+
+        // the placeholder can never log in, its password is not a valid bcrypt hash
+        sqlx::query!(
+            "INSERT OR IGNORE INTO user (id, username, username_skeleton, password, permission, deleted, date_created) VALUES (?, ?, ?, '', ?, 1, ?);",
+            DELETED_USER_ID,
+            DELETED_USER_USERNAME,
+            username_skeleton,
+            permission,
+            date_created,
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| "unable to create deleted user placeholder")?;
+
+        sqlx::query!(
+            "UPDATE post SET creator_id=? WHERE creator_id=?;",
+            DELETED_USER_ID,
+            creator_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| "unable to anonymize posts")?;
+
+        sqlx::query!(
+            "UPDATE reply SET creator_id=? WHERE creator_id=?;",
+            DELETED_USER_ID,
+            creator_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| "unable to anonymize replies")?;
+
+        sqlx::query!(
+            "UPDATE attachment SET creator_id=? WHERE creator_id=?;",
+            DELETED_USER_ID,
+            creator_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| "unable to anonymize attachments")?;
+
+        transaction
+            .commit()
+            .await
+            .with_context(|| "unable to commit anonymized content")?;
+
+        Ok(())
+    }
+
+    async fn remove_content_from_creator(
+        &mut self,
+        creator_id: &UserId,
+    ) -> Result<(), DatabaseError> {
+        let date_edited = utc_date_iso_string();
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .with_context(|| "unable to begin transaction")?;
+
+        sqlx::query!(
+            "UPDATE post SET deleted=1, locked=1, date_edited=? WHERE creator_id=? AND deleted=0;",
+            date_edited,
+            creator_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| "unable to remove posts")?;
+
+        sqlx::query!(
+            "UPDATE reply SET deleted=1, date_edited=? WHERE creator_id=? AND deleted=0;",
+            date_edited,
+            creator_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| "unable to remove replies")?;
+
+        transaction
+            .commit()
+            .await
+            .with_context(|| "unable to commit removed content")?;
+
+        Ok(())
+    }
+
+    async fn remove_private_data_from_user(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), DatabaseError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .with_context(|| "unable to begin transaction")?;
+
+        sqlx::query!("DELETE FROM subscription WHERE user_id=?;", user_id)
+            .execute(&mut *transaction)
+            .await
+            .with_context(|| "unable to remove subscriptions")?;
+
+        sqlx::query!("DELETE FROM notification WHERE user_id=?;", user_id)
+            .execute(&mut *transaction)
+            .await
+            .with_context(|| "unable to remove notifications")?;
+
+        sqlx::query!("DELETE FROM reaction WHERE creator_id=?;", user_id)
+            .execute(&mut *transaction)
+            .await
+            .with_context(|| "unable to remove reactions")?;
+
+        sqlx::query!("DELETE FROM post_read WHERE user_id=?;", user_id)
+            .execute(&mut *transaction)
+            .await
+            .with_context(|| "unable to remove read statuses")?;
+
+        sqlx::query!("DELETE FROM category_visit WHERE user_id=?;", user_id)
+            .execute(&mut *transaction)
+            .await
+            .with_context(|| "unable to remove category visits")?;
+
+        sqlx::query!("DELETE FROM email_settings WHERE user_id=?;", user_id)
+            .execute(&mut *transaction)
+            .await
+            .with_context(|| "unable to remove email settings")?;
+
+        transaction
+            .commit()
+            .await
+            .with_context(|| format!("unable to commit removed data of user '{user_id}'"))?;
+
+        Ok(())
+    }
+
+    async fn category_from_id(&self, id: &CategoryId) -> Result<Option<Category>, DatabaseError> {
+        let category = sqlx::query!("SELECT * FROM category WHERE id=?;", id)
+            .fetch_optional(&self.pool)
+            .await
+            .with_context(|| format!("unable to get category with id='{id}'"))?;
+
+        Ok(category.map(|category| Category {
+            id: CategoryId::from_unchecked(category.id),
+            title: Title::from_unchecked(category.title),
+            minimum_read_permission: category.minimum_read_permission.into(),
+            minimum_write_permission: category.minimum_write_permission.into(),
+            date_created: category.date_created,
+            date_edited: category.date_edited,
+            qa_mode: category.qa_mode != 0,
+            deleted: category.deleted != 0,
+        }))
+    }
+
+    async fn posts_from_category(&self, id: &CategoryId) -> Result<Vec<Post>, DatabaseError> {
+        let posts = sqlx::query!("SELECT * FROM post WHERE category_id=?;", id)
+            .fetch_all(&self.pool)
+            .await
+            .with_context(|| "unable to get posts")?;
+
+        Ok(posts
+            .into_iter()
+            .map(|post| Post {
+                id: PostId::from_unchecked(post.id),
+                category_id: CategoryId::from_unchecked(post.category_id),
+                title: Title::from_unchecked(post.title),
+                content: Content::from_unchecked(post.content),
+                creator_id: UserId::from_unchecked(post.creator_id),
+                date_created: post.date_created,
+                date_edited: post.date_edited,
+                deleted: post.deleted != 0,
+                locked: post.locked != 0,
+                pinned: post.pinned != 0,
+                announcement: post.announcement != 0,
+                accepted_reply_id: post.accepted_reply_id.map(ReplyId::from_unchecked),
+            })
+            .collect())
+    }
+
+    async fn announcements(&self) -> Result<Vec<Post>, DatabaseError> {
+        let posts = sqlx::query!("SELECT * FROM post WHERE announcement=1;")
+            .fetch_all(&self.pool)
+            .await
+            .with_context(|| "unable to get announcements")?;
+
+        Ok(posts
+            .into_iter()
+            .map(|post| Post {
+                id: PostId::from_unchecked(post.id),
+                category_id: CategoryId::from_unchecked(post.category_id),
+                title: Title::from_unchecked(post.title),
+                content: Content::from_unchecked(post.content),
+                creator_id: UserId::from_unchecked(post.creator_id),
+                date_created: post.date_created,
+                date_edited: post.date_edited,
+                deleted: post.deleted != 0,
+                locked: post.locked != 0,
+                pinned: post.pinned != 0,
+                announcement: post.announcement != 0,
+                accepted_reply_id: post.accepted_reply_id.map(ReplyId::from_unchecked),
+            })
+            .collect())
+    }
+
+    async fn all_categories(&self) -> Result<Vec<Category>, DatabaseError> {
+        let categories = sqlx::query!("SELECT * FROM category;")
+            .fetch_all(&self.pool)
+            .await
+            .with_context(|| "unable to get categories")?;
+
+        Ok(categories
+            .into_iter()
+            .map(|category| Category {
+                id: CategoryId::from_unchecked(category.id),
+                title: Title::from_unchecked(category.title),
+                minimum_read_permission: category.minimum_read_permission.into(),
+                minimum_write_permission: category.minimum_write_permission.into(),
+                date_created: category.date_created,
+                date_edited: category.date_edited,
+                qa_mode: category.qa_mode != 0,
+                deleted: category.deleted != 0,
+            })
+            .collect())
+    }
+
+    async fn post_from_id(&self, id: &PostId) -> Result<Option<Post>, DatabaseError> {
+        let post = sqlx::query!("SELECT * FROM post WHERE id=?;", id)
+            .fetch_optional(&self.pool)
+            .await
+            .with_context(|| format!("unable to get post with id='{id}'"))?;
+
+        Ok(post.map(|post| Post {
+            id: PostId::from_unchecked(post.id),
+            category_id: CategoryId::from_unchecked(post.category_id),
+            title: Title::from_unchecked(post.title),
+            content: Content::from_unchecked(post.content),
+            creator_id: UserId::from_unchecked(post.creator_id),
+            date_created: post.date_created,
+            date_edited: post.date_edited,
+            deleted: post.deleted != 0,
+            locked: post.locked != 0,
+            pinned: post.pinned != 0,
+            announcement: post.announcement != 0,
+            accepted_reply_id: post.accepted_reply_id.map(ReplyId::from_unchecked),
+        }))
+    }
+
+    async fn replies_from_post(&self, id: &PostId) -> Result<Vec<Reply>, DatabaseError> {
+        let posts = sqlx::query!(
+            "SELECT * FROM reply WHERE post_id=? ORDER BY date_created, id;",
+            id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "unable to get replies")?;
+
+        Ok(posts
+            .into_iter()
+            .map(|reply| Reply {
+                id: ReplyId::from_unchecked(reply.id),
+                content: Content::from_unchecked(reply.content),
+                creator_id: UserId::from_unchecked(reply.creator_id),
+                post_id: PostId::from_unchecked(reply.post_id),
+                parent_reply_id: reply.parent_reply_id.map(ReplyId::from_unchecked),
+                quote: match (
+                    reply.quote_source,
+                    reply.quote_source_id,
+                    reply.quote_excerpt,
+                ) {
+                    (Some(source), Some(source_id), Some(excerpt)) => Some(Quote {
+                        source: source.into(),
+                        source_id: Id::from_unchecked(source_id),
+                        excerpt: Content::from_unchecked(excerpt),
+                    }),
+                    _ => None,
+                },
+                date_created: reply.date_created,
+                date_edited: reply.date_edited,
+                deleted: reply.deleted != 0,
+            })
+            .collect())
+    }
+
+    async fn posts_from_creator(&self, creator_id: &UserId) -> Result<Vec<Post>, DatabaseError> {
+        let posts = sqlx::query!(
+            "SELECT * FROM post WHERE creator_id=? ORDER BY date_created;",
+            creator_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "unable to get posts from creator")?;
+
+        Ok(posts
+            .into_iter()
+            .map(|post| Post {
+                id: PostId::from_unchecked(post.id),
+                category_id: CategoryId::from_unchecked(post.category_id),
+                title: Title::from_unchecked(post.title),
+                content: Content::from_unchecked(post.content),
+                creator_id: UserId::from_unchecked(post.creator_id),
+                date_created: post.date_created,
+                date_edited: post.date_edited,
+                deleted: post.deleted != 0,
+                locked: post.locked != 0,
+                pinned: post.pinned != 0,
+                announcement: post.announcement != 0,
+                accepted_reply_id: post.accepted_reply_id.map(ReplyId::from_unchecked),
+            })
+            .collect())
+    }
+
+    async fn replies_from_creator(&self, creator_id: &UserId) -> Result<Vec<Reply>, DatabaseError> {
+        let replies = sqlx::query!(
+            "SELECT * FROM reply WHERE creator_id=? ORDER BY date_created;",
+            creator_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "unable to get replies from creator")?;
+
+        Ok(replies
+            .into_iter()
+            .map(|reply| Reply {
+                id: ReplyId::from_unchecked(reply.id),
+                content: Content::from_unchecked(reply.content),
+                creator_id: UserId::from_unchecked(reply.creator_id),
+                post_id: PostId::from_unchecked(reply.post_id),
+                parent_reply_id: reply.parent_reply_id.map(ReplyId::from_unchecked),
+                quote: match (
+                    reply.quote_source,
+                    reply.quote_source_id,
+                    reply.quote_excerpt,
+                ) {
+                    (Some(source), Some(source_id), Some(excerpt)) => Some(Quote {
+                        source: source.into(),
+                        source_id: Id::from_unchecked(source_id),
+                        excerpt: Content::from_unchecked(excerpt),
+                    }),
+                    _ => None,
+                },
+                date_created: reply.date_created,
+                date_edited: reply.date_edited,
+                deleted: reply.deleted != 0,
+            })
+            .collect())
+    }
+
+    async fn reply_from_id(&self, id: &ReplyId) -> Result<Option<Reply>, DatabaseError> {
         let reply = sqlx::query!("SELECT * FROM reply WHERE id=?;", id)
             .fetch_optional(&self.pool)
             .await
             .with_context(|| "unable to get reply")?;
 
         Ok(reply.map(|reply| Reply {
-            id: Id::from_unchecked(reply.id),
+            id: ReplyId::from_unchecked(reply.id),
             content: Content::from_unchecked(reply.content),
-            creator_id: Id::from_unchecked(reply.creator_id),
-            post_id: Id::from_unchecked(reply.post_id),
+            creator_id: UserId::from_unchecked(reply.creator_id),
+            post_id: PostId::from_unchecked(reply.post_id),
+            parent_reply_id: reply.parent_reply_id.map(ReplyId::from_unchecked),
+            quote: match (
+                reply.quote_source,
+                reply.quote_source_id,
+                reply.quote_excerpt,
+            ) {
+                (Some(source), Some(source_id), Some(excerpt)) => Some(Quote {
+                    source: source.into(),
+                    source_id: Id::from_unchecked(source_id),
+                    excerpt: Content::from_unchecked(excerpt),
+                }),
+                _ => None,
+            },
             date_created: reply.date_created,
             date_edited: reply.date_edited,
             deleted: reply.deleted != 0,
         }))
     }
-    async fn attachment_from_id(&self, id: &Id) -> Result<Option<Attachment>, DatabaseError> {
+    async fn attachment_from_id(
+        &self,
+        id: &AttachmentId,
+    ) -> Result<Option<Attachment>, DatabaseError> {
         let attachment = sqlx::query!("SELECT * FROM attachment WHERE id=?;", id)
             .fetch_optional(&self.pool)
             .await
-            .with_context(|| "unable to get reply")?;
+            .with_context(|| "unable to get attachment")?;
 
         Ok(attachment.map(|attachment| Attachment {
-            id: Id::from_unchecked(attachment.id),
-            path: attachment.path,
-            creator_id: Id::from_unchecked(attachment.creator_id),
+            id: AttachmentId::from_unchecked(attachment.id),
+            blob_key: attachment.blob_key,
+            file_name: attachment.file_name,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            creator_id: UserId::from_unchecked(attachment.creator_id),
+            link: match (attachment.link_target, attachment.link_target_id) {
+                (Some(target), Some(target_id)) => Some(AttachmentLink {
+                    target: target.into(),
+                    target_id: Id::from_unchecked(target_id),
+                }),
+                _ => None,
+            },
             date_created: attachment.date_created,
         }))
     }
+
+    async fn attachments_from_creator(
+        &self,
+        creator_id: &UserId,
+    ) -> Result<Vec<Attachment>, DatabaseError> {
+        let attachments = sqlx::query!(
+            "SELECT * FROM attachment WHERE creator_id=? ORDER BY date_created DESC;",
+            creator_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "unable to get attachments from creator")?;
+
+        Ok(attachments
+            .into_iter()
+            .map(|attachment| Attachment {
+                id: AttachmentId::from_unchecked(attachment.id),
+                blob_key: attachment.blob_key,
+                file_name: attachment.file_name,
+                content_type: attachment.content_type,
+                size: attachment.size,
+                creator_id: UserId::from_unchecked(attachment.creator_id),
+                link: match (attachment.link_target, attachment.link_target_id) {
+                    (Some(target), Some(target_id)) => Some(AttachmentLink {
+                        target: target.into(),
+                        target_id: Id::from_unchecked(target_id),
+                    }),
+                    _ => None,
+                },
+                date_created: attachment.date_created,
+            })
+            .collect())
+    }
+
+    async fn attachments_from_target(
+        &self,
+        target: &AttachmentTarget,
+        target_id: &Id,
+    ) -> Result<Vec<Attachment>, DatabaseError> {
+        let attachments = sqlx::query!(
+            "SELECT * FROM attachment WHERE link_target=? AND link_target_id=? ORDER BY date_created;",
+            target,
+            target_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "unable to get attachments from target")?;
+
+        Ok(attachments
+            .into_iter()
+            .map(|attachment| Attachment {
+                id: AttachmentId::from_unchecked(attachment.id),
+                blob_key: attachment.blob_key,
+                file_name: attachment.file_name,
+                content_type: attachment.content_type,
+                size: attachment.size,
+                creator_id: UserId::from_unchecked(attachment.creator_id),
+                link: match (attachment.link_target, attachment.link_target_id) {
+                    (Some(target), Some(target_id)) => Some(AttachmentLink {
+                        target: target.into(),
+                        target_id: Id::from_unchecked(target_id),
+                    }),
+                    _ => None,
+                },
+                date_created: attachment.date_created,
+            })
+            .collect())
+    }
+
+    async fn blob_ref_count(&self, key: &str) -> Result<Option<i64>, DatabaseError> {
+        let blob = sqlx::query!("SELECT ref_count FROM blob WHERE key=?;", key)
+            .fetch_optional(&self.pool)
+            .await
+            .with_context(|| format!("unable to get blob with key {key}"))?;
+
+        Ok(blob.map(|blob| blob.ref_count))
+    }
+
+    async fn attachment_usage(
+        &self,
+        creator_id: &UserId,
+    ) -> Result<AttachmentUsage, DatabaseError> {
+        let usage = sqlx::query!(
+            r#"SELECT COALESCE(SUM(size), 0) AS "bytes!: i64", COUNT(*) AS "files!: i64" FROM attachment WHERE creator_id=?;"#,
+            creator_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .with_context(|| "unable to get attachment usage")?;
+
+        Ok(AttachmentUsage {
+            bytes: usage.bytes,
+            files: usage.files,
+        })
+    }
+
+    async fn user_activity(&self, user_id: &UserId) -> Result<UserActivity, DatabaseError> {
+        let activity = sqlx::query!(
+            r#"SELECT (SELECT COUNT(*) FROM post WHERE creator_id=?1 AND deleted=0) AS "post_count!: i64", (SELECT COUNT(*) FROM reply WHERE creator_id=?1 AND deleted=0) AS "reply_count!: i64";"#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .with_context(|| "unable to get user activity")?;
+
+        Ok(UserActivity {
+            post_count: activity.post_count,
+            reply_count: activity.reply_count,
+        })
+    }
+
+    async fn search_users(&self, data: &SearchUsers) -> Result<Vec<UserSummary>, DatabaseError> {
+        let pattern = data.search.as_deref().map(like_contains_pattern);
+        let oldest_first = matches!(data.sort, UserSort::Oldest);
+
+        let users = sqlx::query!(
+            r#"SELECT id, username, nickname, permission, deleted, date_created, last_login, last_active,
+                (SELECT COUNT(*) FROM post WHERE post.creator_id=user.id AND post.deleted=0) AS "post_count!: i64",
+                (SELECT COUNT(*) FROM reply WHERE reply.creator_id=user.id AND reply.deleted=0) AS "reply_count!: i64"
+            FROM user
+            WHERE (?1 IS NULL OR username LIKE ?1 ESCAPE '!' OR nickname LIKE ?1 ESCAPE '!')
+                AND (?2 IS NULL OR permission=?2)
+                AND (?3 IS NULL OR deleted=?3)
+            ORDER BY CASE WHEN ?4 THEN date_created END ASC, CASE WHEN ?4 THEN id END ASC,
+                date_created DESC, id DESC
+            LIMIT ?5 OFFSET ?6;"#,
+            pattern,
+            data.permission,
+            data.deleted,
+            oldest_first,
+            data.limit,
+            data.offset,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "unable to search users")?;
+
+        Ok(users
+            .into_iter()
+            .map(|user| UserSummary {
+                id: UserId::from_unchecked(user.id),
+                username: Name::from_unchecked(user.username),
+                nickname: user.nickname.map(Name::from_unchecked),
+                permission: user.permission.into(),
+                deleted: user.deleted != 0,
+                date_created: user.date_created,
+                last_login: user.last_login,
+                last_active: user.last_active,
+                post_count: user.post_count,
+                reply_count: user.reply_count,
+            })
+            .collect())
+    }
+
+    async fn count_users(&self, data: &SearchUsers) -> Result<i64, DatabaseError> {
+        let pattern = data.search.as_deref().map(like_contains_pattern);
+
+        let count = sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!: i64" FROM user
+            WHERE (?1 IS NULL OR username LIKE ?1 ESCAPE '!' OR nickname LIKE ?1 ESCAPE '!')
+                AND (?2 IS NULL OR permission=?2)
+                AND (?3 IS NULL OR deleted=?3);"#,
+            pattern,
+            data.permission,
+            data.deleted,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .with_context(|| "unable to count users")?;
+
+        Ok(count.count)
+    }
+
+    async fn unreferenced_attachments(
+        &self,
+        created_before: &str,
+    ) -> Result<Vec<Attachment>, DatabaseError> {
+        let attachments = sqlx::query!(
+            "SELECT * FROM attachment WHERE link_target IS NULL AND date_created < ? AND id NOT IN (SELECT avatar_id FROM user WHERE avatar_id IS NOT NULL);",
+            created_before
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "unable to get unreferenced attachments")?;
+
+        Ok(attachments
+            .into_iter()
+            .map(|attachment| Attachment {
+                id: AttachmentId::from_unchecked(attachment.id),
+                blob_key: attachment.blob_key,
+                file_name: attachment.file_name,
+                content_type: attachment.content_type,
+                size: attachment.size,
+                creator_id: UserId::from_unchecked(attachment.creator_id),
+                link: match (attachment.link_target, attachment.link_target_id) {
+                    (Some(target), Some(target_id)) => Some(AttachmentLink {
+                        target: target.into(),
+                        target_id: Id::from_unchecked(target_id),
+                    }),
+                    _ => None,
+                },
+                date_created: attachment.date_created,
+            })
+            .collect())
+    }
+
+    async fn reactions_from_target(
+        &self,
+        target: &ReactionTarget,
+        target_id: &Id,
+        user_id: Option<&UserId>,
+    ) -> Result<Vec<ReactionCount>, DatabaseError> {
+        let reactions = sqlx::query!(
+            "SELECT kind, COUNT(*) AS count, COALESCE(SUM(creator_id=?), 0) AS reacted FROM reaction WHERE target=? AND target_id=? GROUP BY kind;",
+            user_id,
+            target,
+            target_id,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("unable to get reactions for {target} with id='{target_id}'"))?;
+
+        Ok(reactions
+            .into_iter()
+            .map(|reaction| ReactionCount {
+                kind: reaction.kind.into(),
+                count: reaction.count,
+                reacted: reaction.reacted != 0,
+            })
+            .collect())
+    }
+
+    async fn read_status_from_post(
+        &self,
+        user_id: &UserId,
+        post_id: &PostId,
+    ) -> Result<Option<PostReadStatus>, DatabaseError> {
+        let statuses = self
+            .read_statuses_where(user_id, None, Some(post_id))
+            .await?;
+
+        Ok(statuses.into_iter().next())
+    }
+
+    async fn read_statuses(
+        &self,
+        user_id: &UserId,
+        category_id: Option<&CategoryId>,
+    ) -> Result<Vec<PostReadStatus>, DatabaseError> {
+        self.read_statuses_where(user_id, category_id, None).await
+    }
+
+    async fn category_visit(
+        &self,
+        user_id: &UserId,
+        category_id: &CategoryId,
+    ) -> Result<Option<String>, DatabaseError> {
+        let visit = sqlx::query!(
+            "SELECT date_visited FROM category_visit WHERE user_id=? AND category_id=?;",
+            user_id,
+            category_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| {
+            format!("unable to get visit of user '{user_id}' to category '{category_id}'")
+        })?;
+
+        Ok(visit.map(|visit| visit.date_visited))
+    }
+
+    async fn mark_post_read(&mut self, data: MarkPostRead) -> Result<(), DatabaseError> {
+        let date_read = utc_date_iso_string();
+
+        sqlx::query!(
+            "INSERT INTO post_read (user_id, post_id, last_read_reply_id, date_read) VALUES (?1, ?2, COALESCE(?3, (SELECT id FROM reply WHERE post_id=?2 ORDER BY date_created DESC, id DESC LIMIT 1)), ?4) ON CONFLICT(user_id, post_id) DO UPDATE SET last_read_reply_id=excluded.last_read_reply_id, date_read=excluded.date_read;",
+            data.user_id,
+            data.post_id,
+            data.last_read_reply_id,
+            date_read,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("unable to mark post '{}' as read", data.post_id))?;
+
+        Ok(())
+    }
+
+    async fn mark_category_read(
+        &mut self,
+        user_id: &UserId,
+        category_id: &CategoryId,
+    ) -> Result<(), DatabaseError> {
+        let date_read = utc_date_iso_string();
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .with_context(|| "unable to begin transaction")?;
+
+        sqlx::query!(
+            "INSERT INTO post_read (user_id, post_id, last_read_reply_id, date_read) SELECT ?1, post.id, (SELECT reply.id FROM reply WHERE reply.post_id=post.id ORDER BY reply.date_created DESC, reply.id DESC LIMIT 1), ?3 FROM post WHERE post.category_id=?2 ON CONFLICT(user_id, post_id) DO UPDATE SET last_read_reply_id=excluded.last_read_reply_id, date_read=excluded.date_read;",
+            user_id,
+            category_id,
+            date_read,
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| format!("unable to mark posts of category '{category_id}' as read"))?;
+
+        sqlx::query!(
+            "INSERT INTO category_visit (user_id, category_id, date_visited) VALUES (?, ?, ?) ON CONFLICT(user_id, category_id) DO UPDATE SET date_visited=excluded.date_visited;",
+            user_id,
+            category_id,
+            date_read,
+        )
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| format!("unable to record visit to category '{category_id}'"))?;
+
+        transaction
+            .commit()
+            .await
+            .with_context(|| "unable to commit read category")?;
+
+        Ok(())
+    }
+
+    async fn edit_category_visit(
+        &mut self,
+        user_id: &UserId,
+        category_id: &CategoryId,
+    ) -> Result<(), DatabaseError> {
+        let date_visited = utc_date_iso_string();
+
+        sqlx::query!(
+            "INSERT INTO category_visit (user_id, category_id, date_visited) VALUES (?, ?, ?) ON CONFLICT(user_id, category_id) DO UPDATE SET date_visited=excluded.date_visited;",
+            user_id,
+            category_id,
+            date_visited,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("unable to record visit to category '{category_id}'"))?;
+
+        Ok(())
+    }
+
+    async fn create_subscription(&mut self, data: CreateSubscription) -> Result<(), DatabaseError> {
+        let date_created = utc_date_iso_string();
+
+        sqlx::query!(
+            "INSERT OR IGNORE INTO subscription (user_id, target, target_id, level, date_created) VALUES (?, ?, ?, ?, ?);",
+            data.user_id,
+            data.target,
+            data.target_id,
+            data.level,
+            date_created,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "unable to insert subscription")?;
+
+        Ok(())
+    }
+
+    async fn edit_subscription(&mut self, data: CreateSubscription) -> Result<(), DatabaseError> {
+        let date_created = utc_date_iso_string();
+
+        sqlx::query!(
+            "INSERT INTO subscription (user_id, target, target_id, level, date_created) VALUES (?, ?, ?, ?, ?) ON CONFLICT(user_id, target, target_id) DO UPDATE SET level=excluded.level;",
+            data.user_id,
+            data.target,
+            data.target_id,
+            data.level,
+            date_created,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "unable to save subscription")?;
+
+        Ok(())
+    }
+
+    async fn remove_subscription(
+        &mut self,
+        user_id: &UserId,
+        target: &SubscriptionTarget,
+        target_id: &Id,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "DELETE FROM subscription WHERE user_id=? AND target=? AND target_id=?;",
+            user_id,
+            target,
+            target_id,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "unable to remove subscription")?;
+
+        Ok(())
+    }
+
+    async fn subscription(
+        &self,
+        user_id: &UserId,
+        target: &SubscriptionTarget,
+        target_id: &Id,
+    ) -> Result<Option<Subscription>, DatabaseError> {
+        let subscription = sqlx::query!(
+            "SELECT * FROM subscription WHERE user_id=? AND target=? AND target_id=?;",
+            user_id,
+            target,
+            target_id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| format!("unable to get subscription to {target} with id='{target_id}'"))?;
+
+        Ok(subscription.map(|subscription| Subscription {
+            user_id: UserId::from_unchecked(subscription.user_id),
+            target: subscription.target.into(),
+            target_id: Id::from_unchecked(subscription.target_id),
+            level: subscription.level.into(),
+            date_created: subscription.date_created,
+        }))
+    }
+
+    async fn subscriptions_from_user(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<Subscription>, DatabaseError> {
+        let subscriptions = sqlx::query!(
+            "SELECT * FROM subscription WHERE user_id=? ORDER BY date_created DESC;",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("unable to get subscriptions of user '{user_id}'"))?;
+
+        Ok(subscriptions
+            .into_iter()
+            .map(|subscription| Subscription {
+                user_id: UserId::from_unchecked(subscription.user_id),
+                target: subscription.target.into(),
+                target_id: Id::from_unchecked(subscription.target_id),
+                level: subscription.level.into(),
+                date_created: subscription.date_created,
+            })
+            .collect())
+    }
+
+    async fn subscriptions_from_targets(
+        &self,
+        category_id: &CategoryId,
+        post_id: Option<&PostId>,
+    ) -> Result<Vec<Subscription>, DatabaseError> {
+        let subscriptions = sqlx::query!(
+            "SELECT * FROM subscription WHERE (target='Category' AND target_id=?1) OR (target='Post' AND target_id=?2);",
+            category_id,
+            post_id,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("unable to get subscriptions to category '{category_id}'"))?;
+
+        Ok(subscriptions
+            .into_iter()
+            .map(|subscription| Subscription {
+                user_id: UserId::from_unchecked(subscription.user_id),
+                target: subscription.target.into(),
+                target_id: Id::from_unchecked(subscription.target_id),
+                level: subscription.level.into(),
+                date_created: subscription.date_created,
+            })
+            .collect())
+    }
+
+    async fn create_notification(&mut self, data: CreateNotification) -> Result<(), DatabaseError> {
+        let mut attempt = 1;
+        let date_created = utc_date_iso_string();
+
+        loop {
+            let id = NotificationId::new();
+            let result = sqlx::query!(
+                "INSERT INTO notification (id, user_id, kind, post_id, reply_id, creator_id, date_created) VALUES (?, ?, ?, ?, ?, ?, ?);",
+                id,
+                data.user_id,
+                data.kind,
+                data.post_id,
+                data.reply_id,
+                data.creator_id,
+                date_created,
+            )
+            .execute(&self.pool)
+            .await;
+
+            match result {
+                Err(err)
+                    if attempt < MAX_ID_ATTEMPTS && is_id_collision(&err, "notification.id") =>
+                {
+                    attempt += 1
+                }
+                result => {
+                    result.with_context(|| "unable to insert notification")?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    async fn notifications_from_user(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<Notification>, DatabaseError> {
+        let notifications = sqlx::query!(
+            "SELECT * FROM notification WHERE user_id=? ORDER BY date_created DESC;",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("unable to get notifications of user '{user_id}'"))?;
+
+        Ok(notifications
+            .into_iter()
+            .map(|notification| Notification {
+                id: NotificationId::from_unchecked(notification.id),
+                user_id: UserId::from_unchecked(notification.user_id),
+                kind: notification.kind.into(),
+                post_id: PostId::from_unchecked(notification.post_id),
+                reply_id: notification.reply_id.map(ReplyId::from_unchecked),
+                creator_id: UserId::from_unchecked(notification.creator_id),
+                date_created: notification.date_created,
+            })
+            .collect())
+    }
+
+    async fn email_settings(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<EmailSettings>, DatabaseError> {
+        let settings = sqlx::query!("SELECT * FROM email_settings WHERE user_id=?;", user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .with_context(|| format!("unable to get email settings of user '{user_id}'"))?;
+
+        Ok(settings.map(|settings| EmailSettings {
+            user_id: UserId::from_unchecked(settings.user_id),
+            email: Email::from_unchecked(settings.email),
+            frequency: settings.frequency.into(),
+            unsubscribe_token: settings.unsubscribe_token,
+            last_sent: settings.last_sent,
+            verified: settings.verified != 0,
+            confirmation_token: settings.confirmation_token,
+            confirmation_sent: settings.confirmation_sent,
+            date_edited: settings.date_edited,
+        }))
+    }
+
+    async fn email_settings_from_unsubscribe_token(
+        &self,
+        unsubscribe_token: &str,
+    ) -> Result<Option<EmailSettings>, DatabaseError> {
+        let settings = sqlx::query!(
+            "SELECT * FROM email_settings WHERE unsubscribe_token=?;",
+            unsubscribe_token
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| "unable to get email settings from unsubscribe token")?;
+
+        Ok(settings.map(|settings| EmailSettings {
+            user_id: UserId::from_unchecked(settings.user_id),
+            email: Email::from_unchecked(settings.email),
+            frequency: settings.frequency.into(),
+            unsubscribe_token: settings.unsubscribe_token,
+            last_sent: settings.last_sent,
+            verified: settings.verified != 0,
+            confirmation_token: settings.confirmation_token,
+            confirmation_sent: settings.confirmation_sent,
+            date_edited: settings.date_edited,
+        }))
+    }
+
+    async fn email_settings_from_confirmation_token(
+        &self,
+        confirmation_token: &str,
+    ) -> Result<Option<EmailSettings>, DatabaseError> {
+        let settings = sqlx::query!(
+            "SELECT * FROM email_settings WHERE confirmation_token=?;",
+            confirmation_token
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .with_context(|| "unable to get email settings from confirmation token")?;
+
+        Ok(settings.map(|settings| EmailSettings {
+            user_id: UserId::from_unchecked(settings.user_id),
+            email: Email::from_unchecked(settings.email),
+            frequency: settings.frequency.into(),
+            unsubscribe_token: settings.unsubscribe_token,
+            last_sent: settings.last_sent,
+            verified: settings.verified != 0,
+            confirmation_token: settings.confirmation_token,
+            confirmation_sent: settings.confirmation_sent,
+            date_edited: settings.date_edited,
+        }))
+    }
+
+    async fn email_settings_to_send(&self) -> Result<Vec<EmailSettings>, DatabaseError> {
+        let off = EmailFrequency::Off;
+        let settings = sqlx::query!(
+            "SELECT * FROM email_settings WHERE frequency!=? AND verified=1;",
+            off
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| "unable to get email settings to send")?;
+
+        Ok(settings
+            .into_iter()
+            .map(|settings| EmailSettings {
+                user_id: UserId::from_unchecked(settings.user_id),
+                email: Email::from_unchecked(settings.email),
+                frequency: settings.frequency.into(),
+                unsubscribe_token: settings.unsubscribe_token,
+                last_sent: settings.last_sent,
+                verified: settings.verified != 0,
+                confirmation_token: settings.confirmation_token,
+                confirmation_sent: settings.confirmation_sent,
+                date_edited: settings.date_edited,
+            })
+            .collect())
+    }
+
+    async fn edit_email_settings(&mut self, data: EditEmailSettings) -> Result<(), DatabaseError> {
+        let date_edited = utc_date_iso_string();
+
+        sqlx::query!(
+            "INSERT INTO email_settings (user_id, email, frequency, unsubscribe_token, last_sent, verified, confirmation_token, confirmation_sent, date_edited) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT(user_id) DO UPDATE SET email=excluded.email, frequency=excluded.frequency, unsubscribe_token=excluded.unsubscribe_token, last_sent=excluded.last_sent, verified=excluded.verified, confirmation_token=excluded.confirmation_token, confirmation_sent=excluded.confirmation_sent, date_edited=excluded.date_edited;",
+            data.user_id,
+            data.email,
+            data.frequency,
+            data.unsubscribe_token,
+            data.last_sent,
+            data.verified,
+            data.confirmation_token,
+            data.confirmation_sent,
+            date_edited,
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| "unable to save email settings")?;
+
+        Ok(())
+    }
+
+    async fn edit_email_frequency(
+        &mut self,
+        user_id: &UserId,
+        frequency: &EmailFrequency,
+    ) -> Result<(), DatabaseError> {
+        let date_edited = utc_date_iso_string();
+
+        sqlx::query!(
+            "UPDATE email_settings SET frequency=?, date_edited=? WHERE user_id=?;",
+            frequency,
+            date_edited,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("unable to edit email frequency of user '{user_id}'"))?;
+
+        Ok(())
+    }
+
+    async fn edit_email_last_sent(
+        &mut self,
+        user_id: &UserId,
+        last_sent: &str,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "UPDATE email_settings SET last_sent=? WHERE user_id=?;",
+            last_sent,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("unable to edit last sent email of user '{user_id}'"))?;
+
+        Ok(())
+    }
+
+    async fn confirm_email(
+        &mut self,
+        user_id: &UserId,
+        last_sent: &str,
+    ) -> Result<(), DatabaseError> {
+        let date_edited = utc_date_iso_string();
+
+        sqlx::query!(
+            "UPDATE email_settings SET verified=1, confirmation_token=NULL, confirmation_sent=NULL, last_sent=?, date_edited=? WHERE user_id=?;",
+            last_sent,
+            date_edited,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("unable to confirm email of user '{user_id}'"))?;
+
+        Ok(())
+    }
+
+    async fn remove_email_settings(&mut self, user_id: &UserId) -> Result<(), DatabaseError> {
+        sqlx::query!("DELETE FROM email_settings WHERE user_id=?;", user_id)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("unable to remove email settings of user '{user_id}'"))?;
+
+        Ok(())
+    }
+
+    async fn replies_for_email_digest(
+        &self,
+        user_id: &UserId,
+        after: &str,
+        until: &str,
+    ) -> Result<Vec<Reply>, DatabaseError> {
+        let replies = sqlx::query!(
+            "SELECT reply.* FROM reply JOIN post ON post.id=reply.post_id WHERE reply.deleted=0 AND post.deleted=0 AND reply.creator_id!=?1 AND reply.date_created>?2 AND reply.date_created<=?3 AND (post.creator_id=?1 OR EXISTS (SELECT 1 FROM reply AS own_reply WHERE own_reply.post_id=post.id AND own_reply.creator_id=?1 AND own_reply.deleted=0)) ORDER BY reply.date_created;",
+            user_id,
+            after,
+            until
+        )
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("unable to get replies for email digest of user '{user_id}'"))?;
+
+        Ok(replies
+            .into_iter()
+            .map(|reply| Reply {
+                id: ReplyId::from_unchecked(reply.id),
+                content: Content::from_unchecked(reply.content),
+                creator_id: UserId::from_unchecked(reply.creator_id),
+                post_id: PostId::from_unchecked(reply.post_id),
+                parent_reply_id: reply.parent_reply_id.map(ReplyId::from_unchecked),
+                quote: match (
+                    reply.quote_source,
+                    reply.quote_source_id,
+                    reply.quote_excerpt,
+                ) {
+                    (Some(source), Some(source_id), Some(excerpt)) => Some(Quote {
+                        source: source.into(),
+                        source_id: Id::from_unchecked(source_id),
+                        excerpt: Content::from_unchecked(excerpt),
+                    }),
+                    _ => None,
+                },
+                date_created: reply.date_created,
+                date_edited: reply.date_edited,
+                deleted: reply.deleted != 0,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_user(db: &mut SqliteDb, username: &str) -> UserId {
+        db.create_user(CreateUser {
+            username: Name::from_unchecked(username.to_string()),
+            nickname: None,
+            password: HashedPassword::from_unchecked(String::new()),
+            permission: Permission::User,
+            avatar_id: None,
+        })
+        .await
+        .expect("user should be created")
+    }
+
+    fn search(search: Option<&str>, sort: UserSort, limit: i64, offset: i64) -> SearchUsers {
+        SearchUsers {
+            search: search.map(str::to_string),
+            permission: None,
+            deleted: None,
+            sort,
+            limit,
+            offset,
+        }
+    }
+
+    async fn usernames(db: &SqliteDb, search: SearchUsers) -> Vec<String> {
+        db.search_users(&search)
+            .await
+            .expect("users should be searched")
+            .into_iter()
+            .map(|user| user.username.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn detects_id_collisions() {
+        let mut db = SqliteDb::in_memory().await;
+        let _ = db
+            .create_category(CreateCategory {
+                title: Title::from_unchecked("category".to_string()),
+                minimum_write_permission: Permission::User,
+                minimum_read_permission: Permission::default(),
+                qa_mode: false,
+            })
+            .await
+            .expect("category should be created");
+
+        let err = sqlx::query("INSERT INTO category SELECT * FROM category;")
+            .execute(&db.pool)
+            .await
+            .expect_err("ids should collide");
+        assert!(is_id_collision(&err, "category.id"));
+        assert!(!is_id_collision(&err, "post.id"));
+    }
+
+    async fn read_status(db: &SqliteDb, user_id: &UserId, post_id: &PostId) -> PostReadStatus {
+        db.read_statuses_where(user_id, None, Some(post_id))
+            .await
+            .expect("read status should load")
+            .pop()
+            .expect("post should have a read status")
+    }
+
+    #[tokio::test]
+    async fn orders_replies_created_at_the_same_time_by_id() {
+        let mut db = SqliteDb::in_memory().await;
+        let user_id = create_user(&mut db, "reader").await;
+        let category_id = db
+            .create_category(CreateCategory {
+                title: Title::from_unchecked("category".to_string()),
+                minimum_write_permission: Permission::User,
+                minimum_read_permission: Permission::default(),
+                qa_mode: false,
+            })
+            .await
+            .expect("category should be created");
+        let post_id = db
+            .create_post(CreatePost {
+                category_id: category_id.clone(),
+                title: Title::from_unchecked("post".to_string()),
+                content: Content::from_unchecked("content".to_string()),
+                creator_id: user_id.clone(),
+            })
+            .await
+            .expect("post should be created");
+        let mut reply_ids = Vec::new();
+        for _ in 0..3 {
+            let reply_id = db
+                .create_reply(CreateReply {
+                    creator_id: user_id.clone(),
+                    post_id: post_id.clone(),
+                    parent_reply_id: None,
+                    quote: None,
+                    content: Content::from_unchecked("reply".to_string()),
+                })
+                .await
+                .expect("reply should be created");
+            reply_ids.push(reply_id.to_string());
+        }
+        sqlx::query("UPDATE reply SET date_created='2024-01-01T00:00:00.000Z';")
+            .execute(&db.pool)
+            .await
+            .expect("dates should be set");
+        reply_ids.sort();
+
+        db.mark_post_read(MarkPostRead {
+            user_id: user_id.clone(),
+            post_id: post_id.clone(),
+            last_read_reply_id: Some(ReplyId::from_unchecked(reply_ids[0].clone())),
+        })
+        .await
+        .expect("post should be marked read");
+        let status = read_status(&db, &user_id, &post_id).await;
+        assert_eq!(status.unread_count, 2);
+        assert_eq!(
+            status
+                .first_unread_reply_id
+                .map(|reply_id| reply_id.to_string()),
+            Some(reply_ids[1].clone())
+        );
+
+        db.mark_category_read(&user_id, &category_id)
+            .await
+            .expect("category should be marked read");
+        assert_eq!(read_status(&db, &user_id, &post_id).await.unread_count, 0);
+
+        db.mark_post_read(MarkPostRead {
+            user_id: user_id.clone(),
+            post_id: post_id.clone(),
+            last_read_reply_id: None,
+        })
+        .await
+        .expect("post should be marked read");
+        assert_eq!(read_status(&db, &user_id, &post_id).await.unread_count, 0);
+
+        sqlx::query("UPDATE reply SET date_created='2023-01-01T00:00:00.000Z' WHERE id=?;")
+            .bind(&reply_ids[2])
+            .execute(&db.pool)
+            .await
+            .expect("date should be set");
+        let replies: Vec<String> = db
+            .replies_from_post(&post_id)
+            .await
+            .expect("replies should load")
+            .into_iter()
+            .map(|reply| reply.id.to_string())
+            .collect();
+        assert_eq!(
+            replies,
+            [&reply_ids[2], &reply_ids[0], &reply_ids[1]].map(String::clone)
+        );
+    }
+
+    #[test]
+    fn escapes_like_wildcards() {
+        assert_eq!(like_contains_pattern("ann"), "%ann%");
+        assert_eq!(like_contains_pattern("a_b%c!"), "%a!_b!%c!!%");
+    }
+
+    #[tokio::test]
+    async fn searches_wildcards_literally() {
+        let mut db = SqliteDb::in_memory().await;
+        let _ = create_user(&mut db, "a_b").await;
+        let _ = create_user(&mut db, "axb").await;
+        let _ = create_user(&mut db, "100%").await;
+
+        assert_eq!(
+            usernames(&db, search(Some("_"), UserSort::Newest, 10, 0)).await,
+            ["a_b"]
+        );
+        assert_eq!(
+            usernames(&db, search(Some("%"), UserSort::Newest, 10, 0)).await,
+            ["100%"]
+        );
+        assert_eq!(
+            usernames(&db, search(Some("A"), UserSort::Newest, 10, 0))
+                .await
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn pages_users_created_at_the_same_time() {
+        let mut db = SqliteDb::in_memory().await;
+        let mut ids = Vec::new();
+        for username in ["first", "second", "third"] {
+            ids.push(create_user(&mut db, username).await.to_string());
+        }
+        sqlx::query("UPDATE user SET date_created='2024-01-01T00:00:00.000Z';")
+            .execute(&db.pool)
+            .await
+            .expect("dates should be set");
+        ids.sort();
+
+        for (sort, expected) in [
+            (UserSort::Oldest, ids.clone()),
+            (UserSort::Newest, ids.iter().rev().cloned().collect()),
+        ] {
+            let mut paged = Vec::new();
+            for offset in 0..3 {
+                let page = db
+                    .search_users(&search(None, sort, 1, offset))
+                    .await
+                    .expect("users should be searched");
+                paged.extend(page.into_iter().map(|user| user.id.to_string()));
+            }
+            assert_eq!(paged, expected);
+        }
+    }
 }
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use eyre::Context;
+use lettre::{message::Mailbox, Message};
+
+use crate::{db::models::Email, email_digest::EmailDigestConfig};
+
+/// how long a confirmation link works after it was sent
+pub const CONFIRMATION_VALIDITY: Duration = Duration::from_secs(24 * 60 * 60);
+/// saving the settings again within this only keeps the pending confirmation, so nobody can
+/// flood an address that is not theirs
+pub const RESEND_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// the only email sent to an address before its owner confirmed they want emails
+pub fn compose(config: &EmailDigestConfig, email: &Email, token: &str) -> eyre::Result<Message> {
+    let confirm_url = format!("{}/confirm_email?token={token}", config.frontend_url);
+    let hours = CONFIRMATION_VALIDITY.as_secs() / 60 / 60;
+
+    let to: Mailbox = email
+        .to_string()
+        .parse()
+        .with_context(|| "invalid email address to confirm")?;
+
+    Message::builder()
+        .from(config.from.clone())
+        .to(to)
+        .subject("Confirm your email address")
+        .body(format!(
+            "Someone asked to get emails about new replies on the forum at this address.\n\
+            To confirm it was you, open {confirm_url}\n\
+            The link works for {hours} hours. If it was not you, ignore this email and \
+            nothing else will be sent.\n"
+        ))
+        .with_context(|| "unable to build confirmation email")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_unchecked::FromUnchecked;
+
+    #[test]
+    fn links_to_the_confirmation_page() {
+        let config = EmailDigestConfig {
+            from: "forum@example.com"
+                .parse()
+                .expect("address should be valid"),
+            public_url: "https://api.example.com".to_string(),
+            frontend_url: "https://forum.example.com".to_string(),
+        };
+        let message = compose(
+            &config,
+            &Email::from_unchecked("reader@example.com".to_string()),
+            "token",
+        )
+        .expect("email should be composed");
+
+        assert_eq!(message.headers().get_raw("To"), Some("reader@example.com"));
+        // undoes the quoted-printable soft line breaks and equal signs
+        let email = String::from_utf8(message.formatted())
+            .expect("email should be utf-8")
+            .replace("=\r\n", "")
+            .replace("=3D", "=");
+        assert!(email.contains("https://forum.example.com/confirm_email?token=token"));
+        assert!(email.contains("24 hours"));
+    }
+}
@@ -0,0 +1,709 @@
+use std::{collections::HashMap, fmt::Write, sync::Arc, time::Duration};
+
+use eyre::{eyre, Context};
+use lettre::{
+    message::{
+        header::{HeaderName, HeaderValue},
+        Mailbox, MultiPart,
+    },
+    Message,
+};
+use sqlx::types::chrono::DateTime;
+use tokio::sync::RwLockReadGuard;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    db::{
+        database::{Database, DatabaseParam},
+        models::{
+            CategoryId, Content, EmailFrequency, EmailSettings, Id, Name, PostId, Reply,
+            SubscriptionLevel, SubscriptionTarget, Title, UserId,
+        },
+    },
+    iso_date_strings::{utc_date_iso_string, utc_date_iso_string_ago},
+    mail::transport::MailTransportParam,
+    permission_verification, subscriptions,
+};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+/// replies past this are only counted, so a busy week still makes a readable email
+const MAX_REPLIES_PER_EMAIL: usize = 50;
+/// in graphemes
+const EXCERPT_LENGTH: usize = 500;
+
+/// also used for the mails confirming an address
+pub struct EmailDigestConfig {
+    pub from: Mailbox,
+    /// where the api is reached from outside without a trailing slash, mail clients post
+    /// to the one click unsubscribe link under it
+    pub public_url: String,
+    /// where the forum is opened in a browser without a trailing slash, links meant for
+    /// people start with it
+    pub frontend_url: String,
+}
+
+pub type EmailDigestConfigParam = Arc<EmailDigestConfig>;
+
+struct DigestReply {
+    author: Name,
+    content: Content,
+    date_created: String,
+}
+
+struct DigestPost {
+    id: PostId,
+    category_id: CategoryId,
+    title: Title,
+    replies: Vec<DigestReply>,
+}
+
+/// how long to wait since the last email, none when the user gets no emails
+fn period(frequency: EmailFrequency) -> Option<Duration> {
+    match frequency {
+        EmailFrequency::Immediate => Some(Duration::ZERO),
+        EmailFrequency::Daily => Some(DAY),
+        EmailFrequency::Weekly => Some(7 * DAY),
+        EmailFrequency::Off => None,
+    }
+}
+
+/// new replies grouped by post, leaving out posts the user muted or can no longer read
+async fn gather_posts<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    settings: &EmailSettings,
+    until: &str,
+) -> eyre::Result<Vec<DigestPost>> {
+    let Some(user) = db
+        .user_from_id(&settings.user_id)
+        .await?
+        .filter(|user| !user.deleted)
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut replies_by_post: Vec<(PostId, Vec<Reply>)> = Vec::new();
+    for reply in db
+        .replies_for_email_digest(&user.id, &settings.last_sent, until)
+        .await?
+    {
+        match replies_by_post
+            .iter_mut()
+            .find(|(post_id, _)| *post_id == reply.post_id)
+        {
+            Some((_, replies)) => replies.push(reply),
+            None => replies_by_post.push((reply.post_id.clone(), vec![reply])),
+        }
+    }
+
+    let mut authors: HashMap<String, Name> = HashMap::new();
+    let mut posts = Vec::new();
+    for (post_id, replies) in replies_by_post {
+        let post = db
+            .post_from_id(&post_id)
+            .await?
+            .ok_or_else(|| eyre!("post '{post_id}' does not exist"))?;
+        let category = db
+            .category_from_id(&post.category_id)
+            .await?
+            .ok_or_else(|| eyre!("category '{}' does not exist", post.category_id))?;
+        if category.deleted
+            || !permission_verification::is_allowed(
+                &user.permission,
+                &category.minimum_read_permission,
+            )
+        {
+            continue;
+        }
+
+        let post_subscription = db
+            .subscription(&user.id, &SubscriptionTarget::Post, &Id::from(&post.id))
+            .await?;
+        let category_subscription = db
+            .subscription(
+                &user.id,
+                &SubscriptionTarget::Category,
+                &Id::from(&post.category_id),
+            )
+            .await?;
+        if subscriptions::effective_level(
+            post_subscription.map(|subscription| subscription.level),
+            category_subscription.map(|subscription| subscription.level),
+        ) == Some(SubscriptionLevel::Muted)
+        {
+            continue;
+        }
+
+        let mut digest_replies = Vec::new();
+        for reply in replies {
+            let author = match authors.get(reply.creator_id.to_string().as_str()) {
+                Some(author) => author.clone(),
+                None => {
+                    let author = author_name(db, &reply.creator_id).await?;
+                    authors.insert(reply.creator_id.to_string(), author.clone());
+                    author
+                }
+            };
+            digest_replies.push(DigestReply {
+                author,
+                content: reply.content,
+                date_created: reply.date_created,
+            });
+        }
+
+        posts.push(DigestPost {
+            id: post.id,
+            category_id: post.category_id,
+            title: post.title,
+            replies: digest_replies,
+        });
+    }
+
+    Ok(posts)
+}
+
+async fn author_name<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user_id: &UserId,
+) -> eyre::Result<Name> {
+    let user = db
+        .user_from_id(user_id)
+        .await?
+        .ok_or_else(|| eyre!("user '{user_id}' does not exist"))?;
+
+    Ok(user.username)
+}
+
+fn replies_noun(count: usize) -> &'static str {
+    if count == 1 {
+        "reply"
+    } else {
+        "replies"
+    }
+}
+
+fn excerpt(content: &str) -> String {
+    let mut graphemes = content.graphemes(true);
+    let excerpt: String = graphemes.by_ref().take(EXCERPT_LENGTH).collect();
+    if graphemes.next().is_some() {
+        format!("{excerpt}…")
+    } else {
+        excerpt
+    }
+}
+
+fn readable_date(date: &str) -> String {
+    DateTime::parse_from_rfc3339(date).map_or_else(
+        |_| date.to_string(),
+        |date| date.format("%Y-%m-%d %H:%M UTC").to_string(),
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+fn compose(
+    config: &EmailDigestConfig,
+    settings: &EmailSettings,
+    posts: &[DigestPost],
+) -> eyre::Result<Message> {
+    let reply_count: usize = posts.iter().map(|post| post.replies.len()).sum();
+    let subject = match posts {
+        [post] => format!(
+            "{reply_count} new {} in \"{}\"",
+            replies_noun(reply_count),
+            post.title
+        ),
+        _ => format!(
+            "{reply_count} new {} in {} posts",
+            replies_noun(reply_count),
+            posts.len()
+        ),
+    };
+    let unsubscribe_url = format!(
+        "{}/unsubscribe_email?token={}",
+        config.frontend_url, settings.unsubscribe_token
+    );
+    let one_click_unsubscribe_url = format!(
+        "{}/users/unsubscribe_email?token={}",
+        config.public_url, settings.unsubscribe_token
+    );
+    let frequency = match settings.frequency {
+        EmailFrequency::Immediate => "as new replies come in",
+        EmailFrequency::Daily => "once a day",
+        EmailFrequency::Weekly => "once a week",
+        EmailFrequency::Off => "never",
+    };
+
+    let mut text = String::new();
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<body>\n");
+    let mut shown = 0;
+    for post in posts {
+        if shown == MAX_REPLIES_PER_EMAIL {
+            break;
+        }
+        let post_url = format!(
+            "{}/posts/{}/{}",
+            config.frontend_url, post.category_id, post.id
+        );
+        writeln!(text, "{}\n{post_url}\n", post.title)?;
+        writeln!(
+            html,
+            "<h2><a href=\"{}\">{}</a></h2>",
+            escape_html(&post_url),
+            escape_html(&post.title.to_string())
+        )?;
+
+        for reply in post.replies.iter().take(MAX_REPLIES_PER_EMAIL - shown) {
+            let date = readable_date(&reply.date_created);
+            let content = excerpt(&reply.content.to_string());
+            writeln!(text, "{} wrote on {date}:\n{content}\n", reply.author)?;
+            writeln!(
+                html,
+                "<p><strong>{}</strong> wrote on {}:</p>\n<p style=\"white-space: pre-wrap\">{}</p>",
+                escape_html(&reply.author.to_string()),
+                escape_html(&date),
+                escape_html(&content)
+            )?;
+            shown += 1;
+        }
+    }
+
+    if reply_count > shown {
+        let hidden = reply_count - shown;
+        let more = format!("and {hidden} more {} on the forum", replies_noun(hidden));
+        writeln!(text, "{more}\n")?;
+        writeln!(html, "<p>{}</p>", escape_html(&more))?;
+    }
+
+    let footer =
+        format!("You get this email {frequency} because you created or replied to these posts.");
+    writeln!(
+        text,
+        "--\n{footer}\nTo stop getting emails, open {unsubscribe_url}"
+    )?;
+    writeln!(
+        html,
+        "<hr>\n<p>{}<br>\n<a href=\"{}\">Stop getting emails</a></p>\n</body>\n</html>",
+        escape_html(&footer),
+        escape_html(&unsubscribe_url)
+    )?;
+
+    let to: Mailbox = settings
+        .email
+        .to_string()
+        .parse()
+        .with_context(|| format!("invalid email address of user '{}'", settings.user_id))?;
+
+    Message::builder()
+        .from(config.from.clone())
+        .to(to)
+        .subject(subject)
+        .raw_header(HeaderValue::new(
+            HeaderName::new_from_ascii_str("List-Unsubscribe"),
+            format!("<{one_click_unsubscribe_url}>"),
+        ))
+        .raw_header(HeaderValue::new(
+            HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+            "List-Unsubscribe=One-Click".to_string(),
+        ))
+        .multipart(MultiPart::alternative_plain_html(text, html))
+        .with_context(|| "unable to build email digest")
+}
+
+/// returns whether an email was sent, the user is only emailed when they are due and
+/// there are new replies, but the date they were last checked moves on either way
+async fn send_digest(
+    db: &DatabaseParam,
+    transport: &MailTransportParam,
+    config: &EmailDigestConfig,
+    settings: &EmailSettings,
+) -> eyre::Result<bool> {
+    let Some(period) = period(settings.frequency) else {
+        return Ok(false);
+    };
+    if settings.last_sent > utc_date_iso_string_ago(period)? {
+        return Ok(false);
+    }
+
+    // replies created while the email is put together are left for the next one
+    let until = utc_date_iso_string();
+    let posts = {
+        let db = db.read().await;
+        gather_posts(&db, settings, &until).await?
+    };
+
+    if !posts.is_empty() {
+        transport.send(compose(config, settings, &posts)?).await?;
+    }
+
+    let mut db = db.write().await;
+    db.edit_email_last_sent(&settings.user_id, &until).await?;
+
+    Ok(!posts.is_empty())
+}
+
+/// emails every due user the replies they have not been emailed about, returns how many were
+pub async fn send_digests(
+    db: &DatabaseParam,
+    transport: &MailTransportParam,
+    config: &EmailDigestConfig,
+) -> eyre::Result<usize> {
+    let all_settings = db.read().await.email_settings_to_send().await?;
+
+    let mut sent = 0;
+    for settings in all_settings {
+        match send_digest(db, transport, config, &settings).await {
+            Ok(true) => sent += 1,
+            Ok(false) => {}
+            // the date is left as is, so the same replies are tried again next time
+            Err(err) => log::error!(
+                "unable to send email digest to user {}: {err:?}",
+                settings.user_id
+            ),
+        }
+    }
+
+    Ok(sent)
+}
+
+pub fn spawn(
+    db: DatabaseParam,
+    transport: MailTransportParam,
+    config: EmailDigestConfigParam,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match send_digests(&db, &transport, &config).await {
+                Ok(0) => {}
+                Ok(sent) => log::info!("sent {sent} email digests"),
+                Err(err) => log::error!("unable to send email digests: {err:?}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::db::{
+        database::{CreateCategory, CreatePost, CreateReply, CreateSubscription, CreateUser},
+        models::{Email, Permission},
+        sqlite::SqliteDb,
+    };
+    use crate::from_unchecked::FromUnchecked;
+    use crate::password::HashedPassword;
+
+    fn config() -> EmailDigestConfig {
+        EmailDigestConfig {
+            from: "forum@example.com"
+                .parse()
+                .expect("address should be valid"),
+            public_url: "https://api.example.com".to_string(),
+            frontend_url: "https://forum.example.com".to_string(),
+        }
+    }
+
+    fn settings(user_id: UserId) -> EmailSettings {
+        EmailSettings {
+            user_id,
+            email: Email::from_unchecked("reader@example.com".to_string()),
+            frequency: EmailFrequency::Daily,
+            unsubscribe_token: "token".to_string(),
+            last_sent: String::new(),
+            verified: true,
+            confirmation_token: None,
+            confirmation_sent: None,
+            date_edited: String::new(),
+        }
+    }
+
+    fn digest_post(id: &str, title: &str, replies: usize) -> DigestPost {
+        DigestPost {
+            id: PostId::from_unchecked(id.to_string()),
+            category_id: CategoryId::from_unchecked("category".to_string()),
+            title: Title::from_unchecked(title.to_string()),
+            replies: (0..replies)
+                .map(|index| DigestReply {
+                    author: Name::from_unchecked("writer".to_string()),
+                    content: Content::from_unchecked(format!("reply {index}")),
+                    date_created: "2024-01-02T03:04:05.000Z".to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// the whole email with the quoted-printable soft line breaks and equal signs undone,
+    /// which is enough to search it for links
+    fn formatted(message: &Message) -> String {
+        String::from_utf8(message.formatted())
+            .expect("email should be utf-8")
+            .replace("=\r\n", "")
+            .replace("=3D", "=")
+    }
+
+    #[test]
+    fn waits_according_to_the_frequency() {
+        assert_eq!(period(EmailFrequency::Immediate), Some(Duration::ZERO));
+        assert_eq!(period(EmailFrequency::Daily), Some(DAY));
+        assert_eq!(period(EmailFrequency::Weekly), Some(7 * DAY));
+        assert_eq!(period(EmailFrequency::Off), None);
+    }
+
+    #[test]
+    fn names_replies_by_count() {
+        assert_eq!(replies_noun(1), "reply");
+        assert_eq!(replies_noun(0), "replies");
+        assert_eq!(replies_noun(2), "replies");
+    }
+
+    #[test]
+    fn shortens_long_content_by_graphemes() {
+        assert_eq!(excerpt("short"), "short");
+
+        let exact = "a".repeat(EXCERPT_LENGTH);
+        assert_eq!(excerpt(&exact), exact);
+
+        let long = "e\u{301}".repeat(EXCERPT_LENGTH + 1);
+        let shortened = excerpt(&long);
+        assert!(shortened.ends_with('…'));
+        assert_eq!(shortened.graphemes(true).count(), EXCERPT_LENGTH + 1);
+        assert!(shortened.starts_with(&"e\u{301}".repeat(EXCERPT_LENGTH)));
+    }
+
+    #[test]
+    fn escapes_html_special_characters() {
+        assert_eq!(
+            escape_html("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("plain text"), "plain text");
+    }
+
+    #[test]
+    fn formats_dates_for_reading() {
+        assert_eq!(
+            readable_date("2024-01-02T03:04:05.000Z"),
+            "2024-01-02 03:04 UTC"
+        );
+        assert_eq!(readable_date("not a date"), "not a date");
+    }
+
+    #[test]
+    fn composes_an_email_for_one_post() {
+        let settings = settings(UserId::from_unchecked("reader".to_string()));
+        let message = compose(&config(), &settings, &[digest_post("post", "Hello", 2)])
+            .expect("email should be composed");
+
+        let headers = message.headers();
+        assert_eq!(
+            headers.get_raw("Subject"),
+            Some("2 new replies in \"Hello\"")
+        );
+        assert_eq!(
+            headers.get_raw("List-Unsubscribe"),
+            Some("<https://api.example.com/users/unsubscribe_email?token=token>")
+        );
+        assert_eq!(
+            headers.get_raw("List-Unsubscribe-Post"),
+            Some("List-Unsubscribe=One-Click")
+        );
+
+        let email = formatted(&message);
+        assert!(email.contains("https://forum.example.com/posts/category/post"));
+        assert!(email.contains("https://forum.example.com/unsubscribe_email?token=token"));
+        assert!(email.contains("writer wrote on 2024-01-02 03:04 UTC"));
+        assert!(!email.contains("https://api.example.com/posts"));
+    }
+
+    #[test]
+    fn composes_an_email_for_several_posts() {
+        let settings = settings(UserId::from_unchecked("reader".to_string()));
+        let posts = [
+            digest_post("first", "First", 1),
+            digest_post("second", "Second", 0),
+        ];
+        let message = compose(&config(), &settings, &posts).expect("email should be composed");
+
+        assert_eq!(
+            message.headers().get_raw("Subject"),
+            Some("1 new reply in 2 posts")
+        );
+    }
+
+    #[test]
+    fn only_counts_replies_past_the_limit() {
+        let settings = settings(UserId::from_unchecked("reader".to_string()));
+        let message = compose(
+            &config(),
+            &settings,
+            &[digest_post("post", "Busy", MAX_REPLIES_PER_EMAIL + 3)],
+        )
+        .expect("email should be composed");
+
+        let email = formatted(&message);
+        assert!(email.contains("and 3 more replies on the forum"));
+        assert!(email.contains(&format!("reply {}", MAX_REPLIES_PER_EMAIL - 1)));
+        assert!(!email.contains(&format!("reply {}", MAX_REPLIES_PER_EMAIL)));
+    }
+
+    fn user(username: &str) -> CreateUser {
+        CreateUser {
+            username: Name::from_unchecked(username.to_string()),
+            nickname: None,
+            password: HashedPassword::from_unchecked(String::new()),
+            permission: Permission::User,
+            avatar_id: None,
+        }
+    }
+
+    /// a reader who created a post, and a reply to it by someone else
+    async fn replied_post() -> (DatabaseParam, UserId, CategoryId, PostId) {
+        let mut db = SqliteDb::in_memory().await;
+        let reader_id = db
+            .create_user(user("reader"))
+            .await
+            .expect("user should be created");
+        let writer_id = db
+            .create_user(user("writer"))
+            .await
+            .expect("user should be created");
+        let category_id = db
+            .create_category(CreateCategory {
+                title: Title::from_unchecked("category".to_string()),
+                minimum_write_permission: Permission::User,
+                minimum_read_permission: Permission::default(),
+                qa_mode: false,
+            })
+            .await
+            .expect("category should be created");
+        let post_id = db
+            .create_post(CreatePost {
+                category_id: category_id.clone(),
+                title: Title::from_unchecked("post".to_string()),
+                content: Content::from_unchecked("content".to_string()),
+                creator_id: reader_id.clone(),
+            })
+            .await
+            .expect("post should be created");
+        let _ = db
+            .create_reply(CreateReply {
+                creator_id: writer_id,
+                post_id: post_id.clone(),
+                parent_reply_id: None,
+                quote: None,
+                content: Content::from_unchecked("reply".to_string()),
+            })
+            .await
+            .expect("reply should be created");
+
+        (Arc::new(RwLock::new(db)), reader_id, category_id, post_id)
+    }
+
+    async fn subscribe(db: &DatabaseParam, data: CreateSubscription) {
+        db.write()
+            .await
+            .create_subscription(data)
+            .await
+            .expect("subscription should be created");
+    }
+
+    async fn gathered(db: &DatabaseParam, user_id: &UserId) -> Vec<DigestPost> {
+        gather_posts(
+            &db.read().await,
+            &settings(user_id.clone()),
+            &utc_date_iso_string(),
+        )
+        .await
+        .expect("posts should be gathered")
+    }
+
+    #[tokio::test]
+    async fn gathers_replies_to_own_posts() {
+        let (db, reader_id, _, post_id) = replied_post().await;
+
+        let posts = gathered(&db, &reader_id).await;
+        assert_eq!(posts.len(), 1);
+        assert!(posts[0].id == post_id);
+        assert_eq!(posts[0].replies.len(), 1);
+        assert_eq!(posts[0].replies[0].author.to_string(), "writer");
+    }
+
+    #[tokio::test]
+    async fn leaves_out_muted_posts() {
+        let (db, reader_id, _, post_id) = replied_post().await;
+        subscribe(
+            &db,
+            CreateSubscription {
+                user_id: reader_id.clone(),
+                target: SubscriptionTarget::Post,
+                target_id: Id::from(&post_id),
+                level: SubscriptionLevel::Muted,
+            },
+        )
+        .await;
+
+        assert!(gathered(&db, &reader_id).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn leaves_out_posts_in_muted_categories() {
+        let (db, reader_id, category_id, _) = replied_post().await;
+        subscribe(
+            &db,
+            CreateSubscription {
+                user_id: reader_id.clone(),
+                target: SubscriptionTarget::Category,
+                target_id: Id::from(&category_id),
+                level: SubscriptionLevel::Muted,
+            },
+        )
+        .await;
+
+        assert!(gathered(&db, &reader_id).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn following_a_post_outweighs_a_muted_category() {
+        let (db, reader_id, category_id, post_id) = replied_post().await;
+        subscribe(
+            &db,
+            CreateSubscription {
+                user_id: reader_id.clone(),
+                target: SubscriptionTarget::Category,
+                target_id: Id::from(&category_id),
+                level: SubscriptionLevel::Muted,
+            },
+        )
+        .await;
+        subscribe(
+            &db,
+            CreateSubscription {
+                user_id: reader_id.clone(),
+                target: SubscriptionTarget::Post,
+                target_id: Id::from(&post_id),
+                level: SubscriptionLevel::Watching,
+            },
+        )
+        .await;
+
+        assert_eq!(gathered(&db, &reader_id).await.len(), 1);
+    }
+}
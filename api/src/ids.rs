@@ -0,0 +1,109 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use crate::text_validation::{TextError, TextRules};
+
+/// ids created before they became sortable were 8 characters long and are still accepted
+pub const LEGACY_ID_LENGTH: usize = 8;
+pub const ID_LENGTH: usize = 26;
+
+pub const ID_RULES: TextRules = TextRules::single_line(LEGACY_ID_LENGTH, ID_LENGTH);
+
+/// the crockford base32 alphabet in lowercase, it leaves out "i", "l", "o" and "u"
+const ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// a ULID in lowercase, 48 bits of milliseconds since the epoch followed by 80 random bits,
+/// so ids sort by the time they were created in
+pub fn generate_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis()) as u64;
+
+    // the version and variant bits of a v4 uuid sit in bytes 6 and 8, the rest is random
+    let random = Uuid::new_v4().into_bytes();
+    let random_bits = random[0..6]
+        .iter()
+        .chain(&random[10..14])
+        .fold(0u128, |bits, byte| bits << 8 | u128::from(*byte));
+
+    let value = u128::from(millis & 0xFFFF_FFFF_FFFF) << 80 | random_bits;
+
+    (0..ID_LENGTH)
+        .rev()
+        .map(|index| char::from(ALPHABET[(value >> (index * 5)) as usize & 0x1F]))
+        .collect()
+}
+
+/// accepts generated ids and legacy ids, which are any 8 lowercase ascii letters or digits
+pub fn check_id(value: &str) -> Result<(), TextError> {
+    match value.len() {
+        LEGACY_ID_LENGTH
+            if value
+                .bytes()
+                .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit()) =>
+        {
+            Ok(())
+        }
+        ID_LENGTH if value.bytes().all(|byte| ALPHABET.contains(&byte)) => Ok(()),
+        _ => Err(TextError::InvalidFormat(
+            "must be 26 lowercase crockford base32 characters, or a legacy 8 character id",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn generates_crockford_ids() {
+        let id = generate_id();
+        assert_eq!(id.len(), ID_LENGTH);
+        assert!(id.bytes().all(|byte| ALPHABET.contains(&byte)), "{id}");
+        // 48 bits of milliseconds leave the first character at most 7 until the year 10889
+        assert!(id.as_bytes()[0] <= b'7', "{id}");
+        assert_eq!(check_id(&id), Ok(()));
+    }
+
+    #[test]
+    fn generates_distinct_ids() {
+        let ids: std::collections::HashSet<String> = (0..1000).map(|_| generate_id()).collect();
+        assert_eq!(ids.len(), 1000);
+    }
+
+    #[test]
+    fn sorts_ids_by_creation() {
+        let first = generate_id();
+        std::thread::sleep(Duration::from_millis(2));
+        let second = generate_id();
+        assert!(first < second, "{first} should sort before {second}");
+    }
+
+    #[test]
+    fn accepts_legacy_ids() {
+        assert_eq!(check_id("abcd1234"), Ok(()));
+        assert_eq!(check_id("deleted0"), Ok(()));
+        // legacy ids were not limited to the crockford alphabet
+        assert_eq!(check_id("lumioxyz"), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_ids() {
+        for id in [
+            "",
+            "abc",
+            "ABCD1234",
+            "abcd-234",
+            "01hqzv8k3m9x4t2w6y5r7n0bcd1",
+            "01hqzv8k3m9x4t2w6y5r7n0bc",
+            "01hqzv8k3m9x4t2w6y5r7n0bci",
+            "01HQZV8K3M9X4T2W6Y5R7N0BCD",
+        ] {
+            assert!(check_id(id).is_err(), "{id}");
+        }
+        assert_eq!(check_id("01hqzv8k3m9x4t2w6y5r7n0bcd"), Ok(()));
+    }
+}
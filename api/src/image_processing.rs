@@ -0,0 +1,569 @@
+use std::io::Cursor;
+
+use bytes::Bytes;
+use eyre::{eyre, Context};
+use image::{imageops::FilterType, io::Limits, DynamicImage, ImageOutputFormat};
+use salvo::oapi::ToSchema;
+use serde::Deserialize;
+
+use crate::storage::blob_store::{BlobStore, BlobStoreError};
+
+const MAX_IMAGE_DIMENSION: u32 = 8192;
+
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+pub enum ImageSize {
+    AvatarSmall,
+    AvatarLarge,
+    Thumbnail,
+}
+
+impl ImageSize {
+    pub const ALL: [ImageSize; 3] = [
+        ImageSize::AvatarSmall,
+        ImageSize::AvatarLarge,
+        ImageSize::Thumbnail,
+    ];
+
+    fn suffix(self) -> &'static str {
+        match self {
+            ImageSize::AvatarSmall => "avatar_small",
+            ImageSize::AvatarLarge => "avatar_large",
+            ImageSize::Thumbnail => "thumbnail",
+        }
+    }
+
+    /// variants are stored right next to the original blob
+    pub fn blob_key(self, original_blob_key: &str) -> String {
+        format!("{original_blob_key}.{}", self.suffix())
+    }
+
+    fn resize(self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            ImageSize::AvatarSmall => image.resize_to_fill(48, 48, FilterType::Lanczos3),
+            ImageSize::AvatarLarge => image.resize_to_fill(128, 128, FilterType::Lanczos3),
+            ImageSize::Thumbnail if image.width() <= 480 && image.height() <= 480 => image.clone(),
+            ImageSize::Thumbnail => image.thumbnail(480, 480),
+        }
+    }
+}
+
+pub fn is_image(content_type: &str) -> bool {
+    content_type.starts_with("image/")
+}
+
+/// photos stay jpeg, everything else becomes png to keep transparency
+pub fn variant_content_type(content_type: &str) -> &'static str {
+    if content_type == "image/jpeg" {
+        "image/jpeg"
+    } else {
+        "image/png"
+    }
+}
+
+/// removes a blob along with any variants rendered from it
+pub async fn remove_with_variants(
+    blob_store: &(dyn BlobStore + Send + Sync),
+    blob_key: &str,
+) -> Result<(), BlobStoreError> {
+    for size in ImageSize::ALL {
+        blob_store.remove(&size.blob_key(blob_key)).await?;
+    }
+    blob_store.remove(blob_key).await
+}
+
+pub struct ProcessedImage {
+    pub original: Bytes,
+    pub variants: Vec<(ImageSize, Bytes)>,
+}
+
+/// strips metadata from the original and renders every [`ImageSize`],
+/// re-encoding drops any metadata from the variants as well
+pub fn process_image(data: &[u8], content_type: &str) -> eyre::Result<ProcessedImage> {
+    let stripped = strip_metadata(data, content_type)?;
+
+    let mut reader = image::io::Reader::new(Cursor::new(&stripped.data))
+        .with_guessed_format()
+        .with_context(|| "unable to guess image format")?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    reader.limits(limits);
+    let image = reader.decode().with_context(|| "unable to decode image")?;
+    let image = apply_orientation(image, stripped.orientation);
+
+    // the orientation went with the rest of the metadata, so a turned original is encoded
+    // again from the upright pixels
+    let original = if stripped.orientation == UPRIGHT {
+        stripped.data
+    } else {
+        let format = match content_type {
+            "image/jpeg" => ImageOutputFormat::Jpeg(90),
+            "image/webp" => ImageOutputFormat::WebP,
+            _ => ImageOutputFormat::Png,
+        };
+        encode(&image, format).with_context(|| "unable to encode upright image")?
+    };
+
+    let variants = ImageSize::ALL
+        .into_iter()
+        .map(|size| {
+            let format = match variant_content_type(content_type) {
+                "image/jpeg" => ImageOutputFormat::Jpeg(85),
+                _ => ImageOutputFormat::Png,
+            };
+            let encoded = encode(&size.resize(&image), format)
+                .with_context(|| "unable to encode image variant")?;
+            Ok((size, Bytes::from(encoded)))
+        })
+        .collect::<eyre::Result<_>>()?;
+
+    Ok(ProcessedImage {
+        original: Bytes::from(original),
+        variants,
+    })
+}
+
+fn encode(image: &DynamicImage, format: ImageOutputFormat) -> image::ImageResult<Vec<u8>> {
+    let mut encoded = Cursor::new(Vec::new());
+    image.write_to(&mut encoded, format)?;
+    Ok(encoded.into_inner())
+}
+
+/// the EXIF orientation of an image that needs no turning
+const UPRIGHT: u16 = 1;
+
+struct Stripped {
+    data: Vec<u8>,
+    /// read from the EXIF before it was dropped, [`UPRIGHT`] when there was none
+    orientation: u16,
+}
+
+fn strip_metadata(data: &[u8], content_type: &str) -> eyre::Result<Stripped> {
+    match content_type {
+        "image/jpeg" => strip_jpeg_metadata(data),
+        "image/png" => strip_png_metadata(data),
+        "image/webp" => strip_webp_metadata(data),
+        _ => Ok(Stripped {
+            data: data.to_vec(),
+            orientation: UPRIGHT,
+        }),
+    }
+}
+
+/// turns the pixels the way the EXIF orientation says they should be shown
+fn apply_orientation(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// reads the orientation tag from the first IFD of the TIFF structure EXIF is stored in,
+/// some writers put the "Exif" header of the jpeg segment in front of it elsewhere too
+fn exif_orientation(exif: &[u8]) -> Option<u16> {
+    const ORIENTATION_TAG: u16 = 0x0112;
+    const ENTRY_LENGTH: usize = 12;
+
+    let exif = exif.strip_prefix(b"Exif\0\0").unwrap_or(exif);
+    let big_endian = match exif.get(..4)? {
+        b"MM\0*" => true,
+        b"II*\0" => false,
+        _ => return None,
+    };
+    let read_u16 = |at: usize| {
+        let bytes = [*exif.get(at)?, *exif.get(at + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let ifd = if big_endian {
+        read_u32_be(exif, 4)?
+    } else {
+        read_u32_le(exif, 4)?
+    };
+
+    let entries = usize::from(read_u16(ifd)?);
+    (0..entries)
+        .map(|entry| ifd + 2 + entry * ENTRY_LENGTH)
+        .find(|&entry| read_u16(entry) == Some(ORIENTATION_TAG))
+        // a single SHORT sits at the start of the value field in either byte order
+        .and_then(|entry| read_u16(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+fn read_u16_be(data: &[u8], at: usize) -> Option<usize> {
+    let bytes = data.get(at..at + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+}
+
+fn read_u32_be(data: &[u8], at: usize) -> Option<usize> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+fn read_u32_le(data: &[u8], at: usize) -> Option<usize> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+/// drops APP1 segments, which hold EXIF and XMP
+fn strip_jpeg_metadata(data: &[u8]) -> eyre::Result<Stripped> {
+    let malformed = || eyre!("malformed jpeg");
+
+    let mut stripped = data.get(..2).ok_or_else(malformed)?.to_vec();
+    let mut orientation = None;
+    let mut at = 2;
+
+    loop {
+        if data.get(at) != Some(&0xFF) {
+            return Err(malformed());
+        }
+        let marker = *data.get(at + 1).ok_or_else(malformed)?;
+        match marker {
+            0xFF => at += 1,
+            // start of scan, entropy coded data follows until the end of the image
+            0xDA | 0xD9 => {
+                stripped.extend_from_slice(&data[at..]);
+                return Ok(Stripped {
+                    data: stripped,
+                    orientation: orientation.unwrap_or(UPRIGHT),
+                });
+            }
+            _ => {
+                let length = read_u16_be(data, at + 2).ok_or_else(malformed)?;
+                let segment = data.get(at..at + 2 + length).ok_or_else(malformed)?;
+                if marker != 0xE1 {
+                    stripped.extend_from_slice(segment);
+                } else if let Some(exif) = segment
+                    .get(4..)
+                    .filter(|exif| exif.starts_with(b"Exif\0\0"))
+                {
+                    orientation = orientation.or_else(|| exif_orientation(exif));
+                }
+                at += 2 + length;
+            }
+        }
+    }
+}
+
+/// drops eXIf chunks and the text chunks that commonly carry XMP
+fn strip_png_metadata(data: &[u8]) -> eyre::Result<Stripped> {
+    let malformed = || eyre!("malformed png");
+
+    let mut stripped = data.get(..8).ok_or_else(malformed)?.to_vec();
+    let mut orientation = None;
+    let mut at = 8;
+
+    while at < data.len() {
+        let length = read_u32_be(data, at).ok_or_else(malformed)?;
+        let kind = data.get(at + 4..at + 8).ok_or_else(malformed)?;
+        let chunk = data.get(at..at + 12 + length).ok_or_else(malformed)?;
+        match kind {
+            b"eXIf" => {
+                orientation = orientation.or_else(|| exif_orientation(&chunk[8..8 + length]))
+            }
+            b"iTXt" | b"tEXt" | b"zTXt" => {}
+            _ => stripped.extend_from_slice(chunk),
+        }
+        at += 12 + length;
+    }
+
+    Ok(Stripped {
+        data: stripped,
+        orientation: orientation.unwrap_or(UPRIGHT),
+    })
+}
+
+/// drops EXIF and XMP chunks and clears their flags in the extended header
+fn strip_webp_metadata(data: &[u8]) -> eyre::Result<Stripped> {
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+    let malformed = || eyre!("malformed webp");
+
+    let mut stripped = data.get(..12).ok_or_else(malformed)?.to_vec();
+    let mut orientation = None;
+    let mut at = 12;
+
+    while at < data.len() {
+        let kind = data.get(at..at + 4).ok_or_else(malformed)?;
+        let length = read_u32_le(data, at + 4).ok_or_else(malformed)?;
+        let padded_length = length + length % 2;
+        let chunk = data
+            .get(at..(at + 8 + padded_length).min(data.len()))
+            .ok_or_else(malformed)?;
+        match kind {
+            b"EXIF" => {
+                let exif = chunk.get(8..8 + length).unwrap_or_default();
+                orientation = orientation.or_else(|| exif_orientation(exif));
+            }
+            b"XMP " => {}
+            b"VP8X" => {
+                let flags_at = stripped.len() + 8;
+                stripped.extend_from_slice(chunk);
+                let flags = stripped.get_mut(flags_at).ok_or_else(malformed)?;
+                *flags &= !(EXIF_FLAG | XMP_FLAG);
+            }
+            _ => stripped.extend_from_slice(chunk),
+        }
+        at += 8 + padded_length;
+    }
+
+    let riff_size = u32::try_from(stripped.len() - 8).with_context(|| "webp too large")?;
+    stripped[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    Ok(Stripped {
+        data: stripped,
+        orientation: orientation.unwrap_or(UPRIGHT),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    /// EXIF holding nothing but an orientation tag
+    fn exif(big_endian: bool, orientation: u16) -> Vec<u8> {
+        let u16_bytes = |value: u16| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let u32_bytes = |value: u32| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+
+        let mut exif = if big_endian {
+            b"MM\0*".to_vec()
+        } else {
+            b"II*\0".to_vec()
+        };
+        exif.extend_from_slice(&u32_bytes(8));
+        exif.extend_from_slice(&u16_bytes(1));
+        exif.extend_from_slice(&u16_bytes(0x0112));
+        // SHORT, one value
+        exif.extend_from_slice(&u16_bytes(3));
+        exif.extend_from_slice(&u32_bytes(1));
+        exif.extend_from_slice(&u16_bytes(orientation));
+        exif.extend_from_slice(&[0, 0]);
+        exif.extend_from_slice(&u32_bytes(0));
+        exif
+    }
+
+    /// two pixels side by side, red on the left and blue on the right
+    fn two_pixels() -> DynamicImage {
+        let mut image = RgbImage::new(2, 1);
+        image.put_pixel(0, 0, Rgb([255, 0, 0]));
+        image.put_pixel(1, 0, Rgb([0, 0, 255]));
+        DynamicImage::ImageRgb8(image)
+    }
+
+    fn encoded(format: ImageOutputFormat) -> Vec<u8> {
+        encode(&two_pixels(), format).expect("image should encode")
+    }
+
+    fn jpeg_with_exif(exif: &[u8]) -> Vec<u8> {
+        let jpeg = encoded(ImageOutputFormat::Jpeg(90));
+        let mut payload = b"Exif\0\0".to_vec();
+        payload.extend_from_slice(exif);
+        let length = u16::try_from(payload.len() + 2).expect("segment should fit");
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&length.to_be_bytes());
+        data.extend_from_slice(&payload);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let length = u32::try_from(data.len()).expect("chunk should fit");
+        let mut chunk = length.to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        // the chunks are dropped before decoding, so the crc is never checked
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    /// adds chunks right after IHDR, which is always first
+    fn png_with_chunks(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let png = encoded(ImageOutputFormat::Png);
+        let ihdr_end = 8 + 12 + 13;
+        let mut data = png[..ihdr_end].to_vec();
+        for chunk in chunks {
+            data.extend_from_slice(chunk);
+        }
+        data.extend_from_slice(&png[ihdr_end..]);
+        data
+    }
+
+    fn webp_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let length = u32::try_from(data.len()).expect("chunk should fit");
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&length.to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let size = u32::try_from(body.len() + 4).expect("webp should fit");
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(&body);
+        data
+    }
+
+    fn pixel(image: &DynamicImage, x: u32, y: u32) -> [u8; 3] {
+        image.to_rgb8().get_pixel(x, y).0
+    }
+
+    #[test]
+    fn reads_orientation_in_either_byte_order() {
+        assert_eq!(exif_orientation(&exif(true, 6)), Some(6));
+        assert_eq!(exif_orientation(&exif(false, 8)), Some(8));
+
+        let mut prefixed = b"Exif\0\0".to_vec();
+        prefixed.extend_from_slice(&exif(false, 3));
+        assert_eq!(exif_orientation(&prefixed), Some(3));
+    }
+
+    #[test]
+    fn ignores_invalid_orientation() {
+        assert_eq!(exif_orientation(&exif(true, 0)), None);
+        assert_eq!(exif_orientation(&exif(true, 9)), None);
+        assert_eq!(exif_orientation(b"not exif"), None);
+
+        let truncated = exif(false, 6);
+        assert_eq!(exif_orientation(&truncated[..12]), None);
+    }
+
+    #[test]
+    fn applies_orientation() {
+        let red = [255, 0, 0];
+        let blue = [0, 0, 255];
+
+        let image = apply_orientation(two_pixels(), 1);
+        assert_eq!((image.width(), image.height()), (2, 1));
+
+        let image = apply_orientation(two_pixels(), 2);
+        assert_eq!(pixel(&image, 0, 0), blue);
+
+        let image = apply_orientation(two_pixels(), 6);
+        assert_eq!((image.width(), image.height()), (1, 2));
+        assert_eq!(pixel(&image, 0, 0), red);
+        assert_eq!(pixel(&image, 0, 1), blue);
+
+        let image = apply_orientation(two_pixels(), 8);
+        assert_eq!((image.width(), image.height()), (1, 2));
+        assert_eq!(pixel(&image, 0, 0), blue);
+    }
+
+    #[test]
+    fn strips_jpeg_exif_and_keeps_orientation() {
+        let jpeg = encoded(ImageOutputFormat::Jpeg(90));
+        let stripped =
+            strip_jpeg_metadata(&jpeg_with_exif(&exif(true, 6))).expect("jpeg should strip");
+
+        assert_eq!(stripped.data, jpeg);
+        assert_eq!(stripped.orientation, 6);
+
+        let stripped = strip_jpeg_metadata(&jpeg).expect("jpeg should strip");
+        assert_eq!(stripped.data, jpeg);
+        assert_eq!(stripped.orientation, UPRIGHT);
+    }
+
+    #[test]
+    fn rejects_malformed_jpeg() {
+        assert!(strip_jpeg_metadata(&[0xFF]).is_err());
+        assert!(strip_jpeg_metadata(&[0xFF, 0xD8, 0x00]).is_err());
+        assert!(strip_jpeg_metadata(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10]).is_err());
+    }
+
+    #[test]
+    fn strips_png_metadata_chunks() {
+        let png = encoded(ImageOutputFormat::Png);
+        let with_metadata = png_with_chunks(&[
+            png_chunk(b"tEXt", b"Comment\0hello"),
+            png_chunk(b"eXIf", &exif(false, 3)),
+            png_chunk(b"iTXt", b"XML:com.adobe.xmp\0\0\0\0\0<x/>"),
+        ]);
+        let stripped = strip_png_metadata(&with_metadata).expect("png should strip");
+
+        assert_eq!(stripped.data, png);
+        assert_eq!(stripped.orientation, 3);
+        assert!(strip_png_metadata(&png[..png.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn strips_webp_metadata_chunks() {
+        let vp8x = webp_chunk(b"VP8X", &[0x08 | 0x04 | 0x10, 0, 0, 0, 1, 0, 0, 0, 0, 0]);
+        let image = webp_chunk(b"VP8L", b"pixels");
+        let with_metadata = webp(&[
+            vp8x,
+            image.clone(),
+            webp_chunk(b"EXIF", &exif(true, 8)),
+            webp_chunk(b"XMP ", b"<x/>"),
+        ]);
+        let stripped = strip_webp_metadata(&with_metadata).expect("webp should strip");
+
+        let expected = webp(&[
+            webp_chunk(b"VP8X", &[0x10, 0, 0, 0, 1, 0, 0, 0, 0, 0]),
+            image,
+        ]);
+        assert_eq!(stripped.data, expected);
+        assert_eq!(stripped.orientation, 8);
+        assert!(strip_webp_metadata(b"RIFF").is_err());
+    }
+
+    #[test]
+    fn turns_original_upright() {
+        let processed = process_image(&jpeg_with_exif(&exif(false, 6)), "image/jpeg")
+            .expect("jpeg should process");
+        let original =
+            image::load_from_memory(&processed.original).expect("original should decode");
+        assert_eq!((original.width(), original.height()), (1, 2));
+
+        let png = png_with_chunks(&[png_chunk(b"eXIf", &exif(true, 8))]);
+        let processed = process_image(&png, "image/png").expect("png should process");
+        let original =
+            image::load_from_memory(&processed.original).expect("original should decode");
+        assert_eq!((original.width(), original.height()), (1, 2));
+        assert_eq!(pixel(&original, 0, 0), [0, 0, 255]);
+
+        let png = encoded(ImageOutputFormat::Png);
+        let processed = process_image(&png, "image/png").expect("png should process");
+        assert_eq!(processed.original, png);
+    }
+
+    #[test]
+    fn names_variants() {
+        assert_eq!(
+            ImageSize::Thumbnail.blob_key("sha256/ab/abcd"),
+            "sha256/ab/abcd.thumbnail"
+        );
+        assert_eq!(variant_content_type("image/jpeg"), "image/jpeg");
+        assert_eq!(variant_content_type("image/webp"), "image/png");
+        assert_eq!(variant_content_type("image/gif"), "image/png");
+    }
+}
@@ -1,5 +1,14 @@
-use sqlx::types::chrono::Utc;
+use std::time::{Duration, SystemTime};
+
+use sqlx::types::chrono::{DateTime, Utc};
 
 pub fn utc_date_iso_string() -> String {
     Utc::now().to_rfc3339()
 }
+
+pub fn utc_date_iso_string_ago(duration: Duration) -> eyre::Result<String> {
+    let date = SystemTime::now()
+        .checked_sub(duration)
+        .ok_or_else(|| eyre::eyre!("duration reaches before the epoch"))?;
+    Ok(DateTime::<Utc>::from(date).to_rfc3339())
+}
@@ -0,0 +1,305 @@
+use std::path::Path;
+
+use eyre::Context;
+
+use crate::{
+    attachment_blobs::{self, BlobSource},
+    attachment_validation,
+    db::sqlite::{LegacyAttachment, SqliteDb, UpgradedAttachment},
+    image_processing,
+    storage::blob_store::BlobStore,
+};
+
+/// moves a database from before sortable ids and blob storage to the current schema, the
+/// files of its attachments are read from the paths they were saved at, relative to the
+/// working directory like before, and stored as blobs
+pub async fn upgrade_if_needed(
+    db: &SqliteDb,
+    blob_store: &(dyn BlobStore + Send + Sync),
+) -> eyre::Result<()> {
+    if !db.is_legacy().await? {
+        return Ok(());
+    }
+
+    let legacy_attachments = db.legacy_attachments().await?;
+    log::info!(
+        "upgrading legacy database with {} attachments",
+        legacy_attachments.len()
+    );
+
+    // blobs are stored under their hash, so storing them again after a failed upgrade
+    // only overwrites them
+    let mut attachments = Vec::with_capacity(legacy_attachments.len());
+    for attachment in legacy_attachments {
+        attachments.push(store_attachment(blob_store, attachment).await?);
+    }
+
+    db.upgrade_legacy(&attachments).await?;
+    log::info!("upgraded legacy database");
+
+    Ok(())
+}
+
+async fn store_attachment(
+    blob_store: &(dyn BlobStore + Send + Sync),
+    attachment: LegacyAttachment,
+) -> eyre::Result<UpgradedAttachment> {
+    let path = Path::new(&attachment.path);
+    let content_type = attachment_validation::sniff_content_type(path)
+        .await
+        .with_context(|| format!("unable to read legacy attachment {}", attachment.path))?;
+    let file_name = attachment_validation::sanitize_file_name(
+        path.file_name()
+            .and_then(|file_name| file_name.to_str())
+            .unwrap_or_default(),
+    );
+
+    let processed = if image_processing::is_image(content_type) {
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("unable to read legacy attachment {}", attachment.path))?;
+        tokio::task::spawn_blocking(move || image_processing::process_image(&data, content_type))
+            .await
+            .with_context(|| "unable to join image processing task")?
+            .map_err(|err| {
+                log::warn!(
+                    "storing legacy attachment {} without variants: {err:?}",
+                    attachment.id
+                );
+            })
+            .ok()
+    } else {
+        None
+    };
+    let (source, hash, size) = match processed {
+        Some(processed) => {
+            let hash = attachment_blobs::hash_bytes(&processed.original);
+            let size = processed.original.len() as u64;
+            (BlobSource::Image(processed), hash, size)
+        }
+        None => {
+            let hash = attachment_blobs::hash_file(path)
+                .await
+                .with_context(|| format!("unable to hash legacy attachment {}", attachment.path))?;
+            let size = tokio::fs::metadata(path)
+                .await
+                .with_context(|| format!("unable to read legacy attachment {}", attachment.path))?
+                .len();
+            (BlobSource::File(path), hash, size)
+        }
+    };
+    let blob_key = attachment_blobs::blob_key_from_hash(&hash);
+
+    attachment_blobs::store_blob(blob_store, &blob_key, &source)
+        .await
+        .with_context(|| format!("unable to store legacy attachment {}", attachment.path))?;
+
+    Ok(UpgradedAttachment {
+        id: attachment.id,
+        blob_key,
+        file_name,
+        content_type: content_type.to_string(),
+        size: i64::try_from(size).unwrap_or(i64::MAX),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{
+        database::Database,
+        models::{AttachmentId, AttachmentTarget, CategoryId, Name, PostId, UserId},
+    };
+    use crate::from_unchecked::FromUnchecked;
+    use crate::{ids::generate_id, storage::local::LocalBlobStore};
+
+    /// the schema from before sortable ids and blob storage
+    const LEGACY_SCHEMA: &str = "
+        CREATE TABLE user (
+            id VARCHAR(16) PRIMARY KEY NOT NULL,
+            username TEXT NOT NULL,
+            nickname TEXT,
+            password TEXT NOT NULL,
+            permission TEXT NOT NULL,
+            avatar_id VARCHAR(8),
+            date_edited TEXT,
+            date_created TEXT NOT NULL,
+            deleted INTEGER not null,
+            FOREIGN KEY(avatar_id) REFERENCES attachment(id)
+        );
+        CREATE TABLE category (
+            id VARCHAR(8) PRIMARY KEY NOT NULL,
+            title TEXT NOT NULL,
+            minimum_write_permission TEXT NOT NULL,
+            minimum_read_permission TEXT NOT NULL,
+            deleted INTEGER not null,
+            date_edited TEXT,
+            date_created TEXT NOT NULL
+        );
+        CREATE TABLE post (
+            id VARCHAR(8) PRIMARY KEY NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            category_id VARCHAR(8) NOT NULL,
+            creator_id VARCHAR(8) NOT NULL,
+            locked INTEGER not null,
+            deleted INTEGER not null,
+            date_created TEXT NOT NULL,
+            date_edited TEXT,
+            FOREIGN KEY(creator_id) REFERENCES user(id)
+            FOREIGN KEY(category_id) REFERENCES category(id)
+        );
+        CREATE TABLE reply (
+            id VARCHAR(8) PRIMARY KEY NOT NULL,
+            creator_id VARCHAR(8) NOT NULL,
+            content TEXT NOT NULL,
+            post_id VARCHAR(8) NOT NULL,
+            date_edited TEXT,
+            date_created TEXT NOT NULL,
+            deleted INTEGER not null,
+            FOREIGN KEY(post_id) REFERENCES post(id),
+            FOREIGN KEY(creator_id) REFERENCES user(id)
+        );
+        CREATE TABLE attachment (
+            id VARCHAR(8) PRIMARY KEY NOT NULL,
+            path TEXT NOT NULL,
+            creator_id VARCHAR(8) NOT NULL,
+            date_created TEXT NOT NULL,
+            FOREIGN KEY(creator_id) REFERENCES user(id)
+        );
+    ";
+
+    const DATE: &str = "2023-01-01T00:00:00.000Z";
+
+    /// a legacy forum whose attachments were saved below `files`, `file0001` is embedded
+    /// in the post and `file0003` holds the same bytes but is used nowhere
+    fn legacy_forum(files: &Path) -> String {
+        let path = |id: &str, name: &str| files.join(id).join(name).display().to_string();
+
+        format!(
+            "{LEGACY_SCHEMA}
+            INSERT INTO user VALUES ('user0001', 'Legacy', NULL, 'hash', 'User', NULL, NULL, '{DATE}', 0);
+            INSERT INTO category VALUES ('cate0001', 'category', 'User', 'Unverified', 0, NULL, '{DATE}');
+            INSERT INTO post VALUES ('post0001', 'post', 'see attachment_from_id/file0001', 'cate0001', 'user0001', 0, 0, '{DATE}', NULL);
+            INSERT INTO reply VALUES ('repl0001', 'user0001', 'a reply', 'post0001', NULL, '{DATE}', 0);
+            INSERT INTO attachment VALUES ('file0001', '{}', 'user0001', '{DATE}');
+            INSERT INTO attachment VALUES ('file0002', '{}', 'user0001', '{DATE}');
+            INSERT INTO attachment VALUES ('file0003', '{}', 'user0001', '{DATE}');
+            UPDATE user SET avatar_id='file0002';",
+            path("file0001", "notes.txt"),
+            path("file0002", "avatar.txt"),
+            path("file0003", "copy.txt"),
+        )
+    }
+
+    async fn write_file(files: &Path, id: &str, name: &str, data: &str) {
+        let directory = files.join(id);
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .expect("directory should be created");
+        tokio::fs::write(directory.join(name), data)
+            .await
+            .expect("file should be written");
+    }
+
+    #[tokio::test]
+    async fn keeps_legacy_ids_resolving() {
+        let root = std::env::temp_dir().join(format!("decorum-upgrade-test-{}", generate_id()));
+        let files = root.join("files_uploaded").join("user0001");
+        write_file(&files, "file0001", "notes.txt", "legacy notes").await;
+        write_file(&files, "file0002", "avatar.txt", "an avatar").await;
+        write_file(&files, "file0003", "copy.txt", "legacy notes").await;
+        let blob_store = LocalBlobStore::new(root.join("blobs"));
+        let db = SqliteDb::in_memory_from(&legacy_forum(&files)).await;
+
+        upgrade_if_needed(&db, &blob_store)
+            .await
+            .expect("database should be upgraded");
+        assert!(!db.is_legacy().await.expect("schema should be checked"));
+        // nothing is left to upgrade the next time the api starts
+        upgrade_if_needed(&db, &blob_store)
+            .await
+            .expect("upgraded database should be left alone");
+
+        let user_id = UserId::try_from("user0001".to_string()).expect("legacy id should parse");
+        let user = db
+            .user_from_id(&user_id)
+            .await
+            .expect("user should load")
+            .expect("legacy user should resolve");
+        assert_eq!(
+            user.avatar_id.map(|avatar_id| avatar_id.to_string()),
+            Some("file0002".to_string())
+        );
+        let by_username = db
+            .user_from_username(&Name::from_unchecked("legacy".to_string()))
+            .await
+            .expect("user should load")
+            .expect("usernames should still be found");
+        assert_eq!(by_username.id.to_string(), "user0001");
+
+        let category_id =
+            CategoryId::try_from("cate0001".to_string()).expect("legacy id should parse");
+        let category = db
+            .category_from_id(&category_id)
+            .await
+            .expect("category should load")
+            .expect("legacy category should resolve");
+        assert!(!category.qa_mode);
+
+        let post_id = PostId::try_from("post0001".to_string()).expect("legacy id should parse");
+        let post = db
+            .post_from_id(&post_id)
+            .await
+            .expect("post should load")
+            .expect("legacy post should resolve");
+        assert_eq!(post.category_id.to_string(), "cate0001");
+        assert!(!post.pinned && !post.announcement);
+        let replies = db
+            .replies_from_post(&post_id)
+            .await
+            .expect("replies should load");
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].id.to_string(), "repl0001");
+
+        let attachment = |id: &str| {
+            let id = AttachmentId::try_from(id.to_string()).expect("legacy id should parse");
+            let db = &db;
+            async move {
+                db.attachment_from_id(&id)
+                    .await
+                    .expect("attachment should load")
+                    .expect("legacy attachment should resolve")
+            }
+        };
+        let notes = attachment("file0001").await;
+        assert_eq!(notes.file_name, "notes.txt");
+        assert_eq!(notes.content_type, "text/plain");
+        assert_eq!(notes.size, 12);
+        assert_eq!(notes.creator_id.to_string(), "user0001");
+        let link = notes.link.expect("embedded attachment should be linked");
+        assert!(matches!(link.target, AttachmentTarget::Post));
+        assert_eq!(link.target_id.to_string(), "post0001");
+        assert_eq!(
+            blob_store
+                .size(&notes.blob_key)
+                .await
+                .expect("blob size should load"),
+            Some(12)
+        );
+
+        let copy = attachment("file0003").await;
+        assert_eq!(copy.blob_key, notes.blob_key);
+        assert!(copy.link.is_none());
+        assert_eq!(
+            db.blob_ref_count(&notes.blob_key)
+                .await
+                .expect("blob should load"),
+            Some(2)
+        );
+        assert_eq!(
+            attachment("file0002").await.file_name,
+            "avatar.txt".to_string()
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use eyre::Context;
+use lettre::Message;
+
+use super::transport::{MailTransport, MailTransportError};
+use crate::ids::generate_id;
+
+/// writes every mail to its own .eml file instead of sending it, for testing and development
+pub struct FileMailTransport {
+    root: PathBuf,
+}
+
+impl FileMailTransport {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+#[salvo::async_trait]
+impl MailTransport for FileMailTransport {
+    async fn send(&self, message: Message) -> Result<(), MailTransportError> {
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .with_context(|| format!("unable to create directory {}", self.root.display()))?;
+
+        // generated ids sort by creation time, so the files list in the order they were sent
+        let path = self.root.join(format!("{}.eml", generate_id()));
+        tokio::fs::write(&path, message.formatted())
+            .await
+            .with_context(|| format!("unable to write mail to {}", path.display()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use eyre::Context;
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message,
+    Tokio1Executor,
+};
+
+use super::transport::{MailTransport, MailTransportError};
+
+/// how the connection to the smtp server is secured
+pub enum SmtpSecurity {
+    /// tls from the start, usually on port 465
+    Tls,
+    /// upgraded to tls after connecting, usually on port 587
+    StartTls,
+    /// unencrypted, only meant for a relay on the same machine or network
+    None,
+}
+
+impl TryFrom<&str> for SmtpSecurity {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "tls" => Ok(SmtpSecurity::Tls),
+            "starttls" => Ok(SmtpSecurity::StartTls),
+            "none" => Ok(SmtpSecurity::None),
+            other => Err(format!(
+                "expected `tls`, `starttls` or `none`, got `{other}`"
+            )),
+        }
+    }
+}
+
+pub struct SmtpConfig {
+    pub host: String,
+    /// the default port of the security mode when missing
+    pub port: Option<u16>,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+pub struct SmtpMailTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailTransport {
+    pub fn new(config: SmtpConfig) -> Result<Self, MailTransportError> {
+        let mut builder = match config.security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+                .with_context(|| format!("unable to use smtp host {}", config.host))?,
+            SmtpSecurity::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                    .with_context(|| format!("unable to use smtp host {}", config.host))?
+            }
+            SmtpSecurity::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            }
+        };
+
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+
+        match (config.username, config.password) {
+            (Some(username), Some(password)) => {
+                builder = builder.credentials(Credentials::new(username, password));
+            }
+            (None, None) => {}
+            _ => {
+                return Err(eyre::eyre!(
+                    "smtp username and password must be set together"
+                ))
+            }
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[salvo::async_trait]
+impl MailTransport for SmtpMailTransport {
+    async fn send(&self, message: Message) -> Result<(), MailTransportError> {
+        self.transport
+            .send(message)
+            .await
+            .with_context(|| "unable to send mail over smtp")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+use std::sync::Arc;
+
+use lettre::Message;
+use salvo::async_trait;
+
+pub type MailTransportError = eyre::Report;
+
+pub type MailTransportParam = Arc<dyn MailTransport + Send + Sync>;
+
+#[async_trait]
+pub trait MailTransport {
+    async fn send(&self, message: Message) -> Result<(), MailTransportError>;
+}
@@ -3,23 +3,54 @@
 #![warn(clippy::manual_unwrap_or)]
 #![warn(clippy::map_unwrap_or)]
 
+mod account_deletion;
 mod api;
+mod attachment_blobs;
+mod attachment_gc;
+mod attachment_validation;
 mod db;
+mod email_confirmation;
+mod email_digest;
 mod from_unchecked;
+mod ids;
+mod image_processing;
 mod iso_date_strings;
+mod legacy_upgrade;
+mod mail;
 mod password;
 mod permission_verification;
+mod storage;
+mod subscriptions;
+mod text_validation;
+mod usernames;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use account_deletion::{AccountDeletionConfig, AccountDeletionConfigParam};
+use attachment_validation::{AttachmentConfig, AttachmentConfigParam};
 use db::{database::DatabaseParam, sqlite::SqliteDb};
+use email_digest::{EmailDigestConfig, EmailDigestConfigParam};
 use eyre::Context;
+use mail::{
+    file::FileMailTransport,
+    smtp::{SmtpConfig, SmtpMailTransport, SmtpSecurity},
+    transport::MailTransportParam,
+};
 use salvo::rate_limiter::{BasicQuota, FixedGuard, MokaStore, RateLimiter, RemoteIpIssuer};
-use salvo::{prelude::*, session::CookieStore};
+use salvo::{catcher::Catcher, prelude::*, session::CookieStore};
+use storage::{
+    blob_store::BlobStoreParam,
+    local::LocalBlobStore,
+    s3::{S3BlobStore, S3Config},
+};
 use tokio::sync::RwLock;
 
 fn openapi_route(router: Router) -> Router {
-    let doc = OpenApi::new("Decorum API", env!("CARGO_PKG_VERSION")).merge_router(&router);
+    let mut doc = OpenApi::new("Decorum API", env!("CARGO_PKG_VERSION")).merge_router(&router);
+    doc.components = doc.components.add_security_scheme(
+        api::auth::SESSION_SECURITY_SCHEME,
+        api::auth::session_security_scheme(),
+    );
     router
         .push(doc.into_router("/api-doc/openapi.json"))
         .push(SwaggerUi::new("/api-doc/openapi.json").into_router("/swagger-ui"))
@@ -32,8 +63,12 @@ fn read_routes() -> Router {
         RemoteIpIssuer,
         BasicQuota::per_second(30),
     );
+    // the session is only looked up for requests the limiter let through
     Router::with_hoop(limiter)
+        .hoop(api::auth::load_user)
         .push(Router::with_path("/posts/all_categories").get(api::posts::all_categories_route))
+        .push(Router::with_path("/posts/announcements").get(api::posts::announcements_route))
+        .push(Router::with_path("/posts/unread_posts").get(api::posts::unread_posts_route))
         .push(
             Router::with_path("/posts/posts_from_category/<category_id>")
                 .get(api::posts::posts_from_category_route),
@@ -52,10 +87,32 @@ fn read_routes() -> Router {
         .push(
             Router::with_path("/users/user_from_session").get(api::users::user_from_session_route),
         )
+        .push(
+            Router::with_path("/users/user_from_username/<username>")
+                .get(api::users::user_from_username_route),
+        )
+        .push(Router::with_path("/users/export").get(api::users::export_route))
+        .push(Router::with_path("/users/subscriptions").get(api::users::subscriptions_route))
+        .push(Router::with_path("/users/notifications").get(api::users::notifications_route))
+        .push(Router::with_path("/users/email_settings").get(api::users::email_settings_route))
+        .push(
+            Router::with_path("/users/unsubscribe_email")
+                .get(api::users::email_settings_from_unsubscribe_token_route),
+        )
+        .push(
+            Router::with_path("/users/posts_by_user/<user_id>")
+                .get(api::users::posts_by_user_route),
+        )
+        .push(
+            Router::with_path("/users/replies_by_user/<user_id>")
+                .get(api::users::replies_by_user_route),
+        )
         .push(
             Router::with_path("/attachments/attachment_from_id/<attachment_id>")
                 .get(api::attachments::attachment_from_id_route),
         )
+        .push(Router::with_path("/attachments/list_mine").get(api::attachments::list_mine_route))
+        .push(Router::with_path("/admin/users").get(api::admin::users_route))
 }
 
 fn write_routes() -> Router {
@@ -66,6 +123,7 @@ fn write_routes() -> Router {
         BasicQuota::per_minute(10),
     );
     Router::with_hoop(limiter)
+        .hoop(api::auth::load_user)
         .push(Router::with_path("/users/register").post(api::users::register_route))
         .push(Router::with_path("/users/login").post(api::users::login_route))
         .push(Router::with_path("/users/logout").post(api::users::logout_route))
@@ -74,6 +132,17 @@ fn write_routes() -> Router {
             Router::with_path("/users/edit_user_permission")
                 .post(api::users::edit_user_permission_route),
         )
+        .push(Router::with_path("/users/edit_user_quota").post(api::users::edit_user_quota_route))
+        .push(Router::with_path("/users/change_username").post(api::users::change_username_route))
+        .push(Router::with_path("/users/delete_account").post(api::users::delete_account_route))
+        .push(
+            Router::with_path("/users/edit_email_settings")
+                .post(api::users::edit_email_settings_route),
+        )
+        .push(
+            Router::with_path("/users/unsubscribe_email").post(api::users::unsubscribe_email_route),
+        )
+        .push(Router::with_path("/users/confirm_email").post(api::users::confirm_email_route))
         .push(Router::with_path("/posts/create_post").post(api::posts::create_post_route))
         .push(Router::with_path("/posts/create_category").post(api::posts::create_category_route))
         .push(Router::with_path("/posts/create_reply").post(api::posts::create_reply_route))
@@ -84,11 +153,175 @@ fn write_routes() -> Router {
         .push(Router::with_path("/posts/remove_post").post(api::posts::remove_post_route))
         .push(Router::with_path("/posts/remove_category").post(api::posts::remove_category_route))
         .push(Router::with_path("/posts/remove_reply").post(api::posts::remove_reply_route))
+        .push(Router::with_path("/posts/restore_post").post(api::posts::restore_post_route))
+        .push(Router::with_path("/posts/restore_category").post(api::posts::restore_category_route))
+        .push(Router::with_path("/posts/restore_reply").post(api::posts::restore_reply_route))
+        .push(Router::with_path("/posts/pin_post").post(api::posts::pin_post_route))
+        .push(Router::with_path("/posts/announce_post").post(api::posts::announce_post_route))
+        .push(Router::with_path("/posts/accept_reply").post(api::posts::accept_reply_route))
+        .push(Router::with_path("/posts/react").post(api::posts::react_route))
+        .push(Router::with_path("/posts/unreact").post(api::posts::unreact_route))
+        .push(Router::with_path("/posts/subscribe").post(api::posts::subscribe_route))
+        .push(Router::with_path("/posts/unsubscribe").post(api::posts::unsubscribe_route))
+        .push(Router::with_path("/posts/mark_read").post(api::posts::mark_read_route))
+        .push(
+            Router::with_path("/posts/mark_category_read")
+                .post(api::posts::mark_category_read_route),
+        )
         .push(Router::with_path("/posts/edit_post_lock_status").post(api::posts::lock_post_route))
         .push(
             Router::with_path("/attachments/create_attachment")
                 .post(api::attachments::create_attachment_route),
         )
+        .push(Router::with_path("/attachments/link").post(api::attachments::link_route))
+        .push(Router::with_path("/attachments/remove").post(api::attachments::remove_route))
+        .push(
+            Router::with_path("/attachments/collect_garbage")
+                .post(api::attachments::collect_garbage_route),
+        )
+}
+
+fn blob_store_from_env() -> eyre::Result<BlobStoreParam> {
+    let blob_store = std::env::var("BLOB_STORE").unwrap_or_else(|_| "local".to_string());
+
+    match blob_store.as_str() {
+        "local" => {
+            let path =
+                std::env::var("BLOB_STORE_PATH").unwrap_or_else(|_| "files_uploaded".to_string());
+            Ok(Arc::new(LocalBlobStore::new(path)))
+        }
+        "s3" => {
+            let env = |key: &str| {
+                std::env::var(key).with_context(|| format!("env variable `{key}` should be set"))
+            };
+            let store = S3BlobStore::new(S3Config {
+                endpoint: env("S3_ENDPOINT")?,
+                bucket: env("S3_BUCKET")?,
+                region: env("S3_REGION")?,
+                access_key: env("S3_ACCESS_KEY")?,
+                secret_key: env("S3_SECRET_KEY")?,
+            })?;
+            Ok(Arc::new(store))
+        }
+        other => Err(eyre::eyre!(
+            "env variable `BLOB_STORE` should be `local` or `s3`, got `{other}`"
+        )),
+    }
+}
+
+/// none when `MAIL_TRANSPORT` is `off`, which is the default, so no emails are sent
+fn mail_transport_from_env() -> eyre::Result<Option<MailTransportParam>> {
+    let mail_transport = std::env::var("MAIL_TRANSPORT").unwrap_or_else(|_| "off".to_string());
+
+    match mail_transport.as_str() {
+        "off" => Ok(None),
+        "file" => {
+            let path = std::env::var("MAIL_FILE_PATH").unwrap_or_else(|_| "mail_sent".to_string());
+            Ok(Some(Arc::new(FileMailTransport::new(path))))
+        }
+        "smtp" => {
+            let host = std::env::var("SMTP_HOST")
+                .with_context(|| "env variable `SMTP_HOST` should be set")?;
+            let port = match std::env::var("SMTP_PORT") {
+                Ok(port) => Some(
+                    port.parse()
+                        .with_context(|| "env variable `SMTP_PORT` should be a port number")?,
+                ),
+                Err(_) => None,
+            };
+            let security = match std::env::var("SMTP_SECURITY") {
+                Ok(security) => SmtpSecurity::try_from(security.as_str())
+                    .map_err(|err| eyre::eyre!("env variable `SMTP_SECURITY` invalid: {err}"))?,
+                Err(_) => SmtpSecurity::StartTls,
+            };
+            let transport = SmtpMailTransport::new(SmtpConfig {
+                host,
+                port,
+                security,
+                username: std::env::var("SMTP_USERNAME").ok(),
+                password: std::env::var("SMTP_PASSWORD").ok(),
+            })?;
+            Ok(Some(Arc::new(transport)))
+        }
+        other => Err(eyre::eyre!(
+            "env variable `MAIL_TRANSPORT` should be `off`, `file` or `smtp`, got `{other}`"
+        )),
+    }
+}
+
+fn email_digest_config_from_env() -> eyre::Result<EmailDigestConfig> {
+    let from = std::env::var("MAIL_FROM")
+        .with_context(|| "env variable `MAIL_FROM` should be set to send emails")?
+        .parse()
+        .with_context(|| "env variable `MAIL_FROM` should be an email address")?;
+    let public_url = std::env::var("PUBLIC_URL")
+        .with_context(|| "env variable `PUBLIC_URL` should be set to send emails")?
+        .trim_end_matches('/')
+        .to_string();
+    let frontend_url = std::env::var("FRONTEND_URL")
+        .with_context(|| "env variable `FRONTEND_URL` should be set to send emails")?
+        .trim_end_matches('/')
+        .to_string();
+
+    Ok(EmailDigestConfig {
+        from,
+        public_url,
+        frontend_url,
+    })
+}
+
+fn attachment_config_from_env() -> eyre::Result<AttachmentConfigParam> {
+    let max_size = match std::env::var("ATTACHMENT_MAX_SIZE") {
+        Ok(max_size) => max_size
+            .parse()
+            .with_context(|| "env variable `ATTACHMENT_MAX_SIZE` should be a size in bytes")?,
+        Err(_) => attachment_validation::DEFAULT_MAX_SIZE,
+    };
+
+    let allowed_types = match std::env::var("ATTACHMENT_ALLOWED_TYPES") {
+        Ok(allowed_types) => allowed_types
+            .split(',')
+            .map(str::trim)
+            .filter(|allowed| !allowed.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(_) => attachment_validation::DEFAULT_ALLOWED_TYPES
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    };
+
+    let public_cache_control = std::env::var("ATTACHMENT_PUBLIC_CACHE_CONTROL")
+        .unwrap_or_else(|_| attachment_validation::DEFAULT_PUBLIC_CACHE_CONTROL.to_string());
+    let restricted_cache_control = std::env::var("ATTACHMENT_RESTRICTED_CACHE_CONTROL")
+        .unwrap_or_else(|_| attachment_validation::DEFAULT_RESTRICTED_CACHE_CONTROL.to_string());
+
+    Ok(Arc::new(AttachmentConfig {
+        max_size,
+        allowed_types,
+        public_cache_control,
+        restricted_cache_control,
+    }))
+}
+
+fn account_deletion_config_from_env() -> eyre::Result<AccountDeletionConfigParam> {
+    let username_policy = match std::env::var("DELETED_USERNAME_POLICY") {
+        Ok(policy) => account_deletion::UsernamePolicy::try_from(policy.as_str())
+            .map_err(|err| eyre::eyre!("env variable `DELETED_USERNAME_POLICY` invalid: {err}"))?,
+        Err(_) => account_deletion::DEFAULT_USERNAME_POLICY,
+    };
+
+    Ok(Arc::new(AccountDeletionConfig { username_policy }))
+}
+
+fn duration_from_env(key: &str, default: Duration) -> eyre::Result<Duration> {
+    match std::env::var(key) {
+        Ok(seconds) => seconds
+            .parse()
+            .map(Duration::from_secs)
+            .with_context(|| format!("env variable `{key}` should be a number of seconds")),
+        Err(_) => Ok(default),
+    }
 }
 
 /// TODO: 'wipe' option?
@@ -118,21 +351,52 @@ async fn main() -> eyre::Result<()> {
         .with_context(|| "env variable `DATABASE_URL` should be set")?;
 
     let database = SqliteDb::new(database_url).await?;
+    let blob_store = blob_store_from_env()?;
+    legacy_upgrade::upgrade_if_needed(&database, blob_store.as_ref()).await?;
     let database = Arc::new(RwLock::new(database));
 
-    let router = Router::new();
+    let attachment_config = attachment_config_from_env()?;
+    let account_deletion_config = account_deletion_config_from_env()?;
 
-    let router = router.push(
-        Router::new()
-            .hoop(session_handler)
-            .hoop(affix::inject::<DatabaseParam>(database))
-            .push(write_routes())
-            .push(read_routes()),
+    attachment_gc::spawn(
+        database.clone(),
+        blob_store.clone(),
+        duration_from_env("ATTACHMENT_GC_INTERVAL", attachment_gc::DEFAULT_INTERVAL)?,
+        duration_from_env(
+            "ATTACHMENT_GC_GRACE_PERIOD",
+            attachment_gc::DEFAULT_GRACE_PERIOD,
+        )?,
     );
 
+    let mut api_router = Router::new()
+        .hoop(session_handler)
+        .hoop(affix::inject::<DatabaseParam>(database.clone()))
+        .hoop(affix::inject::<BlobStoreParam>(blob_store))
+        .hoop(affix::inject::<AttachmentConfigParam>(attachment_config))
+        .hoop(affix::inject::<AccountDeletionConfigParam>(
+            account_deletion_config,
+        ));
+
+    // routes find no mail transport in the depot when the forum sends no emails
+    if let Some(mail_transport) = mail_transport_from_env()? {
+        let email_digest_config = Arc::new(email_digest_config_from_env()?);
+        email_digest::spawn(
+            database,
+            mail_transport.clone(),
+            email_digest_config.clone(),
+            duration_from_env("EMAIL_DIGEST_INTERVAL", email_digest::DEFAULT_INTERVAL)?,
+        );
+        api_router = api_router
+            .hoop(affix::inject::<MailTransportParam>(mail_transport))
+            .hoop(affix::inject::<EmailDigestConfigParam>(email_digest_config));
+    }
+
+    let router = Router::new().push(api_router.push(write_routes()).push(read_routes()));
+
     let router = openapi_route(router);
+    let service = Service::new(router).catcher(Catcher::default().hoop(api::error::catcher));
 
     let acceptor = TcpListener::new(&format!("{bind_url}:5800")).bind().await;
-    Server::new(acceptor).serve(router).await;
+    Server::new(acceptor).serve(service).await;
     Ok(())
 }
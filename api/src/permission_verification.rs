@@ -1,4 +1,4 @@
-use crate::db::models::Permission;
+use crate::db::models::{AttachmentQuota, Permission};
 
 pub fn is_allowed(user_permission: &Permission, required_permission: &Permission) -> bool {
     use Permission::{Admin, Banned, Root, Unverified, User};
@@ -21,6 +21,60 @@ pub fn permission_for_attachment_upload() -> Permission {
     Permission::User
 }
 
+/// applies to users without a quota set by an admin
+pub fn attachment_quota_for_permission(permission: &Permission) -> AttachmentQuota {
+    const MIB: i64 = 1024 * 1024;
+    match permission {
+        Permission::Banned | Permission::Unverified => AttachmentQuota {
+            max_bytes: 0,
+            max_files: 0,
+        },
+        Permission::User => AttachmentQuota {
+            max_bytes: 100 * MIB,
+            max_files: 500,
+        },
+        Permission::Admin | Permission::Root => AttachmentQuota {
+            max_bytes: 1024 * MIB,
+            max_files: 5000,
+        },
+    }
+}
+
 pub fn permission_for_important_actions() -> Permission {
     Permission::Admin
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_grows_with_permission() {
+        let unverified = attachment_quota_for_permission(&Permission::Unverified);
+        let user = attachment_quota_for_permission(&Permission::User);
+        let admin = attachment_quota_for_permission(&Permission::Admin);
+
+        assert_eq!((unverified.max_bytes, unverified.max_files), (0, 0));
+        assert_eq!(
+            attachment_quota_for_permission(&Permission::Banned).max_files,
+            0
+        );
+        assert!(user.max_bytes > 0 && user.max_files > 0);
+        assert!(admin.max_bytes > user.max_bytes && admin.max_files > user.max_files);
+    }
+
+    #[test]
+    fn only_uploaders_get_a_quota() {
+        for permission in [
+            Permission::Banned,
+            Permission::Unverified,
+            Permission::User,
+            Permission::Admin,
+            Permission::Root,
+        ] {
+            let can_upload = is_allowed(&permission, &permission_for_attachment_upload());
+            let has_quota = attachment_quota_for_permission(&permission).max_files > 0;
+            assert_eq!(can_upload, has_quota, "{permission}");
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::{io, ops::Range, path::Path, sync::Arc};
+
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use salvo::async_trait;
+
+pub type BlobStoreError = eyre::Report;
+
+pub type BlobStoreParam = Arc<dyn BlobStore + Send + Sync>;
+
+pub struct Blob {
+    pub size: Option<u64>,
+    pub stream: BoxStream<'static, Result<Bytes, io::Error>>,
+}
+
+#[async_trait]
+pub trait BlobStore {
+    async fn put(&self, key: &str, file: &Path) -> Result<(), BlobStoreError>;
+    async fn put_bytes(&self, key: &str, data: Bytes) -> Result<(), BlobStoreError>;
+    async fn get(&self, key: &str) -> Result<Option<Blob>, BlobStoreError>;
+    /// the returned blob only streams the bytes in `range`, which must lie within the blob
+    async fn get_range(&self, key: &str, range: Range<u64>)
+        -> Result<Option<Blob>, BlobStoreError>;
+    async fn size(&self, key: &str) -> Result<Option<u64>, BlobStoreError>;
+    async fn remove(&self, key: &str) -> Result<(), BlobStoreError>;
+}
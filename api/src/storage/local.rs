@@ -0,0 +1,143 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use eyre::Context;
+use futures_util::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use super::blob_store::{Blob, BlobStore, BlobStoreError};
+
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path_from_key(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    async fn create_parent_dirs(&self, key: &str) -> Result<PathBuf, BlobStoreError> {
+        let path = self.path_from_key(key);
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("unable to create directory {}", parent.display()))?;
+        }
+
+        Ok(path)
+    }
+}
+
+async fn open_if_exists(path: &Path) -> Result<Option<tokio::fs::File>, BlobStoreError> {
+    match tokio::fs::File::open(path).await {
+        Ok(file) => Ok(Some(file)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("unable to open {}", path.display())),
+    }
+}
+
+#[salvo::async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, file: &Path) -> Result<(), BlobStoreError> {
+        let path = self.create_parent_dirs(key).await?;
+
+        tokio::fs::copy(file, &path)
+            .await
+            .with_context(|| format!("unable to write file to {}", path.display()))?;
+
+        Ok(())
+    }
+
+    async fn put_bytes(&self, key: &str, data: Bytes) -> Result<(), BlobStoreError> {
+        let path = self.create_parent_dirs(key).await?;
+
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("unable to write file to {}", path.display()))?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Blob>, BlobStoreError> {
+        let path = self.path_from_key(key);
+
+        let Some(file) = open_if_exists(&path).await? else {
+            return Ok(None);
+        };
+
+        let metadata = file
+            .metadata()
+            .await
+            .with_context(|| format!("unable to read metadata of {}", path.display()))?;
+
+        Ok(Some(Blob {
+            size: Some(metadata.len()),
+            stream: ReaderStream::new(file).boxed(),
+        }))
+    }
+
+    async fn get_range(
+        &self,
+        key: &str,
+        range: Range<u64>,
+    ) -> Result<Option<Blob>, BlobStoreError> {
+        let path = self.path_from_key(key);
+
+        let Some(mut file) = open_if_exists(&path).await? else {
+            return Ok(None);
+        };
+
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .with_context(|| format!("unable to seek in {}", path.display()))?;
+
+        let length = range.end - range.start;
+        Ok(Some(Blob {
+            size: Some(length),
+            stream: ReaderStream::new(file.take(length)).boxed(),
+        }))
+    }
+
+    async fn size(&self, key: &str) -> Result<Option<u64>, BlobStoreError> {
+        let path = self.path_from_key(key);
+
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                Err(err).with_context(|| format!("unable to read metadata of {}", path.display()))
+            }
+        }
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), BlobStoreError> {
+        let path = self.path_from_key(key);
+
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("unable to remove {}", path.display()))
+            }
+        }
+
+        // prune directories left empty, removing a non-empty one fails and stops the walk
+        for dir in path.ancestors().skip(1) {
+            if dir == self.root || tokio::fs::remove_dir(dir).await.is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use eyre::eyre;
+use tokio::sync::RwLockReadGuard;
+
+use crate::{
+    db::{
+        database::{CreateNotification, Database, DatabaseParam},
+        models::{
+            Category, NotificationKind, PostId, ReplyId, Subscription, SubscriptionLevel,
+            SubscriptionTarget, UserId,
+        },
+    },
+    permission_verification,
+};
+
+fn rank(level: SubscriptionLevel) -> u8 {
+    match level {
+        SubscriptionLevel::Muted => 0,
+        SubscriptionLevel::Normal => 1,
+        SubscriptionLevel::Watching => 2,
+    }
+}
+
+/// the level a post is followed with, muting a post silences it even in a watched category,
+/// otherwise the more attentive of the two subscriptions applies
+pub fn effective_level(
+    post_level: Option<SubscriptionLevel>,
+    category_level: Option<SubscriptionLevel>,
+) -> Option<SubscriptionLevel> {
+    match (post_level, category_level) {
+        (Some(SubscriptionLevel::Muted), _) => Some(SubscriptionLevel::Muted),
+        (Some(post_level), Some(category_level)) => {
+            Some(std::cmp::max_by_key(post_level, category_level, |level| {
+                rank(*level)
+            }))
+        }
+        (Some(level), None) | (None, Some(level)) => Some(level),
+        (None, None) => None,
+    }
+}
+
+/// the level each subscribed user follows a post with, see [`effective_level`]
+fn effective_levels(subscriptions: Vec<Subscription>) -> Vec<(UserId, SubscriptionLevel)> {
+    let mut levels: HashMap<
+        String,
+        (UserId, Option<SubscriptionLevel>, Option<SubscriptionLevel>),
+    > = HashMap::new();
+    for subscription in subscriptions {
+        let (_, post_level, category_level) = levels
+            .entry(subscription.user_id.to_string())
+            .or_insert_with(|| (subscription.user_id.clone(), None, None));
+        match subscription.target {
+            SubscriptionTarget::Post => *post_level = Some(subscription.level),
+            SubscriptionTarget::Category => *category_level = Some(subscription.level),
+        }
+    }
+
+    levels
+        .into_values()
+        .filter_map(|(user_id, post_level, category_level)| {
+            effective_level(post_level, category_level).map(|level| (user_id, level))
+        })
+        .collect()
+}
+
+/// subscriptions outlive permission changes, so access is checked again for every notification
+async fn can_read<Db: Database + Sync + Send + ?Sized>(
+    db: &RwLockReadGuard<'_, Db>,
+    user_id: &UserId,
+    category: &Category,
+) -> eyre::Result<bool> {
+    let user = db.user_from_id(user_id).await?;
+
+    Ok(user.is_some_and(|user| {
+        !user.deleted
+            && permission_verification::is_allowed(
+                &user.permission,
+                &category.minimum_read_permission,
+            )
+    }))
+}
+
+async fn create_notifications(
+    db: &DatabaseParam,
+    notifications: Vec<CreateNotification>,
+) -> eyre::Result<usize> {
+    let count = notifications.len();
+    let mut db = db.write().await;
+    for notification in notifications {
+        db.create_notification(notification).await?;
+    }
+
+    Ok(count)
+}
+
+/// notifies users watching or following the category of a new post, returns how many were
+pub async fn notify_new_post(db: &DatabaseParam, post_id: &PostId) -> eyre::Result<usize> {
+    let notifications = {
+        let db = db.read().await;
+        let post = db
+            .post_from_id(post_id)
+            .await?
+            .ok_or_else(|| eyre!("post '{post_id}' does not exist"))?;
+        let category = db
+            .category_from_id(&post.category_id)
+            .await?
+            .ok_or_else(|| eyre!("category '{}' does not exist", post.category_id))?;
+        let subscriptions = db.subscriptions_from_targets(&category.id, None).await?;
+
+        let mut notifications = Vec::new();
+        for (user_id, level) in effective_levels(subscriptions) {
+            if user_id == post.creator_id || level == SubscriptionLevel::Muted {
+                continue;
+            }
+            if !can_read(&db, &user_id, &category).await? {
+                continue;
+            }
+            notifications.push(CreateNotification {
+                user_id,
+                kind: NotificationKind::NewPost,
+                post_id: post.id.clone(),
+                reply_id: None,
+                creator_id: post.creator_id.clone(),
+            });
+        }
+        notifications
+    };
+
+    create_notifications(db, notifications).await
+}
+
+/// notifies users watching the post or its category of a new reply, and users following it
+/// normally when the reply answers one of theirs, returns how many were
+pub async fn notify_new_reply(db: &DatabaseParam, reply_id: &ReplyId) -> eyre::Result<usize> {
+    let notifications = {
+        let db = db.read().await;
+        let reply = db
+            .reply_from_id(reply_id)
+            .await?
+            .ok_or_else(|| eyre!("reply '{reply_id}' does not exist"))?;
+        let post = db
+            .post_from_id(&reply.post_id)
+            .await?
+            .ok_or_else(|| eyre!("post '{}' does not exist", reply.post_id))?;
+        let category = db
+            .category_from_id(&post.category_id)
+            .await?
+            .ok_or_else(|| eyre!("category '{}' does not exist", post.category_id))?;
+        let parent_creator_id = match &reply.parent_reply_id {
+            Some(parent_reply_id) => db
+                .reply_from_id(parent_reply_id)
+                .await?
+                .map(|parent_reply| parent_reply.creator_id),
+            None => None,
+        };
+        let subscriptions = db
+            .subscriptions_from_targets(&category.id, Some(&post.id))
+            .await?;
+
+        let mut notifications = Vec::new();
+        for (user_id, level) in effective_levels(subscriptions) {
+            if user_id == reply.creator_id {
+                continue;
+            }
+            let kind = match level {
+                SubscriptionLevel::Muted => continue,
+                _ if parent_creator_id.as_ref() == Some(&user_id) => NotificationKind::ReplyToYou,
+                SubscriptionLevel::Watching => NotificationKind::NewReply,
+                SubscriptionLevel::Normal => continue,
+            };
+            if !can_read(&db, &user_id, &category).await? {
+                continue;
+            }
+            notifications.push(CreateNotification {
+                user_id,
+                kind,
+                post_id: post.id.clone(),
+                reply_id: Some(reply.id.clone()),
+                creator_id: reply.creator_id.clone(),
+            });
+        }
+        notifications
+    };
+
+    create_notifications(db, notifications).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::models::Id, from_unchecked::FromUnchecked};
+
+    fn subscription(
+        user: &str,
+        target: SubscriptionTarget,
+        level: SubscriptionLevel,
+    ) -> Subscription {
+        Subscription {
+            user_id: UserId::from_unchecked(user.to_string()),
+            target,
+            target_id: Id::from_unchecked("target00".to_string()),
+            level,
+            date_created: String::new(),
+        }
+    }
+
+    fn level_of(levels: &[(UserId, SubscriptionLevel)], user: &str) -> Option<SubscriptionLevel> {
+        levels
+            .iter()
+            .find(|(user_id, _)| user_id.to_string() == user)
+            .map(|(_, level)| *level)
+    }
+
+    #[test]
+    fn ranks_levels_by_attention() {
+        assert!(rank(SubscriptionLevel::Muted) < rank(SubscriptionLevel::Normal));
+        assert!(rank(SubscriptionLevel::Normal) < rank(SubscriptionLevel::Watching));
+    }
+
+    #[test]
+    fn muting_a_post_wins_over_its_category() {
+        let levels = effective_levels(vec![
+            subscription(
+                "user0001",
+                SubscriptionTarget::Category,
+                SubscriptionLevel::Watching,
+            ),
+            subscription(
+                "user0001",
+                SubscriptionTarget::Post,
+                SubscriptionLevel::Muted,
+            ),
+        ]);
+        assert_eq!(
+            level_of(&levels, "user0001"),
+            Some(SubscriptionLevel::Muted)
+        );
+    }
+
+    #[test]
+    fn the_more_attentive_subscription_applies() {
+        let levels = effective_levels(vec![
+            subscription(
+                "user0001",
+                SubscriptionTarget::Category,
+                SubscriptionLevel::Watching,
+            ),
+            subscription(
+                "user0001",
+                SubscriptionTarget::Post,
+                SubscriptionLevel::Normal,
+            ),
+            subscription(
+                "user0002",
+                SubscriptionTarget::Category,
+                SubscriptionLevel::Muted,
+            ),
+            subscription(
+                "user0002",
+                SubscriptionTarget::Post,
+                SubscriptionLevel::Watching,
+            ),
+            subscription(
+                "user0003",
+                SubscriptionTarget::Category,
+                SubscriptionLevel::Normal,
+            ),
+        ]);
+
+        assert_eq!(levels.len(), 3);
+        assert_eq!(
+            level_of(&levels, "user0001"),
+            Some(SubscriptionLevel::Watching)
+        );
+        assert_eq!(
+            level_of(&levels, "user0002"),
+            Some(SubscriptionLevel::Watching)
+        );
+        assert_eq!(
+            level_of(&levels, "user0003"),
+            Some(SubscriptionLevel::Normal)
+        );
+    }
+
+    #[test]
+    fn a_muted_category_applies_without_a_post_subscription() {
+        assert_eq!(
+            effective_level(None, Some(SubscriptionLevel::Muted)),
+            Some(SubscriptionLevel::Muted)
+        );
+        assert_eq!(
+            effective_level(
+                Some(SubscriptionLevel::Normal),
+                Some(SubscriptionLevel::Muted)
+            ),
+            Some(SubscriptionLevel::Normal)
+        );
+        assert_eq!(effective_level(None, None), None);
+    }
+
+    #[test]
+    fn no_subscriptions_give_no_levels() {
+        assert!(effective_levels(Vec::new()).is_empty());
+    }
+}
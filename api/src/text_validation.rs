@@ -0,0 +1,260 @@
+use std::fmt;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// a single grapheme made of more bytes than this is almost certainly a stack of
+/// combining marks meant to draw over surrounding text
+const MAX_GRAPHEME_BYTES: usize = 64;
+
+/// the rules user supplied text is checked against, lengths count grapheme clusters
+/// so "å", "👍🏽" and "x" all count as one character
+#[derive(Clone, Copy)]
+pub struct TextRules {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// allows line breaks and tabs, other control characters are always rejected
+    pub multiline: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TextError {
+    TooShort { min: usize, actual: usize },
+    TooLong { max: usize, actual: usize },
+    ControlCharacter { character: char, position: usize },
+    BidiControl { character: char, position: usize },
+    InvisibleCharacter { character: char, position: usize },
+    OversizedCharacter { position: usize },
+    InvalidFormat(&'static str),
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::TooShort { min, actual } => write!(
+                f,
+                "too short, must be at least {min} characters but is {actual}"
+            ),
+            TextError::TooLong { max, actual } => write!(
+                f,
+                "too long, must be at most {max} characters but is {actual}"
+            ),
+            TextError::ControlCharacter {
+                character,
+                position,
+            } => write!(
+                f,
+                "contains control character U+{:04X} at position {position}",
+                u32::from(*character)
+            ),
+            TextError::BidiControl {
+                character,
+                position,
+            } => write!(
+                f,
+                "contains text direction character U+{:04X} at position {position}",
+                u32::from(*character)
+            ),
+            TextError::InvisibleCharacter {
+                character,
+                position,
+            } => write!(
+                f,
+                "contains invisible character U+{:04X} at position {position}",
+                u32::from(*character)
+            ),
+            TextError::OversizedCharacter { position } => write!(
+                f,
+                "character at position {position} is made of too many code points"
+            ),
+            TextError::InvalidFormat(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+impl TextRules {
+    pub const fn single_line(min_length: usize, max_length: usize) -> Self {
+        TextRules {
+            min_length,
+            max_length,
+            multiline: false,
+        }
+    }
+
+    pub const fn multiline(min_length: usize, max_length: usize) -> Self {
+        TextRules {
+            min_length,
+            max_length,
+            multiline: true,
+        }
+    }
+
+    /// normalizes to NFC, then checks the characters and the length,
+    /// positions in errors count graphemes starting at 1
+    pub fn validate(&self, value: String) -> Result<String, TextError> {
+        let value: String = value.nfc().collect();
+
+        let mut length = 0;
+        for (index, grapheme) in value.graphemes(true).enumerate() {
+            let position = index + 1;
+            if grapheme.len() > MAX_GRAPHEME_BYTES {
+                return Err(TextError::OversizedCharacter { position });
+            }
+            for character in grapheme.chars() {
+                self.check_character(character, position)?;
+            }
+            length = position;
+        }
+
+        if length < self.min_length {
+            return Err(TextError::TooShort {
+                min: self.min_length,
+                actual: length,
+            });
+        }
+        if length > self.max_length {
+            return Err(TextError::TooLong {
+                max: self.max_length,
+                actual: length,
+            });
+        }
+
+        Ok(value)
+    }
+
+    fn check_character(&self, character: char, position: usize) -> Result<(), TextError> {
+        if is_bidi_control(character) {
+            return Err(TextError::BidiControl {
+                character,
+                position,
+            });
+        }
+        if is_invisible(character) {
+            return Err(TextError::InvisibleCharacter {
+                character,
+                position,
+            });
+        }
+        if character.is_control() && !(self.multiline && matches!(character, '\n' | '\r' | '\t')) {
+            return Err(TextError::ControlCharacter {
+                character,
+                position,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// embeddings, overrides, isolates and marks, which can make text display in a
+/// different order than it is stored
+fn is_bidi_control(character: char) -> bool {
+    matches!(
+        character,
+        '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+/// zero width characters that hide text or make equal looking strings differ,
+/// the zero width joiner and non-joiner are left alone since emoji and some scripts need them
+fn is_invisible(character: char) -> bool {
+    matches!(
+        character,
+        '\u{00AD}'
+            | '\u{180E}'
+            | '\u{200B}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{FEFF}'
+            | '\u{FFF9}'..='\u{FFFB}'
+    )
+}
+
+/// the UTS 39 skeleton of a text, texts that look alike share a skeleton,
+/// like "paypal" written with a cyrillic "а" and the latin original
+pub fn skeleton(value: &str) -> String {
+    unicode_security::confusable_detection::skeleton(&value.to_lowercase())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: TextRules = TextRules::single_line(1, 4);
+    const CONTENT: TextRules = TextRules::multiline(1, 16);
+
+    #[test]
+    fn normalizes_to_nfc() {
+        let decomposed = "cafe\u{0301}".to_string();
+        assert_eq!(CONTENT.validate(decomposed), Ok("caf\u{00E9}".to_string()));
+    }
+
+    #[test]
+    fn counts_graphemes() {
+        // a thumbs up with a skin tone, a family joined by zero width joiners and a flag
+        assert!(NAME.validate("👍🏽👨‍👩‍👧🇳🇱x".to_string()).is_ok());
+        assert_eq!(
+            NAME.validate("abcde".to_string()),
+            Err(TextError::TooLong { max: 4, actual: 5 })
+        );
+        assert_eq!(
+            NAME.validate(String::new()),
+            Err(TextError::TooShort { min: 1, actual: 0 })
+        );
+    }
+
+    #[test]
+    fn rejects_bidi_controls() {
+        assert_eq!(
+            NAME.validate("a\u{202E}b".to_string()),
+            Err(TextError::BidiControl {
+                character: '\u{202E}',
+                position: 2
+            })
+        );
+        assert!(CONTENT.validate("\u{2066}x\u{2069}".to_string()).is_err());
+    }
+
+    #[test]
+    fn rejects_invisible_characters() {
+        assert_eq!(
+            NAME.validate("a\u{200B}b".to_string()),
+            Err(TextError::InvisibleCharacter {
+                character: '\u{200B}',
+                position: 2
+            })
+        );
+        assert!(NAME.validate("\u{FEFF}ab".to_string()).is_err());
+    }
+
+    #[test]
+    fn allows_line_breaks_only_in_multiline_text() {
+        assert!(CONTENT.validate("one\ntwo\tthree\r\n".to_string()).is_ok());
+        assert!(matches!(
+            NAME.validate("a\nb".to_string()),
+            Err(TextError::ControlCharacter {
+                character: '\n',
+                ..
+            })
+        ));
+        assert!(CONTENT.validate("a\u{0007}".to_string()).is_err());
+    }
+
+    #[test]
+    fn rejects_stacked_combining_marks() {
+        let zalgo = format!("a{}", "\u{0301}".repeat(40));
+        assert_eq!(
+            CONTENT.validate(zalgo),
+            Err(TextError::OversizedCharacter { position: 1 })
+        );
+    }
+
+    #[test]
+    fn skeletons_match_lookalikes() {
+        assert_eq!(skeleton("paypal"), skeleton("p\u{0430}yp\u{0430}l"));
+        assert_eq!(skeleton("Admin"), skeleton("admin"));
+        assert_ne!(skeleton("admin"), skeleton("administrator"));
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use crate::{
+    db::models::{Name, DELETED_USER_USERNAME, RELEASED_USERNAME_PREFIX},
+    iso_date_strings::utc_date_iso_string_ago,
+    text_validation::skeleton,
+};
+
+/// nobody may register or rename to these or anything that looks like them
+pub const RESERVED_USERNAMES: &[&str] = &["admin", "root", "system"];
+
+/// how long a user has to wait between username changes
+pub const CHANGE_COOLDOWN: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// whether the last username change was less than [`CHANGE_COOLDOWN`] ago
+pub fn is_cooling_down(last_change: Option<&str>) -> eyre::Result<bool> {
+    let changed_after = utc_date_iso_string_ago(CHANGE_COOLDOWN)?;
+    Ok(last_change.is_some_and(|last_change| last_change > changed_after.as_str()))
+}
+
+pub fn is_reserved(username: &Name) -> bool {
+    let username = username.to_string();
+    let username_skeleton = skeleton(&username);
+
+    // the placeholder and released usernames of deleted accounts
+    let lowercase = username.to_lowercase();
+    lowercase.starts_with(DELETED_USER_USERNAME)
+        || lowercase.starts_with(RELEASED_USERNAME_PREFIX)
+        || RESERVED_USERNAMES
+            .iter()
+            .any(|reserved| skeleton(reserved) == username_skeleton)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::iso_date_strings::utc_date_iso_string;
+
+    fn name(username: &str) -> Name {
+        Name::try_from(username.to_string()).expect("username should be valid")
+    }
+
+    #[test]
+    fn reserves_names_and_lookalikes() {
+        for username in [
+            "admin",
+            "Root",
+            "SYSTEM",
+            "r00t",
+            "\u{0430}dmin",
+            "[deleted]",
+            "[Deleted]x",
+            "[del]0123",
+        ] {
+            assert!(is_reserved(&name(username)), "{username}");
+        }
+    }
+
+    #[test]
+    fn allows_other_names() {
+        for username in ["administrator", "rooted", "del", "[delta]", "deleted"] {
+            assert!(!is_reserved(&name(username)), "{username}");
+        }
+    }
+
+    #[test]
+    fn cools_down_after_a_change() {
+        assert!(!is_cooling_down(None).expect("cooldown should be checked"));
+
+        let just_now = utc_date_iso_string();
+        assert!(is_cooling_down(Some(&just_now)).expect("cooldown should be checked"));
+
+        let long_ago = utc_date_iso_string_ago(CHANGE_COOLDOWN + Duration::from_secs(60))
+            .expect("date should be valid");
+        assert!(!is_cooling_down(Some(&long_ago)).expect("cooldown should be checked"));
+    }
+}
@@ -0,0 +1,51 @@
+-- copies a database from before sortable ids and blob storage into the current tables.
+-- the api runs this on startup, see src/legacy_upgrade.rs: the legacy tables are renamed
+-- to legacy_*, prepare.sql creates the current tables, the files of legacy attachments are
+-- moved to the blob store and their rows inserted, and then this script copies the rest.
+-- legacy 8 character ids are kept as they are, they are still accepted wherever ids are.
+
+-- the skeletons are computed by the api once the users are copied
+INSERT INTO user (id, username, username_skeleton, nickname, password, permission, avatar_id, date_edited, date_created, deleted)
+SELECT id, username, username, nickname, password, permission, avatar_id, date_edited, date_created, deleted
+FROM legacy_user;
+
+INSERT INTO category (id, title, minimum_write_permission, minimum_read_permission, qa_mode, deleted, date_edited, date_created)
+SELECT id, title, minimum_write_permission, minimum_read_permission, 0, deleted, date_edited, date_created
+FROM legacy_category;
+
+INSERT INTO post (id, title, content, category_id, creator_id, locked, pinned, announcement, deleted, date_created, date_edited)
+SELECT id, title, content, category_id, creator_id, locked, 0, 0, deleted, date_created, date_edited
+FROM legacy_post;
+
+INSERT INTO reply (id, creator_id, content, post_id, date_edited, date_created, deleted)
+SELECT id, creator_id, content, post_id, date_edited, date_created, deleted
+FROM legacy_reply;
+
+-- legacy attachments were never linked, so they are linked to the first post or reply that
+-- embeds their download path. the ones embedded nowhere and used as nobody's avatar are left
+-- to garbage collection like any other unlinked upload
+UPDATE attachment
+SET link_target='Post', link_target_id=(
+    SELECT post.id FROM post
+    WHERE instr(post.content, 'attachment_from_id/' || attachment.id) > 0
+    ORDER BY post.date_created LIMIT 1
+)
+WHERE EXISTS (
+    SELECT 1 FROM post WHERE instr(post.content, 'attachment_from_id/' || attachment.id) > 0
+);
+
+UPDATE attachment
+SET link_target='Reply', link_target_id=(
+    SELECT reply.id FROM reply
+    WHERE instr(reply.content, 'attachment_from_id/' || attachment.id) > 0
+    ORDER BY reply.date_created LIMIT 1
+)
+WHERE link_target IS NULL AND EXISTS (
+    SELECT 1 FROM reply WHERE instr(reply.content, 'attachment_from_id/' || attachment.id) > 0
+);
+
+DROP TABLE legacy_attachment;
+DROP TABLE legacy_reply;
+DROP TABLE legacy_post;
+DROP TABLE legacy_category;
+DROP TABLE legacy_user;